@@ -0,0 +1,152 @@
+use arangors::{
+    AqlQuery, ClientError, Document,
+    document::options::{InsertOptions, RemoveOptions, UpdateOptions},
+};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::{prelude::*, utils::handle_document_response};
+
+/// Database operations used by [`GraphCreatorBase`](crate::base_creator::GraphCreatorBase).
+/// Implemented by [`Database`], [`Transaction`] and [`DryRun`](crate::dry_run::DryRun).
+pub trait DbBackend: Sync {
+    /// Inserts `document` and returns the stored document
+    fn create_document(&self, collection: &str, document: Value) -> Result<Document<Value>>;
+
+    /// Document with `key`, `None` if it doesn't exist
+    fn get_document(&self, collection: &str, key: &str) -> Result<Option<Document<Value>>>;
+
+    /// Merges `attributes` into the document with `key` and returns the updated document
+    fn update_document(
+        &self,
+        collection: &str,
+        key: &str,
+        attributes: Value,
+    ) -> Result<Document<Value>>;
+
+    fn remove_document(&self, collection: &str, key: &str) -> Result<()>;
+
+    fn aql_query(&self, aql: AqlQuery) -> Result<Vec<Value>>;
+
+    /// Whether writes can be grouped in a stream transaction
+    fn supports_transactions(&self) -> bool {
+        true
+    }
+}
+
+impl DbBackend for Database {
+    fn create_document(&self, collection: &str, document: Value) -> Result<Document<Value>> {
+        create_document(&self.collection(collection)?, collection, document)
+    }
+
+    fn get_document(&self, collection: &str, key: &str) -> Result<Option<Document<Value>>> {
+        get_document(&self.collection(collection)?, key)
+    }
+
+    fn update_document(
+        &self,
+        collection: &str,
+        key: &str,
+        attributes: Value,
+    ) -> Result<Document<Value>> {
+        update_document(&self.collection(collection)?, collection, key, attributes)
+    }
+
+    fn remove_document(&self, collection: &str, key: &str) -> Result<()> {
+        remove_document(&self.collection(collection)?, key)
+    }
+
+    fn aql_query(&self, aql: AqlQuery) -> Result<Vec<Value>> {
+        Database::aql_query(self, aql).map_err(schema_violation)
+    }
+}
+
+impl DbBackend for Transaction {
+    fn create_document(&self, collection: &str, document: Value) -> Result<Document<Value>> {
+        create_document(&self.collection(collection)?, collection, document)
+    }
+
+    fn get_document(&self, collection: &str, key: &str) -> Result<Option<Document<Value>>> {
+        get_document(&self.collection(collection)?, key)
+    }
+
+    fn update_document(
+        &self,
+        collection: &str,
+        key: &str,
+        attributes: Value,
+    ) -> Result<Document<Value>> {
+        update_document(&self.collection(collection)?, collection, key, attributes)
+    }
+
+    fn remove_document(&self, collection: &str, key: &str) -> Result<()> {
+        remove_document(&self.collection(collection)?, key)
+    }
+
+    fn aql_query(&self, aql: AqlQuery) -> Result<Vec<Value>> {
+        Transaction::aql_query(self, aql).map_err(schema_violation)
+    }
+}
+
+fn create_document(
+    coll: &Collection,
+    collection: &str,
+    document: Value,
+) -> Result<Document<Value>> {
+    let doc_res = coll
+        .create_document(document, InsertOptions::builder().return_new(true).build())
+        .map_err(schema_violation)?;
+    handle_document_response(collection, doc_res)
+}
+
+fn get_document(coll: &Collection, key: &str) -> Result<Option<Document<Value>>> {
+    match coll.document(key) {
+        Ok(doc) => Ok(Some(doc)),
+        // "ERROR_ARANGO_DOCUMENT_NOT_FOUND"
+        Err(ClientError::Arango(e)) if e.error_num() == 1202 => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn update_document(
+    coll: &Collection,
+    collection: &str,
+    key: &str,
+    attributes: Value,
+) -> Result<Document<Value>> {
+    let doc_res = coll
+        .update_document(
+            key,
+            attributes,
+            UpdateOptions::builder().return_new(true).build(),
+        )
+        .map_err(schema_violation)?;
+    handle_document_response(collection, doc_res)
+}
+
+fn remove_document(coll: &Collection, key: &str) -> Result<()> {
+    coll.remove_document::<Value>(key, RemoveOptions::builder().build(), None)?;
+    Ok(())
+}
+
+/// Converts a rejection by the schema validation of a collection into [`Error::SchemaViolation`]
+fn schema_violation(e: ClientError) -> Error {
+    match e {
+        // "ERROR_VALIDATION_FAILED"
+        ClientError::Arango(e) if e.error_num() == 1620 => {
+            Error::SchemaViolation(e.message().to_string())
+        }
+        e => e.into(),
+    }
+}
+
+/// Converts a document returned by a [`DbBackend`] into a document of type `T`
+pub fn from_value_document<T>(document: Document<Value>) -> Result<Document<T>>
+where
+    T: DeserializeOwned,
+{
+    Ok(Document {
+        header: document.header,
+        document: serde_json::from_value(document.document)?,
+    })
+}
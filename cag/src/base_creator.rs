@@ -1,19 +1,105 @@
-use std::fmt::Debug;
+use std::{collections::HashMap, fmt::Debug};
 
 use arangors::{
-    AqlQuery, ClientError, Document, document::options::InsertOptions, graph::EdgeDefinition,
+    AqlQuery, ClientError, Document,
+    document::Header,
+    graph::EdgeDefinition,
+    transaction::{TransactionCollections, TransactionSettings},
 };
 use schemars::JsonSchema;
-use serde::{Serialize, de::DeserializeOwned};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::Value;
 
 use crate::{
+    backend::{DbBackend, from_value_document},
+    cursor::{DocumentCursor, ResultCursor},
+    filter::{AqlFilter, Filter},
+    limiter::AdaptiveLimiter,
     prelude::*,
-    utils::{config::Config, get_name, handle_document_response},
+    provenance::Provenance,
+    retry::{RetryPolicy, is_transient, is_transient_unapplied, is_unique_constraint_violation},
+    stats::{Outcome, RunStats},
+    trace::{OperationSpan, event},
+    transaction::TxnHandle,
+    utils::{CollectionName, attribute_path, config::Config, get_name, prefixed_name},
 };
 
 pub struct UpsertResult<CollType> {
     pub document: Document<CollType>,
     pub created: bool,
+
+    /// `true` if an existing document was changed. Always `false` for [`UpsertMode::InsertOnly`]
+    pub updated: bool,
+
+    /// `true` if the existing document is soft-deleted, see [`SoftDelete`]. It is returned
+    /// unchanged.
+    pub deleted: bool,
+}
+
+/// How [`GraphCreatorBase::upsert_node_with_mode`] treats an already existing document
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpsertMode {
+    /// Return the existing document unchanged
+    InsertOnly,
+
+    /// Merge the provided data into the existing document. Attributes that are `null` in the
+    /// provided data only overwrite existing values if `overwrite_with_null` is set.
+    InsertOrUpdate { overwrite_with_null: bool },
+}
+
+/// Maximum number of documents per request of [`GraphCreatorBase::upsert_nodes`]
+pub const UPSERT_BATCH_SIZE: usize = 1000;
+
+/// Number of times an upsert is attempted if it conflicts with a concurrent writer
+const UPSERT_ATTEMPTS: u32 = 5;
+
+/// Direction of the edges followed by [`GraphCreatorBase::neighbors`] and
+/// [`GraphCreatorBase::traverse`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Inbound,
+    Outbound,
+    Any,
+}
+
+impl Direction {
+    fn as_aql(&self) -> &'static str {
+        match self {
+            Direction::Inbound => "inbound",
+            Direction::Outbound => "outbound",
+            Direction::Any => "any",
+        }
+    }
+}
+
+/// Edge of any edge collection
+#[derive(Debug, Clone, Deserialize)]
+pub struct EdgeRecord {
+    pub _id: String,
+    pub _key: String,
+    pub _from: String,
+    pub _to: String,
+
+    /// All other attributes of the edge
+    #[serde(flatten)]
+    pub attributes: serde_json::Map<String, Value>,
+}
+
+#[derive(Deserialize, Default)]
+struct TraversalResponse {
+    vertices: Vec<Document<Value>>,
+    edges: Vec<EdgeRecord>,
+}
+
+#[derive(Deserialize)]
+#[serde(bound(deserialize = "CollType: DeserializeOwned"))]
+struct UpsertResponse<CollType> {
+    doc: Document<CollType>,
+    created: bool,
+    #[serde(default)]
+    updated: bool,
+    #[serde(default)]
+    deleted: bool,
 }
 
 pub trait GraphCreatorBase {
@@ -25,79 +111,698 @@ pub trait GraphCreatorBase {
         edge_definitions: Vec<EdgeDefinition>,
     ) -> Result<Document<T>>
     where
-        T: DeserializeOwned + Serialize + Clone + JsonSchema + Debug;
+        T: DeserializeOwned + Serialize + Clone + JsonSchema + CollectionName + Debug;
+
+    /// Database the creator is connected to. Fails for creators without a connection, e.g. dry
+    /// runs, which only use the backend of [`Self::get_backend`].
+    fn get_db(&self) -> Result<&Database>;
+
+    /// Name of the graph the creator works on. Required for [`Self::delete_node`] with `cascade`
+    fn get_graph_name(&self) -> Option<&str> {
+        None
+    }
+
+    /// Limiter for concurrent database requests. No limit is applied by default
+    fn get_limiter(&self) -> Option<&AdaptiveLimiter> {
+        None
+    }
+
+    /// Stats the outcome of node and edge writes is recorded in. Nothing is recorded by default
+    fn get_run_stats(&self) -> Option<&RunStats> {
+        None
+    }
 
-    fn get_db(&self) -> &Database;
+    /// Run the documents created by the upsert helpers are linked to. Nothing is linked by default
+    fn get_provenance(&self) -> Option<&Provenance> {
+        None
+    }
+
+    /// Called with the `_id` of every node and edge the upsert helpers and [`Self::create_vertex`]
+    /// created or found. Does nothing by default, [`TxnHandle`] collects them for
+    /// [`TxnHandle::written_documents`].
+    fn document_written(&self, _id: &str) {}
+
+    /// Retry policy for database requests that failed with a transient error
+    fn get_retry_policy(&self) -> RetryPolicy {
+        RetryPolicy::default()
+    }
+
+    /// Runs a single database request with a permit of the limiter and retries it according to
+    /// [`Self::get_retry_policy`]
+    fn request<T>(&self, operation: &str, mut request: impl FnMut() -> Result<T>) -> Result<T> {
+        self.get_retry_policy().run(operation, || {
+            let _permit = self.get_limiter().map(AdaptiveLimiter::acquire);
+            request()
+        })
+    }
+
+    /// Like [`Self::request`] for requests that must not be applied twice, e.g. inserts. They
+    /// aren't retried after a timeout, see [`is_transient_unapplied`].
+    fn request_non_idempotent<T>(
+        &self,
+        operation: &str,
+        mut request: impl FnMut() -> Result<T>,
+    ) -> Result<T> {
+        self.get_retry_policy()
+            .run_if(operation, is_transient_unapplied, || {
+                let _permit = self.get_limiter().map(AdaptiveLimiter::acquire);
+                request()
+            })
+    }
+
+    /// Backend that documents are read from and written to, usually the database of
+    /// [`Self::get_db`]. [`TxnHandle`] returns its transaction and dry runs return a
+    /// [`DryRun`](crate::dry_run::DryRun).
+    fn get_backend(&self) -> &dyn DbBackend;
+
+    /// Runs an AQL query with the backend of [`Self::get_backend`]
+    fn run_aql<T>(&self, aql: AqlQuery) -> Result<Vec<T>>
+    where
+        T: DeserializeOwned,
+    {
+        self.get_backend()
+            .aql_query(aql)?
+            .into_iter()
+            .map(|value| Ok(serde_json::from_value(value)?))
+            .collect()
+    }
+
+    /// Runs `f` in a stream transaction that writes to `collections`, which are names of
+    /// [`CollectionName`] types without prefix. The transaction is committed if `f` succeeds and
+    /// aborted otherwise, so either all or none of the writes done with the [`TxnHandle`] are
+    /// persisted. When all writes of a sample are done in one transaction, an error can't leave
+    /// behind nodes that are not connected to the rest of the graph.
+    fn transaction<R>(
+        &self,
+        collections: &[&str],
+        f: impl FnOnce(&TxnHandle) -> Result<R>,
+    ) -> Result<R> {
+        if !self.get_backend().supports_transactions() {
+            return f(&TxnHandle::new(self, None));
+        }
+
+        let transaction = self.request_non_idempotent("begin_transaction", || {
+            let settings = TransactionSettings::builder()
+                .collections(
+                    TransactionCollections::builder()
+                        .write(
+                            collections
+                                .iter()
+                                .copied()
+                                .chain(self.get_provenance().map(Provenance::edge_collection))
+                                .map(prefixed_name)
+                                .collect(),
+                        )
+                        .build(),
+                )
+                .build();
+
+            Ok(self.get_db()?.begin_transaction(settings)?)
+        })?;
+        let txn = TxnHandle::new(self, Some(transaction));
+
+        match f(&txn) {
+            Ok(result) => {
+                txn.commit()?;
+                Ok(result)
+            }
+            Err(e) => {
+                // the server aborts the transaction after its idle timeout anyway
+                if let Err(abort_error) = txn.abort() {
+                    event!(
+                        warn,
+                        transaction = txn.id().unwrap_or_default(),
+                        error = %abort_error,
+                        "Aborting transaction failed"
+                    );
+                }
+                Err(e)
+            }
+        }
+    }
 
     fn create_vertex<CollType>(&self, data: CollType) -> Result<Document<CollType>>
     where
-        CollType: DeserializeOwned + Serialize + Clone + JsonSchema,
+        CollType: DeserializeOwned + Serialize + Clone + JsonSchema + CollectionName,
     {
         let collection_name = get_name::<CollType>();
-        let coll = self.get_db().collection(&collection_name)?;
+        let span = OperationSpan::enter("create_vertex", &collection_name);
+        let data = serde_json::to_value(data)?;
 
-        let doc_res = coll
-            .create_document::<CollType>(data, InsertOptions::builder().return_new(true).build())?;
+        let doc = self
+            .request_non_idempotent("create_vertex", || {
+                self.get_backend()
+                    .create_document(&collection_name, data.clone())
+            })
+            .and_then(from_value_document)
+            .map_err(|e| Error::Insert {
+                collection: collection_name.clone(),
+                source: Box::new(e),
+            });
 
-        let doc = handle_document_response(doc_res)?;
-        Ok(doc)
+        record(self, span, &collection_name, &doc, false, |_| {
+            Outcome::Created
+        });
+        if let Ok(doc) = &doc {
+            self.document_written(&doc.header._id);
+        }
+        doc
     }
 
+    /// Inserts `data` unless a document with `alt_key == alt_val` already exists, in which case
+    /// the existing document is returned. The existing document is looked up first and `data` is
+    /// only inserted if there is none, so the existing document isn't written and doesn't conflict
+    /// with the transactions of concurrent writers. Two writers can both miss the document and
+    /// insert it: with a unique index on `alt_key` the second insert fails with
+    /// `ERROR_ARANGO_UNIQUE_CONSTRAINT_VIOLATED` (1210) and is retried, finding the document of
+    /// the first one. Without the index both inserts succeed and duplicates exist. `alt_val` is
+    /// compared as JSON value, so e.g. numeric values match numbers, not their string
+    /// representation.
     fn upsert_node<CollType>(
         &self,
         data: CollType,
         alt_key: &str,
-        alt_val: &str,
+        alt_val: impl Serialize,
     ) -> Result<UpsertResult<CollType>>
     where
-        CollType: DeserializeOwned + Serialize + Clone + JsonSchema + Debug,
-    {
-        match self.create_vertex::<CollType>(data) {
-            Ok(document) => Ok(UpsertResult {
-                document,
-                created: true,
-            }),
-            // check if error type is "ERROR_ARANGO_UNIQUE_CONSTRAINT_VIOLATED"
-            Err(Error::ArangoClientError(ClientError::Arango(e)))
-                if [1200, 1210].contains(&e.error_num()) =>
-            {
-                let document = self.get_document::<CollType>(alt_key, alt_val)?;
-                Ok(UpsertResult {
-                    document,
-                    created: false,
-                })
+        CollType: DeserializeOwned + Serialize + Clone + JsonSchema + CollectionName + Debug,
+    {
+        self.upsert_node_with_mode(data, alt_key, alt_val, UpsertMode::InsertOnly)
+    }
+
+    /// Like [`Self::upsert_node`], but existing documents are handled according to `mode`
+    fn upsert_node_with_mode<CollType>(
+        &self,
+        data: CollType,
+        alt_key: &str,
+        alt_val: impl Serialize,
+        mode: UpsertMode,
+    ) -> Result<UpsertResult<CollType>>
+    where
+        CollType: DeserializeOwned + Serialize + Clone + JsonSchema + CollectionName + Debug,
+    {
+        let collection_name = get_name::<CollType>();
+        let span = OperationSpan::enter("upsert_node", &collection_name);
+        let alt_val = serde_json::to_value(alt_val)?;
+
+        let result = upsert_node_query(self, &collection_name, data, alt_key, &alt_val, mode)
+            .map_err(|e| Error::Upsert {
+                collection: collection_name.clone(),
+                alt_key: alt_key.to_string(),
+                alt_val: match &alt_val {
+                    Value::String(s) => s.clone(),
+                    value => value.to_string(),
+                },
+                source: Box::new(e),
+            });
+        record(
+            self,
+            span,
+            &collection_name,
+            &result,
+            false,
+            |result| match result {
+                UpsertResult { created: true, .. } => Outcome::Created,
+                UpsertResult { updated: true, .. } => Outcome::Updated,
+                _ => Outcome::Existing,
+            },
+        );
+
+        let result = result?;
+        self.document_written(&result.document.header._id);
+        if result.created {
+            link_created(self, &collection_name, &result.document.header._id)?;
+        }
+        Ok(result)
+    }
+
+    /// Bulk version of [`Self::upsert_node`] that upserts up to [`UPSERT_BATCH_SIZE`] documents
+    /// per request. The value of `alt_key` is taken from each document. The results are in the
+    /// order of `data`; if several documents share the same value, only the first one is inserted
+    /// and the others return the same document with `created == false`. The values are compared
+    /// as JSON, so e.g. `1` and `"1"` are different values. If a chunk fails, all documents are
+    /// recorded as failed in the [`RunStats`], even though earlier chunks may have been written.
+    fn upsert_nodes<CollType>(
+        &self,
+        data: Vec<CollType>,
+        alt_key: &str,
+    ) -> Result<Vec<UpsertResult<CollType>>>
+    where
+        CollType: DeserializeOwned + Serialize + Clone + JsonSchema + CollectionName + Debug,
+    {
+        let collection_name = get_name::<CollType>();
+        let span = OperationSpan::enter("upsert_nodes", &collection_name);
+        let documents = data.len();
+
+        let result = upsert_nodes_query::<Self, CollType>(self, &collection_name, data, alt_key);
+        if result.is_err()
+            && let Some(stats) = self.get_run_stats()
+        {
+            for _ in 0..documents {
+                stats.record(&collection_name, Outcome::Failed);
             }
-            Err(e) => Err(e),
         }
+        span.finish(&result, |_| "ok");
+
+        let results = result?;
+        for result in &results {
+            if let Some(stats) = self.get_run_stats() {
+                let outcome = if result.created {
+                    Outcome::Created
+                } else {
+                    Outcome::Existing
+                };
+                stats.record(&collection_name, outcome);
+            }
+            self.document_written(&result.document.header._id);
+            if result.created {
+                link_created(self, &collection_name, &result.document.header._id)?;
+            }
+        }
+
+        Ok(results)
+    }
+
+    /// Merges `attributes` into the stored document
+    fn update_node_attributes<CollType>(
+        &self,
+        document: &Document<CollType>,
+        attributes: Value,
+    ) -> Result<()>
+    where
+        CollType: JsonSchema + CollectionName,
+    {
+        let collection_name = get_name::<CollType>();
+
+        self.request("update_node_attributes", || {
+            self.get_backend().update_document(
+                &collection_name,
+                &document.header._key,
+                attributes.clone(),
+            )
+        })?;
+
+        Ok(())
     }
 
     /// Searches for a document in collection `CollType` with the key, value combination alt_key,
-    /// alt_val
-    fn get_document<CollType>(&self, alt_key: &str, alt_val: &str) -> Result<Document<CollType>>
+    /// alt_val. `alt_key` may be a nested path like `metadata.package_name`, see
+    /// [`attribute_path`]. `alt_val` is compared as JSON value, so numbers only match numbers.
+    /// Soft-deleted documents are only found with `include_deleted`, see [`SoftDelete`].
+    fn get_document<CollType>(
+        &self,
+        alt_key: &str,
+        alt_val: impl Serialize,
+        include_deleted: bool,
+    ) -> Result<Document<CollType>>
     where
-        CollType: DeserializeOwned + JsonSchema,
+        CollType: DeserializeOwned + JsonSchema + CollectionName,
     {
+        self.get_document_by::<CollType, _>(&[(alt_key, alt_val)], include_deleted)
+    }
+
+    /// Like [`Self::get_document`], but the document has to match all `filters`
+    fn get_document_by<CollType, F>(
+        &self,
+        filters: &[(&str, F)],
+        include_deleted: bool,
+    ) -> Result<Document<CollType>>
+    where
+        CollType: DeserializeOwned + JsonSchema + CollectionName,
+        F: Serialize,
+    {
+        if filters.is_empty() {
+            return Err(Error::Generic(
+                "A document lookup needs at least one filter".to_string(),
+            ));
+        }
         let collection_name = get_name::<CollType>();
+        let span = OperationSpan::enter("get_document", &collection_name);
 
-        let aql = AqlQuery::builder()
-            .query("for d in @@collection_name filter d.@alt_key == @alt_val limit 1 return d")
-            .bind_var("@collection_name", collection_name)
-            .bind_var("alt_key", alt_key)
-            .bind_var("alt_val", alt_val)
-            .build();
+        let mut conditions = vec![];
+        let mut described = vec![];
+        let mut bind_vars = vec![(
+            "@collection_name".to_string(),
+            collection_name.clone().into(),
+        )];
+        for (i, (path, value)) in filters.iter().enumerate() {
+            // only the names of the bind variables are part of the query
+            conditions.push(format!("d.@path{i} == @value{i}"));
+            let value = serde_json::to_value(value)?;
+            described.push(format!("{path} == {value}"));
+            bind_vars.push((format!("path{i}"), attribute_path(path)?.into()));
+            bind_vars.push((format!("value{i}"), value));
+        }
+        if !include_deleted {
+            conditions.push("d.deleted_at == null".to_string());
+        }
+        let query = format!(
+            "for d in @@collection_name filter {} limit 1 return d",
+            conditions.join(" && ")
+        );
 
-        let db = self.get_db();
+        let result: Result<Vec<Document<CollType>>> = self
+            .request("get_document", || {
+                let aql = AqlQuery::builder()
+                    .query(&query)
+                    .bind_vars(
+                        bind_vars
+                            .iter()
+                            .map(|(name, value)| (name.as_str(), value.clone()))
+                            .collect(),
+                    )
+                    .build();
 
-        let mut result: Vec<Document<CollType>> = db.aql_query(aql)?;
+                self.run_aql(aql)
+            })
+            .map_err(|e| Error::Lookup {
+                collection: collection_name.clone(),
+                filters: described.join(" and "),
+                source: Box::new(e),
+            });
 
-        match result.pop() {
+        let result = result.and_then(|mut result| match result.pop() {
             Some(doc) => Ok(doc),
             None => Err(Error::DocumentNotFound(format!(
-                "Document with alt_key: '{alt_key}' and alt_val '{alt_val}' was not found"
+                "Document in '{collection_name}' with {} was not found",
+                described.join(" and ")
             ))),
+        });
+        span.finish(&result, |_| "found");
+        result
+    }
+
+    /// Iterates over all documents of collection `CollType`, optionally only the ones where the
+    /// key, value combination of `filter` matches. The documents are fetched lazily in batches of
+    /// `batch_size`. Soft-deleted documents are skipped unless `include_deleted` is set.
+    fn get_documents<CollType>(
+        &self,
+        filter: Option<(&str, &str)>,
+        batch_size: usize,
+        include_deleted: bool,
+    ) -> Result<DocumentCursor<'_, CollType>>
+    where
+        CollType: DeserializeOwned + JsonSchema + CollectionName,
+    {
+        let collection_name = get_name::<CollType>();
+        let (alt_key, alt_val) = match filter {
+            Some((alt_key, alt_val)) => (Value::from(alt_key), Value::from(alt_val)),
+            None => (Value::Null, Value::Null),
+        };
+
+        let cursor = self.request("get_documents", || {
+            let aql = AqlQuery::builder()
+                .query(
+                    "for d in @@collection_name
+                        filter @alt_key == null || d[@alt_key] == @alt_val
+                        filter @include_deleted || d.deleted_at == null
+                        return d",
+                )
+                .bind_var("@collection_name", collection_name.clone())
+                .bind_var("alt_key", alt_key.clone())
+                .bind_var("alt_val", alt_val.clone())
+                .bind_var("include_deleted", include_deleted)
+                .batch_size(batch_size.clamp(1, u32::MAX as usize) as u32)
+                .build();
+
+            Ok(self.get_db()?.aql_query_batch(aql)?)
+        })?;
+
+        Ok(DocumentCursor::new(
+            self.get_db()?,
+            self.get_limiter(),
+            cursor,
+        ))
+    }
+
+    /// Runs `aql` and iterates over its results, which are fetched in batches of the batch size
+    /// of `aql` (1000 by default). Only the current batch is kept in memory. The query doesn't
+    /// use the backend of [`Self::get_backend`], so it doesn't see uncommitted writes of a
+    /// transaction.
+    fn aql_query_stream<T>(&self, aql: AqlQuery) -> Result<ResultCursor<'_, T>>
+    where
+        T: DeserializeOwned,
+    {
+        let cursor = {
+            let _permit = self.get_limiter().map(AdaptiveLimiter::acquire);
+            self.get_db()?.aql_query_batch(aql)?
+        };
+
+        Ok(ResultCursor::new(
+            self.get_db()?,
+            self.get_limiter(),
+            cursor,
+        ))
+    }
+
+    /// Documents of collection `CollType` that match `filter`, at most `limit` of them. Soft-deleted
+    /// documents are included, `.eq("deleted_at", Value::Null)` skips them.
+    fn get_documents_filtered<CollType>(
+        &self,
+        filter: Filter,
+        limit: Option<u32>,
+    ) -> Result<Vec<Document<CollType>>>
+    where
+        CollType: DeserializeOwned + JsonSchema + CollectionName,
+    {
+        let collection_name = get_name::<CollType>();
+        let AqlFilter {
+            condition,
+            mut bind_vars,
+        } = filter.to_aql("d")?;
+
+        bind_vars.insert(
+            "@collection_name".to_string(),
+            collection_name.clone().into(),
+        );
+        let query = match limit {
+            Some(limit) => {
+                bind_vars.insert("limit".to_string(), limit.into());
+                format!("for d in @@collection_name filter {condition} limit @limit return d")
+            }
+            None => format!("for d in @@collection_name filter {condition} return d"),
+        };
+
+        self.request("get_documents_filtered", || {
+            let aql = AqlQuery::builder()
+                .query(&query)
+                .bind_vars(
+                    bind_vars
+                        .iter()
+                        .map(|(name, value)| (name.as_str(), value.clone()))
+                        .collect(),
+                )
+                .build();
+
+            self.run_aql(aql)
+        })
+        .map_err(|e| collection_not_found(e, &collection_name))
+    }
+
+    /// Number of documents in collection `CollType`. Fails with [`Error::CollectionNotFound`] if
+    /// the collection doesn't exist
+    fn count_documents<CollType>(&self) -> Result<u64>
+    where
+        CollType: JsonSchema + CollectionName,
+    {
+        let collection_name = get_name::<CollType>();
+
+        let mut result: Vec<u64> = self
+            .request("count_documents", || {
+                let aql = AqlQuery::builder()
+                    .query("return length(@@collection_name)")
+                    .bind_var("@collection_name", collection_name.clone())
+                    .build();
+
+                self.run_aql(aql)
+            })
+            .map_err(|e| collection_not_found(e, &collection_name))?;
+
+        Ok(result.pop().unwrap_or(0))
+    }
+
+    /// Whether a document with `alt_key == alt_val` exists in collection `CollType`, see
+    /// [`Self::get_document`]. Only `1` is returned by the query, not the document. Fails with
+    /// [`Error::CollectionNotFound`] if the collection doesn't exist
+    fn exists<CollType>(&self, alt_key: &str, alt_val: impl Serialize) -> Result<bool>
+    where
+        CollType: JsonSchema + CollectionName,
+    {
+        let collection_name = get_name::<CollType>();
+        let path = attribute_path(alt_key)?;
+        let alt_val = serde_json::to_value(alt_val)?;
+
+        let result: Vec<u8> = self
+            .request("exists", || {
+                let aql = AqlQuery::builder()
+                    .query("for d in @@collection_name filter d.@path == @alt_val limit 1 return 1")
+                    .bind_var("@collection_name", collection_name.clone())
+                    .bind_var("path", path.clone())
+                    .bind_var("alt_val", alt_val.clone())
+                    .build();
+
+                self.run_aql(aql)
+            })
+            .map_err(|e| collection_not_found(e, &collection_name))?;
+
+        Ok(!result.is_empty())
+    }
+
+    /// Documents of collection `ToType` that are connected to `node` by an edge of collection
+    /// `EdgeType` in `direction`
+    fn neighbors<FromType, ToType, EdgeType>(
+        &self,
+        node: &Document<FromType>,
+        direction: Direction,
+    ) -> Result<Vec<Document<ToType>>>
+    where
+        ToType: DeserializeOwned + JsonSchema + CollectionName,
+        EdgeType: JsonSchema + CollectionName,
+    {
+        let query = format!(
+            "for v in 1..1 {} @start @@edge_collection
+                filter is_same_collection(@to_collection, v)
+                return distinct v",
+            direction.as_aql()
+        );
+
+        self.request("neighbors", || {
+            let aql = AqlQuery::builder()
+                .query(&query)
+                .bind_var("start", node.header._id.clone())
+                .bind_var("@edge_collection", get_name::<EdgeType>())
+                .bind_var("to_collection", get_name::<ToType>())
+                .build();
+
+            self.run_aql(aql)
+        })
+    }
+
+    /// Walks the graph (see [`Self::get_graph_name`]) from `node` up to `max_depth` edges in
+    /// `direction`. Every vertex is visited once, so the returned edges are the ones the vertices
+    /// were reached by.
+    fn traverse<FromType>(
+        &self,
+        node: &Document<FromType>,
+        direction: Direction,
+        max_depth: u32,
+    ) -> Result<(Vec<Document<Value>>, Vec<EdgeRecord>)> {
+        let graph_name = self.get_graph_name().ok_or(Error::Generic(
+            "Traversals require the name of the graph".to_string(),
+        ))?;
+
+        let query = format!(
+            "let walk = (
+                for v, e in 1..@max_depth {} @start graph @graph
+                    options {{ order: 'bfs', uniqueVertices: 'global' }}
+                    return {{ v, e }}
+            )
+            return {{ vertices: walk[*].v, edges: walk[*].e }}",
+            direction.as_aql()
+        );
+
+        let mut result: Vec<TraversalResponse> = self.request("traverse", || {
+            let aql = AqlQuery::builder()
+                .query(&query)
+                .bind_var("start", node.header._id.clone())
+                .bind_var("graph", graph_name)
+                .bind_var("max_depth", max_depth)
+                .build();
+
+            self.run_aql(aql)
+        })?;
+
+        let TraversalResponse { vertices, edges } = result.pop().unwrap_or_default();
+        Ok((vertices, edges))
+    }
+
+    /// Deletes the document with `alt_key == alt_val`. If `cascade` is set, all edges of the
+    /// graph's edge collections that start or end at the document are deleted as well. Returns
+    /// the number of deleted documents, which is 0 if the document doesn't exist.
+    fn delete_node<CollType>(
+        &self,
+        alt_key: &str,
+        alt_val: impl Serialize,
+        cascade: bool,
+    ) -> Result<u64>
+    where
+        CollType: DeserializeOwned + Serialize + JsonSchema + CollectionName,
+    {
+        let document = match self.get_document::<CollType>(alt_key, alt_val, true) {
+            Ok(document) => document,
+            Err(Error::DocumentNotFound(_)) => return Ok(0),
+            Err(e) => return Err(e),
+        };
+
+        self.delete_node_by_id(&document.header._id, cascade)
+    }
+
+    /// Like [`Self::delete_node`] for the document with the id `id` (`collection/key`) in any
+    /// collection. Fails if the document doesn't exist.
+    fn delete_node_by_id(&self, id: &str, cascade: bool) -> Result<u64> {
+        let Some((collection_name, key)) = id.split_once('/') else {
+            return Err(Error::Generic(format!("Invalid document id '{id}'")));
+        };
+
+        let mut removed = 0;
+
+        if cascade {
+            let graph_name = self.get_graph_name().ok_or(Error::Generic(
+                "Cascading deletes require the name of the graph".to_string(),
+            ))?;
+            let graph = self.request("delete_node", || Ok(self.get_db()?.graph(graph_name)?))?;
+
+            for edge_definition in graph.edge_definitions {
+                let mut result: Vec<u64> = self.request("delete_node", || {
+                    let aql = AqlQuery::builder()
+                        .query(
+                            "return length(
+                                for e in @@collection_name
+                                    filter e._from == @id || e._to == @id
+                                    remove e in @@collection_name
+                                    return 1
+                            )",
+                        )
+                        .bind_var("@collection_name", edge_definition.collection.clone())
+                        .bind_var("id", id)
+                        .build();
+
+                    self.run_aql(aql)
+                })?;
+                removed += result.pop().unwrap_or(0);
+            }
         }
+
+        self.request("delete_node", || {
+            self.get_backend().remove_document(collection_name, key)
+        })?;
+
+        Ok(removed + 1)
+    }
+
+    /// Marks the document with `alt_key == alt_val` as deleted by setting `deleted_at` to the
+    /// current time (RFC 3339, UTC). The document and its edges are kept, but it is skipped by
+    /// lookups and not changed by upserts. Returns `false` if the document doesn't exist.
+    fn soft_delete_node<CollType>(&self, alt_key: &str, alt_val: impl Serialize) -> Result<bool>
+    where
+        CollType: SoftDelete + JsonSchema + CollectionName,
+    {
+        let deleted_at = chrono::Utc::now().to_rfc3339();
+        set_deleted_at::<Self, CollType>(self, alt_key, alt_val, deleted_at.into())
+    }
+
+    /// Removes the `deleted_at` tombstone of the document with `alt_key == alt_val`, see
+    /// [`Self::soft_delete_node`]. Returns `false` if the document doesn't exist.
+    fn restore_node<CollType>(&self, alt_key: &str, alt_val: impl Serialize) -> Result<bool>
+    where
+        CollType: SoftDelete + JsonSchema + CollectionName,
+    {
+        set_deleted_at::<Self, CollType>(self, alt_key, alt_val, Value::Null)
     }
 
+    /// Inserts the edge from `from_doc` to `to_doc` unless an edge between them already exists,
+    /// in which case the existing edge is returned. This is done with a single AQL `UPSERT`, so
+    /// concurrent upserts of the same edge all return the same edge.
     fn upsert_edge<FromType, ToType, EdgeType>(
         &self,
         from_doc: &Document<FromType>,
@@ -106,43 +811,655 @@ pub trait GraphCreatorBase {
     where
         FromType: DeserializeOwned + Serialize + Clone,
         ToType: DeserializeOwned + Serialize + Clone,
-        EdgeType:
-            DeserializeOwned + Serialize + Clone + JsonSchema + Debug + EdgeAttributes + Default,
+        EdgeType: DeserializeOwned
+            + Serialize
+            + Clone
+            + JsonSchema
+            + CollectionName
+            + Debug
+            + EdgeAttributes
+            + Default,
+    {
+        self.upsert_edge_with(from_doc, to_doc, EdgeType::default(), false)
+    }
+
+    /// Like [`Self::upsert_edge`], but the edge is created with the fields of `edge`. `_key`,
+    /// `_from` and `_to` are filled in. If the edge already exists, the stored edge is returned
+    /// untouched unless `update_existing` is set, in which case its fields are replaced by the
+    /// ones of `edge`.
+    fn upsert_edge_with<FromType, ToType, EdgeType>(
+        &self,
+        from_doc: &Document<FromType>,
+        to_doc: &Document<ToType>,
+        mut edge: EdgeType,
+        update_existing: bool,
+    ) -> Result<Document<EdgeType>>
+    where
+        FromType: DeserializeOwned + Serialize + Clone,
+        ToType: DeserializeOwned + Serialize + Clone,
+        EdgeType: DeserializeOwned
+            + Serialize
+            + Clone
+            + JsonSchema
+            + CollectionName
+            + Debug
+            + EdgeAttributes,
     {
         let collection_name = get_name::<EdgeType>();
+        let span = OperationSpan::enter("upsert_edge", &collection_name);
 
-        let db = self.get_db();
-        let coll = db.collection(&collection_name)?;
+        // the composed key keeps the edges readable, they are matched by `_from` and `_to`
+        edge.apply_edge_attributes(from_doc.header._id.clone(), to_doc.header._id.clone());
+        let edge = serde_json::to_value(edge)?;
 
-        let mut edge = EdgeType::default();
+        let result: Result<Option<Vec<UpsertResponse<EdgeType>>>> = run_upsert_query(self, || {
+            // an existing edge is returned without writing it, so shared edges don't conflict
+            // between the transactions of concurrent samples
+            if !update_existing {
+                return AqlQuery::builder()
+                    .query(
+                        "let existing = first(
+                            for e in @@collection_name
+                            filter e._from == @from && e._to == @to limit 1 return e
+                        )
+                        let inserted = (
+                            for i in (existing == null ? [1] : [])
+                            insert @edge in @@collection_name return NEW
+                        )
+                        return {
+                            doc: existing != null ? existing : inserted[0],
+                            created: existing == null,
+                            updated: false
+                        }",
+                    )
+                    .bind_var("@collection_name", collection_name.clone())
+                    .bind_var("from", from_doc.header._id.clone())
+                    .bind_var("to", to_doc.header._id.clone())
+                    .bind_var("edge", edge.clone())
+                    .build();
+            }
+
+            AqlQuery::builder()
+                .query(
+                    "upsert { _from: @from, _to: @to }
+                        insert @edge
+                        update @update_existing ? unset(@edge, '_key', '_from', '_to') : {}
+                        in @@collection_name
+                        return {
+                            doc: NEW,
+                            created: OLD == null,
+                            updated: OLD != null && unset(OLD, '_rev') != unset(NEW, '_rev')
+                        }",
+                )
+                .bind_var("@collection_name", collection_name.clone())
+                .bind_var("update_existing", update_existing)
+                .bind_var("from", from_doc.header._id.clone())
+                .bind_var("to", to_doc.header._id.clone())
+                .bind_var("edge", edge.clone())
+                .build()
+        });
 
-        // construct edge key
+        let result = result
+            .and_then(|result| match result {
+                Some(mut result) => result
+                    .pop()
+                    .ok_or_else(|| Error::Generic("Upsert returned nothing".to_string())),
+                None => Err(Error::UpsertRace(
+                    "Concurrent writers kept conflicting".to_string(),
+                )),
+            })
+            .map_err(|e| Error::EdgeInsert {
+                collection: collection_name.clone(),
+                from: from_doc.header._id.clone(),
+                to: to_doc.header._id.clone(),
+                source: Box::new(e),
+            });
+
+        record(
+            self,
+            span,
+            &collection_name,
+            &result,
+            true,
+            |result| match result {
+                UpsertResponse { created: true, .. } => Outcome::Created,
+                UpsertResponse { updated: true, .. } => Outcome::Updated,
+                _ => Outcome::Existing,
+            },
+        );
+        if let Ok(result) = &result {
+            self.document_written(&result.doc.header._id);
+        }
+        result.map(|result| result.doc)
+    }
+
+    /// Like [`Self::upsert_edge`], but an existing edge has its `observed` counter incremented
+    /// instead of being returned untouched. New edges start with a count of 1, existing edges
+    /// without a counter are counted as observed once before. The increment is done
+    /// server-side, so concurrent upserts of the same edge are all counted.
+    fn upsert_counted_edge<FromType, ToType, EdgeType>(
+        &self,
+        from_doc: &Document<FromType>,
+        to_doc: &Document<ToType>,
+    ) -> Result<Document<EdgeType>>
+    where
+        FromType: DeserializeOwned + Serialize + Clone,
+        ToType: DeserializeOwned + Serialize + Clone,
+        EdgeType: DeserializeOwned
+            + Serialize
+            + Clone
+            + JsonSchema
+            + CollectionName
+            + CountedEdge
+            + Default,
+    {
+        let collection_name = get_name::<EdgeType>();
+        let span = OperationSpan::enter("upsert_counted_edge", &collection_name);
+
+        let mut edge = EdgeType::default();
         edge.apply_edge_attributes(from_doc.header._id.clone(), to_doc.header._id.clone());
-        let edge_key = edge.get_key();
-
-        // check if edge already exists in DB
-        match coll.document::<EdgeType>(&edge_key) {
-            Err(ClientError::Arango(e)) => {
-                // check if error type is "ERROR_ARANGO_DOCUMENT_NOT_FOUND"
-                if e.error_num() != 1202 {
-                    return Err(Error::ArangoArangoError(e));
-                }
+        let edge = serde_json::to_value(edge)?;
+
+        // matched by `_from` and `_to` like upsert_edge_with, the key of an existing edge may
+        // differ
+        let result: Result<Option<Vec<UpsertResponse<EdgeType>>>> = run_upsert_query(self, || {
+            AqlQuery::builder()
+                .query(
+                    "upsert { _from: @from, _to: @to }
+                        insert merge(@edge, { [@counter]: 1 })
+                        update { [@counter]: NOT_NULL(OLD[@counter], 1) + 1 }
+                        in @@collection_name
+                        return { doc: NEW, created: OLD == null, updated: OLD != null }",
+                )
+                .bind_var("@collection_name", collection_name.clone())
+                .bind_var("counter", "observed")
+                .bind_var("from", from_doc.header._id.clone())
+                .bind_var("to", to_doc.header._id.clone())
+                .bind_var("edge", edge.clone())
+                .build()
+        });
+
+        let result = result
+            .and_then(|result| match result {
+                Some(mut result) => result
+                    .pop()
+                    .ok_or_else(|| Error::Generic("Upsert returned nothing".to_string())),
+                None => Err(Error::UpsertRace(
+                    "Concurrent writers kept conflicting".to_string(),
+                )),
+            })
+            .map_err(|e| Error::EdgeInsert {
+                collection: collection_name.clone(),
+                from: from_doc.header._id.clone(),
+                to: to_doc.header._id.clone(),
+                source: Box::new(e),
+            });
+
+        record(
+            self,
+            span,
+            &collection_name,
+            &result,
+            true,
+            |result| match result.created {
+                true => Outcome::Created,
+                false => Outcome::Updated,
+            },
+        );
+        if let Ok(result) = &result {
+            self.document_written(&result.doc.header._id);
+        }
+        result.map(|result| result.doc)
+    }
+}
+
+/// Removes all attributes with a `null` value, so they don't overwrite existing values when merged
+fn without_nulls(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .filter(|(_, v)| !v.is_null())
+                .map(|(k, v)| (k, without_nulls(v)))
+                .collect(),
+        ),
+        v => v,
+    }
+}
+
+/// Sets `deleted_at` of the document with `alt_key == alt_val`. `null` removes the attribute.
+/// Returns whether the document exists.
+fn set_deleted_at<G, CollType>(
+    creator: &G,
+    alt_key: &str,
+    alt_val: impl Serialize,
+    deleted_at: Value,
+) -> Result<bool>
+where
+    G: GraphCreatorBase + ?Sized,
+    CollType: JsonSchema + CollectionName,
+{
+    let collection_name = get_name::<CollType>();
+    let path = attribute_path(alt_key)?;
+    let alt_val = serde_json::to_value(alt_val)?;
+
+    let result: Vec<u8> = creator
+        .request("set_deleted_at", || {
+            let aql = AqlQuery::builder()
+                .query(
+                    "for d in @@collection_name
+                        filter d.@path == @alt_val
+                        update d with { deleted_at: @deleted_at } in @@collection_name
+                        options { keepNull: false }
+                        return 1",
+                )
+                .bind_var("@collection_name", collection_name.clone())
+                .bind_var("path", path.clone())
+                .bind_var("alt_val", alt_val.clone())
+                .bind_var("deleted_at", deleted_at.clone())
+                .build();
+
+            creator.run_aql(aql)
+        })
+        .map_err(|e| collection_not_found(e, &collection_name))?;
+
+    Ok(!result.is_empty())
+}
+
+/// Upserts a single node for [`GraphCreatorBase::upsert_node_with_mode`]
+fn upsert_node_query<G, CollType>(
+    creator: &G,
+    collection_name: &str,
+    data: CollType,
+    alt_key: &str,
+    alt_val: &Value,
+    mode: UpsertMode,
+) -> Result<UpsertResult<CollType>>
+where
+    G: GraphCreatorBase + ?Sized,
+    CollType: DeserializeOwned + Serialize,
+{
+    let data = serde_json::to_value(data)?;
+
+    let update = match mode {
+        // an existing document is returned without writing it, so shared documents don't
+        // conflict between the transactions of concurrent samples
+        UpsertMode::InsertOnly => None,
+        UpsertMode::InsertOrUpdate {
+            overwrite_with_null: true,
+        } => Some(data.clone()),
+        UpsertMode::InsertOrUpdate {
+            overwrite_with_null: false,
+        } => Some(without_nulls(data.clone())),
+    };
+
+    let result: Option<Vec<UpsertResponse<CollType>>> = run_upsert_query(creator, || {
+        let Some(update) = &update else {
+            return AqlQuery::builder()
+                .query(
+                    "let existing = first(
+                        for d in @@collection_name filter d[@alt_key] == @alt_val limit 1 return d
+                    )
+                    let inserted = (
+                        for i in (existing == null ? [1] : [])
+                        insert @data in @@collection_name return NEW
+                    )
+                    return {
+                        doc: existing != null ? existing : inserted[0],
+                        created: existing == null,
+                        updated: false,
+                        deleted: existing != null && existing.deleted_at != null
+                    }",
+                )
+                .bind_var("@collection_name", collection_name)
+                .bind_var("alt_key", alt_key)
+                .bind_var("alt_val", alt_val.clone())
+                .bind_var("data", data.clone())
+                .build();
+        };
+
+        AqlQuery::builder()
+            .query(
+                "upsert { [@alt_key]: @alt_val } insert @data
+                    update OLD.deleted_at == null ? @update : {} in @@collection_name
+                    options { mergeObjects: true }
+                    return {
+                        doc: NEW,
+                        created: OLD == null,
+                        updated: OLD != null && unset(OLD, '_rev') != unset(NEW, '_rev'),
+                        deleted: OLD != null && OLD.deleted_at != null
+                    }",
+            )
+            .bind_var("@collection_name", collection_name)
+            .bind_var("alt_key", alt_key)
+            .bind_var("alt_val", alt_val.clone())
+            .bind_var("data", data.clone())
+            .bind_var("update", update.clone())
+            .build()
+    })?;
+
+    let Some(mut result) = result else {
+        return Err(Error::UpsertRace(format!(
+            "Concurrent writers kept conflicting. Without a unique index on '{alt_key}' \
+             duplicates may exist"
+        )));
+    };
 
-                // edge is not in DB, create and return edge
-                let doc: Document<EdgeType> = self.create_vertex::<EdgeType>(edge.clone())?;
-                Ok(doc)
+    let UpsertResponse {
+        doc,
+        created,
+        updated,
+        deleted,
+    } = result.pop().ok_or_else(|| {
+        Error::Generic(format!("Upsert into '{collection_name}' returned nothing"))
+    })?;
+
+    Ok(UpsertResult {
+        document: doc,
+        created,
+        updated,
+        deleted,
+    })
+}
+
+/// Upserts the documents of [`GraphCreatorBase::upsert_nodes`] in chunks of
+/// [`UPSERT_BATCH_SIZE`]
+fn upsert_nodes_query<G, CollType>(
+    creator: &G,
+    collection_name: &str,
+    data: Vec<CollType>,
+    alt_key: &str,
+) -> Result<Vec<UpsertResult<CollType>>>
+where
+    G: GraphCreatorBase + ?Sized,
+    CollType: DeserializeOwned + Serialize + Clone,
+{
+    // a single query can't see its own inserts, so duplicates are only sent once
+    let mut unique: Vec<Value> = vec![];
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    let mut indices = Vec::with_capacity(data.len());
+
+    for document in data {
+        let document = serde_json::to_value(document)?;
+        let alt_val = match document.get(alt_key) {
+            Some(v) if !v.is_null() => canonical_json(v),
+            _ => {
+                return Err(Error::Generic(format!(
+                    "Document for '{collection_name}' has no value for '{alt_key}'"
+                )));
             }
+        };
+
+        let index = *positions.entry(alt_val).or_insert_with(|| {
+            unique.push(document);
+            unique.len() - 1
+        });
+        indices.push(index);
+    }
+
+    let mut upserted: Vec<UpsertResponse<CollType>> = Vec::with_capacity(unique.len());
+    for chunk in unique.chunks(UPSERT_BATCH_SIZE) {
+        upserted.extend(upsert_chunk::<G, CollType>(
+            creator,
+            collection_name,
+            chunk,
+            alt_key,
+        )?);
+    }
+
+    let mut first_seen = vec![false; upserted.len()];
+    Ok(indices
+        .into_iter()
+        .map(|i| {
+            let created = upserted[i].created && !first_seen[i];
+            first_seen[i] = true;
+            let doc = &upserted[i].doc;
+            UpsertResult {
+                document: Document {
+                    header: Header {
+                        _id: doc.header._id.clone(),
+                        _key: doc.header._key.clone(),
+                        _rev: doc.header._rev.clone(),
+                    },
+                    document: doc.document.clone(),
+                },
+                created,
+                updated: false,
+                deleted: upserted[i].deleted,
+            }
+        })
+        .collect())
+}
+
+/// JSON of `value` with the attributes of objects sorted by name and integral floats written as
+/// integers, so values that AQL considers equal have the same representation, e.g. `{"a": 1.0,
+/// "b": 2}` and `{"b": 2, "a": 1}`. Strings keep their quotes, so `"1"` and `1` differ.
+fn canonical_json(value: &Value) -> String {
+    match value {
+        Value::Object(map) => {
+            let mut attributes: Vec<_> = map.iter().collect();
+            attributes.sort_by_key(|(name, _)| *name);
+            let attributes: Vec<String> = attributes
+                .into_iter()
+                .map(|(name, value)| {
+                    format!("{}:{}", Value::from(name.as_str()), canonical_json(value))
+                })
+                .collect();
+            format!("{{{}}}", attributes.join(","))
+        }
+        Value::Array(values) => {
+            let values: Vec<String> = values.iter().map(canonical_json).collect();
+            format!("[{}]", values.join(","))
+        }
+        Value::Number(n) => match n.as_f64() {
+            Some(f) if n.is_f64() && f.fract() == 0.0 && f.abs() < 2f64.powi(53) => {
+                (f as i64).to_string()
+            }
+            _ => n.to_string(),
+        },
+        value => value.to_string(),
+    }
+}
+
+/// Upserts one chunk of [`GraphCreatorBase::upsert_nodes`]. Like the
+/// [`UpsertMode::InsertOnly`] upsert of a single node, existing documents are only read, so they
+/// don't conflict with the transactions of concurrent writers.
+fn upsert_chunk<G, CollType>(
+    creator: &G,
+    collection_name: &str,
+    chunk: &[Value],
+    alt_key: &str,
+) -> Result<Vec<UpsertResponse<CollType>>>
+where
+    G: GraphCreatorBase + ?Sized,
+    CollType: DeserializeOwned,
+{
+    let result: Option<Vec<UpsertResponse<CollType>>> = run_upsert_query(creator, || {
+        AqlQuery::builder()
+            .query(
+                "for d in @docs
+                    let existing = first(
+                        for e in @@collection_name filter e[@alt_key] == d[@alt_key] limit 1
+                        return e
+                    )
+                    let inserted = (
+                        for i in (existing == null ? [1] : [])
+                        insert d in @@collection_name return NEW
+                    )
+                    return {
+                        doc: existing != null ? existing : inserted[0],
+                        created: existing == null,
+                        deleted: existing != null && existing.deleted_at != null
+                    }",
+            )
+            .bind_var("@collection_name", collection_name)
+            .bind_var("alt_key", alt_key)
+            .bind_var("docs", chunk.to_vec())
+            .build()
+    })?;
+
+    match result {
+        Some(result) if result.len() == chunk.len() => Ok(result),
+        Some(result) => Err(Error::Generic(format!(
+            "Upsert into '{collection_name}' returned {} of {} documents",
+            result.len(),
+            chunk.len()
+        ))),
+        None => Err(Error::UpsertRace(format!(
+            "Bulk upsert into '{collection_name}' kept conflicting with concurrent writers. \
+             Without a unique index on '{alt_key}' duplicates may exist"
+        ))),
+    }
+}
+
+/// Runs an upsert query and repeats it if it conflicts with a concurrent writer. Returns `None` if
+/// it still conflicts after at least [`UPSERT_ATTEMPTS`] attempts, which the callers report as
+/// [`Error::UpsertRace`].
+fn run_upsert_query<'a, G, T>(
+    creator: &G,
+    build_query: impl Fn() -> AqlQuery<'a>,
+) -> Result<Option<Vec<T>>>
+where
+    G: GraphCreatorBase + ?Sized,
+    T: DeserializeOwned,
+{
+    let policy = creator.get_retry_policy();
+    let policy = RetryPolicy {
+        max_attempts: policy.max_attempts.max(UPSERT_ATTEMPTS),
+        ..policy
+    };
+
+    // another writer inserted the same document concurrently, the query is rolled back and the
+    // next attempt will find the document once it is committed
+    let is_retryable = |e: &Error| is_transient(e) || is_unique_constraint_violation(e);
 
-            // other error
-            Err(e) => Err(Error::ArangoClientError(e)),
+    let result = policy.run_if("upsert", is_retryable, || {
+        let _permit = creator.get_limiter().map(AdaptiveLimiter::acquire);
+        creator.run_aql(build_query())
+    });
 
-            // edge is already in DB
-            Ok(doc) => Ok(doc),
+    match result {
+        Ok(result) => Ok(Some(result)),
+        // "ERROR_ARANGO_CONFLICT" or "ERROR_ARANGO_UNIQUE_CONSTRAINT_VIOLATED"
+        Err(Error::ArangoClientError(ClientError::Arango(e)))
+            if [1200, 1210].contains(&e.error_num()) =>
+        {
+            Ok(None)
         }
+        Err(e) => Err(e),
     }
 }
 
+/// Converts the error of a query on a collection that doesn't exist into
+/// [`Error::CollectionNotFound`]
+fn collection_not_found(error: Error, collection_name: &str) -> Error {
+    match error {
+        // "ERROR_ARANGO_DATA_SOURCE_NOT_FOUND"
+        Error::ArangoClientError(ClientError::Arango(e)) if e.error_num() == 1203 => {
+            Error::CollectionNotFound(collection_name.to_string())
+        }
+        e => e,
+    }
+}
+
+/// Records the outcome of a write to the node or `edge` collection `collection_name` in the stats
+/// of `creator`, if it has any, and in its span
+fn record<G, T>(
+    creator: &G,
+    span: OperationSpan,
+    collection_name: &str,
+    result: &Result<T>,
+    edge: bool,
+    outcome: impl FnOnce(&T) -> Outcome,
+) where
+    G: GraphCreatorBase + ?Sized,
+{
+    let outcome = result.as_ref().map_or(Outcome::Failed, outcome);
+    match creator.get_run_stats() {
+        Some(stats) if edge => stats.record_edge(collection_name, outcome),
+        Some(stats) => stats.record(collection_name, outcome),
+        None => {}
+    }
+    span.finish(result, |_| outcome.as_str());
+}
+
+/// Links the document `document_id` that was created in `collection_name` to the run of
+/// `creator`, if it has one, see [`GraphCreatorBase::get_provenance`]
+fn link_created<G>(creator: &G, collection_name: &str, document_id: &str) -> Result<()>
+where
+    G: GraphCreatorBase + ?Sized,
+{
+    let Some(provenance) = creator.get_provenance() else {
+        return Ok(());
+    };
+
+    let linked = creator.request("link_created", || {
+        provenance.link(creator.get_backend(), collection_name, document_id)
+    })?;
+    if linked && let Some(stats) = creator.get_run_stats() {
+        stats.record_edge(
+            &prefixed_name(provenance.edge_collection()),
+            Outcome::Created,
+        );
+    }
+
+    Ok(())
+}
+
+/// Implements [`EdgeAttributes`] for structs with the `String` fields `_key`, `_from` and `_to`.
+/// `#[edge(key_strategy = "hash")]` always uses [`hashed_edge_key`](crate::utils::hashed_edge_key)
+/// instead of [`edge_key`](crate::utils::edge_key) for the key.
+pub use macon_cag_derive::EdgeAttributes;
+
 pub trait EdgeAttributes {
     fn apply_edge_attributes(&mut self, from_id: String, to_id: String);
     fn get_key(&self) -> String;
 }
+
+/// Nodes with a `deleted_at: Option<String>` field that can be retired with
+/// [`GraphCreatorBase::soft_delete_node`] instead of being deleted. Soft-deleted documents are
+/// skipped by lookups unless `include_deleted` is set, and upserts return them unchanged with
+/// [`UpsertResult::deleted`] set, so re-ingesting a retired sample doesn't resurrect it.
+pub trait SoftDelete {
+    fn deleted_at(&self) -> Option<&str>;
+}
+
+/// Edges with an `observed: u64` field that counts how often the edge was upserted with
+/// [`GraphCreatorBase::upsert_counted_edge`]
+pub trait CountedEdge: EdgeAttributes {
+    fn observed(&self) -> u64;
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn canonical_json_of_equal_values() {
+        assert_eq!(
+            canonical_json(&json!({"b": [2, {"d": 1, "c": null}], "a": 1.0})),
+            canonical_json(&json!({"a": 1, "b": [2, {"c": null, "d": 1}]}))
+        );
+        assert_eq!(canonical_json(&json!({"a\"b": 1})), r#"{"a\"b":1}"#);
+        assert_eq!(canonical_json(&json!(1.5)), "1.5");
+    }
+
+    #[test]
+    fn canonical_json_of_different_values() {
+        let values = [
+            json!(1),
+            json!("1"),
+            json!("\"1\""),
+            json!(true),
+            json!("true"),
+            json!([1]),
+            json!({"1": 1}),
+        ];
+        for (i, a) in values.iter().enumerate() {
+            for b in &values[i + 1..] {
+                assert_ne!(canonical_json(a), canonical_json(b), "{a} and {b}");
+            }
+        }
+    }
+}
@@ -0,0 +1,264 @@
+use std::{
+    io::{self, ErrorKind, Read},
+    path::PathBuf,
+    sync::{LazyLock, RwLock},
+    time::Duration,
+};
+
+use arangors::{ClientError, client::ClientExt};
+use http::{HeaderMap, HeaderValue};
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize, de::IgnoredAny};
+
+/// Options of the HTTP client used for all requests to the ArangoDB. Unset options keep the
+/// defaults of reqwest.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpOptions {
+    /// Timeout of a whole request in seconds (reqwest default: 30)
+    pub request_timeout_secs: Option<u64>,
+
+    /// Timeout for establishing a connection in seconds
+    pub connect_timeout_secs: Option<u64>,
+
+    /// Proxy for all requests, e.g. `http://proxy:3128`
+    pub proxy_url: Option<String>,
+
+    /// Additional PEM encoded CA certificate that is trusted
+    pub tls_ca_path: Option<PathBuf>,
+
+    /// Accept invalid TLS certificates. Only use this for testing
+    pub danger_accept_invalid_certs: bool,
+}
+
+// arangors creates the clients itself without any context, so the options have to be global
+static HTTP_OPTIONS: LazyLock<RwLock<HttpOptions>> = LazyLock::new(Default::default);
+
+/// Sets the options of all clients created from now on
+pub fn set_http_options(options: HttpOptions) {
+    *HTTP_OPTIONS.write().unwrap_or_else(|e| e.into_inner()) = options;
+}
+
+fn build_client(options: &HttpOptions) -> Result<Client, String> {
+    let mut builder = Client::builder().gzip(true);
+
+    if let Some(secs) = options.request_timeout_secs {
+        builder = builder.timeout(Duration::from_secs(secs));
+    }
+    if let Some(secs) = options.connect_timeout_secs {
+        builder = builder.connect_timeout(Duration::from_secs(secs));
+    }
+    if let Some(proxy_url) = &options.proxy_url {
+        let proxy = reqwest::Proxy::all(proxy_url)
+            .map_err(|e| format!("Invalid proxy url '{proxy_url}': {e}"))?;
+        builder = builder.proxy(proxy);
+    }
+    if let Some(path) = &options.tls_ca_path {
+        let pem = std::fs::read(path)
+            .map_err(|e| format!("Reading CA certificate '{}' failed: {e}", path.display()))?;
+        let certificate = reqwest::Certificate::from_pem(&pem)
+            .map_err(|e| format!("Invalid CA certificate '{}': {e}", path.display()))?;
+        builder = builder.add_root_certificate(certificate);
+    }
+    if options.danger_accept_invalid_certs {
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+
+    builder.build().map_err(|e| format!("{e:?}"))
+}
+
+/// Request that failed before the ArangoDB answered. arangors only passes [`ClientError`]
+/// through, whose only variant with an error source is [`ClientError::Serde`]. So the kind is
+/// carried as the [`std::io::ErrorKind`] of an I/O error within a `serde_json` error, with the
+/// reqwest error as its inner error, see [`TransportError::client_error`]. It is turned into
+/// [`Error::Timeout`](crate::Error::Timeout) or [`Error::Connect`](crate::Error::Connect).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportError {
+    /// The request was sent, but no answer arrived in time. It may have been applied.
+    Timeout,
+
+    /// No connection could be established, so the request was never sent
+    Connect,
+}
+
+impl TransportError {
+    fn of(error: &reqwest::Error) -> Option<Self> {
+        // a connect timeout is a connect error, the request wasn't sent
+        if error.is_connect() {
+            Some(Self::Connect)
+        } else if error.is_timeout() {
+            Some(Self::Timeout)
+        } else {
+            None
+        }
+    }
+
+    fn io_kind(self) -> ErrorKind {
+        match self {
+            Self::Timeout => ErrorKind::TimedOut,
+            Self::Connect => ErrorKind::NotConnected,
+        }
+    }
+
+    /// [`ClientError`] of a request that failed with `self`, `source` is the cause
+    pub fn client_error(
+        self,
+        source: impl Into<Box<dyn std::error::Error + Send + Sync>>,
+    ) -> ClientError {
+        /// Reader that fails with its error, as `serde_json` only wraps I/O errors it got itself
+        struct FailingReader(Option<io::Error>);
+
+        impl Read for FailingReader {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(self.0.take().unwrap_or_else(|| ErrorKind::Other.into()))
+            }
+        }
+
+        let reader = FailingReader(Some(io::Error::new(self.io_kind(), source)));
+        match serde_json::from_reader::<_, IgnoredAny>(reader) {
+            Err(e) => ClientError::Serde(e),
+            Ok(_) => unreachable!("reading from FailingReader always fails"),
+        }
+    }
+
+    /// Kind and message of a [`ClientError`] created by [`Self::client_error`]. `None` for other
+    /// errors, e.g. an invalid proxy url.
+    pub fn of_client_error(error: &ClientError) -> Option<(Self, String)> {
+        let ClientError::Serde(e) = error else {
+            return None;
+        };
+
+        let kind = match e.io_error_kind()? {
+            ErrorKind::TimedOut => Self::Timeout,
+            ErrorKind::NotConnected => Self::Connect,
+            _ => return None,
+        };
+        // without a position, the message is the one of the I/O error and thus of the reqwest
+        // error, whose causes are the sources of the serde_json error
+        let mut message = e.to_string();
+        let mut source = std::error::Error::source(e);
+        while let Some(cause) = source {
+            message.push_str(&format!(": {cause}"));
+            source = cause.source();
+        }
+        Some((kind, message))
+    }
+}
+
+/// reqwest client that honors the [`HttpOptions`] set with [`set_http_options`]. Apart from the
+/// options it behaves like the client of arangors.
+#[derive(Debug, Clone)]
+pub struct HttpClient {
+    client: Client,
+    headers: HeaderMap,
+}
+
+impl ClientExt for HttpClient {
+    fn new<U: Into<Option<HeaderMap>>>(headers: U) -> Result<Self, ClientError> {
+        let options = HTTP_OPTIONS.read().unwrap_or_else(|e| e.into_inner());
+        let client = build_client(&options).map_err(ClientError::HttpClient)?;
+
+        Ok(Self {
+            client,
+            headers: headers.into().unwrap_or_default(),
+        })
+    }
+
+    fn headers(&mut self) -> &mut HeaderMap<HeaderValue> {
+        &mut self.headers
+    }
+
+    fn request(
+        &self,
+        mut request: http::Request<String>,
+    ) -> Result<http::Response<String>, ClientError> {
+        let headers = request.headers_mut();
+        for (header, value) in self.headers.iter() {
+            if !headers.contains_key(header) {
+                headers.insert(header, value.clone());
+            }
+        }
+        let request = request
+            .try_into()
+            .map_err(|e| ClientError::HttpClient(format!("{e:?}")))?;
+
+        let map_err = |e: reqwest::Error| match TransportError::of(&e) {
+            Some(kind) => kind.client_error(e),
+            None => ClientError::HttpClient(format!("{e:?}")),
+        };
+
+        let resp = self.client.execute(request).map_err(map_err)?;
+
+        let status_code = resp.status();
+        let headers = resp.headers().clone();
+        let version = resp.version();
+        let content = resp.text().map_err(map_err)?;
+
+        let mut build = http::Response::builder();
+        for header in headers.iter() {
+            build = build.header(header.0, header.1);
+        }
+
+        build
+            .status(status_code)
+            .version(version)
+            .body(content)
+            .map_err(|e| ClientError::HttpClient(format!("{e:?}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::*;
+
+    #[test]
+    fn transport_errors_keep_their_kind() {
+        for kind in [TransportError::Timeout, TransportError::Connect] {
+            let error = kind.client_error("no answer");
+            assert_eq!(
+                TransportError::of_client_error(&error),
+                Some((kind, "no answer".to_string()))
+            );
+
+            match (kind, Error::from(error)) {
+                (TransportError::Timeout, Error::Timeout(message))
+                | (TransportError::Connect, Error::Connect(message)) => {
+                    assert_eq!(message, "no answer")
+                }
+                (kind, e) => panic!("{kind:?} became {e:?}"),
+            }
+        }
+
+        // the messages of other errors don't matter
+        for message in [
+            "Request timed out: no answer",
+            "Invalid proxy url 'foo': relative URL without a base",
+        ] {
+            let error = ClientError::HttpClient(message.to_string());
+            assert_eq!(TransportError::of_client_error(&error), None);
+            assert!(matches!(Error::from(error), Error::ArangoClientError(_)));
+        }
+        let error = serde_json::from_str::<IgnoredAny>("{").unwrap_err();
+        assert_eq!(
+            TransportError::of_client_error(&ClientError::Serde(error)),
+            None
+        );
+    }
+
+    #[test]
+    fn refused_connection_is_a_connect_error() {
+        // nothing listens on port 1 of the loopback address, so the connection is refused
+        let client = HttpClient::new(None).unwrap();
+        let request = http::Request::get("http://127.0.0.1:1/_api/version")
+            .body(String::new())
+            .unwrap();
+
+        let error = client.request(request).unwrap_err();
+        assert!(matches!(
+            TransportError::of_client_error(&error),
+            Some((TransportError::Connect, _))
+        ));
+        assert!(matches!(Error::from(error), Error::Connect(_)));
+    }
+}
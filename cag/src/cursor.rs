@@ -0,0 +1,95 @@
+use std::collections::VecDeque;
+
+use arangors::{Document, aql::Cursor, client::ClientExt};
+use serde::de::DeserializeOwned;
+
+use crate::{limiter::AdaptiveLimiter, prelude::*, trace::event};
+
+/// Iterator over the documents of an AQL cursor, see [`ResultCursor`]
+pub type DocumentCursor<'a, CollType> = ResultCursor<'a, Document<CollType>>;
+
+/// Iterator over the results of an AQL cursor. The next batch is only fetched from the server once
+/// the current one is consumed. An error while fetching a batch is yielded once and ends the
+/// iteration. If the iterator is dropped before all batches were fetched, the cursor is deleted
+/// on the server instead of being kept until its timeout.
+pub struct ResultCursor<'a, T> {
+    db: &'a Database,
+    limiter: Option<&'a AdaptiveLimiter>,
+    batch: VecDeque<T>,
+    cursor_id: Option<String>,
+    more: bool,
+}
+
+impl<'a, T> ResultCursor<'a, T>
+where
+    T: DeserializeOwned,
+{
+    pub fn new(db: &'a Database, limiter: Option<&'a AdaptiveLimiter>, cursor: Cursor<T>) -> Self {
+        Self {
+            db,
+            limiter,
+            batch: cursor.result.into(),
+            cursor_id: cursor.id,
+            more: cursor.more,
+        }
+    }
+
+    fn fetch_next_batch(&mut self) -> Result<()> {
+        let cursor_id = self.cursor_id.as_deref().ok_or(Error::Generic(
+            "Cursor has more results but no id".to_string(),
+        ))?;
+
+        let _permit = self.limiter.map(AdaptiveLimiter::acquire);
+        let cursor: Cursor<T> = self.db.aql_next_batch(cursor_id)?;
+
+        self.batch = cursor.result.into();
+        self.cursor_id = cursor.id;
+        self.more = cursor.more;
+        Ok(())
+    }
+}
+
+impl<T> Iterator for ResultCursor<'_, T>
+where
+    T: DeserializeOwned,
+{
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.batch.is_empty() {
+            if !self.more {
+                return None;
+            }
+
+            if let Err(e) = self.fetch_next_batch() {
+                self.more = false;
+                return Some(Err(e));
+            }
+        }
+
+        self.batch.pop_front().map(Ok)
+    }
+}
+
+impl<T> Drop for ResultCursor<'_, T> {
+    fn drop(&mut self) {
+        if !self.more {
+            return;
+        }
+        let Some(cursor_id) = &self.cursor_id else {
+            return;
+        };
+
+        let result = self
+            .db
+            .url()
+            .join(&format!("_api/cursor/{cursor_id}"))
+            .map_err(|e| Error::Generic(format!("Invalid cursor url: {e}")))
+            .and_then(|url| Ok(self.db.session().delete(url, "")?));
+
+        // the server removes the cursor after its timeout anyway
+        if let Err(e) = result {
+            event!(debug, cursor_id, error = %e, "Deleting cursor failed");
+        }
+    }
+}
@@ -0,0 +1,416 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+};
+
+use arangors::{AqlQuery, Document};
+use serde::Serialize;
+use serde_json::{Value, json};
+
+use crate::{backend::DbBackend, prelude::*};
+
+/// Operation recorded by [`DryRun`]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "operation", rename_all = "snake_case")]
+pub enum JournalEntry {
+    /// Insert of a document. `from` and `to` are set for edges
+    Insert {
+        collection: String,
+        key: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        from: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        to: Option<String>,
+    },
+
+    /// Upsert of the document with `alt_key == alt_val`. `from` and `to` are set for edges
+    Upsert {
+        collection: String,
+        alt_key: String,
+        alt_val: Value,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        from: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        to: Option<String>,
+        created: bool,
+        updated: bool,
+    },
+
+    Update {
+        collection: String,
+        key: String,
+    },
+
+    Remove {
+        collection: String,
+        key: String,
+    },
+
+    /// AQL query that is not one of the upserts of
+    /// [`GraphCreatorBase`](crate::base_creator::GraphCreatorBase). It returns no results.
+    Query {
+        query: String,
+        bind_vars: Value,
+    },
+}
+
+#[derive(Default)]
+struct DryRunState {
+    journal: Vec<JournalEntry>,
+
+    /// Documents written during the dry run by collection and key
+    documents: HashMap<String, HashMap<String, Value>>,
+    next_key: u64,
+}
+
+/// Backend that doesn't write to the database. Writes are recorded in a journal and answered
+/// with fabricated documents that have synthetic `_id`s, so the extraction keeps running. Writes
+/// of the same document are answered consistently, e.g. the second upsert of a node returns the
+/// first one with `created == false`. Documents that already exist in the database are not
+/// known to the dry run.
+#[derive(Default)]
+pub struct DryRun {
+    state: Mutex<DryRunState>,
+}
+
+impl DryRun {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Operations in the order they were requested
+    pub fn journal(&self) -> Vec<JournalEntry> {
+        self.state.lock().unwrap().journal.clone()
+    }
+
+    pub fn journal_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(&self.journal())?)
+    }
+
+    /// Number of documents by collection that would have been created or updated
+    pub fn document_counts(&self) -> BTreeMap<String, usize> {
+        self.state
+            .lock()
+            .unwrap()
+            .documents
+            .iter()
+            .map(|(collection, documents)| (collection.clone(), documents.len()))
+            .collect()
+    }
+}
+
+impl DryRunState {
+    /// Stores `document` with its `_key` or a synthetic one and returns the stored document
+    fn insert(&mut self, collection: &str, mut document: Value) -> Value {
+        let key = match document
+            .get("_key")
+            .and_then(Value::as_str)
+            .filter(|key| !key.is_empty())
+        {
+            Some(key) => key.to_string(),
+            None => {
+                self.next_key += 1;
+                format!("dry-run-{}", self.next_key)
+            }
+        };
+
+        document["_key"] = key.clone().into();
+        document["_id"] = format!("{collection}/{key}").into();
+        document["_rev"] = "dry-run".into();
+
+        self.documents
+            .entry(collection.to_string())
+            .or_default()
+            .insert(key, document.clone());
+
+        document
+    }
+
+    fn find(&mut self, collection: &str, alt_key: &str, alt_val: &Value) -> Option<&mut Value> {
+        self.find_by(collection, |document| {
+            document.get(alt_key) == Some(alt_val)
+        })
+    }
+
+    fn find_by(
+        &mut self,
+        collection: &str,
+        predicate: impl Fn(&Value) -> bool,
+    ) -> Option<&mut Value> {
+        self.documents
+            .get_mut(collection)?
+            .values_mut()
+            .find(|document| predicate(document))
+    }
+
+    /// Upserts the document with `alt_key == alt_val`. Returns the document and whether it was
+    /// created or updated. Soft-deleted documents are not updated.
+    fn upsert(
+        &mut self,
+        collection: &str,
+        alt_key: &str,
+        alt_val: Value,
+        data: Value,
+        update: &Value,
+    ) -> (Value, bool, bool) {
+        let (document, created, updated) = match self.find(collection, alt_key, &alt_val) {
+            Some(existing) => {
+                let before = existing.clone();
+                if is_deleted(existing) {
+                    (existing.clone(), false, false)
+                } else {
+                    merge(existing, update);
+                    (existing.clone(), false, *existing != before)
+                }
+            }
+            None => (self.insert(collection, data), true, false),
+        };
+
+        self.journal.push(JournalEntry::Upsert {
+            collection: collection.to_string(),
+            alt_key: alt_key.to_string(),
+            alt_val,
+            from: endpoint(&document, "_from"),
+            to: endpoint(&document, "_to"),
+            created,
+            updated,
+        });
+
+        (document, created, updated)
+    }
+
+    /// Answers the upsert queries of
+    /// [`GraphCreatorBase`](crate::base_creator::GraphCreatorBase) based on their bind variables
+    fn aql_query(&mut self, aql: Value) -> Result<Vec<Value>> {
+        let bind_vars = &aql["bindVars"];
+        let collection = bind_vars["@collection_name"].as_str().unwrap_or_default();
+        let alt_key = bind_vars["alt_key"].as_str();
+
+        // upsert_node_with_mode
+        if let (Some(alt_key), Some(data)) = (alt_key, bind_vars.get("data")) {
+            let (doc, created, updated) = self.upsert(
+                collection,
+                alt_key,
+                bind_vars["alt_val"].clone(),
+                data.clone(),
+                // insert only
+                bind_vars.get("update").unwrap_or(&json!({})),
+            );
+            let deleted = is_deleted(&doc);
+            return Ok(vec![json!({
+                "doc": doc,
+                "created": created,
+                "updated": updated,
+                "deleted": deleted
+            })]);
+        }
+
+        // upsert_nodes
+        if let (Some(alt_key), Some(Value::Array(docs))) = (alt_key, bind_vars.get("docs")) {
+            return Ok(docs
+                .iter()
+                .map(|data| {
+                    let alt_val = data[alt_key].clone();
+                    let (doc, created, _) =
+                        self.upsert(collection, alt_key, alt_val, data.clone(), &json!({}));
+                    let deleted = is_deleted(&doc);
+                    json!({ "doc": doc, "created": created, "deleted": deleted })
+                })
+                .collect());
+        }
+
+        // upsert_counted_edge
+        if let (Some(from), Some(to), Some(counter), Some(edge)) = (
+            bind_vars["from"].as_str(),
+            bind_vars["to"].as_str(),
+            bind_vars["counter"].as_str(),
+            bind_vars.get("edge"),
+        ) {
+            let (doc, created) = match self.find_by(collection, |document| {
+                document["_from"] == from && document["_to"] == to
+            }) {
+                Some(existing) => {
+                    let observed = existing.get(counter).and_then(Value::as_u64).unwrap_or(1);
+                    existing[counter] = (observed + 1).into();
+                    (existing.clone(), false)
+                }
+                None => {
+                    let mut data = edge.clone();
+                    data[counter] = 1.into();
+                    (self.insert(collection, data), true)
+                }
+            };
+
+            self.journal.push(JournalEntry::Upsert {
+                collection: collection.to_string(),
+                alt_key: "_key".to_string(),
+                alt_val: doc["_key"].clone(),
+                from: Some(from.to_string()),
+                to: Some(to.to_string()),
+                created,
+                updated: !created,
+            });
+            return Ok(vec![
+                json!({ "doc": doc, "created": created, "updated": !created }),
+            ]);
+        }
+
+        // upsert_edge_with
+        if let (Some(from), Some(to), Some(edge)) = (
+            bind_vars["from"].as_str(),
+            bind_vars["to"].as_str(),
+            bind_vars.get("edge"),
+        ) {
+            let update_existing = bind_vars["update_existing"].as_bool().unwrap_or(false);
+
+            let (doc, created, updated) = match self.find_by(collection, |document| {
+                document["_from"] == from && document["_to"] == to
+            }) {
+                Some(existing) => {
+                    let before = existing.clone();
+                    if update_existing {
+                        let mut update = edge.clone();
+                        if let Some(update) = update.as_object_mut() {
+                            update.retain(|name, _| !["_key", "_from", "_to"].contains(&&**name));
+                        }
+                        merge(existing, &update);
+                    }
+                    (existing.clone(), false, *existing != before)
+                }
+                None => (self.insert(collection, edge.clone()), true, false),
+            };
+
+            self.journal.push(JournalEntry::Upsert {
+                collection: collection.to_string(),
+                alt_key: "_key".to_string(),
+                alt_val: doc["_key"].clone(),
+                from: Some(from.to_string()),
+                to: Some(to.to_string()),
+                created,
+                updated,
+            });
+            return Ok(vec![
+                json!({ "doc": doc, "created": created, "updated": updated }),
+            ]);
+        }
+
+        self.journal.push(JournalEntry::Query {
+            query: aql["query"].as_str().unwrap_or_default().to_string(),
+            bind_vars: bind_vars.clone(),
+        });
+        Ok(vec![])
+    }
+}
+
+impl DbBackend for DryRun {
+    fn create_document(&self, collection: &str, document: Value) -> Result<Document<Value>> {
+        let mut state = self.state.lock().unwrap();
+        let document = state.insert(collection, document);
+
+        state.journal.push(JournalEntry::Insert {
+            collection: collection.to_string(),
+            key: document["_key"].as_str().unwrap_or_default().to_string(),
+            from: endpoint(&document, "_from"),
+            to: endpoint(&document, "_to"),
+        });
+
+        Ok(serde_json::from_value(document)?)
+    }
+
+    fn get_document(&self, collection: &str, key: &str) -> Result<Option<Document<Value>>> {
+        let state = self.state.lock().unwrap();
+        let document = state
+            .documents
+            .get(collection)
+            .and_then(|documents| documents.get(key));
+
+        match document {
+            Some(document) => Ok(Some(serde_json::from_value(document.clone())?)),
+            None => Ok(None),
+        }
+    }
+
+    fn update_document(
+        &self,
+        collection: &str,
+        key: &str,
+        attributes: Value,
+    ) -> Result<Document<Value>> {
+        let mut state = self.state.lock().unwrap();
+        state.journal.push(JournalEntry::Update {
+            collection: collection.to_string(),
+            key: key.to_string(),
+        });
+
+        // documents that exist in the database are unknown, so their update is only recorded
+        let document = match state.find(collection, "_key", &key.into()) {
+            Some(document) => {
+                merge(document, &attributes);
+                document.clone()
+            }
+            None => {
+                let mut document = attributes;
+                document["_key"] = key.into();
+                document["_id"] = format!("{collection}/{key}").into();
+                document["_rev"] = "dry-run".into();
+                document
+            }
+        };
+
+        Ok(serde_json::from_value(document)?)
+    }
+
+    fn remove_document(&self, collection: &str, key: &str) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(documents) = state.documents.get_mut(collection) {
+            documents.remove(key);
+        }
+
+        state.journal.push(JournalEntry::Remove {
+            collection: collection.to_string(),
+            key: key.to_string(),
+        });
+        Ok(())
+    }
+
+    fn aql_query(&self, aql: AqlQuery) -> Result<Vec<Value>> {
+        let aql = serde_json::to_value(&aql)?;
+        self.state.lock().unwrap().aql_query(aql)
+    }
+
+    fn supports_transactions(&self) -> bool {
+        false
+    }
+}
+
+/// Merges the attributes of `update` into `document` like AQL with `mergeObjects: true`
+fn merge(document: &mut Value, update: &Value) {
+    match (document, update) {
+        (Value::Object(document), Value::Object(update)) => {
+            for (key, value) in update {
+                match document.get_mut(key) {
+                    Some(existing) if existing.is_object() && value.is_object() => {
+                        merge(existing, value)
+                    }
+                    _ => {
+                        document.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        (document, update) => *document = update.clone(),
+    }
+}
+
+/// Whether `document` has a `deleted_at` tombstone, see
+/// [`SoftDelete`](crate::base_creator::SoftDelete)
+fn is_deleted(document: &Value) -> bool {
+    document.get("deleted_at").is_some_and(|v| !v.is_null())
+}
+
+fn endpoint(document: &Value, field: &str) -> Option<String> {
+    document
+        .get(field)
+        .and_then(Value::as_str)
+        .map(str::to_string)
+}
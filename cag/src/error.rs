@@ -1,3 +1,7 @@
+use arangors::ClientError;
+
+use crate::client::TransportError;
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("Generic {0}")]
@@ -6,11 +10,72 @@ pub enum Error {
     #[error("DocumentNotFound {0}")]
     DocumentNotFound(String),
 
+    #[error("CollectionNotFound {0}")]
+    CollectionNotFound(String),
+
+    #[error("MalformedResponse from collection '{collection}': {reason}")]
+    MalformedResponse { collection: String, reason: String },
+
+    #[error("UpsertRace {0}")]
+    UpsertRace(String),
+
+    #[error("SchemaViolation {0}")]
+    SchemaViolation(String),
+
+    #[error("AuthenticationRejected {0}")]
+    AuthenticationRejected(String),
+
+    #[error("PermissionDenied {0}")]
+    PermissionDenied(String),
+
+    #[error("ServerUnreachable {0}")]
+    ServerUnreachable(String),
+
+    #[error("Insert into '{collection}' failed: {source}")]
+    Insert {
+        collection: String,
+        source: Box<Error>,
+    },
+
+    #[error("Upsert into '{collection}' with {alt_key} == '{alt_val}' failed: {source}")]
+    Upsert {
+        collection: String,
+        alt_key: String,
+        alt_val: String,
+        source: Box<Error>,
+    },
+
+    #[error("Upsert of the edge from '{from}' to '{to}' in '{collection}' failed: {source}")]
+    EdgeInsert {
+        collection: String,
+        from: String,
+        to: String,
+        source: Box<Error>,
+    },
+
+    #[error("Lookup in '{collection}' with {filters} failed: {source}")]
+    Lookup {
+        collection: String,
+        filters: String,
+        source: Box<Error>,
+    },
+
     #[error("ArangoArangoError {0}")]
     ArangoArangoError(#[from] arangors::error::ArangoError),
 
+    /// The request was sent, but no answer arrived in time, see [`TransportError::Timeout`]
+    #[error("Timeout {0}")]
+    Timeout(String),
+
+    /// The request was never sent, see [`TransportError::Connect`]
+    #[error("Connect {0}")]
+    Connect(String),
+
     #[error("ArangoClientError {0}")]
-    ArangoClientError(#[from] arangors::error::ClientError),
+    ArangoClientError(ClientError),
+
+    #[error("IoError {0}")]
+    IoError(#[from] std::io::Error),
 
     #[error("SerdeJsonError {0}")]
     SerdeJsonError(#[from] serde_json::Error),
@@ -18,3 +83,30 @@ pub enum Error {
     #[error(transparent)]
     Other(#[from] anyhow::Error),
 }
+
+impl From<ClientError> for Error {
+    fn from(error: ClientError) -> Self {
+        if let Some((kind, message)) = TransportError::of_client_error(&error) {
+            return match kind {
+                TransportError::Timeout => Error::Timeout(message),
+                TransportError::Connect => Error::Connect(message),
+            };
+        }
+
+        Error::ArangoClientError(error)
+    }
+}
+
+impl Error {
+    /// The error without the context added by [`Error::Insert`], [`Error::Upsert`],
+    /// [`Error::EdgeInsert`] and [`Error::Lookup`], e.g. to match on the underlying Arango error
+    pub fn without_context(&self) -> &Error {
+        match self {
+            Error::Insert { source, .. }
+            | Error::Upsert { source, .. }
+            | Error::EdgeInsert { source, .. }
+            | Error::Lookup { source, .. } => source.without_context(),
+            e => e,
+        }
+    }
+}
@@ -0,0 +1,461 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::Write,
+};
+
+use arangors::{AqlQuery, Document};
+use serde_json::Value;
+
+use crate::{cursor::DocumentCursor, prelude::*};
+
+/// Number of documents fetched per batch while exporting
+const EXPORT_BATCH_SIZE: u32 = 1000;
+
+/// Number of exported vertices and edges
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ExportStats {
+    pub vertices: u64,
+    pub edges: u64,
+}
+
+/// Writes the graph `graph_name` as GraphML. Attributes of the documents that don't start with
+/// `_` become `data` elements, non-string values are written as JSON. The name of the
+/// collection of a document is written as `_collection`. The documents are streamed from the
+/// database, only the attribute names are collected up front to declare the GraphML keys. With
+/// `only`, just the vertices with these `_id`s and the edges between them are written.
+pub fn to_graphml(
+    db: &Database,
+    graph_name: &str,
+    only: Option<&[String]>,
+    out: &mut impl Write,
+) -> Result<ExportStats> {
+    let (vertex_collections, edge_collections) = graph_collections(db, graph_name)?;
+    let node_keys = attribute_keys(db, &vertex_collections, "n")?;
+    let edge_keys = attribute_keys(db, &edge_collections, "e")?;
+
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        out,
+        r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+    )?;
+    writeln!(
+        out,
+        r#"  <key id="collection" for="all" attr.name="_collection" attr.type="string"/>"#
+    )?;
+    for (domain, keys) in [("node", &node_keys), ("edge", &edge_keys)] {
+        for (name, id) in keys {
+            writeln!(
+                out,
+                r#"  <key id="{id}" for="{domain}" attr.name="{}" attr.type="string"/>"#,
+                xml_escape(name)
+            )?;
+        }
+    }
+    writeln!(
+        out,
+        r#"  <graph id="{}" edgedefault="directed">"#,
+        xml_escape(graph_name)
+    )?;
+
+    let mut stats = ExportStats::default();
+
+    for collection in &vertex_collections {
+        for_each_exported(db, collection, only, false, |document| {
+            writeln!(
+                out,
+                r#"    <node id="{}">"#,
+                xml_escape(&document.header._id)
+            )?;
+            write_graphml_data(out, collection, &document.document, &node_keys)?;
+            writeln!(out, "    </node>")?;
+            stats.vertices += 1;
+            Ok(())
+        })?;
+    }
+
+    for collection in &edge_collections {
+        for_each_exported(db, collection, only, true, |document| {
+            writeln!(
+                out,
+                r#"    <edge id="{}" source="{}" target="{}">"#,
+                xml_escape(&document.header._id),
+                xml_escape(document.document["_from"].as_str().unwrap_or_default()),
+                xml_escape(document.document["_to"].as_str().unwrap_or_default())
+            )?;
+            write_graphml_data(out, collection, &document.document, &edge_keys)?;
+            writeln!(out, "    </edge>")?;
+            stats.edges += 1;
+            Ok(())
+        })?;
+    }
+
+    writeln!(out, "  </graph>")?;
+    writeln!(out, "</graphml>")?;
+
+    Ok(stats)
+}
+
+/// Writes the graph `graph_name` in the DOT language of Graphviz. Attributes and `only` are
+/// handled like with [`to_graphml`]. The documents are streamed from the database.
+pub fn to_dot(
+    db: &Database,
+    graph_name: &str,
+    only: Option<&[String]>,
+    out: &mut impl Write,
+) -> Result<ExportStats> {
+    let (vertex_collections, edge_collections) = graph_collections(db, graph_name)?;
+
+    writeln!(out, "digraph {} {{", dot_quote(graph_name))?;
+
+    let mut stats = ExportStats::default();
+
+    for collection in &vertex_collections {
+        for_each_exported(db, collection, only, false, |document| {
+            writeln!(
+                out,
+                "  {} [{}];",
+                dot_quote(&document.header._id),
+                dot_attributes(collection, &document.document)
+            )?;
+            stats.vertices += 1;
+            Ok(())
+        })?;
+    }
+
+    for collection in &edge_collections {
+        for_each_exported(db, collection, only, true, |document| {
+            writeln!(
+                out,
+                "  {} -> {} [{}];",
+                dot_quote(document.document["_from"].as_str().unwrap_or_default()),
+                dot_quote(document.document["_to"].as_str().unwrap_or_default()),
+                dot_attributes(collection, &document.document)
+            )?;
+            stats.edges += 1;
+            Ok(())
+        })?;
+    }
+
+    writeln!(out, "}}")?;
+
+    Ok(stats)
+}
+
+/// Writes the graph `graph_name` as a JSON object with the arrays `vertices` and `edges`. The
+/// documents are written as they are stored, with the name of their collection as
+/// `_collection`. `only` is handled like with [`to_graphml`]. The documents are streamed from
+/// the database.
+pub fn to_json(
+    db: &Database,
+    graph_name: &str,
+    only: Option<&[String]>,
+    out: &mut impl Write,
+) -> Result<ExportStats> {
+    let (vertex_collections, edge_collections) = graph_collections(db, graph_name)?;
+
+    write!(out, "{{\"graph\":{}", Value::from(graph_name))?;
+
+    let mut stats = ExportStats::default();
+
+    for (name, collections, edges) in [
+        ("vertices", &vertex_collections, false),
+        ("edges", &edge_collections, true),
+    ] {
+        write!(out, ",\"{name}\":[")?;
+        let mut count = 0;
+        for collection in collections {
+            for_each_exported(db, collection, only, edges, |document| {
+                let mut document = document.document;
+                if let Value::Object(attributes) = &mut document {
+                    attributes.insert("_collection".to_string(), collection.as_str().into());
+                }
+                if count > 0 {
+                    write!(out, ",")?;
+                }
+                writeln!(out)?;
+                serde_json::to_writer(&mut *out, &document)?;
+                count += 1;
+                Ok(())
+            })?;
+        }
+        write!(out, "]")?;
+
+        match edges {
+            false => stats.vertices = count,
+            true => stats.edges = count,
+        }
+    }
+
+    writeln!(out, "}}")?;
+
+    Ok(stats)
+}
+
+/// Vertex and edge collections of the graph, sorted by name
+pub fn graph_collections(db: &Database, graph_name: &str) -> Result<(Vec<String>, Vec<String>)> {
+    let graph = db.graph(graph_name)?;
+
+    let edge_collections: BTreeSet<String> = graph
+        .edge_definitions
+        .iter()
+        .map(|ed| ed.collection.clone())
+        .collect();
+
+    let vertex_collections: BTreeSet<String> = graph
+        .edge_definitions
+        .into_iter()
+        .flat_map(|ed| ed.from.into_iter().chain(ed.to))
+        .chain(graph.orphan_collections)
+        .collect();
+
+    Ok((
+        vertex_collections.into_iter().collect(),
+        edge_collections.into_iter().collect(),
+    ))
+}
+
+/// Names of all attributes that don't start with `_` in `collections`, mapped to GraphML key ids
+/// with the prefix `prefix`. Only the names are transferred, not the documents.
+fn attribute_keys(
+    db: &Database,
+    collections: &[String],
+    prefix: &str,
+) -> Result<BTreeMap<String, String>> {
+    let mut names = BTreeSet::new();
+
+    for collection in collections {
+        let aql = AqlQuery::builder()
+            .query(
+                "for d in @@collection_name
+                    for name in attributes(d, true)
+                        collect unique = name
+                        return unique",
+            )
+            .bind_var("@collection_name", collection.as_str())
+            .build();
+
+        names.extend(db.aql_query::<String>(aql)?);
+    }
+
+    Ok(names
+        .into_iter()
+        .enumerate()
+        .map(|(i, name)| (name, format!("{prefix}{i}")))
+        .collect())
+}
+
+/// Streams all documents of `collection` to `f`
+pub(crate) fn for_each_document(
+    db: &Database,
+    collection: &str,
+    mut f: impl FnMut(Document<Value>) -> Result<()>,
+) -> Result<()> {
+    let aql = AqlQuery::builder()
+        .query("for d in @@collection_name return d")
+        .bind_var("@collection_name", collection)
+        .batch_size(EXPORT_BATCH_SIZE)
+        .build();
+
+    for document in DocumentCursor::new(db, None, db.aql_query_batch(aql)?) {
+        f(document?)?;
+    }
+
+    Ok(())
+}
+
+/// Streams the documents of `collection` that are exported to `f`: all of them without `only`,
+/// otherwise the vertices in `only` or the `edges` between them
+fn for_each_exported(
+    db: &Database,
+    collection: &str,
+    only: Option<&[String]>,
+    edges: bool,
+    mut f: impl FnMut(Document<Value>) -> Result<()>,
+) -> Result<()> {
+    let Some(only) = only else {
+        return for_each_document(db, collection, f);
+    };
+
+    let condition = match edges {
+        true => "d._from in @only && d._to in @only",
+        false => "d._id in @only",
+    };
+    let query = format!("for d in @@collection_name filter {condition} return d");
+    let aql = AqlQuery::builder()
+        .query(&query)
+        .bind_var("@collection_name", collection)
+        .bind_var("only", only.to_vec())
+        .batch_size(EXPORT_BATCH_SIZE)
+        .build();
+
+    for document in DocumentCursor::new(db, None, db.aql_query_batch(aql)?) {
+        f(document?)?;
+    }
+
+    Ok(())
+}
+
+/// Attributes of `document` that don't start with `_`, non-string values as JSON
+fn attributes(document: &Value) -> impl Iterator<Item = (&String, String)> {
+    document
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter(|(name, value)| !name.starts_with('_') && !value.is_null())
+        .map(|(name, value)| match value {
+            Value::String(s) => (name, s.clone()),
+            value => (name, value.to_string()),
+        })
+}
+
+fn write_graphml_data(
+    out: &mut impl Write,
+    collection: &str,
+    document: &Value,
+    keys: &BTreeMap<String, String>,
+) -> Result<()> {
+    writeln!(
+        out,
+        r#"      <data key="collection">{}</data>"#,
+        xml_escape(collection)
+    )?;
+    for (name, value) in attributes(document) {
+        if let Some(id) = keys.get(name) {
+            writeln!(
+                out,
+                r#"      <data key="{id}">{}</data>"#,
+                xml_escape(&value)
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn dot_attributes(collection: &str, document: &Value) -> String {
+    let collection = ("_collection".to_string(), collection.to_string());
+
+    std::iter::once(collection)
+        .chain(attributes(document).map(|(name, value)| (name.clone(), value)))
+        .map(|(name, value)| format!("{}={}", dot_quote(&name), dot_quote(&value)))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn xml_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // not allowed in XML 1.0
+            c if c.is_control() && !['\t', '\n', '\r'].contains(&c) => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Quoted DOT id
+fn dot_quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => {}
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    const AWKWARD: &str = "say \"hi\" <b> & 'bye'\nback\\slash";
+
+    #[test]
+    fn xml_escaping() {
+        assert_eq!(
+            xml_escape(AWKWARD),
+            "say &quot;hi&quot; &lt;b&gt; &amp; &apos;bye&apos;\nback\\slash"
+        );
+        // control characters aren't allowed in XML 1.0
+        assert_eq!(xml_escape("a\u{0}b\u{1b}c\td"), "abc\td");
+    }
+
+    #[test]
+    fn dot_quoting() {
+        assert_eq!(
+            dot_quote(AWKWARD),
+            r#""say \"hi\" <b> & 'bye'\nback\\slash""#
+        );
+        assert_eq!(dot_quote("a\r\nb"), r#""a\nb""#);
+        assert_eq!(
+            dot_attributes("Sample", &json!({"_key": "1", "name": AWKWARD, "size": 3})),
+            r#""_collection"="Sample", "name"="say \"hi\" <b> & 'bye'\nback\\slash", "size"="3""#
+        );
+    }
+
+    #[test]
+    fn escaped_graphml_is_well_formed() -> Result<()> {
+        let document = json!({
+            "_key": "1",
+            "name": AWKWARD,
+            "tags": ["<a>", "&"],
+            "nothing": null,
+        });
+        let keys = BTreeMap::from([
+            ("name".to_string(), "n0".to_string()),
+            ("tags".to_string(), "n1".to_string()),
+        ]);
+
+        let mut out = vec![];
+        writeln!(
+            out,
+            r#"<graphml><key id="n0" attr.name="{}"/><graph><node id="{}">"#,
+            xml_escape("na<m>e"),
+            xml_escape("Sample/\"1\"")
+        )?;
+        write_graphml_data(&mut out, "Sam&ple", &document, &keys)?;
+        writeln!(out, "</node></graph></graphml>")?;
+
+        let xml = String::from_utf8(out).unwrap();
+        let parsed = roxmltree::Document::parse(&xml).expect("well-formed GraphML");
+
+        let key = parsed
+            .descendants()
+            .find(|n| n.has_tag_name("key"))
+            .unwrap();
+        assert_eq!(key.attribute("attr.name"), Some("na<m>e"));
+        let node = parsed
+            .descendants()
+            .find(|n| n.has_tag_name("node"))
+            .unwrap();
+        assert_eq!(node.attribute("id"), Some("Sample/\"1\""));
+
+        let data: Vec<_> = node
+            .children()
+            .filter(|n| n.has_tag_name("data"))
+            .map(|n| (n.attribute("key").unwrap(), n.text().unwrap_or_default()))
+            .collect();
+        assert_eq!(
+            data,
+            [
+                ("collection", "Sam&ple"),
+                ("n0", AWKWARD),
+                ("n1", r#"["<a>","&"]"#),
+            ]
+        );
+        Ok(())
+    }
+}
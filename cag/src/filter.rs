@@ -0,0 +1,242 @@
+use std::collections::BTreeMap;
+
+use serde_json::Value;
+
+use crate::prelude::*;
+
+/// Comparison of a [`Filter`] condition
+#[derive(Debug, Clone, PartialEq)]
+enum Operator {
+    Eq(Value),
+    Ne(Value),
+    In(Vec<Value>),
+    Like(String),
+    Gte(Value),
+    Lt(Value),
+    Exists,
+}
+
+/// Conditions on the attributes of a document that all have to match, e.g.
+/// `Filter::new().eq("sha256sum", sha256sum).eq("architecture", "ARM")`. Attributes may be nested
+/// paths like `metadata.package_name` and may only consist of `A-Z`, `a-z`, `0-9`, `_` and
+/// `.`. The attributes and values are passed as bind variables, they are never part of the query.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Filter {
+    conditions: Vec<(String, Operator)>,
+}
+
+/// AQL condition generated by [`Filter::to_aql`] and its bind variables
+#[derive(Debug, Clone, PartialEq)]
+pub struct AqlFilter {
+    pub condition: String,
+    pub bind_vars: BTreeMap<String, Value>,
+}
+
+impl Filter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The attribute equals `value`. `null` also matches documents without the attribute
+    pub fn eq(self, attribute: &str, value: impl Into<Value>) -> Self {
+        self.with(attribute, Operator::Eq(value.into()))
+    }
+
+    /// The attribute doesn't equal `value`
+    pub fn ne(self, attribute: &str, value: impl Into<Value>) -> Self {
+        self.with(attribute, Operator::Ne(value.into()))
+    }
+
+    /// The attribute equals one of `values`
+    pub fn in_<V: Into<Value>>(self, attribute: &str, values: impl IntoIterator<Item = V>) -> Self {
+        let values = values.into_iter().map(Into::into).collect();
+        self.with(attribute, Operator::In(values))
+    }
+
+    /// The attribute matches the AQL `LIKE` pattern, `%` matches any number of characters and `_`
+    /// a single one
+    pub fn like(self, attribute: &str, pattern: &str) -> Self {
+        self.with(attribute, Operator::Like(pattern.to_string()))
+    }
+
+    /// The attribute is at least `value`. Strings are compared by their characters, so
+    /// timestamps compare by time if they have the same format, e.g. RFC 3339 in UTC
+    pub fn gte(self, attribute: &str, value: impl Into<Value>) -> Self {
+        self.with(attribute, Operator::Gte(value.into()))
+    }
+
+    /// The attribute is less than `value`, see [`Self::gte`]
+    pub fn lt(self, attribute: &str, value: impl Into<Value>) -> Self {
+        self.with(attribute, Operator::Lt(value.into()))
+    }
+
+    /// The attribute is set to a value other than `null`
+    pub fn exists(self, attribute: &str) -> Self {
+        self.with(attribute, Operator::Exists)
+    }
+
+    fn with(mut self, attribute: &str, operator: Operator) -> Self {
+        self.conditions.push((attribute.to_string(), operator));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.conditions.is_empty()
+    }
+
+    /// Generates the condition for the document variable `document`, e.g.
+    /// `d.@path0 == @value0 && like(d.@path1, @value1)`. An empty filter is `true`. Fails with
+    /// [`Error::Generic`] if an attribute contains other characters than the allowed ones.
+    pub fn to_aql(&self, document: &str) -> Result<AqlFilter> {
+        if self.conditions.is_empty() {
+            return Ok(AqlFilter {
+                condition: "true".to_string(),
+                bind_vars: BTreeMap::new(),
+            });
+        }
+
+        let mut conditions = vec![];
+        let mut bind_vars = BTreeMap::new();
+
+        for (i, (attribute, operator)) in self.conditions.iter().enumerate() {
+            bind_vars.insert(format!("path{i}"), Value::from(attribute_parts(attribute)?));
+
+            let attribute = format!("{document}.@path{i}");
+            let value = format!("@value{i}");
+            let (condition, value_var) = match operator {
+                Operator::Eq(v) => (format!("{attribute} == {value}"), Some(v.clone())),
+                Operator::Ne(v) => (format!("{attribute} != {value}"), Some(v.clone())),
+                Operator::In(v) => (format!("{attribute} in {value}"), Some(v.clone().into())),
+                Operator::Like(v) => (
+                    format!("like({attribute}, {value})"),
+                    Some(v.clone().into()),
+                ),
+                Operator::Gte(v) => (format!("{attribute} >= {value}"), Some(v.clone())),
+                Operator::Lt(v) => (format!("{attribute} < {value}"), Some(v.clone())),
+                Operator::Exists => (format!("{attribute} != null"), None),
+            };
+
+            conditions.push(condition);
+            if let Some(value_var) = value_var {
+                bind_vars.insert(format!("value{i}"), value_var);
+            }
+        }
+
+        Ok(AqlFilter {
+            condition: conditions.join(" && "),
+            bind_vars,
+        })
+    }
+}
+
+/// Parts of the attribute path `attribute`, which may only consist of `A-Z`, `a-z`, `0-9`, `_`
+/// and `.`
+fn attribute_parts(attribute: &str) -> Result<Vec<String>> {
+    let valid = attribute
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.');
+    let parts: Vec<String> = attribute.split('.').map(str::to_string).collect();
+
+    if !valid || parts.iter().any(String::is_empty) {
+        return Err(Error::Generic(format!(
+            "Invalid attribute '{attribute}' in filter, only A-Z, a-z, 0-9, '_' and '.' are allowed"
+        )));
+    }
+
+    Ok(parts)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn aql_of_all_operators() -> Result<()> {
+        let filter = Filter::new()
+            .eq("sha256sum", "abc")
+            .ne("architecture", "ARM")
+            .in_("metadata.version", [1, 2])
+            .like("name", "%.apk")
+            .exists("metadata.package_name");
+
+        let aql = filter.to_aql("d")?;
+        assert_eq!(
+            aql.condition,
+            "d.@path0 == @value0 && d.@path1 != @value1 && d.@path2 in @value2 && like(d.@path3, @value3) && d.@path4 != null"
+        );
+        assert_eq!(
+            json!(aql.bind_vars),
+            json!({
+                "path0": ["sha256sum"],
+                "value0": "abc",
+                "path1": ["architecture"],
+                "value1": "ARM",
+                "path2": ["metadata", "version"],
+                "value2": [1, 2],
+                "path3": ["name"],
+                "value3": "%.apk",
+                "path4": ["metadata", "package_name"],
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn aql_of_ranges() -> Result<()> {
+        let aql = Filter::new()
+            .gte("started_at", "2026-10-12T00:00:00+00:00")
+            .lt("started_at", "2026-10-19T00:00:00+00:00")
+            .to_aql("run")?;
+
+        assert_eq!(
+            aql.condition,
+            "run.@path0 >= @value0 && run.@path1 < @value1"
+        );
+        assert_eq!(aql.bind_vars["value0"], "2026-10-12T00:00:00+00:00");
+        assert_eq!(aql.bind_vars["value1"], "2026-10-19T00:00:00+00:00");
+
+        Ok(())
+    }
+
+    #[test]
+    fn values_are_never_part_of_the_query() -> Result<()> {
+        let injection = "x\" || true || \"";
+        let aql = Filter::new().eq("sha256sum", injection).to_aql("doc")?;
+
+        assert_eq!(aql.condition, "doc.@path0 == @value0");
+        assert_eq!(aql.bind_vars["value0"], injection);
+
+        Ok(())
+    }
+
+    #[test]
+    fn empty_filter_matches_everything() -> Result<()> {
+        let aql = Filter::new().to_aql("d")?;
+        assert_eq!(aql.condition, "true");
+        assert!(aql.bind_vars.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_attributes_are_rejected() {
+        for attribute in [
+            "",
+            "a b",
+            "a.",
+            ".a",
+            "a..b",
+            "d.x == 1 || d.y",
+            "sha256sum`",
+        ] {
+            let result = Filter::new().eq(attribute, 1).to_aql("d");
+            assert!(
+                matches!(result, Err(Error::Generic(_))),
+                "'{attribute}' is invalid"
+            );
+        }
+    }
+}
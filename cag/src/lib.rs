@@ -1,4 +1,17 @@
+pub mod backend;
 pub mod base_creator;
+pub mod client;
+pub mod cursor;
+pub mod dry_run;
 pub mod error;
+pub mod export;
+pub mod filter;
+pub mod limiter;
 pub mod prelude;
+pub mod provenance;
+pub mod retry;
+pub mod snapshot;
+pub mod stats;
+mod trace;
+pub mod transaction;
 pub mod utils;
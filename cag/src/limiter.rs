@@ -0,0 +1,93 @@
+use std::{
+    sync::{Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Limits the number of concurrent database requests and adapts the limit to the observed
+/// latency (additive increase, multiplicative decrease). Workers that wait for a permit are
+/// throttled, so an overloaded database slows down parsing instead of timing out.
+pub struct AdaptiveLimiter {
+    state: Mutex<LimiterState>,
+    available: Condvar,
+    max_limit: usize,
+    target_latency: Duration,
+}
+
+struct LimiterState {
+    limit: usize,
+    in_flight: usize,
+    successes: usize,
+    last_decrease: Instant,
+}
+
+/// Permit for a single request. The latency of the request is recorded when it is dropped.
+pub struct Permit<'a> {
+    limiter: &'a AdaptiveLimiter,
+    start: Instant,
+}
+
+impl AdaptiveLimiter {
+    pub fn new(max_limit: usize, target_latency: Duration) -> Self {
+        let max_limit = max_limit.max(1);
+
+        Self {
+            state: Mutex::new(LimiterState {
+                limit: max_limit,
+                in_flight: 0,
+                successes: 0,
+                last_decrease: Instant::now(),
+            }),
+            available: Condvar::new(),
+            max_limit,
+            target_latency,
+        }
+    }
+
+    /// Blocks until a request may be sent
+    pub fn acquire(&self) -> Permit<'_> {
+        let mut state = self.state.lock().unwrap();
+        while state.in_flight >= state.limit {
+            state = self.available.wait(state).unwrap();
+        }
+        state.in_flight += 1;
+
+        Permit {
+            limiter: self,
+            start: Instant::now(),
+        }
+    }
+
+    /// Current number of allowed concurrent requests
+    pub fn limit(&self) -> usize {
+        self.state.lock().unwrap().limit
+    }
+
+    fn release(&self, latency: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.in_flight -= 1;
+
+        if latency > self.target_latency {
+            // requests that were already in flight are slow as well, so only decrease once per
+            // target latency
+            if state.last_decrease.elapsed() > self.target_latency {
+                state.limit = (state.limit / 2).max(1);
+                state.successes = 0;
+                state.last_decrease = Instant::now();
+            }
+        } else {
+            state.successes += 1;
+            if state.successes >= state.limit {
+                state.limit = (state.limit + 1).min(self.max_limit);
+                state.successes = 0;
+            }
+        }
+
+        self.available.notify_all();
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        self.limiter.release(self.start.elapsed());
+    }
+}
@@ -2,5 +2,7 @@ pub use crate::error::Error;
 
 pub type Result<T> = core::result::Result<T, Error>;
 
-pub type Database = arangors::Database<arangors::client::reqwest::ReqwestClient>;
-pub type Collection = arangors::Collection<arangors::client::reqwest::ReqwestClient>;
+pub type Connection = arangors::connection::GenericConnection<crate::client::HttpClient>;
+pub type Database = arangors::Database<crate::client::HttpClient>;
+pub type Collection = arangors::Collection<crate::client::HttpClient>;
+pub type Transaction = arangors::transaction::Transaction<crate::client::HttpClient>;
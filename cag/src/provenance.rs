@@ -0,0 +1,71 @@
+use std::collections::HashSet;
+
+use serde_json::json;
+
+use crate::{
+    backend::DbBackend,
+    prelude::*,
+    utils::{CollectionName, edge_key, prefixed_name},
+};
+
+/// Links the documents created during a run to a document of the run, see
+/// [`GraphCreatorBase::get_provenance`](crate::base_creator::GraphCreatorBase::get_provenance).
+/// Only documents that are created are linked, documents that already existed keep the links to
+/// the run that created them.
+#[derive(Debug, Clone)]
+pub struct Provenance {
+    /// `_id` of the document of the run
+    run_id: String,
+
+    /// Name of the edge collection without prefix
+    edge_collection: &'static str,
+
+    /// Names of the collections whose documents are linked, with prefix
+    collections: HashSet<String>,
+}
+
+impl Provenance {
+    /// Links the documents created in `collections` (names with prefix, e.g. of
+    /// [`get_name`](crate::utils::get_name)) to the document `run_id` with edges of `EdgeType`
+    pub fn new<EdgeType: CollectionName>(
+        run_id: impl Into<String>,
+        collections: impl IntoIterator<Item = String>,
+    ) -> Self {
+        Self {
+            run_id: run_id.into(),
+            edge_collection: EdgeType::NAME,
+            collections: collections.into_iter().collect(),
+        }
+    }
+
+    pub fn run_id(&self) -> &str {
+        &self.run_id
+    }
+
+    /// Name of the edge collection without prefix, as used for the collections of transactions
+    pub fn edge_collection(&self) -> &'static str {
+        self.edge_collection
+    }
+
+    /// Creates the edge from the document `document_id` to the run if documents of
+    /// `collection_name` are linked. Returns whether an edge was created.
+    pub(crate) fn link(
+        &self,
+        backend: &dyn DbBackend,
+        collection_name: &str,
+        document_id: &str,
+    ) -> Result<bool> {
+        if !self.collections.contains(collection_name) {
+            return Ok(false);
+        }
+
+        let edge = json!({
+            "_key": edge_key(document_id, &self.run_id),
+            "_from": document_id,
+            "_to": self.run_id,
+        });
+        backend.create_document(&prefixed_name(self.edge_collection), edge)?;
+
+        Ok(true)
+    }
+}
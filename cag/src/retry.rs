@@ -0,0 +1,157 @@
+use std::{
+    hash::{BuildHasher, RandomState},
+    time::Duration,
+};
+
+use arangors::{ArangoError, ClientError};
+use serde::{Deserialize, Serialize};
+
+use crate::{prelude::*, trace::event};
+
+/// Retries database requests that failed with a transient error, waiting `base_delay_ms * 2^n`
+/// milliseconds before the n-th retry
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts including the first one. 1 disables retries
+    pub max_attempts: u32,
+
+    pub base_delay_ms: u64,
+
+    /// Randomize the delay by ±50% so concurrent workers don't retry in lockstep
+    pub jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay_ms: 100,
+            jitter: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Runs `request` and retries it as long as it fails with a transient error, see
+    /// [`is_transient`]
+    pub fn run<T>(&self, operation: &str, request: impl FnMut() -> Result<T>) -> Result<T> {
+        self.run_if(operation, is_transient, request)
+    }
+
+    /// Like [`Self::run`], but retries as long as `is_retryable` returns `true` for the error
+    pub fn run_if<T>(
+        &self,
+        operation: &str,
+        is_retryable: impl Fn(&Error) -> bool,
+        mut request: impl FnMut() -> Result<T>,
+    ) -> Result<T> {
+        let mut attempt = 1;
+        loop {
+            match request() {
+                Err(e) if attempt < self.max_attempts && is_retryable(&e) => {
+                    let delay = self.delay(attempt);
+                    event!(
+                        debug,
+                        operation,
+                        attempt,
+                        max_attempts = self.max_attempts,
+                        delay_ms = delay.as_millis() as u64,
+                        error = %e,
+                        outcome = "retried",
+                        "Request failed, retrying"
+                    );
+                    std::thread::sleep(delay);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn delay(&self, attempt: u32) -> Duration {
+        let delay = self
+            .base_delay_ms
+            .saturating_mul(1 << (attempt - 1).min(16)) as f64;
+
+        let factor = match self.jitter {
+            true => 0.5 + (RandomState::new().hash_one(attempt) % 1000) as f64 / 1000.0,
+            false => 1.0,
+        };
+
+        Duration::from_millis((delay * factor) as u64)
+    }
+}
+
+/// Timeouts, failed connections, unavailable servers (503) and write-write conflicts (409,
+/// 1200). Schema, authorization, configuration (e.g. an invalid proxy url) and all other errors
+/// are not transient.
+pub fn is_transient(error: &Error) -> bool {
+    let is_transient_arango =
+        |e: &ArangoError| [409, 503].contains(&e.code()) || e.error_num() == 1200;
+
+    match error {
+        Error::Timeout(_) | Error::Connect(_) => true,
+        Error::ArangoClientError(ClientError::Arango(e)) | Error::ArangoArangoError(e) => {
+            is_transient_arango(e)
+        }
+        _ => false,
+    }
+}
+
+/// Like [`is_transient`], but without timeouts. A request that timed out may have been applied,
+/// so requests that must not be applied twice (e.g. inserts) are only retried if they weren't.
+pub fn is_transient_unapplied(error: &Error) -> bool {
+    !matches!(error, Error::Timeout(_)) && is_transient(error)
+}
+
+/// Unique constraint violations (1210). Only transient if the request doesn't blindly insert
+/// again, e.g. for an `UPSERT` that will find the conflicting document on the next attempt.
+pub fn is_unique_constraint_violation(error: &Error) -> bool {
+    match error {
+        Error::ArangoClientError(ClientError::Arango(e)) | Error::ArangoArangoError(e) => {
+            e.error_num() == 1210
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::TransportError;
+
+    fn attempts(error: impl Fn() -> Error, is_retryable: impl Fn(&Error) -> bool) -> u32 {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            base_delay_ms: 0,
+            jitter: false,
+        };
+
+        let mut attempts = 0;
+        let _ = policy.run_if::<()>("test", is_retryable, || {
+            attempts += 1;
+            Err(error())
+        });
+        attempts
+    }
+
+    #[test]
+    fn only_transient_errors_are_retried() {
+        let timeout = || Error::from(TransportError::Timeout.client_error("operation timed out"));
+        let connect = || Error::from(TransportError::Connect.client_error("refused"));
+        let config = || {
+            Error::from(ClientError::HttpClient(
+                "Reading CA certificate 'ca.pem' failed: No such file".to_string(),
+            ))
+        };
+
+        assert_eq!(attempts(timeout, is_transient), 3);
+        assert_eq!(attempts(connect, is_transient), 3);
+        assert_eq!(attempts(config, is_transient), 1);
+
+        // an insert that timed out may have been applied
+        assert_eq!(attempts(timeout, is_transient_unapplied), 1);
+        assert_eq!(attempts(connect, is_transient_unapplied), 3);
+    }
+}
@@ -0,0 +1,216 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use arangors::{
+    ClientError,
+    client::ClientExt,
+    collection::{CollectionType, options::CreateOptions},
+    graph::EdgeDefinition,
+    index::{Index, IndexSettings},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    export::{for_each_document, graph_collections},
+    prelude::*,
+    utils::{
+        ImportStats, OnDuplicate, deserialize_response, ensure_graph, graph_url, import_lines,
+    },
+};
+
+/// Name of the manifest file of a snapshot
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// How [`import`] treats documents that already exist in the database
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportMode {
+    /// Remove all documents of the snapshot's collections before importing
+    Fresh,
+
+    /// Keep existing documents, documents of the snapshot with the same `_key` are skipped
+    Merge,
+}
+
+/// Content of the manifest file written by [`export`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotManifest {
+    pub graph: String,
+    pub edge_definitions: Vec<EdgeDefinition>,
+    pub orphan_collections: Vec<String>,
+    pub collections: Vec<SnapshotCollection>,
+}
+
+/// Collection of a snapshot. Its documents are stored in `<name>.jsonl`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotCollection {
+    pub name: String,
+    pub edge: bool,
+    pub documents: u64,
+
+    /// Indexes other than the primary and edge index
+    pub indexes: Vec<Index>,
+}
+
+/// Writes the graph `graph_name` to `dir`: one JSONL file per vertex and edge collection and a
+/// manifest with the edge definitions and indexes. `_key`, `_from` and `_to` are kept, so the
+/// edges still resolve after an [`import`]. The documents are streamed from the database.
+pub fn export(db: &Database, graph_name: &str, dir: &Path) -> Result<SnapshotManifest> {
+    std::fs::create_dir_all(dir)?;
+
+    let graph = db.graph(graph_name)?;
+    let (vertex_collections, edge_collections) = graph_collections(db, graph_name)?;
+
+    let collections = vertex_collections
+        .iter()
+        .map(|name| (name, false))
+        .chain(edge_collections.iter().map(|name| (name, true)));
+
+    let mut snapshot_collections = vec![];
+    for (name, edge) in collections {
+        let mut out = BufWriter::new(File::create(collection_file(dir, name))?);
+        let mut documents = 0;
+
+        for_each_document(db, name, |document| {
+            let mut document = document.document;
+            if let Some(document) = document.as_object_mut() {
+                document.remove("_id");
+                document.remove("_rev");
+            }
+            writeln!(out, "{}", serde_json::to_string(&document)?)?;
+            documents += 1;
+            Ok(())
+        })?;
+        out.flush()?;
+
+        let indexes = db
+            .indexes(name)?
+            .indexes
+            .into_iter()
+            .filter(|index| {
+                !matches!(
+                    index.settings,
+                    IndexSettings::Primary { .. } | IndexSettings::Edge { .. }
+                )
+            })
+            .collect();
+
+        snapshot_collections.push(SnapshotCollection {
+            name: name.clone(),
+            edge,
+            documents,
+            indexes,
+        });
+    }
+
+    let manifest = SnapshotManifest {
+        graph: graph_name.to_string(),
+        edge_definitions: graph.edge_definitions,
+        orphan_collections: graph.orphan_collections,
+        collections: snapshot_collections,
+    };
+
+    let out = BufWriter::new(File::create(dir.join(MANIFEST_FILE))?);
+    serde_json::to_writer_pretty(out, &manifest)?;
+
+    Ok(manifest)
+}
+
+/// Restores a snapshot written by [`export`]. Missing collections, indexes and the graph are
+/// created, existing ones are kept. The documents are bulk imported with their `_key`, existing
+/// documents are handled according to `mode`. Returns the stats of the import per collection.
+pub fn import(
+    db: &Database,
+    dir: &Path,
+    mode: ImportMode,
+) -> Result<BTreeMap<String, ImportStats>> {
+    let manifest: SnapshotManifest =
+        serde_json::from_reader(BufReader::new(File::open(dir.join(MANIFEST_FILE))?))?;
+
+    for collection in &manifest.collections {
+        ensure_snapshot_collection(db, collection, mode)?;
+    }
+
+    let graph = ensure_graph(db, &manifest.graph, manifest.edge_definitions, false)?;
+    for orphan in &manifest.orphan_collections {
+        if !graph.orphan_collections.contains(orphan) {
+            let url = graph_url(db, &manifest.graph, "vertex")?;
+            let body = serde_json::json!({ "collection": orphan });
+            let resp = db.session().post(url, body.to_string())?;
+            deserialize_response::<Value>(resp.body())?;
+        }
+    }
+
+    let mut stats = BTreeMap::new();
+    for collection in &manifest.collections {
+        let file = File::open(collection_file(dir, &collection.name))?;
+        let lines = BufReader::new(file)
+            .lines()
+            .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+            .map(|line| Ok(line?));
+
+        let collection_stats = import_lines(db, &collection.name, lines, OnDuplicate::Ignore)?;
+        stats.insert(collection.name.clone(), collection_stats);
+    }
+
+    Ok(stats)
+}
+
+/// Creates the collection and its indexes if they don't exist. With [`ImportMode::Fresh`], the
+/// documents of an existing collection are removed.
+fn ensure_snapshot_collection(
+    db: &Database,
+    collection: &SnapshotCollection,
+    mode: ImportMode,
+) -> Result<()> {
+    match db.collection(&collection.name) {
+        Ok(existing) => {
+            if mode == ImportMode::Fresh {
+                existing.truncate()?;
+            }
+        }
+        // "ERROR_ARANGO_DATA_SOURCE_NOT_FOUND"
+        Err(ClientError::Arango(e)) if e.error_num() == 1203 => {
+            let collection_type = match collection.edge {
+                true => CollectionType::Edge,
+                false => CollectionType::Document,
+            };
+            let create_options = CreateOptions::builder()
+                .name(&collection.name)
+                .collection_type(collection_type)
+                .build();
+            db.create_collection_with_options(create_options, Default::default())?;
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    let existing: Vec<String> = db
+        .indexes(&collection.name)?
+        .indexes
+        .into_iter()
+        .map(|index| index.name)
+        .collect();
+
+    for index in &collection.indexes {
+        if existing.contains(&index.name) {
+            continue;
+        }
+
+        let index = Index::builder()
+            .name(index.name.clone())
+            .fields(index.fields.clone())
+            .settings(index.settings.clone())
+            .build();
+        db.create_index(&collection.name, &index)?;
+    }
+
+    Ok(())
+}
+
+fn collection_file(dir: &Path, collection_name: &str) -> PathBuf {
+    dir.join(format!("{collection_name}.jsonl"))
+}
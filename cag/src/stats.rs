@@ -0,0 +1,200 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        RwLock,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+/// Outcome of a single write recorded in [`RunStats`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Created,
+    Existing,
+    Updated,
+    Failed,
+}
+
+impl Outcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Outcome::Created => "created",
+            Outcome::Existing => "existing",
+            Outcome::Updated => "updated",
+            Outcome::Failed => "failed",
+        }
+    }
+}
+
+/// Snapshot of the counters of one collection
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct CollectionCounts {
+    /// The collection is an edge collection
+    pub edge: bool,
+    pub created: u64,
+    pub existing: u64,
+    pub updated: u64,
+    pub failed: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    edge: AtomicBool,
+    created: AtomicU64,
+    existing: AtomicU64,
+    updated: AtomicU64,
+    failed: AtomicU64,
+}
+
+impl Counters {
+    fn get(&self, outcome: Outcome) -> &AtomicU64 {
+        match outcome {
+            Outcome::Created => &self.created,
+            Outcome::Existing => &self.existing,
+            Outcome::Updated => &self.updated,
+            Outcome::Failed => &self.failed,
+        }
+    }
+
+    fn snapshot(&self) -> CollectionCounts {
+        CollectionCounts {
+            edge: self.edge.load(Ordering::Relaxed),
+            created: self.created.load(Ordering::Relaxed),
+            existing: self.existing.load(Ordering::Relaxed),
+            updated: self.updated.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Counts the outcome of the writes of a run per collection. The counters are atomic, so the
+/// stats can be shared between workers.
+pub struct RunStats {
+    started: Instant,
+    collections: RwLock<BTreeMap<String, Counters>>,
+}
+
+impl Default for RunStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RunStats {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            collections: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn record(&self, collection: &str, outcome: Outcome) {
+        self.with_counters(collection, |counters| {
+            counters.get(outcome).fetch_add(1, Ordering::Relaxed);
+        });
+    }
+
+    /// Like [`RunStats::record`] for a write to the edge collection `collection`
+    pub fn record_edge(&self, collection: &str, outcome: Outcome) {
+        self.with_counters(collection, |counters| {
+            counters.edge.store(true, Ordering::Relaxed);
+            counters.get(outcome).fetch_add(1, Ordering::Relaxed);
+        });
+    }
+
+    fn with_counters(&self, collection: &str, f: impl FnOnce(&Counters)) {
+        if let Some(counters) = self.collections.read().unwrap().get(collection) {
+            return f(counters);
+        }
+
+        let mut collections = self.collections.write().unwrap();
+        f(collections.entry(collection.to_string()).or_default());
+    }
+
+    /// Counters of `collection`. All zero if nothing was recorded for it
+    pub fn counts(&self, collection: &str) -> CollectionCounts {
+        self.collections
+            .read()
+            .unwrap()
+            .get(collection)
+            .map(Counters::snapshot)
+            .unwrap_or_default()
+    }
+
+    /// Time since the stats were created
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Counters of all collections that were written to
+    pub fn snapshot(&self) -> BTreeMap<String, CollectionCounts> {
+        self.collections
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(name, counters)| (name.clone(), counters.snapshot()))
+            .collect()
+    }
+
+    /// Number of `samples` handled per second since the stats were created
+    pub fn throughput(&self, samples: usize) -> f64 {
+        let secs = self.elapsed().as_secs_f64();
+        if secs > 0.0 {
+            samples as f64 / secs
+        } else {
+            0.0
+        }
+    }
+
+    /// Renders the counters of all collections, the number of edges that were added and the wall
+    /// time as a table
+    pub fn report(&self) -> String {
+        let rows: Vec<(String, CollectionCounts)> = self.snapshot().into_iter().collect();
+
+        let sum = |edges_only: bool| {
+            rows.iter().filter(|(_, c)| c.edge || !edges_only).fold(
+                CollectionCounts::default(),
+                |acc, (_, c)| CollectionCounts {
+                    edge: edges_only,
+                    created: acc.created + c.created,
+                    existing: acc.existing + c.existing,
+                    updated: acc.updated + c.updated,
+                    failed: acc.failed + c.failed,
+                },
+            )
+        };
+        let total = sum(false);
+        let edges = sum(true);
+
+        let width = rows
+            .iter()
+            .map(|(name, _)| name.len())
+            .chain(["collection".len()])
+            .max()
+            .unwrap_or_default();
+
+        let mut report = format!(
+            "{:<width$}  {:>9}  {:>9}  {:>9}  {:>9}\n",
+            "collection", "created", "existing", "updated", "failed"
+        );
+        for (name, c) in rows.iter().chain([&("total".to_string(), total)]) {
+            let _ = writeln!(
+                report,
+                "{name:<width$}  {:>9}  {:>9}  {:>9}  {:>9}",
+                c.created, c.existing, c.updated, c.failed
+            );
+        }
+        let _ = writeln!(
+            report,
+            "edges added: {}, already existing: {}",
+            edges.created, edges.existing
+        );
+        let _ = write!(report, "wall time: {:.1?}", self.elapsed());
+
+        report
+    }
+}
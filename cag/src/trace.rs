@@ -0,0 +1,112 @@
+//! Spans of database operations. They are only emitted with the `tracing` feature, without it
+//! [`OperationSpan`] does nothing. Events are logged with [`event!`], with `tracing` or `log`.
+
+#[cfg(feature = "tracing")]
+use std::time::Instant;
+
+use crate::prelude::*;
+
+/// Event at `$level` (`warn`, `debug`, ...) with the fields and message written like for
+/// `tracing`, e.g. `event!(warn, collection = name, error = %e, "Request failed")`. Without the
+/// `tracing` feature it is logged with `log`, with the fields appended to the message as
+/// `name=value`. The message can't capture variables, as it is extended with `concat!`.
+macro_rules! event {
+    ($level:ident, $($fields:tt)+) => {
+        $crate::trace::event!(@fields $level, [] [] $($fields)+)
+    };
+    (@fields $level:ident, [$($t:tt)*] [$($l:tt)*] $name:ident = %$value:expr, $($rest:tt)+) => {
+        $crate::trace::event!(
+            @fields $level, [$($t)* $name = %$value,] [$($l)* ($name, $value)] $($rest)+
+        )
+    };
+    (@fields $level:ident, [$($t:tt)*] [$($l:tt)*] $name:ident = $value:expr, $($rest:tt)+) => {
+        $crate::trace::event!(
+            @fields $level, [$($t)* $name = $value,] [$($l)* ($name, $value)] $($rest)+
+        )
+    };
+    (@fields $level:ident, [$($t:tt)*] [$($l:tt)*] $name:ident, $($rest:tt)+) => {
+        $crate::trace::event!(@fields $level, [$($t)* $name,] [$($l)* ($name, $name)] $($rest)+)
+    };
+    (@fields $level:ident, [$($t:tt)*] [$(($name:ident, $value:expr))*] $message:literal) => {{
+        #[cfg(feature = "tracing")]
+        tracing::$level!($($t)* $message);
+        #[cfg(not(feature = "tracing"))]
+        log::$level!(concat!($message $(, " ", stringify!($name), "={}")*) $(, $value)*);
+    }};
+}
+
+pub(crate) use event;
+
+/// Span `db_operation` with the fields `operation`, `collection` (the graph or database for the
+/// operations on them), `elapsed_ms` and `outcome`. Retries within the span are logged as
+/// events, see [`RetryPolicy`](crate::retry::RetryPolicy).
+pub(crate) struct OperationSpan {
+    #[cfg(feature = "tracing")]
+    span: tracing::span::EnteredSpan,
+    #[cfg(feature = "tracing")]
+    started: Instant,
+}
+
+impl OperationSpan {
+    pub(crate) fn enter(operation: &'static str, collection: &str) -> Self {
+        #[cfg(feature = "tracing")]
+        {
+            let span = tracing::debug_span!(
+                "db_operation",
+                operation,
+                collection,
+                elapsed_ms = tracing::field::Empty,
+                outcome = tracing::field::Empty,
+            );
+
+            Self {
+                span: span.entered(),
+                started: Instant::now(),
+            }
+        }
+
+        #[cfg(not(feature = "tracing"))]
+        {
+            let _ = (operation, collection);
+            Self {}
+        }
+    }
+
+    /// Records the elapsed time and the `outcome` of a successful operation. Errors are recorded
+    /// as `failed`, or `not_found` for [`Error::DocumentNotFound`].
+    pub(crate) fn finish<T>(self, result: &Result<T>, outcome: impl FnOnce(&T) -> &'static str) {
+        #[cfg(feature = "tracing")]
+        {
+            let elapsed_ms = self.started.elapsed().as_millis() as u64;
+            let outcome = match result {
+                Ok(value) => outcome(value),
+                Err(Error::DocumentNotFound(_)) => "not_found",
+                Err(_) => "failed",
+            };
+
+            self.span.record("elapsed_ms", elapsed_ms);
+            self.span.record("outcome", outcome);
+            match result {
+                Err(e) if outcome == "failed" => {
+                    tracing::debug!(elapsed_ms, outcome, error = %e, "finished")
+                }
+                _ => tracing::debug!(elapsed_ms, outcome, "finished"),
+            }
+        }
+
+        #[cfg(not(feature = "tracing"))]
+        let _ = (self, result, outcome);
+    }
+
+    /// Runs `f` in the span of `operation` and records `ok` as its outcome
+    pub(crate) fn run<T>(
+        operation: &'static str,
+        collection: &str,
+        f: impl FnOnce() -> Result<T>,
+    ) -> Result<T> {
+        let span = Self::enter(operation, collection);
+        let result = f();
+        span.finish(&result, |_| "ok");
+        result
+    }
+}
@@ -0,0 +1,155 @@
+use std::{collections::HashSet, fmt::Debug, sync::Mutex};
+
+use arangors::{Document, graph::EdgeDefinition};
+use schemars::JsonSchema;
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::{
+    backend::DbBackend,
+    base_creator::GraphCreatorBase,
+    limiter::AdaptiveLimiter,
+    prelude::*,
+    provenance::Provenance,
+    retry::RetryPolicy,
+    stats::RunStats,
+    utils::{CollectionName, config::Config},
+};
+
+/// Stream transaction started by [`GraphCreatorBase::transaction`]. The handle is a graph creator
+/// itself, so e.g. `txn.upsert_node(..)` and `txn.upsert_edge(..)` are done in the transaction
+/// and only become visible once it is committed. If the backend of the creator doesn't support
+/// transactions, the handle writes to the backend directly.
+pub struct TxnHandle<'a> {
+    transaction: Option<Transaction>,
+    backend: &'a dyn DbBackend,
+
+    /// Only set with a transaction, without one the handle only uses the backend, e.g. for dry
+    /// runs that don't connect to the database
+    db: Option<&'a Database>,
+    graph_name: Option<&'a str>,
+    limiter: Option<&'a AdaptiveLimiter>,
+    run_stats: Option<&'a RunStats>,
+    provenance: Option<&'a Provenance>,
+    retry_policy: RetryPolicy,
+
+    /// `_id` of the documents written with the handle, see [`TxnHandle::written_documents`]
+    written: Mutex<Vec<String>>,
+}
+
+impl<'a> TxnHandle<'a> {
+    pub(crate) fn new<G>(creator: &'a G, transaction: Option<Transaction>) -> Self
+    where
+        G: GraphCreatorBase + ?Sized,
+    {
+        Self {
+            backend: creator.get_backend(),
+            db: transaction.as_ref().and_then(|_| creator.get_db().ok()),
+            graph_name: creator.get_graph_name(),
+            limiter: creator.get_limiter(),
+            run_stats: creator.get_run_stats(),
+            provenance: creator.get_provenance(),
+            written: Mutex::default(),
+            retry_policy: creator.get_retry_policy(),
+            transaction,
+        }
+    }
+
+    /// Id of the stream transaction
+    pub fn id(&self) -> Option<&str> {
+        self.transaction
+            .as_ref()
+            .map(|transaction| transaction.id().as_str())
+    }
+
+    /// `_id` of the nodes and edges that were created or found with the handle, in the order
+    /// they were first written
+    pub fn written_documents(&self) -> Vec<String> {
+        let mut seen = HashSet::new();
+        self.written
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|id| seen.insert(id.as_str()))
+            .cloned()
+            .collect()
+    }
+
+    pub(crate) fn commit(&self) -> Result<()> {
+        if let Some(transaction) = &self.transaction {
+            let _permit = self.limiter.map(AdaptiveLimiter::acquire);
+            transaction.commit()?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn abort(&self) -> Result<()> {
+        if let Some(transaction) = &self.transaction {
+            let _permit = self.limiter.map(AdaptiveLimiter::acquire);
+            transaction.abort()?;
+        }
+        Ok(())
+    }
+}
+
+impl GraphCreatorBase for TxnHandle<'_> {
+    fn init<T>(
+        &self,
+        _config: Config,
+        _corpus_node_data: T,
+        _edge_definitions: Vec<EdgeDefinition>,
+    ) -> Result<Document<T>>
+    where
+        T: DeserializeOwned + Serialize + Clone + JsonSchema + CollectionName + Debug,
+    {
+        Err(Error::Generic(
+            "A graph can't be initialized inside a transaction".to_string(),
+        ))
+    }
+
+    /// Fails without a transaction, see [`GraphCreatorBase::transaction`]
+    fn get_db(&self) -> Result<&Database> {
+        self.db.ok_or_else(|| {
+            Error::Generic("Handles without transaction only use the backend".to_string())
+        })
+    }
+
+    fn get_graph_name(&self) -> Option<&str> {
+        self.graph_name
+    }
+
+    fn get_limiter(&self) -> Option<&AdaptiveLimiter> {
+        self.limiter
+    }
+
+    fn get_run_stats(&self) -> Option<&RunStats> {
+        self.run_stats
+    }
+
+    fn get_provenance(&self) -> Option<&Provenance> {
+        self.provenance
+    }
+
+    fn document_written(&self, id: &str) {
+        self.written.lock().unwrap().push(id.to_string());
+    }
+
+    fn get_retry_policy(&self) -> RetryPolicy {
+        self.retry_policy
+    }
+
+    fn get_backend(&self) -> &dyn DbBackend {
+        match &self.transaction {
+            Some(transaction) => transaction,
+            None => self.backend,
+        }
+    }
+
+    /// Nested transactions are part of the already running transaction
+    fn transaction<R>(
+        &self,
+        _collections: &[&str],
+        f: impl FnOnce(&TxnHandle) -> Result<R>,
+    ) -> Result<R> {
+        f(self)
+    }
+}
@@ -1,12 +1,79 @@
+use std::{path::Path, str::FromStr};
+
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+use crate::{client::HttpOptions, prelude::*, retry::RetryPolicy, utils::apply_prefix};
+
+#[derive(Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct Config {
     pub url: String,
     pub user: String,
     pub password: String,
     pub database: String,
     pub graph: String,
+    pub auth: AuthMethod,
+
+    #[serde(flatten)]
+    pub http: HttpOptions,
+
+    pub retry: RetryPolicy,
+
+    /// Maximum number of concurrent database requests. Unlimited if not set
+    pub max_concurrent_requests: Option<usize>,
+
+    /// Create the edge collections of the graph with `waitForSync`, so edge writes are only
+    /// acknowledged once they are synchronized to disk
+    pub edge_wait_for_sync: bool,
+
+    /// Prefix of the names of all collections and of the graph, so several corpora can be stored
+    /// in one database. Applied by [`get_name`](crate::utils::get_name) and [`Self::graph_name`]
+    pub collection_prefix: Option<String>,
+}
+
+/// How to authenticate against the ArangoDB. `Basic` and `Jwt` use [`Config::user`] and
+/// [`Config::password`]; for `Jwt` they are exchanged for a token when connecting.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthMethod {
+    #[default]
+    Basic,
+    Jwt,
+    None,
+}
+
+impl FromStr for AuthMethod {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "basic" => Ok(Self::Basic),
+            "jwt" => Ok(Self::Jwt),
+            "none" => Ok(Self::None),
+            _ => Err(Error::Generic(format!(
+                "Invalid auth method '{s}', expected one of 'basic', 'jwt', 'none'"
+            ))),
+        }
+    }
+}
+
+// the password is never printed
+impl std::fmt::Debug for Config {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Config")
+            .field("url", &self.url)
+            .field("user", &self.user)
+            .field("password", &"<redacted>")
+            .field("database", &self.database)
+            .field("graph", &self.graph)
+            .field("auth", &self.auth)
+            .field("http", &self.http)
+            .field("retry", &self.retry)
+            .field("max_concurrent_requests", &self.max_concurrent_requests)
+            .field("edge_wait_for_sync", &self.edge_wait_for_sync)
+            .field("collection_prefix", &self.collection_prefix)
+            .finish()
+    }
 }
 
 impl Default for Config {
@@ -17,6 +84,12 @@ impl Default for Config {
             password: "root".to_string(),
             database: "cag_default_database".to_string(),
             graph: "cag_default_graph".to_string(),
+            auth: AuthMethod::default(),
+            http: HttpOptions::default(),
+            retry: RetryPolicy::default(),
+            max_concurrent_requests: None,
+            edge_wait_for_sync: false,
+            collection_prefix: None,
         }
     }
 }
@@ -29,6 +102,193 @@ impl Config {
             password: password.into(),
             database: database.into(),
             graph: graph.into(),
+            auth: AuthMethod::default(),
+            http: HttpOptions::default(),
+            retry: RetryPolicy::default(),
+            max_concurrent_requests: None,
+            edge_wait_for_sync: false,
+            collection_prefix: None,
+        }
+    }
+
+    /// Name of the graph with [`Self::collection_prefix`]
+    pub fn graph_name(&self) -> String {
+        apply_prefix(self.collection_prefix.as_deref(), &self.graph)
+    }
+
+    /// Loads the config from a TOML file. Keys that are missing in the file keep their default
+    /// value.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        Self::default().merge_file(path)
+    }
+
+    /// Overrides the values of `self` with the keys that are set in the TOML file `path`
+    pub fn merge_file(self, path: &Path) -> Result<Self> {
+        let invalid = |e: &dyn std::fmt::Display| {
+            Error::Generic(format!("Invalid config file '{}': {e}", path.display()))
+        };
+
+        let content = std::fs::read_to_string(path).map_err(|e| {
+            Error::Generic(format!(
+                "Reading config file '{}' failed: {e}",
+                path.display()
+            ))
+        })?;
+        let file: toml::Table = toml::from_str(&content).map_err(|e| invalid(&e))?;
+
+        let mut merged = match toml::Value::try_from(&self).map_err(|e| invalid(&e))? {
+            toml::Value::Table(table) => table,
+            _ => unreachable!("the config is serialized as table"),
+        };
+        merged.extend(file);
+
+        toml::Value::Table(merged)
+            .try_into()
+            .map_err(|e| invalid(&e))
+    }
+
+    /// Loads the config from the environment variables `MACON_ARANGO_URL`, `MACON_ARANGO_USER`,
+    /// `MACON_ARANGO_PASSWORD`, `MACON_ARANGO_DATABASE`, `MACON_ARANGO_GRAPH`,
+    /// `MACON_ARANGO_AUTH`, `MACON_ARANGO_MAX_CONCURRENT_REQUESTS`,
+    /// `MACON_ARANGO_EDGE_WAIT_FOR_SYNC` and `MACON_ARANGO_COLLECTION_PREFIX`. Unset or empty
+    /// variables keep their default value.
+    pub fn from_env() -> Result<Self> {
+        Self::default().with_env()
+    }
+
+    /// Loads the config from the defaults, overridden by `path` if given, overridden by the
+    /// environment variables (see [`Self::from_env`])
+    pub fn layered(path: Option<&Path>) -> Result<Self> {
+        Self::layered_from(Self::default(), path)
+    }
+
+    /// Like [`Self::layered`], but with `defaults` instead of the defaults of [`Config`], e.g.
+    /// the database and graph of a corpus
+    pub fn layered_from(defaults: Self, path: Option<&Path>) -> Result<Self> {
+        let config = match path {
+            Some(path) => defaults.merge_file(path)?,
+            None => defaults,
+        };
+
+        config.with_env()
+    }
+
+    fn with_env(mut self) -> Result<Self> {
+        let fields = [
+            ("MACON_ARANGO_URL", &mut self.url),
+            ("MACON_ARANGO_USER", &mut self.user),
+            ("MACON_ARANGO_PASSWORD", &mut self.password),
+            ("MACON_ARANGO_DATABASE", &mut self.database),
+            ("MACON_ARANGO_GRAPH", &mut self.graph),
+        ];
+
+        for (var, field) in fields {
+            if let Ok(value) = std::env::var(var)
+                && !value.is_empty()
+            {
+                *field = value;
+            }
+        }
+
+        if let Ok(value) = std::env::var("MACON_ARANGO_AUTH")
+            && !value.is_empty()
+        {
+            self.auth = value.parse()?;
+        }
+
+        if let Ok(value) = std::env::var("MACON_ARANGO_MAX_CONCURRENT_REQUESTS")
+            && !value.is_empty()
+        {
+            let max = value.parse().map_err(|e| {
+                Error::Generic(format!(
+                    "Invalid MACON_ARANGO_MAX_CONCURRENT_REQUESTS '{value}': {e}"
+                ))
+            })?;
+            self.max_concurrent_requests = Some(max);
+        }
+
+        if let Ok(value) = std::env::var("MACON_ARANGO_COLLECTION_PREFIX")
+            && !value.is_empty()
+        {
+            self.collection_prefix = Some(value);
+        }
+
+        if let Ok(value) = std::env::var("MACON_ARANGO_EDGE_WAIT_FOR_SYNC")
+            && !value.is_empty()
+        {
+            self.edge_wait_for_sync = value.parse().map_err(|e| {
+                Error::Generic(format!(
+                    "Invalid MACON_ARANGO_EDGE_WAIT_FOR_SYNC '{value}': {e}"
+                ))
+            })?;
+        }
+
+        Ok(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_file(content: &str) -> tempfile::NamedTempFile {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), content).unwrap();
+        file
+    }
+
+    #[test]
+    fn file_overrides_defaults() -> Result<()> {
+        let file = config_file(
+            r#"
+            url = "https://arango.example:8530"
+            auth = "jwt"
+            max_concurrent_requests = 4
+            request_timeout_secs = 60
+            "#,
+        );
+
+        let defaults = Config::new("url", "user", "password", "corpus_db", "corpus_graph");
+        let config = defaults.merge_file(file.path())?;
+
+        assert_eq!(config.url, "https://arango.example:8530");
+        assert_eq!(config.auth, AuthMethod::Jwt);
+        assert_eq!(config.max_concurrent_requests, Some(4));
+        assert_eq!(config.http.request_timeout_secs, Some(60));
+
+        // the keys that are not in the file keep the values of the defaults
+        assert_eq!(config.user, "user");
+        assert_eq!(config.database, "corpus_db");
+        assert_eq!(config.graph, "corpus_graph");
+        assert_eq!(config.collection_prefix, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn missing_file_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("macon.toml");
+
+        let error = Config::from_file(&path).err().unwrap();
+        assert!(error.to_string().contains("Reading config file"), "{error}");
+        assert!(Config::layered(Some(&path)).is_err());
+    }
+
+    #[test]
+    fn unknown_keys_are_errors() {
+        for content in [
+            "databse = \"typo\"",
+            "[arango]\nurl = \"http://127.0.0.1:8529\"",
+            "max_concurrent_requests = \"many\"",
+            "auth = \"kerberos\"",
+        ] {
+            let file = config_file(content);
+            let error = Config::from_file(file.path()).err().unwrap();
+            assert!(
+                error.to_string().contains("Invalid config file"),
+                "{content}: {error}"
+            );
         }
     }
 }
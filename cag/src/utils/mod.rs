@@ -1,153 +1,1172 @@
 pub mod config;
 
+use std::sync::RwLock;
+
 use arangors::{
-    Connection, Document,
+    ArangoError, ClientError, Document,
+    client::ClientExt,
     collection::{
         CollectionType,
-        options::{CreateOptions, CreateParameters},
+        options::{CreateOptions, CreateParameters, KeyOptions},
     },
-    document::{Header, response::DocumentResponse},
-    graph::{EdgeDefinition, Graph},
+    document::response::DocumentResponse,
+    graph::{EdgeDefinition, Graph, GraphResponse},
     index::{Index, IndexSettings},
 };
 use schemars::JsonSchema;
-use serde::{Serialize, de::DeserializeOwned};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::Value;
 
-use crate::{prelude::*, utils::config::Config};
+use crate::{
+    client::{TransportError, set_http_options},
+    prelude::*,
+    trace::{OperationSpan, event},
+    utils::config::{AuthMethod, Config},
+};
 
+/// Connects to the ArangoDB with the auth method of `config`. The credentials are checked right
+/// away, so a rejected authentication is reported as [`Error::AuthenticationRejected`] and
+/// connection problems as [`Error::ServerUnreachable`].
 pub fn establish_database_connection(config: &Config) -> Result<Connection> {
-    match Connection::establish_basic_auth(&config.url, &config.user, &config.password) {
+    set_http_options(config.http.clone());
+    set_collection_prefix(config.collection_prefix.clone());
+
+    let connection = match config.auth {
+        AuthMethod::Basic => {
+            Connection::establish_basic_auth(&config.url, &config.user, &config.password)
+        }
+        AuthMethod::Jwt => Connection::establish_jwt(&config.url, &config.user, &config.password),
+        AuthMethod::None => Connection::establish_without_auth(&config.url),
+    };
+
+    // basic auth is only checked with the first request
+    let connection = connection.and_then(|conn| conn.accessible_databases().map(|_| conn));
+
+    match connection {
         Ok(connection) => Ok(connection),
-        Err(e) => Err(Error::ArangoClientError(e)),
+        Err(ClientError::Arango(e)) if e.code() == 401 => {
+            Err(Error::AuthenticationRejected(format!(
+                "User '{}' was rejected by '{}': {e}",
+                config.user, config.url
+            )))
+        }
+        Err(e) => match TransportError::of_client_error(&e) {
+            Some((_, message)) => Err(Error::ServerUnreachable(format!(
+                "Connecting to '{}' failed: {message}",
+                config.url
+            ))),
+            None => Err(Error::ArangoClientError(e)),
+        },
+    }
+}
+
+/// Type of an index created by [`ensure_index`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexKind {
+    Hash,
+    Persistent,
+    Skiplist,
+}
+
+impl IndexKind {
+    /// Type of the index in ArangoDB
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Hash => "hash",
+            Self::Persistent => "persistent",
+            Self::Skiplist => "skiplist",
+        }
+    }
+}
+
+/// Index created by [`ensure_index`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexSpec {
+    pub fields: Vec<String>,
+    pub unique: bool,
+    pub sparse: bool,
+    pub kind: IndexKind,
+
+    /// Defaults to `<collection>--<field1>-<field2>`
+    pub name: Option<String>,
+}
+
+impl IndexSpec {
+    /// Unique sparse hash index, which is what [`ensure_unique_index`] creates
+    pub fn unique(fields: Vec<String>) -> Self {
+        Self {
+            fields,
+            unique: true,
+            sparse: true,
+            kind: IndexKind::Hash,
+            name: None,
+        }
+    }
+
+    fn settings(&self) -> IndexSettings {
+        let (unique, sparse, deduplicate) = (self.unique, self.sparse, false);
+
+        match self.kind {
+            IndexKind::Hash => IndexSettings::Hash {
+                unique,
+                sparse,
+                deduplicate,
+            },
+            IndexKind::Persistent => IndexSettings::Persistent {
+                unique,
+                sparse,
+                deduplicate,
+            },
+            IndexKind::Skiplist => IndexSettings::Skiplist {
+                unique,
+                sparse,
+                deduplicate,
+            },
+        }
+    }
+
+    /// Name of the index on the collection `collection_name`
+    fn name(&self, collection_name: &str) -> String {
+        self.name
+            .clone()
+            .unwrap_or_else(|| format!("{}--{}", collection_name, self.fields.join("-")))
+    }
+
+    /// Whether `index` is equivalent to the spec, see [`Self::differences`]
+    fn matches(&self, index: &Index) -> bool {
+        self.differences(index).is_empty()
+    }
+
+    /// Attributes in which `index` differs from the spec, e.g. `sparse is false instead of true`.
+    /// The kind is ignored, as hash and skiplist indexes are persistent indexes in newer ArangoDB
+    /// versions, but other types of indexes differ in their type.
+    fn differences(&self, index: &Index) -> Vec<String> {
+        let mut differences = vec![];
+
+        if index.fields != self.fields {
+            differences.push(format!(
+                "fields are {:?} instead of {:?}",
+                index.fields, self.fields
+            ));
+        }
+
+        match index.settings {
+            IndexSettings::Hash { unique, sparse, .. }
+            | IndexSettings::Persistent { unique, sparse, .. }
+            | IndexSettings::Skiplist { unique, sparse, .. } => {
+                if unique != self.unique {
+                    differences.push(format!("unique is {unique} instead of {}", self.unique));
+                }
+                if sparse != self.sparse {
+                    differences.push(format!("sparse is {sparse} instead of {}", self.sparse));
+                }
+            }
+            ref settings => {
+                let kind = serde_json::to_value(settings)
+                    .ok()
+                    .and_then(|settings| Some(settings.get("type")?.as_str()?.to_string()))
+                    .unwrap_or_default();
+                differences.push(format!("type is {kind} instead of {}", self.kind.as_str()));
+            }
+        }
+
+        differences
     }
 }
 
-pub fn ensure_index<CollType>(db: &Database, fields: Vec<String>) -> Result<Index>
+/// Creates the index of `spec` on the collection of `CollType`. If an equivalent index already
+/// exists, it is returned instead.
+pub fn ensure_index<CollType>(db: &Database, spec: IndexSpec) -> Result<Index>
 where
-    CollType: JsonSchema,
+    CollType: JsonSchema + CollectionName,
 {
     let collection_name = get_name::<CollType>();
 
-    let index = Index::builder()
-        .name(format!("{}--{}", collection_name, fields.join("-")))
-        .fields(fields)
-        .settings(IndexSettings::Hash {
-            unique: true,
-            sparse: true,
-            deduplicate: false,
+    OperationSpan::run("ensure_index", &collection_name, || {
+        let existing = db
+            .indexes(&collection_name)?
+            .indexes
+            .into_iter()
+            .find(|index| spec.matches(index));
+        if let Some(index) = existing {
+            return Ok(index);
+        }
+
+        let index = Index::builder()
+            .name(spec.name(&collection_name))
+            .fields(spec.fields.clone())
+            .settings(spec.settings())
+            .build();
+
+        let index = db.create_index(&collection_name, &index)?;
+        Ok(index)
+    })
+}
+
+/// Creates a unique sparse hash index on `fields`, see [`IndexSpec::unique`]
+pub fn ensure_unique_index<CollType>(db: &Database, fields: Vec<String>) -> Result<Index>
+where
+    CollType: JsonSchema + CollectionName,
+{
+    ensure_index::<CollType>(db, IndexSpec::unique(fields))
+}
+
+/// Documents that expire, see [`ensure_ttl_index`]
+pub trait Timestamped {
+    /// Attribute the TTL index is created on
+    fn expires_field() -> &'static str {
+        "created_at"
+    }
+}
+
+/// Creates a TTL index on `field`, so documents are removed by ArangoDB `expire_after_secs`
+/// seconds after the time in `field`. The time has to be a numeric Unix timestamp in seconds
+/// or an ISO 8601 date string, documents without a valid time are never removed. The removal
+/// runs in the background, by default every 30 seconds.
+///
+/// A collection can only have one TTL index. If an equivalent one exists, it is returned, a TTL
+/// index on another field or with another expiry is reported as [`Error::Generic`].
+///
+/// The TTL index is independent of unique indexes on the same collection, e.g. the one on
+/// `sha256sum` created by [`ensure_collection`]. Once a document is expired, its unique values
+/// are free again, so upserting the same node afterwards creates a new document.
+pub fn ensure_ttl_index<CollType>(
+    db: &Database,
+    field: &str,
+    expire_after_secs: u64,
+) -> Result<Index>
+where
+    CollType: JsonSchema + CollectionName,
+{
+    let collection_name = get_name::<CollType>();
+
+    OperationSpan::run("ensure_ttl_index", &collection_name, || {
+        let expire_after = u32::try_from(expire_after_secs).map_err(|_| {
+            Error::Generic(format!(
+                "TTL of {expire_after_secs} seconds is too large for an index of '{collection_name}'"
+            ))
+        })?;
+
+        let existing = db
+            .indexes(&collection_name)?
+            .indexes
+            .into_iter()
+            .find(|index| matches!(index.settings, IndexSettings::Ttl { .. }));
+        if let Some(index) = existing {
+            return match index.settings {
+                IndexSettings::Ttl {
+                    expire_after: existing_expire_after,
+                } if index.fields == [field] && existing_expire_after == expire_after => Ok(index),
+                _ => Err(Error::Generic(format!(
+                    "Collection '{collection_name}' already has the TTL index '{}' on {:?}",
+                    index.name, index.fields
+                ))),
+            };
+        }
+
+        let index = Index::builder()
+            .name(format!("{collection_name}--{field}--ttl"))
+            .fields(vec![field.to_string()])
+            .settings(IndexSettings::Ttl { expire_after })
+            .build();
+
+        let index = db.create_index(&collection_name, &index)?;
+        Ok(index)
+    })
+}
+
+/// Differences between the expected and the existing indexes of a collection
+#[derive(Debug, Default)]
+pub struct IndexDrift {
+    pub collection: String,
+
+    /// Fields of indexes that were missing and have been created
+    pub created: Vec<Vec<String>>,
+
+    /// Names of indexes that exist but are not expected
+    pub extra: Vec<String>,
+
+    /// Indexes on the fields or with the name of an expected index whose settings differ from
+    /// the expected ones
+    pub conflicting: Vec<IndexConflict>,
+}
+
+/// Existing index that differs from the expected one, see [`IndexDrift`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexConflict {
+    pub name: String,
+
+    /// Attributes that differ, e.g. `unique is false instead of true`
+    pub differences: Vec<String>,
+}
+
+impl IndexDrift {
+    pub fn warnings(&self) -> Vec<String> {
+        let extra = self.extra.iter().map(|name| {
+            format!(
+                "Collection '{}' has unexpected index '{name}'",
+                self.collection
+            )
+        });
+
+        let conflicting = self.conflicting.iter().map(|conflict| {
+            format!(
+                "Index '{}' of collection '{}' differs from the expected one: {}",
+                conflict.name,
+                self.collection,
+                conflict.differences.join(", ")
+            )
+        });
+
+        extra.chain(conflicting).collect()
+    }
+}
+
+/// Creates missing indexes of `expected` and reports extra or conflicting indexes. An existing
+/// index is the one of a spec if it has the fields or the name of the spec. Existing indexes are
+/// never modified or dropped.
+pub fn reconcile_indexes<CollType>(db: &Database, expected: Vec<IndexSpec>) -> Result<IndexDrift>
+where
+    CollType: JsonSchema + CollectionName,
+{
+    let collection_name = get_name::<CollType>();
+
+    let existing: Vec<Index> = db
+        .indexes(&collection_name)?
+        .indexes
+        .into_iter()
+        .filter(|index| {
+            !matches!(
+                index.settings,
+                IndexSettings::Primary { .. } | IndexSettings::Edge { .. }
+            )
+        })
+        .collect();
+
+    let (missing, conflicting) = compare_indexes(&collection_name, &existing, &expected);
+    let mut drift = IndexDrift {
+        conflicting,
+        ..Default::default()
+    };
+
+    for spec in missing {
+        ensure_index::<CollType>(db, spec.clone())?;
+        drift.created.push(spec.fields.clone());
+    }
+
+    drift.extra = existing
+        .iter()
+        .filter(|index| {
+            !expected.iter().any(|spec| {
+                index.fields == spec.fields || index.name == spec.name(&collection_name)
+            })
         })
-        .build();
+        .map(|index| index.name.clone())
+        .collect();
+    drift.collection = collection_name;
+
+    Ok(drift)
+}
+
+/// Specs of `expected` without an existing index and the existing indexes that differ from their
+/// spec, see [`reconcile_indexes`]
+fn compare_indexes<'a>(
+    collection_name: &str,
+    existing: &[Index],
+    expected: &'a [IndexSpec],
+) -> (Vec<&'a IndexSpec>, Vec<IndexConflict>) {
+    let mut missing = vec![];
+    let mut conflicting = vec![];
+
+    for spec in expected {
+        let name = spec.name(collection_name);
+        let index = existing
+            .iter()
+            .find(|index| index.fields == spec.fields)
+            .or_else(|| existing.iter().find(|index| index.name == name));
+
+        match index {
+            None => missing.push(spec),
+            Some(index) => {
+                let differences = spec.differences(index);
+                if !differences.is_empty() {
+                    conflicting.push(IndexConflict {
+                        name: index.name.clone(),
+                        differences,
+                    });
+                }
+            }
+        }
+    }
 
-    let index = db.create_index(&collection_name, &index)?;
-    Ok(index)
+    (missing, conflicting)
 }
 
+/// Opens the database `db_name` and creates it if it doesn't exist. Rejected credentials are
+/// reported as [`Error::AuthenticationRejected`], missing permissions as
+/// [`Error::PermissionDenied`] and connection problems as [`Error::ServerUnreachable`].
 pub fn ensure_database(conn: &Connection, db_name: &str) -> Result<Database> {
-    if let Ok(db) = conn.db(db_name) {
-        return Ok(db);
+    OperationSpan::run("ensure_database", db_name, || {
+        let result = match conn.db(db_name) {
+            // "ERROR_ARANGO_DATABASE_NOT_FOUND"
+            Err(ClientError::Arango(e)) if e.error_num() == 1228 => conn.create_database(db_name),
+            result => result,
+        };
+
+        match result {
+            Ok(db) => Ok(db),
+            Err(ClientError::Arango(e)) if e.code() == 401 => Err(Error::AuthenticationRejected(
+                format!("Access to database '{db_name}' was rejected: {e}"),
+            )),
+            // "ERROR_FORBIDDEN"
+            Err(ClientError::Arango(e)) if e.code() == 403 || e.error_num() == 11 => Err(
+                Error::PermissionDenied(format!("No permission for database '{db_name}': {e}")),
+            ),
+            Err(e) => match TransportError::of_client_error(&e) {
+                Some((_, message)) => Err(Error::ServerUnreachable(format!(
+                    "Connecting to '{}' failed: {message}",
+                    conn.url()
+                ))),
+                None => Err(Error::ArangoClientError(e)),
+            },
+        }
+    })
+}
+
+/// Drops the graph `graph_name`. With `drop_collections`, its vertex and edge collections are
+/// dropped as well, except for the ones that are used by another graph. Returns the collections
+/// of the graph.
+pub fn drop_graph(db: &Database, graph_name: &str, drop_collections: bool) -> Result<Vec<String>> {
+    let graph = db.graph(graph_name)?;
+
+    let mut collections: Vec<String> = graph
+        .edge_definitions
+        .into_iter()
+        .flat_map(|ed| std::iter::once(ed.collection).chain(ed.from).chain(ed.to))
+        .chain(graph.orphan_collections)
+        .collect();
+    collections.sort();
+    collections.dedup();
+
+    db.drop_graph(graph_name, drop_collections)?;
+    Ok(collections)
+}
+
+/// Drops the database `db_name` with all its graphs and collections. As a guard against dropping
+/// the wrong database, `confirmation` has to be the name of the database. Returns the
+/// collections that were dropped with the database, system collections excluded.
+pub fn reset_database(conn: &Connection, db_name: &str, confirmation: &str) -> Result<Vec<String>> {
+    if confirmation != db_name {
+        return Err(Error::Generic(format!(
+            "Refusing to drop database '{db_name}', the confirmation '{confirmation}' doesn't \
+             match its name"
+        )));
+    }
+
+    let db = conn.db(db_name)?;
+    let mut collections: Vec<String> = db
+        .accessible_collections()?
+        .into_iter()
+        .filter(|info| !info.is_system)
+        .map(|info| info.name)
+        .collect();
+    collections.sort();
+
+    conn.drop_database(db_name)?;
+    Ok(collections)
+}
+
+/// Result of [`health_check`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerInfo {
+    /// Version of the ArangoDB server, e.g. `3.11.5`
+    pub version: String,
+
+    /// Whether the database already exists. If not, the user is allowed to create it
+    pub database_exists: bool,
+
+    pub graph_exists: bool,
+}
+
+#[derive(Deserialize)]
+struct VersionResponse {
+    version: String,
+}
+
+#[derive(Deserialize)]
+struct PermissionResponse {
+    result: String,
+}
+
+/// Checks that the server of `config` is reachable, the credentials are accepted and the user may
+/// write to the database, or create it if it doesn't exist yet. Nothing is created. Meant to be
+/// called before a run starts, so a misconfiguration fails with a single error.
+pub fn health_check(config: &Config) -> Result<ServerInfo> {
+    let conn = establish_database_connection(config)?;
+
+    let url = conn
+        .url()
+        .join("_api/version")
+        .map_err(|e| Error::Generic(format!("Invalid version url: {e}")))?;
+    let resp = conn.session().get(url, "")?;
+    let version = deserialize_response::<VersionResponse>(resp.body())?.version;
+
+    let db = match conn.db(&config.database) {
+        Ok(db) => Some(db),
+        // "ERROR_ARANGO_DATABASE_NOT_FOUND"
+        Err(ClientError::Arango(e)) if e.error_num() == 1228 => None,
+        Err(ClientError::Arango(e)) if e.code() == 401 || e.code() == 403 => {
+            return Err(Error::PermissionDenied(format!(
+                "User '{}' can't access database '{}': {e}",
+                config.user, config.database
+            )));
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    // a new database is created from `_system`
+    let permission_db = match db {
+        Some(_) => config.database.as_str(),
+        None => "_system",
+    };
+    if config.auth != AuthMethod::None && !can_write(&conn, &config.user, permission_db)? {
+        return Err(Error::PermissionDenied(match db {
+            Some(_) => format!(
+                "User '{}' has no write permission on database '{}'",
+                config.user, config.database
+            ),
+            None => format!(
+                "Database '{}' doesn't exist and user '{}' is not allowed to create it",
+                config.database, config.user
+            ),
+        }));
+    }
+
+    let graph_exists = match &db {
+        Some(db) => match db.graph(&config.graph_name()) {
+            Ok(_) => true,
+            // "ERROR_GRAPH_NOT_FOUND"
+            Err(ClientError::Arango(e)) if e.error_num() == 1924 => false,
+            Err(e) => return Err(e.into()),
+        },
+        None => false,
     };
 
-    let db = conn.create_database(db_name)?;
-    Ok(db)
+    Ok(ServerInfo {
+        version,
+        database_exists: db.is_some(),
+        graph_exists,
+    })
+}
+
+/// Whether `user` has read-write access to the database `db_name`
+fn can_write(conn: &Connection, user: &str, db_name: &str) -> Result<bool> {
+    let mut url = conn.url().clone();
+    url.path_segments_mut()
+        .map_err(|_| Error::Generic(format!("Invalid server url '{}'", conn.url())))?
+        .pop_if_empty()
+        .extend(["_api", "user", user, "database", db_name]);
+
+    let resp = conn.session().get(url, "")?;
+    Ok(deserialize_response::<PermissionResponse>(resp.body())?.result == "rw")
+}
+
+/// Level of the schema validation of a collection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchemaLevel {
+    /// New documents and modifications of valid documents have to be valid. Documents that are
+    /// already invalid can still be modified
+    Moderate,
+
+    /// All new and modified documents have to be valid
+    Strict,
+}
+
+impl SchemaLevel {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SchemaLevel::Moderate => "moderate",
+            SchemaLevel::Strict => "strict",
+        }
+    }
+}
+
+/// ArangoDB `schema` property of a collection that validates documents against the JSON schema
+/// of `CollType`. System attributes like `_key` or `_from` are not part of the rule as ArangoDB
+/// doesn't validate them.
+pub fn collection_schema<CollType>(level: SchemaLevel) -> Result<Value>
+where
+    CollType: JsonSchema + CollectionName,
+{
+    let mut rule = serde_json::to_value(schemars::schema_for!(CollType))?;
+
+    if let Some(rule) = rule.as_object_mut() {
+        rule.remove("$schema");
+        rule.remove("title");
+
+        if let Some(Value::Object(properties)) = rule.get_mut("properties") {
+            properties.retain(|name, _| !name.starts_with('_'));
+        }
+        if let Some(Value::Array(required)) = rule.get_mut("required") {
+            required.retain(|name| !name.as_str().is_some_and(|name| name.starts_with('_')));
+        }
+    }
+
+    Ok(serde_json::json!({
+        "rule": rule,
+        "level": level.as_str(),
+        "message": format!("Document does not match the schema of '{}'", get_name::<CollType>()),
+    }))
+}
+
+/// Sets the schema validation of an existing collection to the JSON schema of `CollType`, see
+/// [`collection_schema`]. Documents that are already stored are not checked.
+pub fn update_collection_schema<CollType>(db: &Database, level: SchemaLevel) -> Result<()>
+where
+    CollType: JsonSchema + CollectionName,
+{
+    let url = properties_url(db, &get_name::<CollType>())?;
+    let body = serde_json::json!({ "schema": collection_schema::<CollType>(level)? });
+
+    let resp = db.session().put(url, body.to_string())?;
+    deserialize_response::<Value>(resp.body())?;
+
+    Ok(())
+}
+
+/// Sets `waitForSync` of the existing collection `collection_name`. Collections that are created
+/// by [`ensure_graph`] don't go through [`ensure_collection`], so their options are set this way.
+pub fn set_wait_for_sync(db: &Database, collection_name: &str, wait_for_sync: bool) -> Result<()> {
+    let url = properties_url(db, collection_name)?;
+    let body = serde_json::json!({ "waitForSync": wait_for_sync });
+
+    let resp = db.session().put(url, body.to_string())?;
+    deserialize_response::<Value>(resp.body())?;
+
+    Ok(())
 }
 
+fn properties_url(db: &Database, collection_name: &str) -> Result<url::Url> {
+    db.url()
+        .join(&format!("_api/collection/{collection_name}/properties"))
+        .map_err(|e| Error::Generic(format!("Invalid collection url: {e}")))
+}
+
+/// Key generator of a collection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyType {
+    Traditional,
+    Autoincrement,
+    Uuid,
+    Padded,
+}
+
+impl KeyType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            KeyType::Traditional => "traditional",
+            KeyType::Autoincrement => "autoincrement",
+            KeyType::Uuid => "uuid",
+            KeyType::Padded => "padded",
+        }
+    }
+}
+
+/// Options of a collection created by [`ensure_collection`]. Options that are not set keep the
+/// defaults of ArangoDB.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CollectionOptions {
+    /// Wait until writes are synchronized to disk before returning
+    pub wait_for_sync: Option<bool>,
+
+    /// Whether documents may be inserted with their own `_key`. Keys are only generated by
+    /// ArangoDB if this is `false`.
+    pub allow_user_keys: Option<bool>,
+
+    pub key_type: Option<KeyType>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CollectionProperties {
+    wait_for_sync: bool,
+    key_options: CollectionKeyOptions,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CollectionKeyOptions {
+    allow_user_keys: bool,
+    #[serde(rename = "type")]
+    key_type: String,
+}
+
+impl CollectionOptions {
+    fn key_options(&self) -> KeyOptions {
+        let mut key_options = KeyOptions::default();
+        if let Some(allow_user_keys) = self.allow_user_keys {
+            key_options.allow_user_keys = allow_user_keys;
+        }
+        key_options.key_type = self.key_type.map(|key_type| key_type.as_str().to_string());
+        key_options
+    }
+
+    /// Descriptions of the options that differ from the properties of an existing collection
+    fn mismatches(&self, properties: &CollectionProperties) -> Vec<String> {
+        let mut mismatches = vec![];
+
+        if let Some(wait_for_sync) = self.wait_for_sync
+            && wait_for_sync != properties.wait_for_sync
+        {
+            mismatches.push(format!(
+                "waitForSync is {}, expected {wait_for_sync}",
+                properties.wait_for_sync
+            ));
+        }
+        if let Some(allow_user_keys) = self.allow_user_keys
+            && allow_user_keys != properties.key_options.allow_user_keys
+        {
+            mismatches.push(format!(
+                "allowUserKeys is {}, expected {allow_user_keys}",
+                properties.key_options.allow_user_keys
+            ));
+        }
+        if let Some(key_type) = self.key_type
+            && key_type.as_str() != properties.key_options.key_type
+        {
+            mismatches.push(format!(
+                "key generator is '{}', expected '{}'",
+                properties.key_options.key_type,
+                key_type.as_str()
+            ));
+        }
+
+        mismatches
+    }
+}
+
+/// Creates the collection of `CollType` if it does not exist. With `validation`, a new collection
+/// only accepts documents that match the JSON schema of `CollType`; the schema of an existing
+/// collection is left as is, see [`update_collection_schema`]. A new collection is created with
+/// `options`, for an existing one a warning is logged for each option that differs, as most of
+/// them can't be changed afterwards. Edge collections get a unique index on `_from` and `_to`.
 pub fn ensure_collection<CollType>(
     db: &Database,
     collection_type: CollectionType,
     index_fields: Option<Vec<String>>,
+    validation: Option<SchemaLevel>,
+    options: CollectionOptions,
 ) -> Result<Collection>
 where
-    CollType: DeserializeOwned + Serialize + JsonSchema,
+    CollType: DeserializeOwned + Serialize + JsonSchema + CollectionName,
 {
     let collection_name = get_name::<CollType>();
 
-    if let Ok(collection) = db.collection(&collection_name) {
-        if let Some(fields) = index_fields {
-            ensure_index::<CollType>(db, fields)?;
-        }
-        return Ok(collection);
-    }
+    OperationSpan::run("ensure_collection", &collection_name, || {
+        let collection = match db.collection(&collection_name) {
+            Ok(collection) => {
+                let resp = db
+                    .session()
+                    .get(properties_url(db, &collection_name)?, "")?;
+                let properties = deserialize_response::<CollectionProperties>(resp.body())?;
+                for mismatch in options.mismatches(&properties) {
+                    event!(
+                        warn,
+                        collection = collection_name,
+                        mismatch = %mismatch,
+                        "Collection doesn't match its options"
+                    );
+                }
+                collection
+            }
+            Err(_) => {
+                let schema = validation.map(collection_schema::<CollType>).transpose()?;
+                // `false` and the default key options are the defaults of ArangoDB
+                let wait_for_sync = options.wait_for_sync.unwrap_or(false);
+                let create_options = match schema {
+                    Some(schema) => CreateOptions::builder()
+                        .name(&collection_name)
+                        .collection_type(collection_type)
+                        .wait_for_sync(wait_for_sync)
+                        .key_options(options.key_options())
+                        .schema(schema)
+                        .build(),
+                    None => CreateOptions::builder()
+                        .name(&collection_name)
+                        .collection_type(collection_type)
+                        .wait_for_sync(wait_for_sync)
+                        .key_options(options.key_options())
+                        .build(),
+                };
+                let create_parameters = CreateParameters::builder().build();
 
-    let create_options = CreateOptions::builder()
-        .name(&collection_name)
-        .collection_type(collection_type)
-        .build();
-    let create_parameters = CreateParameters::builder().build();
+                db.create_collection_with_options(create_options, create_parameters)?
+            }
+        };
 
-    let collection = db.create_collection_with_options(create_options, create_parameters)?;
+        if let Some(fields) = index_fields {
+            ensure_unique_index::<CollType>(db, fields)?;
+        }
 
-    if let Some(fields) = index_fields {
-        ensure_index::<CollType>(db, fields)?;
-    }
+        // an edge between two documents is only stored once
+        if matches!(collection_type, CollectionType::Edge) {
+            let spec = IndexSpec {
+                fields: vec!["_from".to_string(), "_to".to_string()],
+                unique: true,
+                sparse: false,
+                kind: IndexKind::Persistent,
+                name: None,
+            };
+            ensure_index::<CollType>(db, spec)?;
+        }
 
-    Ok(collection)
+        Ok(collection)
+    })
 }
 
+/// Creates the graph if it does not exist. If it already exists, edge definitions that are missing
+/// in the existing graph are added and the `from`/`to` collections of existing definitions are
+/// extended. Everything else that is already present is left in place. With `strict`, an error
+/// is returned instead of modifying an existing graph.
 pub fn ensure_graph(
     db: &Database,
     graph_name: &str,
     edge_definitions: Vec<EdgeDefinition>,
+    strict: bool,
 ) -> Result<Graph> {
-    if let Ok(graph) = db.graph(graph_name) {
-        return Ok(graph);
-    };
+    OperationSpan::run("ensure_graph", graph_name, || {
+        if let Ok(graph) = db.graph(graph_name) {
+            return reconcile_edge_definitions(db, graph, edge_definitions, strict);
+        };
+
+        let graph = Graph::builder()
+            .name(graph_name.to_string())
+            .edge_definitions(edge_definitions)
+            .build();
+
+        let graph = db.create_graph(graph, true)?;
+        Ok(graph)
+    })
+}
 
-    let graph = Graph::builder()
-        .name(graph_name.to_string())
-        .edge_definitions(edge_definitions)
-        .build();
+fn reconcile_edge_definitions(
+    db: &Database,
+    mut graph: Graph,
+    edge_definitions: Vec<EdgeDefinition>,
+    strict: bool,
+) -> Result<Graph> {
+    for edge_definition in edge_definitions {
+        let existing = graph
+            .edge_definitions
+            .iter()
+            .find(|ed| ed.collection == edge_definition.collection);
+
+        match existing {
+            // add missing edge definition
+            None if strict => {
+                return Err(Error::Generic(format!(
+                    "Graph '{}' has no edge definition for '{}'",
+                    graph.name, edge_definition.collection
+                )));
+            }
+            None => {
+                let url = graph_url(db, &graph.name, "edge")?;
+                let body = serde_json::to_string(&edge_definition)?;
+                let resp = db.session().post(url, body)?;
+                graph = deserialize_response::<GraphResponse>(resp.body())?.graph;
+            }
+
+            // extend from/to collections of existing edge definition
+            Some(existing) => {
+                let mut merged = existing.clone();
+                for from in edge_definition.from {
+                    if !merged.from.contains(&from) {
+                        merged.from.push(from);
+                    }
+                }
+                for to in edge_definition.to {
+                    if !merged.to.contains(&to) {
+                        merged.to.push(to);
+                    }
+                }
+
+                if merged.from.len() == existing.from.len() && merged.to.len() == existing.to.len()
+                {
+                    continue;
+                }
+
+                if strict {
+                    return Err(Error::Generic(format!(
+                        "Edge definition '{}' of graph '{}' is missing from/to collections",
+                        merged.collection, graph.name
+                    )));
+                }
+
+                let url = graph_url(db, &graph.name, &format!("edge/{}", merged.collection))?;
+                let body = serde_json::to_string(&merged)?;
+                let resp = db.session().put(url, body)?;
+                graph = deserialize_response::<GraphResponse>(resp.body())?.graph;
+            }
+        }
+    }
 
-    let graph = db.create_graph(graph, true)?;
     Ok(graph)
 }
 
-pub fn handle_document_response<T>(document_response: DocumentResponse<T>) -> Result<Document<T>>
+/// How [`bulk_import`] treats documents whose `_key` or unique index value already exists
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnDuplicate {
+    /// Don't import the document and count it as an error
+    Error,
+
+    /// Keep the existing document
+    Ignore,
+
+    /// Merge the document into the existing one
+    Update,
+}
+
+impl OnDuplicate {
+    fn as_param(&self) -> &'static str {
+        match self {
+            OnDuplicate::Error => "error",
+            OnDuplicate::Ignore => "ignore",
+            OnDuplicate::Update => "update",
+        }
+    }
+}
+
+/// Result of [`bulk_import`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct ImportStats {
+    pub created: u64,
+    pub ignored: u64,
+    pub updated: u64,
+    pub errors: u64,
+}
+
+/// Maximum number of documents per request of [`bulk_import`]
+pub const BULK_IMPORT_BATCH_SIZE: usize = 10_000;
+
+/// Imports `docs` into collection `CollType` with the `_api/import` endpoint, which is a lot
+/// faster than upserting the documents one by one. The documents are sent as JSONL in batches of
+/// [`BULK_IMPORT_BATCH_SIZE`]. Documents that fail to import are only counted in
+/// [`ImportStats::errors`], the others are imported anyway.
+pub fn bulk_import<CollType>(
+    db: &Database,
+    docs: impl Iterator<Item = CollType>,
+    on_duplicate: OnDuplicate,
+) -> Result<ImportStats>
+where
+    CollType: Serialize + JsonSchema + CollectionName,
+{
+    let lines = docs.map(|doc| Ok(serde_json::to_string(&doc)?));
+    import_lines(db, &get_name::<CollType>(), lines, on_duplicate)
+}
+
+/// Like [`bulk_import`], but with documents that are already serialized as single-line JSON
+pub(crate) fn import_lines(
+    db: &Database,
+    collection_name: &str,
+    lines: impl Iterator<Item = Result<String>>,
+    on_duplicate: OnDuplicate,
+) -> Result<ImportStats> {
+    let mut url = db
+        .url()
+        .join("_api/import")
+        .map_err(|e| Error::Generic(format!("Invalid import url: {e}")))?;
+    url.query_pairs_mut()
+        .append_pair("collection", collection_name)
+        .append_pair("type", "documents")
+        .append_pair("onDuplicate", on_duplicate.as_param());
+
+    let mut stats = ImportStats::default();
+    let mut lines = lines.peekable();
+
+    while lines.peek().is_some() {
+        let mut body = String::new();
+        for line in lines.by_ref().take(BULK_IMPORT_BATCH_SIZE) {
+            body.push_str(&line?);
+            body.push('\n');
+        }
+
+        let resp = db.session().post(url.clone(), body)?;
+        let batch_stats: ImportStats = deserialize_response(resp.body())?;
+
+        stats.created += batch_stats.created;
+        stats.ignored += batch_stats.ignored;
+        stats.updated += batch_stats.updated;
+        stats.errors += batch_stats.errors;
+    }
+
+    Ok(stats)
+}
+
+pub(crate) fn graph_url(db: &Database, graph_name: &str, path: &str) -> Result<url::Url> {
+    db.url()
+        .join(&format!("_api/gharial/{graph_name}/{path}"))
+        .map_err(|e| Error::Generic(format!("Invalid graph url: {e}")))
+}
+
+/// Deserializes the body of a raw response from the ArangoDB HTTP API. Error responses are
+/// converted to [`Error::ArangoArangoError`].
+pub fn deserialize_response<T>(body: &str) -> Result<T>
 where
-    T: Clone,
+    T: DeserializeOwned,
 {
-    let header = match document_response.header() {
-        None => panic!(),
-        Some(h) => h,
+    let value: Value = serde_json::from_str(body)?;
+
+    if value.get("error").and_then(Value::as_bool) == Some(true) {
+        let error: ArangoError = serde_json::from_value(value)?;
+        return Err(Error::ArangoArangoError(error));
+    }
+
+    Ok(serde_json::from_value(value)?)
+}
+
+/// Converts the response of a write into the new document. The write has to be done with
+/// `return_new`, otherwise [`Error::MalformedResponse`] is returned.
+pub fn handle_document_response<T>(
+    collection: &str,
+    document_response: DocumentResponse<T>,
+) -> Result<Document<T>> {
+    let reason = match document_response {
+        DocumentResponse::Response {
+            header,
+            new: Some(document),
+            ..
+        } => return Ok(Document { header, document }),
+        DocumentResponse::Response { new: None, .. } => "the response has no new document",
+        DocumentResponse::Silent => "the response is empty",
     };
-    let document = match document_response.new_doc() {
-        None => panic!(),
-        Some(d) => d,
+
+    Err(Error::MalformedResponse {
+        collection: collection.to_string(),
+        reason: reason.to_string(),
+    })
+}
+
+/// Name of the collection the documents of a type are stored in. Implemented with
+/// `#[derive(CollectionName)]`, the name can be set with `#[collection(name = "...")]` so it
+/// stays stable when the type is renamed or moved.
+pub trait CollectionName {
+    const NAME: &'static str;
+}
+
+pub use macon_cag_derive::CollectionName;
+
+static COLLECTION_PREFIX: RwLock<Option<String>> = RwLock::new(None);
+
+/// Sets the prefix of all collection names resolved from now on, see
+/// [`Config::collection_prefix`]
+pub fn set_collection_prefix(prefix: Option<String>) {
+    *COLLECTION_PREFIX.write().unwrap_or_else(|e| e.into_inner()) = prefix;
+}
+
+/// Resolves the name of a collection or graph to the name in the database by applying the prefix
+/// of [`set_collection_prefix`]. All names of [`CollectionName`] types go through this function.
+pub fn prefixed_name(name: &str) -> String {
+    let prefix = COLLECTION_PREFIX.read().unwrap_or_else(|e| e.into_inner());
+    apply_prefix(prefix.as_deref(), name)
+}
+
+pub(crate) fn apply_prefix(prefix: Option<&str>, name: &str) -> String {
+    match prefix {
+        Some(prefix) => format!("{prefix}{name}"),
+        None => name.to_string(),
+    }
+}
+
+pub fn get_name<T: CollectionName>() -> String {
+    prefixed_name(T::NAME)
+}
+
+/// Implements [`CollectionName`](crate::utils::CollectionName) with the name of the type or with
+/// an explicit name, e.g. `impl_collection_name!(Renamed, "OldName")`
+#[deprecated(note = "use `#[derive(CollectionName)]` instead")]
+#[macro_export]
+macro_rules! impl_collection_name {
+    ($type:ident) => {
+        $crate::impl_collection_name!($type, stringify!($type));
     };
-    let new_doc: Document<T> = Document {
-        header: Header {
-            _id: header._id.clone(),
-            _key: header._key.clone(),
-            _rev: header._rev.clone(),
-        },
-        document: document.clone(),
+    ($type:ty, $name:expr) => {
+        impl $crate::utils::CollectionName for $type {
+            const NAME: &'static str = $name;
+        }
     };
-    Ok(new_doc)
 }
 
-pub fn get_name<T>() -> String {
-    std::any::type_name::<T>()
-        .split("::")
-        .last()
-        .unwrap_or_else(|| {
-            panic!(
-                "Getting the name of Type '{}' failed",
-                std::any::type_name::<T>()
-            )
-        })
-        .to_owned()
+/// Splits a dot-separated attribute path like `metadata.package_name` into its segments.
+/// Segments that contain dots are quoted with backticks, e.g. ``files.`a.apk`.size``, a backtick
+/// inside a quoted segment is escaped with a backslash. The segments are meant to be bound as
+/// array bind variable (`d.@path`), so they never become part of the query text.
+pub fn attribute_path(path: &str) -> Result<Vec<String>> {
+    let invalid =
+        |reason: &str| Error::Generic(format!("Invalid attribute path '{path}': {reason}"));
+
+    let mut segments = vec![];
+    let mut segment = String::new();
+    let mut quoted = false;
+    let mut chars = path.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '`' => quoted = !quoted,
+            '\\' if quoted => match chars.next() {
+                Some(c) => segment.push(c),
+                None => return Err(invalid("dangling escape")),
+            },
+            '.' if !quoted => {
+                if segment.is_empty() {
+                    return Err(invalid("empty segment"));
+                }
+                segments.push(std::mem::take(&mut segment));
+            }
+            c => segment.push(c),
+        }
+    }
+
+    if quoted {
+        return Err(invalid("unterminated backtick"));
+    }
+    if segment.is_empty() {
+        return Err(invalid("empty segment"));
+    }
+    segments.push(segment);
+
+    Ok(segments)
+}
+
+/// Maximum length of a document key accepted by ArangoDB
+pub const MAX_KEY_LENGTH: usize = 254;
+
+/// Key of the edge between the documents `from_id` and `to_id`. Slashes are replaced by dashes
+/// as they are not allowed in keys. Keys longer than [`MAX_KEY_LENGTH`] are replaced by the
+/// sha256 hash of the composed key.
+pub fn edge_key(from_id: &str, to_id: &str) -> String {
+    let key = format!("{from_id}--{to_id}").replace('/', "-");
+
+    if key.len() > MAX_KEY_LENGTH {
+        return hashed_edge_key(from_id, to_id);
+    }
+    key
+}
+
+/// Key of the edge between the documents `from_id` and `to_id` that is always the sha256 hash of
+/// the composed key, see [`edge_key`]
+pub fn hashed_edge_key(from_id: &str, to_id: &str) -> String {
+    sha256::digest(format!("{from_id}--{to_id}"))
 }
 
+#[deprecated(note = "use `#[derive(EdgeAttributes)]` instead")]
 #[macro_export]
 macro_rules! impl_edge_attributes {
     ($edge:ty) => {
         impl $crate::base_creator::EdgeAttributes for $edge {
             fn apply_edge_attributes(&mut self, from_id: String, to_id: String) {
-                self._from = from_id.clone();
-                self._to = to_id.clone();
-
-                let from_id = from_id.replace('/', "-");
-                let to_id = to_id.replace('/', "-");
-                self._key = format!("{from_id}--{to_id}");
+                self._key = $crate::utils::edge_key(&from_id, &to_id);
+                self._from = from_id;
+                self._to = to_id;
             }
 
             fn get_key(&self) -> String {
@@ -156,3 +1175,180 @@ macro_rules! impl_edge_attributes {
         }
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_attribute_paths() -> Result<()> {
+        assert_eq!(attribute_path("sha256sum")?, ["sha256sum"]);
+        assert_eq!(
+            attribute_path("metadata.package_name")?,
+            ["metadata", "package_name"]
+        );
+        assert_eq!(attribute_path("a.b.c.d")?, ["a", "b", "c", "d"]);
+        Ok(())
+    }
+
+    #[test]
+    fn escaped_attribute_paths() -> Result<()> {
+        // dots inside backticks are part of the segment
+        assert_eq!(
+            attribute_path("files.`a.apk`.size")?,
+            ["files", "a.apk", "size"]
+        );
+        assert_eq!(attribute_path(r"`a\`b`.c")?, ["a`b", "c"]);
+        assert_eq!(attribute_path(r"`a\\b`")?, [r"a\b"]);
+        // characters that would end an AQL expression stay in the segment
+        assert_eq!(attribute_path("x RETURN 1 //")?, ["x RETURN 1 //"]);
+        assert_eq!(attribute_path("`a.b` || 1")?, ["a.b || 1"]);
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_attribute_paths() {
+        for path in ["", ".", "a.", ".a", "a..b", "a.``", "`a", r"`a\"] {
+            assert!(attribute_path(path).is_err(), "{path:?}");
+        }
+    }
+
+    fn index(name: &str, fields: &[&str], settings: IndexSettings) -> Index {
+        Index::builder()
+            .name(name.to_string())
+            .fields(fields.iter().map(|field| field.to_string()).collect())
+            .settings(settings)
+            .build()
+    }
+
+    fn hash(unique: bool, sparse: bool) -> IndexSettings {
+        IndexSettings::Hash {
+            unique,
+            sparse,
+            deduplicate: false,
+        }
+    }
+
+    #[test]
+    fn index_differences_name_the_attribute() {
+        let spec = IndexSpec::unique(vec!["sha256sum".to_string()]);
+
+        // the kind doesn't matter
+        let persistent = IndexSettings::Persistent {
+            unique: true,
+            sparse: true,
+            deduplicate: true,
+        };
+        assert!(spec.matches(&index("i", &["sha256sum"], persistent)));
+        assert!(spec.matches(&index("i", &["sha256sum"], hash(true, true))));
+
+        for (index, differences) in [
+            (
+                index("i", &["sha256sum"], hash(false, true)),
+                vec!["unique is false instead of true"],
+            ),
+            (
+                index("i", &["sha256sum"], hash(true, false)),
+                vec!["sparse is false instead of true"],
+            ),
+            (
+                index("i", &["sha256sum"], hash(false, false)),
+                vec![
+                    "unique is false instead of true",
+                    "sparse is false instead of true",
+                ],
+            ),
+            (
+                index("i", &["md5sum"], hash(true, true)),
+                vec![r#"fields are ["md5sum"] instead of ["sha256sum"]"#],
+            ),
+            (
+                index("i", &["sha256sum"], IndexSettings::Ttl { expire_after: 60 }),
+                vec!["type is ttl instead of hash"],
+            ),
+        ] {
+            assert_eq!(spec.differences(&index), differences);
+        }
+    }
+
+    #[test]
+    fn compare_existing_indexes_with_specs() {
+        let specs = [
+            IndexSpec::unique(vec!["sha256sum".to_string()]),
+            IndexSpec::unique(vec!["name".to_string()]),
+            IndexSpec::unique(vec!["md5sum".to_string()]),
+        ];
+        let existing = [
+            index("by_hash", &["sha256sum"], hash(true, false)),
+            // the index with the default name of the spec is the one of the spec
+            index("Sample--name", &["display_name"], hash(true, true)),
+        ];
+
+        let (missing, conflicting) = compare_indexes("Sample", &existing, &specs);
+        assert_eq!(missing, [&specs[2]]);
+        assert_eq!(
+            conflicting,
+            [
+                IndexConflict {
+                    name: "by_hash".to_string(),
+                    differences: vec!["sparse is false instead of true".to_string()],
+                },
+                IndexConflict {
+                    name: "Sample--name".to_string(),
+                    differences: vec![
+                        r#"fields are ["display_name"] instead of ["name"]"#.to_string()
+                    ],
+                },
+            ]
+        );
+
+        let drift = IndexDrift {
+            collection: "Sample".to_string(),
+            conflicting,
+            ..Default::default()
+        };
+        assert_eq!(
+            drift.warnings()[0],
+            "Index 'by_hash' of collection 'Sample' differs from the expected one: sparse is \
+             false instead of true"
+        );
+    }
+
+    /// Ids whose composed key is `len` characters long
+    fn ids_of_key_length(len: usize) -> (String, String) {
+        // "Sample/" + from + "--" + "File/" + to
+        let from = "a".repeat(len - 14 - 7);
+        (format!("Sample/{from}"), "File/1234567".to_string())
+    }
+
+    #[test]
+    fn short_edge_keys_are_readable() {
+        assert_eq!(edge_key("Sample/1", "File/2"), "Sample-1--File-2");
+    }
+
+    #[test]
+    fn edge_keys_at_the_length_limit() {
+        let (from, to) = ids_of_key_length(MAX_KEY_LENGTH);
+        let key = edge_key(&from, &to);
+        assert_eq!(key.len(), MAX_KEY_LENGTH);
+        assert_eq!(key, format!("{from}--{to}").replace('/', "-"));
+
+        let (from, to) = ids_of_key_length(MAX_KEY_LENGTH + 1);
+        let key = edge_key(&from, &to);
+        assert_eq!(key, hashed_edge_key(&from, &to));
+        assert!(key.len() <= MAX_KEY_LENGTH);
+    }
+
+    #[test]
+    fn long_edge_keys_are_stable_and_distinct() {
+        let (from, to) = ids_of_key_length(1000);
+        let key = edge_key(&from, &to);
+        assert_eq!(key, edge_key(&from, &to));
+        assert!(key.len() <= MAX_KEY_LENGTH);
+
+        // a different endpoint or the reversed edge has another key
+        assert_ne!(key, edge_key(&format!("{from}b"), &to));
+        assert_ne!(key, edge_key(&from, "File/7654321"));
+        assert_ne!(key, edge_key(&to, &from));
+    }
+}
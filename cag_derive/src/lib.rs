@@ -0,0 +1,168 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{Data, DeriveInput, Fields, LitStr, Type, parse_macro_input, spanned::Spanned};
+
+/// Fields every edge has to have
+const EDGE_FIELDS: [&str; 3] = ["_key", "_from", "_to"];
+
+/// Implements `macon_cag::base_creator::EdgeAttributes` for a struct with the `String` fields
+/// `_key`, `_from` and `_to`. The key is composed of the ids of both documents, see
+/// `macon_cag::utils::edge_key`. With `#[edge(key_strategy = "hash")]`, the key is always the
+/// hash of the ids, see `macon_cag::utils::hashed_edge_key`.
+#[proc_macro_derive(EdgeAttributes, attributes(edge))]
+pub fn derive_edge_attributes(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match edge_attributes(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn edge_attributes(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let Data::Struct(data) = &input.data else {
+        return Err(syn::Error::new(
+            input.span(),
+            "EdgeAttributes can only be derived for structs",
+        ));
+    };
+    let Fields::Named(fields) = &data.fields else {
+        return Err(syn::Error::new(
+            input.span(),
+            "EdgeAttributes requires a struct with named fields",
+        ));
+    };
+
+    for name in EDGE_FIELDS {
+        let field = fields
+            .named
+            .iter()
+            .find(|field| field.ident.as_ref().is_some_and(|ident| ident == name));
+
+        match field {
+            None => {
+                return Err(syn::Error::new(
+                    input.ident.span(),
+                    format!("EdgeAttributes requires the field `{name}: String`"),
+                ));
+            }
+            Some(field) if !is_string(&field.ty) => {
+                return Err(syn::Error::new(
+                    field.ty.span(),
+                    format!("`{name}` has to be a `String`"),
+                ));
+            }
+            Some(_) => (),
+        }
+    }
+
+    let key = match key_strategy(input)?.as_deref() {
+        None | Some("composed") => quote!(::macon_cag::utils::edge_key(&from_id, &to_id)),
+        Some("hash") => quote!(::macon_cag::utils::hashed_edge_key(&from_id, &to_id)),
+        Some(other) => {
+            return Err(syn::Error::new(
+                input.span(),
+                format!("Unknown key_strategy `{other}`, expected `composed` or `hash`"),
+            ));
+        }
+    };
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::macon_cag::base_creator::EdgeAttributes for #name #ty_generics
+            #where_clause
+        {
+            fn apply_edge_attributes(&mut self, from_id: String, to_id: String) {
+                self._key = #key;
+                self._from = from_id;
+                self._to = to_id;
+            }
+
+            fn get_key(&self) -> String {
+                self._key.clone()
+            }
+        }
+    })
+}
+
+/// Implements `macon_cag::utils::CollectionName` with the name of the type. The name can be
+/// overridden with `#[collection(name = "...")]`, e.g. to keep the collection of a renamed type.
+#[proc_macro_derive(CollectionName, attributes(collection))]
+pub fn derive_collection_name(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match collection_name(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn collection_name(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let mut collection = None;
+
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("collection"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                let name = meta.value()?.parse::<LitStr>()?;
+                if name.value().is_empty() {
+                    return Err(syn::Error::new(name.span(), "The name can't be empty"));
+                }
+                collection = Some(name.value());
+                Ok(())
+            } else {
+                Err(meta.error("Unknown collection attribute, expected `name`"))
+            }
+        })?;
+    }
+
+    let name = &input.ident;
+    let collection = collection.unwrap_or_else(|| name.to_string());
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::macon_cag::utils::CollectionName for #name #ty_generics
+            #where_clause
+        {
+            const NAME: &'static str = #collection;
+        }
+    })
+}
+
+/// Value of `#[edge(key_strategy = "...")]`
+fn key_strategy(input: &DeriveInput) -> syn::Result<Option<String>> {
+    let mut strategy = None;
+
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("edge"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("key_strategy") {
+                strategy = Some(meta.value()?.parse::<LitStr>()?.value());
+                Ok(())
+            } else {
+                Err(meta.error("Unknown edge attribute, expected `key_strategy`"))
+            }
+        })?;
+    }
+
+    Ok(strategy)
+}
+
+fn is_string(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "String" && segment.arguments.is_empty()),
+        _ => false,
+    }
+}
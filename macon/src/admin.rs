@@ -0,0 +1,48 @@
+use std::io::Write;
+
+use anyhow::{Result, bail};
+use macon_cag::utils::{config::Config, establish_database_connection, reset_database};
+
+use crate::cli::{AdminCommands, ConfigArgs, ResetArgs};
+
+pub fn admin_main(admin_command: AdminCommands, config_args: ConfigArgs) -> Result<()> {
+    let config = config_args.load(Config::default())?;
+
+    match admin_command {
+        AdminCommands::Reset(reset_args) => reset(&config, &config_args, reset_args),
+    }
+}
+
+fn reset(config: &Config, config_args: &ConfigArgs, reset_args: ResetArgs) -> Result<()> {
+    let ResetArgs { yes_i_mean_it } = reset_args;
+
+    // the database to drop is never taken from the config file or the environment
+    let Some(database) = config_args.database.clone() else {
+        bail!("The database to drop has to be given with --database");
+    };
+
+    let confirmation = match yes_i_mean_it {
+        true => database.clone(),
+        false => {
+            print!("Type the name of the database to drop '{database}': ");
+            std::io::stdout().flush()?;
+
+            let mut line = String::new();
+            std::io::stdin().read_line(&mut line)?;
+            line.trim().to_string()
+        }
+    };
+
+    let conn = establish_database_connection(config)?;
+    let collections = reset_database(&conn, &database, &confirmation)?;
+
+    println!(
+        "Dropped database '{database}' with {} collections",
+        collections.len()
+    );
+    for collection in collections {
+        println!("  {collection}");
+    }
+
+    Ok(())
+}
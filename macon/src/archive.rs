@@ -0,0 +1,549 @@
+//! Samples in zip and tar.gz archives. A member is addressed by the path of the archive and the
+//! name of the member joined by [`MEMBER_SEPARATOR`], e.g. `corpus.zip::dir/sample.bat`. Members
+//! of nested archives are addressed by chaining the names, e.g.
+//! `corpus.zip::inner.tar.gz::sample.bat`. The members are read from the archives in memory,
+//! they are never written to disk.
+
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    fmt::Display,
+    io::{Cursor, Read},
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock, Mutex},
+};
+
+use anyhow::{Result, anyhow};
+use flate2::read::GzDecoder;
+use tracing::warn;
+use zip::{ZipArchive, result::ZipError};
+
+use crate::utils::{
+    FileTooLarge, NAME_MATCH_FALLBACK, SampleData, extract_from_zip, load_sample, match_zip_member,
+    max_file_size,
+};
+
+/// Separates the path of an archive and the name of a member
+pub const MEMBER_SEPARATOR: &str = "::";
+
+/// Maximum number of archives kept in [`ARCHIVES`]
+const MAX_CACHED_ARCHIVES: usize = 16;
+
+/// Maximum size of the archives kept in [`ARCHIVES`]. The last opened archive is kept even if it
+/// is larger.
+const MAX_CACHED_BYTES: usize = 1 << 30;
+
+/// Archives that were opened, so their members can be read without opening them again. tar.gz
+/// archives are kept decompressed, as their members can't be read without decompressing
+/// everything before them.
+static ARCHIVES: LazyLock<Mutex<ArchiveCache>> = LazyLock::new(Default::default);
+
+/// Opened archives, the least recently used ones are evicted once there are more than
+/// [`MAX_CACHED_ARCHIVES`] or they are larger than [`MAX_CACHED_BYTES`]. The members are read in
+/// the order of their paths, so the members of an archive are mostly read before the next one is
+/// opened. Readers of an evicted archive keep it until they are done.
+#[derive(Default)]
+struct ArchiveCache {
+    archives: HashMap<PathBuf, Arc<Archive>>,
+
+    /// Paths of the archives, least recently used first
+    order: VecDeque<PathBuf>,
+    bytes: usize,
+}
+
+impl ArchiveCache {
+    fn get(&mut self, path: &Path) -> Option<Arc<Archive>> {
+        let archive = self.archives.get(path)?.clone();
+        if let Some(position) = self.order.iter().position(|p| p == path) {
+            let path = self.order.remove(position)?;
+            self.order.push_back(path);
+        }
+        Some(archive)
+    }
+
+    fn insert(&mut self, path: PathBuf, archive: Arc<Archive>) {
+        if let Some(old) = self.archives.insert(path.clone(), archive.clone()) {
+            self.bytes -= old.size();
+            self.order.retain(|p| *p != path);
+        }
+        self.bytes += archive.size();
+        self.order.push_back(path);
+
+        while self.order.len() > MAX_CACHED_ARCHIVES
+            || (self.bytes > MAX_CACHED_BYTES && self.order.len() > 1)
+        {
+            let Some(evicted) = self.order.pop_front() else {
+                break;
+            };
+            if let Some(archive) = self.archives.remove(&evicted) {
+                self.bytes -= archive.size();
+            }
+        }
+    }
+}
+
+/// Content of an archive that is shared between the readers of its members
+#[derive(Clone)]
+struct SharedData(Arc<SampleData>);
+
+impl AsRef<[u8]> for SharedData {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+enum Archive {
+    Zip(ZipArchive<Cursor<SharedData>>),
+    Tar {
+        data: Vec<u8>,
+        members: BTreeMap<String, Range<usize>>,
+    },
+}
+
+/// Error for members of zip archives that need a password. The member should be skipped with a
+/// warning instead of being reported as failed.
+#[derive(Debug)]
+pub struct EncryptedMember {
+    pub path: PathBuf,
+}
+
+impl Display for EncryptedMember {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Skipping {}, it is encrypted", self.path.display())
+    }
+}
+
+impl std::error::Error for EncryptedMember {}
+
+/// Replaces the zip and tar.gz archives in `files` with their members. Archives in the archives
+/// are replaced with their members up to `depth` levels deep, deeper ones are kept as samples.
+/// APKs are zip archives as well, but they are kept as samples.
+pub fn expand_archives(files: Vec<PathBuf>, depth: usize) -> Result<Vec<PathBuf>> {
+    let mut expanded = vec![];
+
+    for file in files {
+        if !has_archive_magic(&file)? {
+            expanded.push(file);
+            continue;
+        }
+
+        let data = load_sample(&file)?;
+        expand(file, data, depth, &mut expanded)?;
+    }
+
+    Ok(expanded)
+}
+
+fn expand(
+    path: PathBuf,
+    data: SampleData,
+    depth: usize,
+    expanded: &mut Vec<PathBuf>,
+) -> Result<()> {
+    let archive = match open_archive(&path, data) {
+        Ok(Some(archive)) => archive,
+        Ok(None) => {
+            expanded.push(path);
+            return Ok(());
+        }
+        // the members of a tar.gz archive that inflates beyond the limit are skipped
+        Err(e) => {
+            warn!("{e}");
+            return Ok(());
+        }
+    };
+
+    for name in archive.member_names() {
+        let member = member_path(&path, &name);
+
+        // members that can't be read are kept, so they are reported with the sample
+        match archive.read(&member, &name) {
+            Ok(data) if depth > 0 => expand(member, data, depth - 1, expanded)?,
+            _ => expanded.push(member),
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether the file at `path` starts like a zip or gzip file
+fn has_archive_magic(path: &Path) -> Result<bool> {
+    let mut magic = [0; 4];
+    let mut file = std::fs::File::open(path)?;
+    let read = file.read(&mut magic)?;
+
+    Ok(read == magic.len() && (magic == *b"PK\x03\x04" || magic[..2] == [0x1f, 0x8b]))
+}
+
+/// Whether `path` is the member of an archive. Files with [`MEMBER_SEPARATOR`] in their name are
+/// samples as long as they exist.
+pub fn is_member(path: &Path) -> bool {
+    path.to_string_lossy().contains(MEMBER_SEPARATOR) && !path.exists()
+}
+
+/// Reads the member of an archive at `path`, see [`is_member`]. Fails with [`FileTooLarge`] for
+/// members above the maximum file size and with [`EncryptedMember`] for members that need a
+/// password.
+pub fn load_member(path: &Path) -> Result<SampleData> {
+    let (archive_path, name) = path
+        .to_str()
+        .and_then(|p| p.rsplit_once(MEMBER_SEPARATOR))
+        .ok_or(anyhow!("{} is not a member of an archive", path.display()))?;
+    let archive_path = PathBuf::from(archive_path);
+
+    let cached = ARCHIVES.lock().unwrap().get(&archive_path);
+    let archive = match cached {
+        Some(archive) => archive,
+        None => {
+            let data = load_sample(&archive_path)?;
+            open_archive(&archive_path, data)?.ok_or(anyhow!(
+                "{} is not a zip or tar.gz archive",
+                archive_path.display()
+            ))?
+        }
+    };
+
+    archive.read(path, name)
+}
+
+/// Opens the archive in `data` and caches it by `path`. `None` if `data` is no zip or tar.gz
+/// archive or an APK. Fails with [`FileTooLarge`] for tar.gz archives that decompress to more
+/// than the maximum file size, they are never decompressed further.
+fn open_archive(path: &Path, data: SampleData) -> Result<Option<Arc<Archive>>> {
+    let archive = if data.starts_with(b"PK\x03\x04") {
+        let Ok(zip) = ZipArchive::new(Cursor::new(SharedData(Arc::new(data)))) else {
+            return Ok(None);
+        };
+        if zip.index_for_name("AndroidManifest.xml").is_some() {
+            return Ok(None);
+        }
+        Archive::Zip(zip)
+    } else if data.starts_with(&[0x1f, 0x8b]) {
+        let Some(tar) = gunzip(path, &data, max_file_size())? else {
+            return Ok(None);
+        };
+
+        // a gzip compressed file that is not a tar archive
+        if tar.get(257..262) != Some(b"ustar") {
+            return Ok(None);
+        }
+        let Ok(archive) = tar_archive(tar) else {
+            return Ok(None);
+        };
+        archive
+    } else {
+        return Ok(None);
+    };
+
+    let archive = Arc::new(archive);
+    ARCHIVES
+        .lock()
+        .unwrap()
+        .insert(path.to_path_buf(), archive.clone());
+    Ok(Some(archive))
+}
+
+/// Decompresses the gzip file in `data`, at most `max_file_size` bytes of it. `None` if `data`
+/// is no valid gzip file, [`FileTooLarge`] if it decompresses to more.
+fn gunzip(path: &Path, data: &[u8], max_file_size: u64) -> Result<Option<Vec<u8>>> {
+    let mut decompressed = vec![];
+    let mut decoder = GzDecoder::new(data).take(max_file_size.saturating_add(1));
+    if decoder.read_to_end(&mut decompressed).is_err() {
+        return Ok(None);
+    }
+
+    if decompressed.len() as u64 > max_file_size {
+        return Err(FileTooLarge {
+            path: path.to_path_buf(),
+            size: decompressed.len() as u64,
+            max_file_size,
+        }
+        .into());
+    }
+    Ok(Some(decompressed))
+}
+
+fn tar_archive(data: Vec<u8>) -> Result<Archive> {
+    let mut members = BTreeMap::new();
+
+    let mut archive = tar::Archive::new(Cursor::new(&data[..]));
+    for entry in archive.entries()? {
+        let entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+
+        let start = entry.raw_file_position() as usize;
+        let end = start + entry.size() as usize;
+        members.insert(entry.path()?.to_string_lossy().to_string(), start..end);
+    }
+
+    Ok(Archive::Tar { data, members })
+}
+
+fn member_path(archive_path: &Path, name: &str) -> PathBuf {
+    let mut path = archive_path.as_os_str().to_os_string();
+    path.push(MEMBER_SEPARATOR);
+    path.push(name);
+    PathBuf::from(path)
+}
+
+impl Archive {
+    /// Size of the data of the archive in memory
+    fn size(&self) -> usize {
+        match self {
+            Archive::Zip(zip) => zip.clone().into_inner().get_ref().0.len(),
+            Archive::Tar { data, .. } => data.len(),
+        }
+    }
+
+    /// Names of the files in the archive, sorted by name
+    fn member_names(&self) -> Vec<String> {
+        match self {
+            Archive::Zip(zip) => {
+                let mut names: Vec<String> = zip
+                    .file_names()
+                    .filter(|name| !name.ends_with('/'))
+                    .map(str::to_string)
+                    .collect();
+                names.sort();
+                names
+            }
+            Archive::Tar { members, .. } => members.keys().cloned().collect(),
+        }
+    }
+
+    /// Reads the member `name`, `path` is only used for the errors. Members of zip archives are
+    /// matched leniently, see [`NAME_MATCH_FALLBACK`].
+    fn read(&self, path: &Path, name: &str) -> Result<SampleData> {
+        let too_large = |size: u64| FileTooLarge {
+            path: path.to_path_buf(),
+            size,
+            max_file_size: max_file_size(),
+        };
+
+        match self {
+            Archive::Zip(zip) => {
+                let name = &match_zip_member(zip, name, &NAME_MATCH_FALLBACK)?;
+                let mut zip = zip.clone();
+                let result = zip.by_name(name).map(|mut member| -> Result<Vec<u8>> {
+                    if member.size() > max_file_size() {
+                        return Err(too_large(member.size()).into());
+                    }
+                    let mut data = Vec::with_capacity(member.size() as usize);
+                    member.read_to_end(&mut data)?;
+                    Ok(data)
+                });
+
+                match result {
+                    Ok(data) => Ok(SampleData::Read(data?)),
+                    // droppers often set the encryption bit without encrypting the member
+                    Err(ZipError::UnsupportedArchive(ZipError::PASSWORD_REQUIRED)) => {
+                        let shared = zip.into_inner().into_inner();
+                        let mut zip = ZipArchive::new(Cursor::new(shared.as_ref()))?;
+                        extract_from_zip(&mut zip, name, true)
+                            .map(SampleData::Read)
+                            .map_err(|_| {
+                                EncryptedMember {
+                                    path: path.to_path_buf(),
+                                }
+                                .into()
+                            })
+                    }
+                    Err(e) => Err(e.into()),
+                }
+            }
+            Archive::Tar { data, members } => {
+                let range = members
+                    .get(name)
+                    .ok_or(anyhow!("{} doesn't exist", path.display()))?;
+                if range.len() as u64 > max_file_size() {
+                    return Err(too_large(range.len() as u64).into());
+                }
+
+                Ok(SampleData::Read(data[range.clone()].to_vec()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{Compression, write::GzEncoder};
+    use zip::{ZipWriter, write::SimpleFileOptions};
+
+    use super::*;
+
+    fn zip_archive(members: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        for (name, data) in members {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    fn tar_gz_archive(members: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut tar = tar::Builder::new(GzEncoder::new(vec![], Compression::default()));
+        for (name, data) in members {
+            let mut header = tar::Header::new_ustar();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            tar.append_data(&mut header, name, *data).unwrap();
+        }
+        tar.into_inner().unwrap().finish().unwrap()
+    }
+
+    fn tar(size: usize) -> Arc<Archive> {
+        Arc::new(Archive::Tar {
+            data: vec![0; size],
+            members: BTreeMap::new(),
+        })
+    }
+
+    #[test]
+    fn expand_and_load_members() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let inner = tar_gz_archive(&[("b.ps1", b"inner")]);
+        let outer = dir.path().join("outer.zip");
+        std::fs::write(
+            &outer,
+            zip_archive(&[("a.bat", b"outer"), ("inner.tar.gz", &inner)]),
+        )?;
+        let plain = dir.path().join("plain.bat");
+        std::fs::write(&plain, b"plain")?;
+
+        let files = expand_archives(vec![outer.clone(), plain.clone()], 1)?;
+        let a = member_path(&outer, "a.bat");
+        let b = member_path(&member_path(&outer, "inner.tar.gz"), "b.ps1");
+        assert_eq!(files, [a.clone(), b.clone(), plain.clone()]);
+
+        assert!(is_member(&a));
+        assert!(!is_member(&plain));
+        assert_eq!(&*load_member(&a)?, b"outer");
+        assert_eq!(&*load_member(&b)?, b"inner");
+
+        // without recursion the nested archive is kept as a sample
+        let files = expand_archives(vec![outer.clone()], 0)?;
+        assert_eq!(files, [a, member_path(&outer, "inner.tar.gz")]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn nesting_depth() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let innermost = zip_archive(&[("c.js", b"innermost")]);
+        let inner = tar_gz_archive(&[("b.ps1", b"inner"), ("innermost.zip", &innermost)]);
+        let outer = dir.path().join("nested.zip");
+        std::fs::write(
+            &outer,
+            zip_archive(&[("a.bat", b"outer"), ("inner.tar.gz", &inner)]),
+        )?;
+
+        let a = member_path(&outer, "a.bat");
+        let inner = member_path(&outer, "inner.tar.gz");
+        let b = member_path(&inner, "b.ps1");
+        let innermost = member_path(&inner, "innermost.zip");
+        let c = member_path(&innermost, "c.js");
+
+        // the tar.gz archive in the zip archive is one level deeper, the zip archive in it two
+        assert_eq!(
+            expand_archives(vec![outer.clone()], 0)?,
+            [a.clone(), inner.clone()]
+        );
+        assert_eq!(
+            expand_archives(vec![outer.clone()], 1)?,
+            [a.clone(), b.clone(), innermost.clone()]
+        );
+        assert_eq!(
+            expand_archives(vec![outer.clone()], 2)?,
+            [a.clone(), b.clone(), c.clone()]
+        );
+        assert_eq!(expand_archives(vec![outer], 3)?, [a, b, c.clone()]);
+
+        assert_eq!(&*load_member(&c)?, b"innermost");
+        assert!(load_member(&innermost)?.starts_with(b"PK\x03\x04"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn gzip_bombs_are_not_decompressed() -> Result<()> {
+        let path = Path::new("bomb.tar.gz");
+        let bomb = tar_gz_archive(&[("zeros", &vec![0; 1 << 20])]);
+        assert!(bomb.len() < 1 << 12);
+
+        let e = gunzip(path, &bomb, 1 << 16).unwrap_err();
+        let e = e.downcast::<FileTooLarge>()?;
+        assert_eq!((e.size, e.max_file_size), ((1 << 16) + 1, 1 << 16));
+
+        // the limit is the size of the decompressed tar archive
+        let tar = gunzip(path, &bomb, 1 << 21)?.unwrap();
+        assert!(gunzip(path, &bomb, tar.len() as u64)?.is_some());
+        assert!(gunzip(path, &bomb, tar.len() as u64 - 1).is_err());
+
+        assert!(gunzip(path, b"\x1f\x8b not gzip", 1 << 16)?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn evicted_archives_are_opened_again() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut members = vec![];
+        for i in 0..MAX_CACHED_ARCHIVES + 2 {
+            let path = dir.path().join(format!("{i}.tar.gz"));
+            std::fs::write(
+                &path,
+                tar_gz_archive(&[("sample", i.to_string().as_bytes())]),
+            )?;
+            members.extend(expand_archives(vec![path], 0)?);
+        }
+
+        for (i, member) in members.iter().enumerate() {
+            assert_eq!(*load_member(member)?, *i.to_string().as_bytes());
+        }
+        assert!(ARCHIVES.lock().unwrap().order.len() <= MAX_CACHED_ARCHIVES);
+
+        // the first archive was evicted
+        assert_eq!(&*load_member(&members[0])?, b"0");
+
+        Ok(())
+    }
+
+    #[test]
+    fn cache_evicts_least_recently_used() {
+        let mut cache = ArchiveCache::default();
+        for i in 0..MAX_CACHED_ARCHIVES {
+            cache.insert(PathBuf::from(i.to_string()), tar(1));
+        }
+
+        // using the first archive keeps it in the cache
+        assert!(cache.get(Path::new("0")).is_some());
+        cache.insert(PathBuf::from("new"), tar(1));
+
+        assert!(cache.get(Path::new("0")).is_some());
+        assert!(cache.get(Path::new("1")).is_none());
+        assert_eq!(cache.archives.len(), MAX_CACHED_ARCHIVES);
+        assert_eq!(cache.bytes, MAX_CACHED_ARCHIVES);
+    }
+
+    #[test]
+    fn cache_is_bounded_by_size() {
+        let mut cache = ArchiveCache::default();
+        cache.insert(PathBuf::from("a"), tar(MAX_CACHED_BYTES / 2));
+        cache.insert(PathBuf::from("b"), tar(MAX_CACHED_BYTES / 2));
+        assert_eq!(cache.archives.len(), 2);
+
+        cache.insert(PathBuf::from("c"), tar(1));
+        assert!(cache.get(Path::new("a")).is_none());
+        assert_eq!(cache.bytes, MAX_CACHED_BYTES / 2 + 1);
+
+        // an archive above the limit is kept as long as it is the last one
+        cache.insert(PathBuf::from("d"), tar(MAX_CACHED_BYTES + 1));
+        assert_eq!(cache.order, [PathBuf::from("d")]);
+    }
+}
@@ -0,0 +1,432 @@
+use std::{borrow::Cow, fmt::Display, path::PathBuf};
+
+use anyhow::Result;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use tracing::{debug, warn};
+
+use crate::{
+    cli::{ClassifyArgs, MainArgs},
+    detection::{Detection, PLAUSIBLE_SCORE},
+    graph_creators::focused_graph::{carnavalheist, coper, dark_watchmen, mintsloader},
+    utils::load_sample,
+};
+
+/// Malware family of the `focused` subcommand
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MalwareFamily {
+    Carnavalheist,
+    Coper,
+    DarkWatchmen,
+    Mintsloader,
+}
+
+impl MalwareFamily {
+    /// Name of the family as used by the `focused` subcommand
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Carnavalheist => "carnavalheist",
+            Self::Coper => "coper",
+            Self::DarkWatchmen => "dark-watchmen",
+            Self::Mintsloader => "mintsloader",
+        }
+    }
+
+    /// Family named by [`Self::as_str`], `_` may be used instead of `-` and case is ignored
+    #[cfg_attr(not(feature = "yara"), allow(dead_code))]
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.to_lowercase().replace('_', "-");
+        [
+            Self::Carnavalheist,
+            Self::Coper,
+            Self::DarkWatchmen,
+            Self::Mintsloader,
+        ]
+        .into_iter()
+        .find(|family| family.as_str() == name)
+    }
+}
+
+/// Heuristic of a sample type detector that matched a sample
+#[derive(Debug, Clone)]
+pub struct Evidence {
+    /// Name of the heuristic, e.g. `dex_header` or `powershell_hidden_encoded`, or of the YARA
+    /// rule as `yara:<namespace>:<rule>`
+    pub rule: Cow<'static, str>,
+
+    /// Confidence of the heuristic between 0 and 1
+    pub score: f32,
+}
+
+impl Display for Evidence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({:.2})", self.rule, self.score)
+    }
+}
+
+/// Family, score and the heuristics the score was computed from
+pub type RankedFamily = (MalwareFamily, f32, Vec<Evidence>);
+
+/// Families whose sample type detectors matched a sample, see [`rank_families`]
+#[derive(Debug)]
+pub struct Classification {
+    /// Sorted by score (highest first), families with equal scores keep the order of
+    /// [`MalwareFamily`]
+    pub candidates: Vec<RankedFamily>,
+}
+
+impl Classification {
+    /// Describes the plausible families if there is more than one of them
+    pub fn ambiguity_report(&self, sample_filename: &str) -> Option<String> {
+        let plausible: Vec<String> = self
+            .candidates
+            .iter()
+            .filter(|(_, score, _)| *score >= PLAUSIBLE_SCORE)
+            .map(|(family, score, _)| format!("{} ({score:.2})", family.as_str()))
+            .collect();
+
+        (plausible.len() > 1).then(|| {
+            format!(
+                "Ambiguous malware family of {sample_filename}: {}",
+                plausible.join(", ")
+            )
+        })
+    }
+
+    /// The family with the highest score if the score is plausible
+    pub fn into_top(self) -> Result<RankedFamily, Unclassified> {
+        match self.candidates.first() {
+            Some((_, score, _)) if *score >= PLAUSIBLE_SCORE => {
+                Ok(self.candidates.into_iter().next().unwrap())
+            }
+            _ => Err(Unclassified {
+                evidence: self
+                    .candidates
+                    .into_iter()
+                    .flat_map(|(family, _, evidence)| {
+                        evidence.into_iter().map(move |e| (family, e))
+                    })
+                    .collect(),
+            }),
+        }
+    }
+}
+
+/// None of the families matched a sample with a plausible score
+#[derive(Debug)]
+pub struct Unclassified {
+    /// Heuristics that matched, by family
+    pub evidence: Vec<(MalwareFamily, Evidence)>,
+}
+
+impl Display for Unclassified {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.evidence.is_empty() {
+            true => write!(f, "No malware family matched"),
+            false => {
+                let evidence: Vec<String> = self
+                    .evidence
+                    .iter()
+                    .map(|(family, e)| format!("{}: {e}", family.as_str()))
+                    .collect();
+                write!(
+                    f,
+                    "No malware family matched, considered {}",
+                    evidence.join(", ")
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for Unclassified {}
+
+/// Ranks the families by the heuristics of their sample type detectors that match the sample.
+/// Every matching heuristic raises the score of its family: the score is the probability that
+/// at least one of them is right, so several weak heuristics of a family outweigh a single one of
+/// another family. With `--yara-rules` the matching rules are evidence of their families as well.
+pub fn rank_families(sample_data: &[u8]) -> Classification {
+    fn evidence<T>(detection: Detection<T>) -> Vec<Evidence> {
+        detection
+            .candidates()
+            .iter()
+            .map(|c| Evidence {
+                rule: Cow::Borrowed(c.rule),
+                score: c.score,
+            })
+            .collect()
+    }
+
+    let yara = yara_evidence(sample_data);
+
+    let mut candidates: Vec<RankedFamily> = [
+        (
+            MalwareFamily::Carnavalheist,
+            evidence(carnavalheist::detect_sample_type(sample_data)),
+        ),
+        (
+            MalwareFamily::Coper,
+            evidence(coper::detect_sample_type(sample_data)),
+        ),
+        (
+            MalwareFamily::DarkWatchmen,
+            evidence(dark_watchmen::detect_sample_type(sample_data)),
+        ),
+        (
+            MalwareFamily::Mintsloader,
+            evidence(mintsloader::detect_sample_type(sample_data)),
+        ),
+    ]
+    .into_iter()
+    .map(|(family, mut evidence)| {
+        evidence.splice(
+            0..0,
+            yara.iter()
+                .filter(|(f, _)| *f == family)
+                .map(|(_, e)| e.clone()),
+        );
+        (family, evidence)
+    })
+    .filter(|(_, evidence)| !evidence.is_empty())
+    .map(|(family, evidence)| {
+        let miss: f32 = evidence
+            .iter()
+            .map(|e| 1.0 - e.score.clamp(0.0, 1.0))
+            .product();
+        (family, 1.0 - miss, evidence)
+    })
+    .collect();
+
+    // stable, so families with equal scores keep their order
+    candidates.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    Classification { candidates }
+}
+
+/// Matches of the rules of `--yara-rules` by family. A rule is certain, so it outweighs the
+/// heuristics. Rules without a known family are ignored.
+#[cfg(feature = "yara")]
+fn yara_evidence(sample_data: &[u8]) -> Vec<(MalwareFamily, Evidence)> {
+    let Some(rules) = crate::yara::rules() else {
+        return vec![];
+    };
+
+    match rules.scan(sample_data) {
+        Ok(matches) => matches
+            .into_iter()
+            .filter_map(|m| {
+                let family = m.family()?;
+                let rule = format!("yara:{}:{}", m.namespace, m.rule);
+                Some((
+                    family,
+                    Evidence {
+                        rule: Cow::Owned(rule),
+                        score: 1.0,
+                    },
+                ))
+            })
+            .collect(),
+        Err(e) => {
+            warn!("Scanning with the YARA rules failed, using the heuristics only: {e}");
+            vec![]
+        }
+    }
+}
+
+#[cfg(not(feature = "yara"))]
+fn yara_evidence(_sample_data: &[u8]) -> Vec<(MalwareFamily, Evidence)> {
+    vec![]
+}
+
+/// Classifies a sample by the sample type detectors of the families. The family with the highest
+/// score wins, but only if the score is plausible: weak heuristics like the JS fallback of
+/// DarkWatchmen match almost every sample.
+pub fn classify_sample(
+    sample_filename: &str,
+    sample_data: &[u8],
+) -> Result<RankedFamily, Unclassified> {
+    let classification = rank_families(sample_data);
+    if let Some(report) = classification.ambiguity_report(sample_filename) {
+        warn!("{report}");
+    }
+
+    classification.into_top()
+}
+
+/// Prints `path<TAB>family` for every sample, `unknown` if no family matched. With `--ranking`
+/// every family that matched follows as `<TAB>family<TAB>score<TAB>evidence`.
+pub fn classify_main(classify_args: ClassifyArgs) -> Result<()> {
+    let ClassifyArgs { main_args, ranking } = classify_args;
+    let MainArgs { files, .. } = main_args;
+
+    let results: Vec<(&PathBuf, Result<Classification>)> = files
+        .par_iter()
+        .map(|entry| (entry, load_sample(entry).map(|data| rank_families(&data))))
+        .collect();
+
+    for (entry, result) in results {
+        let classification = match result {
+            Ok(classification) => classification,
+            Err(e) => {
+                warn!(sample = %entry.display(), "{e}");
+                continue;
+            }
+        };
+
+        if let Some(report) = classification.ambiguity_report(&entry.display().to_string()) {
+            warn!("{report}");
+        }
+        let ranked = ranking.then(|| classification.candidates.clone());
+
+        match classification.into_top() {
+            Ok((family, _, _)) => println!("{}\t{}", entry.display(), family.as_str()),
+            Err(unclassified) => {
+                debug!(sample = %entry.display(), "{unclassified}");
+                println!("{}\tunknown", entry.display());
+            }
+        }
+
+        for (family, score, evidence) in ranked.iter().flatten() {
+            let evidence: Vec<String> = evidence.iter().map(Evidence::to_string).collect();
+            println!("\t{}\t{score:.2}\t{}", family.as_str(), evidence.join(", "));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+
+    fn rules(evidence: &[Evidence]) -> Vec<&str> {
+        evidence.iter().map(|e| &*e.rule).collect()
+    }
+
+    #[test]
+    fn one_sample_per_family() -> Result<()> {
+        let mintsloader = std::fs::read(format!(
+            "{FIXTURES}/mintsloader/start_process_encodedcommand.ps1"
+        ))?;
+        let samples: [(&[u8], MalwareFamily, &str); 4] = [
+            (
+                b"@echo off\r\npowershell -WindowStyle Hidden -Command \"iex $s\"\r\n",
+                MalwareFamily::Carnavalheist,
+                "powershell_hidden_command",
+            ),
+            (
+                b"PK\x03\x04\x14\x00\x00\x00",
+                MalwareFamily::Coper,
+                "zip_local_file_header",
+            ),
+            (
+                b"MZ\x90\x00\x03\x00\x00\x00",
+                MalwareFamily::DarkWatchmen,
+                "mz_magic",
+            ),
+            (
+                &mintsloader,
+                MalwareFamily::Mintsloader,
+                "start_process_powershell",
+            ),
+        ];
+
+        for (data, expected, rule) in samples {
+            let classification = rank_families(data);
+            assert!(classification.ambiguity_report("sample").is_none());
+
+            let (family, score, evidence) = classify_sample("sample", data)?;
+            assert_eq!(family, expected);
+            assert!(score >= PLAUSIBLE_SCORE);
+            assert_eq!(rules(&evidence), [rule]);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn ambiguous_sample() -> Result<()> {
+        // a batch file of Carnavalheist that starts PowerShell like Mintsloader does
+        let data = b"start-process powershell -WindowStyle Hidden -e JABhAD0A";
+        let classification = rank_families(data);
+
+        let families: Vec<String> = classification
+            .candidates
+            .iter()
+            .map(|(family, score, _)| format!("{} {score:.2}", family.as_str()))
+            .collect();
+        assert_eq!(
+            families,
+            [
+                "carnavalheist 0.90",
+                "mintsloader 0.80",
+                "dark-watchmen 0.10"
+            ]
+        );
+        assert_eq!(
+            classification.ambiguity_report("a.bat").as_deref(),
+            Some("Ambiguous malware family of a.bat: carnavalheist (0.90), mintsloader (0.80)")
+        );
+
+        let (family, _, _) = classification.into_top()?;
+        assert_eq!(family, MalwareFamily::Carnavalheist);
+
+        Ok(())
+    }
+
+    #[test]
+    fn heuristics_of_a_family_add_up() {
+        let data = b"powershell -WindowStyle Hidden -e JABhAD0A\nRANDOMIZADO = 1\n";
+        let classification = rank_families(data);
+
+        let (family, score, evidence) = &classification.candidates[0];
+        assert_eq!(*family, MalwareFamily::Carnavalheist);
+        assert!((score - 0.99).abs() < 1e-6, "{score}");
+        assert_eq!(
+            rules(evidence),
+            ["powershell_hidden_encoded", "randomizado_marker"]
+        );
+    }
+
+    #[test]
+    fn weak_heuristics_are_unclassified() {
+        let unclassified = classify_sample("a.txt", b"echo hello world").unwrap_err();
+
+        let evidence: Vec<(MalwareFamily, &str)> = unclassified
+            .evidence
+            .iter()
+            .map(|(family, e)| (*family, &*e.rule))
+            .collect();
+        assert_eq!(
+            evidence,
+            [
+                (MalwareFamily::Mintsloader, "few_lines"),
+                (MalwareFamily::DarkWatchmen, "fallback"),
+            ]
+        );
+        assert_eq!(
+            unclassified.to_string(),
+            "No malware family matched, considered mintsloader: few_lines (0.30), dark-watchmen: fallback (0.10)"
+        );
+
+        assert!(classify_sample("a.bin", b"").is_err());
+    }
+
+    #[test]
+    fn family_names() {
+        for family in [
+            MalwareFamily::Carnavalheist,
+            MalwareFamily::Coper,
+            MalwareFamily::DarkWatchmen,
+            MalwareFamily::Mintsloader,
+        ] {
+            assert_eq!(MalwareFamily::from_name(family.as_str()), Some(family));
+        }
+        assert_eq!(
+            MalwareFamily::from_name("Dark_Watchmen"),
+            Some(MalwareFamily::DarkWatchmen)
+        );
+        assert_eq!(MalwareFamily::from_name("emotet"), None);
+    }
+}
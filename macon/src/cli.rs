@@ -1,24 +1,356 @@
 use std::path::PathBuf;
 
-use clap::{Args, Parser, Subcommand};
+use anyhow::anyhow;
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
+use macon_cag::utils::{config::Config, set_collection_prefix};
+use rand::{SeedableRng, rngs::StdRng};
+use tracing::info;
+
+use crate::{
+    archive::expand_archives,
+    graph_creators::focused_graph::maintenance::{MaintenanceSchedule, parse_maintenance},
+    output::{OutputTarget, parse_output},
+    prefilter::DEFAULT_DENIED_EXTENSIONS,
+    utils::{collect_files, parse_size},
+};
 
 #[derive(Parser, Debug)]
 #[command(name = "macon", version, about = "Malware Corpus Normalization")]
 pub struct Cli {
     #[command(subcommand)]
     pub command: MainCommands,
+
+    #[command(flatten)]
+    pub config: ConfigArgs,
+
+    #[arg(
+        long,
+        global = true,
+        value_parser = clap::value_parser!(u16).range(1..),
+        help = "Number of threads the samples are processed with",
+        long_help = "Number of threads the samples are processed with. Defaults to the number of CPUs, 1 processes the samples one after another. The number of concurrent database requests is limited independently by max_concurrent_requests of the config"
+    )]
+    pub threads: Option<u16>,
+
+    #[arg(
+        short,
+        long,
+        global = true,
+        action = ArgAction::Count,
+        help = "Log more, -v for debug and -vv for trace messages",
+        long_help = "Log more, -v for debug and -vv for trace messages. The RUST_LOG environment variable takes precedence, e.g. RUST_LOG=macon_cag=debug"
+    )]
+    pub verbose: u8,
+
+    #[arg(
+        short,
+        long,
+        global = true,
+        conflicts_with = "verbose",
+        help = "Only log errors and hide the progress bars"
+    )]
+    pub quiet: bool,
+
+    #[arg(long, global = true, help = "Log as JSON lines")]
+    pub log_json: bool,
+
+    #[cfg(feature = "yara")]
+    #[arg(
+        long,
+        global = true,
+        value_name = "DIR",
+        help = "Classify the samples with the YARA rules in DIR before the heuristics",
+        long_help = "Classify the samples with the .yar and .yara files in DIR before the heuristics. The family of a rule is its `family` metadata or the name of its file, e.g. rules/coper.yar. Files that don't compile are reported and skipped"
+    )]
+    pub yara_rules: Option<PathBuf>,
+}
+
+/// Settings of the database connection. The values are taken from, in order of precedence:
+///
+/// 1. the `--db-*`, `--database` and `--graph` options
+/// 2. the `MACON_ARANGO_*` environment variables
+/// 3. the config file given with `--config`
+/// 4. the defaults of the command, e.g. the database of the corpus
+#[derive(Args, Debug, Default)]
+#[command(next_help_heading = "Database")]
+pub struct ConfigArgs {
+    #[arg(
+        long,
+        global = true,
+        value_parser = validate_file,
+        help = "Path to a TOML config file",
+        long_help = "Path to a TOML config file with the settings of the ArangoDB (url, user, password, auth = basic|jwt|none, database, graph, ...). Omitted keys keep their default value. The MACON_ARANGO_* environment variables take precedence over the file, the --db-*, --database and --graph options take precedence over both"
+    )]
+    pub config: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "URL of the ArangoDB, e.g. http://127.0.0.1:8529"
+    )]
+    pub db_url: Option<String>,
+
+    #[arg(long, global = true, help = "User of the ArangoDB")]
+    pub db_user: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        conflicts_with = "db_password_file",
+        help = "Password of the ArangoDB user",
+        long_help = "Password of the ArangoDB user. Prefer --db-password-file or the MACON_ARANGO_PASSWORD environment variable, so the password doesn't end up in the shell history"
+    )]
+    pub db_password: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        value_parser = validate_file,
+        help = "Path to a file with the password of the ArangoDB user",
+        long_help = "Path to a file with the password of the ArangoDB user. A trailing newline is ignored"
+    )]
+    pub db_password_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Name of the database",
+        long_help = "Name of the database. Overrides the database of the corpus, for commands that use both corpora it applies to both"
+    )]
+    pub database: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Name of the graph",
+        long_help = "Name of the graph. Overrides the graph of the corpus, for commands that use both corpora it applies to both"
+    )]
+    pub graph: Option<String>,
+}
+
+impl ConfigArgs {
+    /// Loads the config from `defaults`, overridden by the config file, the environment and the
+    /// options, see [`ConfigArgs`]. Sets the collection prefix of the config, so all collection
+    /// names that are resolved afterwards have it, e.g. the ones of the edge definitions.
+    pub fn load(&self, defaults: Config) -> anyhow::Result<Config> {
+        let mut config = Config::layered_from(defaults, self.config.as_deref())?;
+
+        let password = match &self.db_password_file {
+            Some(path) => Some(
+                std::fs::read_to_string(path)
+                    .map_err(|e| anyhow!("Reading password file '{}' failed: {e}", path.display()))?
+                    .trim_end_matches(['\r', '\n'])
+                    .to_string(),
+            ),
+            None => self.db_password.clone(),
+        };
+
+        let overrides = [
+            (&self.db_url, &mut config.url),
+            (&self.db_user, &mut config.user),
+            (&password, &mut config.password),
+            (&self.database, &mut config.database),
+            (&self.graph, &mut config.graph),
+        ];
+        for (value, field) in overrides {
+            if let Some(value) = value {
+                *field = value.clone();
+            }
+        }
+
+        set_collection_prefix(config.collection_prefix.clone());
+
+        Ok(config)
+    }
 }
 
 #[derive(Subcommand, Debug)]
 pub enum MainCommands {
+    #[command(about = "Analyze malware samples where the family is already known")]
+    Focused(FocusedArgs),
+
+    #[command(
+        about = "Analyze malware samples where the family is *not* known",
+        long_about = "Analyze malware samples where the family is *not* known. The samples are clustered by the distances of their similarity hashes and the clusterings are evaluated against the malware families, which are taken from the names of the directories the samples are in, e.g. samples/coper/a.apk belongs to the family coper. The evaluation of each parameter combination is written to <ALGORITHM>_<DISTANCE>.csv in the output directory"
+    )]
+    General(GeneralArgs),
+
+    #[command(about = "Search the stored samples of both corpora for samples similar to a sample")]
+    Similar(SimilarArgs),
+
+    #[command(
+        about = "Print the malware family of samples",
+        long_about = "Print the malware family of samples as <PATH><TAB><FAMILY>, or unknown if the sample type detectors of no family match. The database is not used"
+    )]
+    Classify(ClassifyArgs),
+
+    #[command(about = "Export the graph of a corpus for Gephi or Graphviz")]
+    Export(ExportArgs),
+
     #[command(
-        subcommand,
-        about = "Analyze malware samples where the family is already known"
+        about = "Print the number of documents in a corpus",
+        long_about = "Print the number of documents in each collection of the graph of a corpus, the number of edges and the number of documents reachable from the main node of each family"
     )]
-    Focused(FocusedFamilies),
+    Stats(StatsArgs),
 
-    #[command(about = "Analyze malware samples where the family is *not* known")]
-    General(MainArgs),
+    #[command(
+        about = "Delete a family and the documents only it refers to",
+        long_about = "Delete the main node of a family of the focused corpus and all documents that are only reachable through it, together with their edges. Documents that are also reachable from other families, e.g. shared certificates, are kept"
+    )]
+    Prune(PruneArgs),
+
+    #[command(subcommand, about = "Manage the databases of the corpora")]
+    Admin(AdminCommands),
+}
+
+#[derive(Subcommand, Debug)]
+pub enum AdminCommands {
+    #[command(
+        about = "Drop the database given with --database with all its graphs and collections",
+        long_about = "Drop the database given with --database with all its graphs and collections. The graph creators recreate the database, collections, indexes and graph on their next run"
+    )]
+    Reset(ResetArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct ResetArgs {
+    #[arg(
+        long,
+        help = "Don't ask for confirmation",
+        long_help = "Don't ask for confirmation. Without it, the name of the database has to be typed in to confirm"
+    )]
+    pub yes_i_mean_it: bool,
+}
+
+/// Name of the corpus node the families or samples are linked to. Several corpora can share a
+/// database, the families are shared between them.
+#[derive(Args, Debug)]
+pub struct CorpusArgs {
+    #[arg(
+        long,
+        global = true,
+        help = "Name of the corpus node, e.g. 2024-Q4",
+        long_help = "Name of the corpus node, e.g. 2024-Q4. Runs with different names create separate corpus nodes in the same database, the main nodes of the families are shared and get an edge from every corpus they were analyzed for. Defaults to FocusedCorpus or GeneralCorpus"
+    )]
+    pub corpus_name: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Display name of the corpus node, defaults to its name"
+    )]
+    pub corpus_display_name: Option<String>,
+}
+
+impl CorpusArgs {
+    /// Name and display name of the corpus node, `default` unless they are given
+    pub fn name_and_display_name(&self, default: &str) -> (String, String) {
+        let name = self.corpus_name.as_deref().unwrap_or(default).to_string();
+        let display_name = self.corpus_display_name.clone().unwrap_or(name.clone());
+        (name, display_name)
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct FocusedArgs {
+    #[command(subcommand)]
+    pub family: FocusedFamilies,
+
+    #[clap(flatten)]
+    pub corpus: CorpusArgs,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Record the database writes instead of doing them",
+        long_help = "Record the database writes instead of doing them. The samples are analyzed as usual, the recorded writes are printed as JSON at the end, followed by the number of documents per collection. The database isn't connected to, so it doesn't have to be reachable"
+    )]
+    pub dry_run: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Write the errors of the samples as JSON lines to this file",
+        long_help = "Write the errors of the samples as JSON lines to this file instead of printing them. Each line has the path of the sample (file), the stage the error happened in (stage: read, detect, extract or db) and the error message (error)"
+    )]
+    pub error_report: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Stop after the first sample that fails",
+        long_help = "Stop after the first sample that fails. Samples that are already being analyzed are finished, the remaining ones are skipped"
+    )]
+    pub fail_fast: bool,
+
+    #[arg(
+        long,
+        global = true,
+        conflicts_with = "dry_run",
+        help = "Skip samples that were already analyzed",
+        long_help = "Skip samples that were already analyzed. The sha256 of each sample is looked up in the IngestedFile collection before the sample is analyzed, samples are added to it once they were analyzed successfully. The number of skipped samples is part of the summary"
+    )]
+    pub skip_known: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Analyze every file, also the ones that obviously aren't samples",
+        long_help = "Analyze every file, also the ones that obviously aren't samples. By default empty files, files with an extension of --prefilter-deny-ext and files in formats none of the families has as samples (plain JSON or JSON lines, PNG, JPEG and PDF) are skipped. The skipped files are logged and listed in the error report"
+    )]
+    pub no_prefilter: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_delimiter = ',',
+        default_values = DEFAULT_DENIED_EXTENSIONS,
+        conflicts_with = "no_prefilter",
+        help = "Extensions of the files the pre-filter skips, separated by commas"
+    )]
+    pub prefilter_deny_ext: Vec<String>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Don't link the created sample nodes to a node of the run",
+        long_help = "Don't link the created sample nodes to a node of the run. By default every run creates an IngestRun node with its id, start and end time, the version of macon, the command line and the hostname, and every sample node it creates gets a CreatedDuring edge to it"
+    )]
+    pub no_provenance: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "json[=PATH]",
+        value_parser = parse_output,
+        help = "Write the result of every sample as JSON line to stdout or to PATH",
+        long_help = "Write the result of every sample as JSON line. With json the lines are printed to stdout once the run is finished and the summary is printed to stderr instead, with json=PATH each line is written to PATH as soon as its sample is finished. Each line has the path of the sample below the directory it was found in (path), its sha256 (sha256), the family (family), the detected sample type (sample_type), the _id of the created or linked documents (documents), the duration (duration_ms), the status (status: analyzed, failed or skipped), the error message (error) and warnings like an ambiguous sample type (warnings)"
+    )]
+    pub output: Option<OutputTarget>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "DIR",
+        value_parser = validate_dir,
+        conflicts_with = "fail_fast",
+        help = "Keep analyzing the samples that are dropped into this directory",
+        long_help = "Keep analyzing the samples that are dropped into this directory. The files already in it are analyzed first, then every new file is analyzed once it was closed or its size didn't change for 2 seconds. Samples that fail don't stop the watching. Ctrl-C stops it, the summary and the error report are written as usual"
+    )]
+    pub watch: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "JOB=INTERVAL",
+        value_parser = parse_maintenance,
+        requires = "watch",
+        conflicts_with = "dry_run",
+        help = "Run a maintenance job periodically while watching, e.g. stats=1h",
+        long_help = "Run a maintenance job periodically while watching, can be given multiple times, e.g. --maintenance stats=1h --maintenance prune=1d. The interval is a number with the unit s, m, h or d, a job first runs one interval after the start and runs between the batches of new samples. The jobs are stats, which logs the statistics of the corpus like the stats subcommand, reconcile, which creates missing indexes and collections and warns about differing indexes, and prune, which deletes the edges whose start or end vertex doesn't exist anymore. A failed job is logged and tried again at its next interval"
+    )]
+    pub maintenance: Vec<MaintenanceSchedule>,
 }
 
 #[derive(Subcommand, Debug)]
@@ -33,16 +365,416 @@ pub enum FocusedFamilies {
     DarkWatchmen(VMArgs),
     #[command(about = "Analyze sample from the Mintsloader malware")]
     Mintsloader(MainArgs),
+    #[command(
+        about = "Analyze samples of mixed families",
+        long_about = "Analyze samples of mixed families. Each sample is classified like with the classify subcommand and handled by its family. Samples that can't be classified are reported and skipped. DarkWatchmen samples are only handled if the VM is given, as they are run in it"
+    )]
+    Auto(AutoArgs),
+}
+
+impl FocusedFamilies {
+    pub fn main_args(&self) -> &MainArgs {
+        match self {
+            FocusedFamilies::Carnavalheist(main_args)
+            | FocusedFamilies::Coper(main_args)
+            | FocusedFamilies::Mintsloader(main_args) => main_args,
+            FocusedFamilies::DarkWatchmen(vm_args) => &vm_args.main_args,
+            FocusedFamilies::Auto(auto_args) => &auto_args.main_args,
+        }
+    }
+
+    pub fn main_args_mut(&mut self) -> &mut MainArgs {
+        match self {
+            FocusedFamilies::Carnavalheist(main_args)
+            | FocusedFamilies::Coper(main_args)
+            | FocusedFamilies::Mintsloader(main_args) => main_args,
+            FocusedFamilies::DarkWatchmen(vm_args) => &mut vm_args.main_args,
+            FocusedFamilies::Auto(auto_args) => &mut auto_args.main_args,
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct AutoArgs {
+    #[clap(flatten)]
+    pub main_args: MainArgs,
+
+    #[arg(
+        help = "Name of the VM for DarkWatchmen samples",
+        long,
+        requires_all = ["vm_user", "vm_pass", "shared_dir"]
+    )]
+    pub vm_name: Option<String>,
+
+    #[arg(help = "Username of the VM", long, requires = "vm_name")]
+    pub vm_user: Option<String>,
+
+    #[arg(help = "Password associated with the user", long, requires = "vm_name")]
+    pub vm_pass: Option<String>,
+
+    #[arg(
+        help = "Path of the shared directory on the host",
+        long,
+        value_parser = validate_dir,
+        requires = "vm_name"
+    )]
+    pub shared_dir: Option<PathBuf>,
+}
+
+impl AutoArgs {
+    /// Arguments to run `files` in the VM, if the VM is given
+    pub fn vm_args(&self, files: Vec<PathBuf>) -> Option<VMArgs> {
+        Some(VMArgs {
+            main_args: MainArgs {
+                files,
+                max_depth: self.main_args.max_depth,
+                follow_symlinks: self.main_args.follow_symlinks,
+                limit: None,
+                sample: None,
+                seed: None,
+                max_file_size: self.main_args.max_file_size,
+                // the members of the archives are already in `files`
+                archives: false,
+                recurse_archives: None,
+                roots: self.main_args.roots.clone(),
+            },
+            vm_name: self.vm_name.clone()?,
+            vm_user: self.vm_user.clone()?,
+            vm_pass: self.vm_pass.clone()?,
+            shared_dir: self.shared_dir.clone()?,
+        })
+    }
 }
 
 #[derive(Args, Debug)]
 pub struct MainArgs {
     #[arg(
-        value_parser = validate_file,
-        help = "Path to the sample(s)",
-        long_help = "Set the path to the sample(s) you want to analyze"
+        value_parser = validate_input,
+        help = "Path to the sample(s) or directories with samples",
+        long_help = "Set the path to the sample(s) you want to analyze. Directories are walked recursively, hidden files and directories are skipped"
     )]
     pub files: Vec<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Maximum depth of the walked directories",
+        long_help = "Maximum depth of the walked directories. 0 only takes the files directly in the given directories"
+    )]
+    pub max_depth: Option<usize>,
+
+    #[arg(
+        long,
+        help = "Follow symbolic links to directories",
+        long_help = "Follow symbolic links to directories while walking. Directories that were already walked are skipped, so links can't cause loops"
+    )]
+    pub follow_symlinks: bool,
+
+    #[arg(
+        long,
+        conflicts_with = "sample",
+        help = "Only analyze the first N samples",
+        long_help = "Only analyze the first N samples, after the directories were walked"
+    )]
+    pub limit: Option<usize>,
+
+    #[arg(
+        long,
+        help = "Only analyze N randomly selected samples",
+        long_help = "Only analyze N randomly selected samples, after the directories were walked. The seed is printed, so the selection can be repeated with --seed"
+    )]
+    pub sample: Option<usize>,
+
+    #[arg(long, requires = "sample", help = "Seed of the selection of --sample")]
+    pub seed: Option<u64>,
+
+    #[arg(
+        long,
+        value_parser = parse_size,
+        default_value = "2G",
+        help = "Skip samples larger than this",
+        long_help = "Skip samples larger than this, e.g. 512K, 100M or 2G. Skipped samples are logged as a warning and don't count as failed"
+    )]
+    pub max_file_size: u64,
+
+    #[arg(
+        long,
+        help = "Analyze the members of zip and tar.gz archives",
+        long_help = "Analyze the members of zip and tar.gz archives instead of the archives. The members are read in memory and named like archive.zip::path/of/member. Archives in archives are analyzed as samples unless --recurse-archives is given. APKs are analyzed as samples, encrypted members are skipped with a warning"
+    )]
+    pub archives: bool,
+
+    #[arg(
+        long,
+        requires = "archives",
+        help = "Analyze the members of archives in archives up to this depth"
+    )]
+    pub recurse_archives: Option<usize>,
+
+    /// Directories that were given in `files`, the samples are named relative to them in the
+    /// reports, see [`SampleRef`](crate::utils::SampleRef)
+    #[arg(skip)]
+    pub roots: Vec<PathBuf>,
+}
+
+impl MainArgs {
+    /// Replaces the directories in `files` with the files in them, see [`collect_files`]
+    pub fn expand_dirs(&mut self) -> anyhow::Result<()> {
+        self.roots = self.files.iter().filter(|f| f.is_dir()).cloned().collect();
+        self.files = collect_files(&self.files, self.max_depth, self.follow_symlinks)?;
+        Ok(())
+    }
+
+    /// Replaces the archives in `files` with their members if `archives` is set, see
+    /// [`expand_archives`]
+    pub fn expand_archives(&mut self) -> anyhow::Result<()> {
+        if self.archives {
+            let files = std::mem::take(&mut self.files);
+            self.files = expand_archives(files, self.recurse_archives.unwrap_or(0))?;
+        }
+        Ok(())
+    }
+
+    /// Keeps the files selected by `limit` or `sample`. The randomly selected files keep their
+    /// order.
+    pub fn select_files(&mut self) {
+        let total = self.files.len();
+
+        if let Some(limit) = self.limit {
+            self.files.truncate(limit);
+            info!("Processing {} of {total} files", self.files.len());
+        } else if let Some(sample) = self.sample {
+            let seed = self.seed.unwrap_or_else(rand::random);
+            let mut rng = StdRng::seed_from_u64(seed);
+
+            let mut indices =
+                rand::seq::index::sample(&mut rng, total, sample.min(total)).into_vec();
+            indices.sort_unstable();
+            self.files = indices.into_iter().map(|i| self.files[i].clone()).collect();
+
+            info!(
+                "Processing {} of {total} files, seed {seed}",
+                self.files.len()
+            );
+        }
+    }
+}
+
+impl MainCommands {
+    /// Sample arguments of the command, if it takes samples
+    pub fn main_args_mut(&mut self) -> Option<&mut MainArgs> {
+        match self {
+            MainCommands::Focused(FocusedArgs { family, .. }) => Some(family.main_args_mut()),
+            MainCommands::General(GeneralArgs { main_args, .. }) => Some(main_args),
+            MainCommands::Classify(ClassifyArgs { main_args, .. }) => Some(main_args),
+            MainCommands::Similar(_)
+            | MainCommands::Export(_)
+            | MainCommands::Stats(_)
+            | MainCommands::Prune(_)
+            | MainCommands::Admin(_) => None,
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct ClassifyArgs {
+    #[clap(flatten)]
+    pub main_args: MainArgs,
+
+    #[arg(
+        long,
+        help = "Print the score and evidence of every family that matched",
+        long_help = "Print the score and evidence of every family that matched below the family of each sample, as <TAB><FAMILY><TAB><SCORE><TAB><EVIDENCE>. The evidence are the heuristics of the sample type detectors that matched"
+    )]
+    pub ranking: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct GeneralArgs {
+    #[clap(flatten)]
+    pub main_args: MainArgs,
+
+    #[clap(flatten)]
+    pub corpus: CorpusArgs,
+
+    #[arg(
+        help = "Directory the CSV files with the evaluations are written to",
+        short,
+        long,
+        default_value = "."
+    )]
+    pub out_dir: PathBuf,
+
+    #[arg(
+        help = "Distances the samples are clustered by",
+        long_help = "Distances the samples are clustered by. combined is the euclidean distance of the ssdeep, lavin and tlsh distances",
+        short,
+        long,
+        value_enum,
+        value_delimiter = ',',
+        default_values_t = [Distance::Ssdeep, Distance::Lavin, Distance::Tlsh, Distance::Combined]
+    )]
+    pub distances: Vec<Distance>,
+
+    #[arg(
+        help = "Clustering algorithm",
+        long_help = "Clustering algorithm. dbscan is evaluated for eps from 1 to 99 and min_pts from 2 to 99, kmeans for 2 to 99 clusters",
+        short,
+        long,
+        value_enum,
+        default_value_t = ClusterAlgorithm::Dbscan
+    )]
+    pub algorithm: ClusterAlgorithm,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Distance {
+    Ssdeep,
+    Lavin,
+    Tlsh,
+    Combined,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum ClusterAlgorithm {
+    Dbscan,
+    Kmeans,
+}
+
+#[derive(Args, Debug)]
+pub struct SimilarArgs {
+    #[arg(
+        help = "SHA-256 hash of a stored sample or path to a sample",
+        long_help = "SHA-256 hash of a sample that is already stored with its ssdeep hash or path to a sample that will be hashed"
+    )]
+    pub target: String,
+
+    #[arg(
+        help = "Minimum ssdeep similarity (0-100)",
+        short,
+        long,
+        default_value_t = 50,
+        value_parser = clap::value_parser!(u8).range(0..=100)
+    )]
+    pub threshold: u8,
+
+    #[arg(
+        help = "Maximum number of similar samples to print",
+        short,
+        long,
+        default_value_t = 10
+    )]
+    pub limit: usize,
+}
+
+#[derive(Args, Debug)]
+pub struct ExportArgs {
+    #[arg(help = "Output format", short, long, value_enum, default_value_t = ExportFormat::Graphml)]
+    pub format: ExportFormat,
+
+    #[arg(help = "Path of the output file", short, long)]
+    pub out: PathBuf,
+
+    #[arg(help = "Corpus to export", short, long, value_enum, default_value_t = Corpus::Focused)]
+    pub corpus: Corpus,
+
+    #[arg(
+        long,
+        help = "Only export the documents reachable from this family",
+        long_help = "Only export the main node of this family of the focused corpus, e.g. Carnavalheist, the documents reachable from it and the edges between them. The name is compared case-insensitively"
+    )]
+    pub family: Option<String>,
+
+    #[clap(flatten)]
+    pub window: TimeWindowArgs,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum ExportFormat {
+    Graphml,
+    Dot,
+    Json,
+}
+
+#[derive(Args, Debug)]
+pub struct StatsArgs {
+    #[arg(help = "Output format", short, long, value_enum, default_value_t = StatsFormat::Table)]
+    pub format: StatsFormat,
+
+    #[arg(help = "Corpus to count", short, long, value_enum, default_value_t = Corpus::Focused)]
+    pub corpus: Corpus,
+
+    #[clap(flatten)]
+    pub window: TimeWindowArgs,
+}
+
+/// Time slice of the focused corpus: the sample nodes created by the runs that started in it,
+/// see [`created_during`](crate::graph_creators::focused_graph::created_during)
+#[derive(Args, Debug, Clone, Default)]
+pub struct TimeWindowArgs {
+    #[arg(
+        long,
+        value_name = "TIME",
+        value_parser = parse_time,
+        help = "Only the sample nodes of runs that started at or after TIME",
+        long_help = "Only the sample nodes created by runs that started at or after TIME, e.g. 2026-10-12 or 2026-10-12T08:00:00+02:00. A date is midnight UTC. Needs the IngestRun nodes of the runs, see --no-provenance"
+    )]
+    pub since: Option<DateTime<Utc>>,
+
+    #[arg(
+        long,
+        value_name = "TIME",
+        value_parser = parse_time,
+        help = "Only the sample nodes of runs that started before TIME",
+        long_help = "Only the sample nodes created by runs that started before TIME, e.g. 2026-10-19. A date is midnight UTC"
+    )]
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl TimeWindowArgs {
+    /// Whether neither `--since` nor `--until` is set, so the whole corpus is used
+    pub fn is_unbounded(&self) -> bool {
+        self.since.is_none() && self.until.is_none()
+    }
+
+    /// Fails if the window can't contain any run
+    pub fn validate(&self) -> anyhow::Result<()> {
+        match (self.since, self.until) {
+            (Some(since), Some(until)) if since >= until => Err(anyhow!(
+                "--since {} is not before --until {}",
+                since.to_rfc3339(),
+                until.to_rfc3339()
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct PruneArgs {
+    #[arg(
+        long,
+        help = "Name of the family to delete, e.g. Carnavalheist",
+        long_help = "Name of the family to delete, e.g. Carnavalheist. The name is compared case-insensitively with the names of the main nodes of the families"
+    )]
+    pub family: String,
+
+    #[arg(
+        long,
+        help = "Print the documents that would be deleted instead of deleting them"
+    )]
+    pub dry_run: bool,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum StatsFormat {
+    Table,
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Corpus {
+    Focused,
+    General,
 }
 
 #[derive(Args, Debug)]
@@ -50,13 +782,14 @@ pub struct VMArgs {
     #[clap(flatten)]
     pub main_args: MainArgs,
 
-    #[arg(help = "Name of the VM", short, long)]
+    // the first letters would all be -v, which is --verbose
+    #[arg(help = "Name of the VM", short = 'n', long)]
     pub vm_name: String,
 
-    #[arg(help = "Username of the VM", short, long)]
+    #[arg(help = "Username of the VM", short = 'u', long)]
     pub vm_user: String,
 
-    #[arg(help = "Password associated with the user", short, long)]
+    #[arg(help = "Password associated with the user", short = 'p', long)]
     pub vm_pass: String,
 
     #[arg(help = "Path of the shared directory on the host", short, long, value_parser = validate_dir)]
@@ -75,6 +808,29 @@ fn validate_file(s: &str) -> Result<PathBuf, String> {
     Ok(pathbuf)
 }
 
+/// Parses an RFC 3339 time or a date, which is midnight UTC
+fn parse_time(s: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return Ok(time.to_utc());
+    }
+
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .map(|date| date.and_time(NaiveTime::MIN).and_utc())
+        .map_err(|_| "The time has to be a date like 2026-10-12 or an RFC 3339 time".to_string())
+}
+
+fn validate_input(s: &str) -> Result<PathBuf, String> {
+    let pathbuf = PathBuf::from(s);
+
+    if !pathbuf.exists() {
+        return Err("The path does not exists".to_string());
+    } else if !pathbuf.is_file() && !pathbuf.is_dir() {
+        return Err("The specified path is neither a file nor a directory, permissions are missing or symbolic links are broken".to_string());
+    }
+
+    Ok(pathbuf)
+}
+
 fn validate_dir(s: &str) -> Result<PathBuf, String> {
     let pathbuf = PathBuf::from(s);
 
@@ -86,3 +842,348 @@ fn validate_dir(s: &str) -> Result<PathBuf, String> {
 
     Ok(pathbuf)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph_creators::COLLECTION_PREFIX_LOCK;
+
+    /// Files of `macon classify <args> <dir>` after the expansion and selection of `main`
+    fn selected(dir: &std::path::Path, args: &[&str]) -> anyhow::Result<Vec<PathBuf>> {
+        let args = ["macon", "classify"]
+            .into_iter()
+            .chain(args.iter().copied())
+            .chain(dir.to_str());
+        let MainCommands::Classify(ClassifyArgs { mut main_args, .. }) =
+            Cli::try_parse_from(args)?.command
+        else {
+            unreachable!("parsed as classify");
+        };
+
+        main_args.expand_dirs()?;
+        main_args.select_files();
+        Ok(main_args.files)
+    }
+
+    fn corpus() -> anyhow::Result<tempfile::TempDir> {
+        let dir = tempfile::tempdir()?;
+        for i in 0..50 {
+            std::fs::write(dir.path().join(format!("{i:02}.bat")), "@echo off")?;
+        }
+        Ok(dir)
+    }
+
+    #[test]
+    fn sample_is_reproducible_with_seed() -> anyhow::Result<()> {
+        let dir = corpus()?;
+        let all = selected(dir.path(), &[])?;
+
+        let first = selected(dir.path(), &["--sample", "10", "--seed", "42"])?;
+        assert_eq!(first.len(), 10);
+        assert_eq!(
+            selected(dir.path(), &["--sample", "10", "--seed", "42"])?,
+            first
+        );
+        assert_ne!(
+            selected(dir.path(), &["--sample", "10", "--seed", "7"])?,
+            first
+        );
+
+        // the selected files keep the order of the expansion
+        assert!(first.is_sorted());
+        assert!(first.iter().all(|file| all.contains(file)));
+
+        // a sample larger than the corpus keeps every file
+        assert_eq!(
+            selected(dir.path(), &["--sample", "80", "--seed", "1"])?,
+            all
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn time_windows() -> anyhow::Result<()> {
+        let cli = Cli::try_parse_from([
+            "macon",
+            "stats",
+            "--since",
+            "2026-10-12",
+            "--until",
+            "2026-10-19T02:00:00+02:00",
+        ])?;
+        let MainCommands::Stats(StatsArgs { window, .. }) = cli.command else {
+            unreachable!("parsed as stats");
+        };
+
+        assert_eq!(
+            window.since.map(|t| t.to_rfc3339()).as_deref(),
+            Some("2026-10-12T00:00:00+00:00")
+        );
+        assert_eq!(
+            window.until.map(|t| t.to_rfc3339()).as_deref(),
+            Some("2026-10-19T00:00:00+00:00")
+        );
+        assert!(!window.is_unbounded());
+        window.validate()?;
+
+        let reversed = TimeWindowArgs {
+            since: window.until,
+            until: window.since,
+        };
+        assert!(reversed.validate().is_err());
+        assert!(TimeWindowArgs::default().is_unbounded());
+
+        assert!(parse_time("last week").is_err());
+        assert!(parse_time("2026-02-30").is_err());
+        assert!(
+            Cli::try_parse_from(["macon", "export", "-o", "a.graphml", "--since", "x"]).is_err()
+        );
+
+        Ok(())
+    }
+
+    /// Config of `macon stats <args>` with the database `corpus_db` and the graph `corpus_graph` as
+    /// defaults
+    fn stats_config(args: &[&str]) -> anyhow::Result<Config> {
+        let args = ["macon", "stats"].into_iter().chain(args.iter().copied());
+        let defaults = Config::new(
+            "http://default:8529",
+            "default_user",
+            "default_password",
+            "corpus_db",
+            "corpus_graph",
+        );
+
+        let config = Cli::try_parse_from(args)?.config.load(defaults);
+        set_collection_prefix(None);
+        config
+    }
+
+    #[test]
+    fn options_override_the_config_file() -> anyhow::Result<()> {
+        let _lock = COLLECTION_PREFIX_LOCK.lock().unwrap();
+
+        let dir = tempfile::tempdir()?;
+        let config = dir.path().join("macon.toml");
+        std::fs::write(
+            &config,
+            "url = \"http://file:8529\"\nuser = \"file_user\"\ndatabase = \"file_db\"\n",
+        )?;
+        let password = dir.path().join("password");
+        std::fs::write(&password, "s3cret\n")?;
+
+        let config = config.to_str().unwrap();
+        let password = password.to_str().unwrap();
+        let loaded = stats_config(&[
+            "--config",
+            config,
+            "--db-user",
+            "cli_user",
+            "--db-password-file",
+            password,
+        ])?;
+
+        // file over the defaults, options over the file
+        assert_eq!(loaded.url, "http://file:8529");
+        assert_eq!(loaded.user, "cli_user");
+        assert_eq!(loaded.password, "s3cret");
+        assert_eq!(loaded.database, "file_db");
+        assert_eq!(loaded.graph, "corpus_graph");
+
+        let loaded = stats_config(&["--config", config, "--database", "cli_db"])?;
+        assert_eq!(loaded.database, "cli_db");
+        assert_eq!(loaded.user, "file_user");
+
+        // without a file only the options override the defaults
+        let loaded = stats_config(&["--graph", "cli_graph"])?;
+        assert_eq!(loaded.graph, "cli_graph");
+        assert_eq!(loaded.database, "corpus_db");
+
+        // the file has to exist
+        let missing = dir.path().join("missing.toml");
+        assert!(stats_config(&["--config", missing.to_str().unwrap()]).is_err());
+
+        Ok(())
+    }
+
+    /// Sets environment variables until it is dropped. Only for tests that hold
+    /// `COLLECTION_PREFIX_LOCK`, like all tests that load a config, so no other test reads the
+    /// environment meanwhile.
+    struct EnvVars(Vec<&'static str>);
+
+    impl EnvVars {
+        fn set(vars: &[(&'static str, &str)]) -> Self {
+            for (name, value) in vars {
+                // SAFETY: the tests that read the environment are serialized by the lock
+                unsafe { std::env::set_var(name, value) };
+            }
+            Self(vars.iter().map(|(name, _)| *name).collect())
+        }
+    }
+
+    impl Drop for EnvVars {
+        fn drop(&mut self) {
+            for name in &self.0 {
+                // SAFETY: see `EnvVars::set`
+                unsafe { std::env::remove_var(name) };
+            }
+        }
+    }
+
+    #[test]
+    fn environment_overrides_the_config_file() -> anyhow::Result<()> {
+        let _lock = COLLECTION_PREFIX_LOCK.lock().unwrap();
+
+        let dir = tempfile::tempdir()?;
+        let config = dir.path().join("macon.toml");
+        std::fs::write(
+            &config,
+            "url = \"http://file:8529\"\nuser = \"file_user\"\ndatabase = \"file_db\"\n\
+             graph = \"file_graph\"\nmax_concurrent_requests = 2\n",
+        )?;
+        let config = config.to_str().unwrap();
+
+        let _env = EnvVars::set(&[
+            ("MACON_ARANGO_USER", "env_user"),
+            ("MACON_ARANGO_PASSWORD", "env_password"),
+            ("MACON_ARANGO_DATABASE", "env_db"),
+            ("MACON_ARANGO_MAX_CONCURRENT_REQUESTS", "8"),
+            // empty variables are ignored
+            ("MACON_ARANGO_GRAPH", ""),
+        ]);
+
+        let loaded = stats_config(&["--config", config])?;
+        assert_eq!(loaded.url, "http://file:8529");
+        assert_eq!(loaded.user, "env_user");
+        assert_eq!(loaded.password, "env_password");
+        assert_eq!(loaded.database, "env_db");
+        assert_eq!(loaded.graph, "file_graph");
+        assert_eq!(loaded.max_concurrent_requests, Some(8));
+
+        // the options override the environment
+        let loaded = stats_config(&[
+            "--config",
+            config,
+            "--db-user",
+            "cli_user",
+            "--db-password",
+            "cli_password",
+            "--database",
+            "cli_db",
+        ])?;
+        assert_eq!(loaded.user, "cli_user");
+        assert_eq!(loaded.password, "cli_password");
+        assert_eq!(loaded.database, "cli_db");
+        assert_eq!(loaded.max_concurrent_requests, Some(8));
+
+        // the environment also overrides the defaults without a file
+        let loaded = stats_config(&[])?;
+        assert_eq!(loaded.user, "env_user");
+        assert_eq!(loaded.graph, "corpus_graph");
+
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_environment_values_are_errors() {
+        let _lock = COLLECTION_PREFIX_LOCK.lock().unwrap();
+
+        for (name, value) in [
+            ("MACON_ARANGO_AUTH", "kerberos"),
+            ("MACON_ARANGO_MAX_CONCURRENT_REQUESTS", "many"),
+            ("MACON_ARANGO_EDGE_WAIT_FOR_SYNC", "maybe"),
+        ] {
+            let _env = EnvVars::set(&[(name, value)]);
+            assert!(stats_config(&[]).is_err(), "{name}={value}");
+        }
+    }
+
+    #[test]
+    fn vm_short_flags() -> anyhow::Result<()> {
+        use clap::CommandFactory;
+
+        Cli::command().debug_assert();
+
+        let dir = corpus()?;
+        let shared = dir.path().to_str().unwrap();
+        let sample = dir.path().join("00.bat");
+        let cli = Cli::try_parse_from([
+            "macon",
+            "focused",
+            "-v",
+            "dark-watchmen",
+            "-n",
+            "win10",
+            "-u",
+            "analyst",
+            "-p",
+            "secret",
+            "-s",
+            shared,
+            sample.to_str().unwrap(),
+        ])?;
+        assert_eq!(cli.verbose, 1);
+        let MainCommands::Focused(FocusedArgs {
+            family: FocusedFamilies::DarkWatchmen(vm_args),
+            ..
+        }) = cli.command
+        else {
+            unreachable!("parsed as dark-watchmen");
+        };
+        assert_eq!(
+            (&*vm_args.vm_name, &*vm_args.vm_user, &*vm_args.vm_pass),
+            ("win10", "analyst", "secret")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn limit_keeps_the_first_files() -> anyhow::Result<()> {
+        let dir = corpus()?;
+        let all = selected(dir.path(), &[])?;
+
+        assert_eq!(selected(dir.path(), &["--limit", "3"])?, all[..3]);
+        assert!(selected(dir.path(), &["--limit", "3", "--sample", "3"]).is_err());
+        assert!(selected(dir.path(), &["--seed", "3"]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn maintenance_jobs_need_watch() -> anyhow::Result<()> {
+        use crate::graph_creators::focused_graph::maintenance::MaintenanceJob;
+
+        let dir = corpus()?;
+        let dir = dir.path().to_str().unwrap();
+        let focused = |args: &[&str]| {
+            Cli::try_parse_from(
+                ["macon", "focused"]
+                    .into_iter()
+                    .chain(args.iter().copied())
+                    .chain(["mintsloader", dir]),
+            )
+        };
+
+        let cli = focused(&[
+            "--watch",
+            dir,
+            "--maintenance",
+            "stats=1h",
+            "--maintenance",
+            "prune=1d",
+        ])?;
+        let MainCommands::Focused(FocusedArgs { maintenance, .. }) = cli.command else {
+            unreachable!("parsed as focused");
+        };
+        let jobs: Vec<MaintenanceJob> = maintenance.iter().map(|m| m.job).collect();
+        assert_eq!(jobs, [MaintenanceJob::Stats, MaintenanceJob::Prune]);
+
+        assert!(focused(&["--maintenance", "stats=1h"]).is_err());
+        assert!(focused(&["--watch", dir, "--dry-run", "--maintenance", "stats=1h"]).is_err());
+        assert!(focused(&["--watch", dir, "--maintenance", "stats=soon"]).is_err());
+
+        Ok(())
+    }
+}
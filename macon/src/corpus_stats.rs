@@ -0,0 +1,239 @@
+use std::collections::{BTreeMap, HashSet};
+
+use anyhow::{Result, anyhow, bail};
+use arangors::AqlQuery;
+use macon_cag::{
+    export::graph_collections,
+    prelude::Database,
+    utils::{establish_database_connection, get_name},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cli::{ConfigArgs, Corpus, StatsArgs, StatsFormat},
+    graph_creators::{
+        focused_graph::{FocusedCorpus, created_during, focused_graph_config},
+        general_graph::{GeneralCorpus, general_graph_config},
+    },
+};
+
+/// Maximum depth of the traversals that count the documents of a family
+const FAMILY_DEPTH: u32 = 16;
+
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum CollectionKind {
+    Vertex,
+    Edge,
+}
+
+#[derive(Serialize, Debug)]
+struct CollectionStats {
+    kind: CollectionKind,
+    documents: u64,
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct CorpusStats {
+    collections: BTreeMap<String, CollectionStats>,
+    edges: u64,
+
+    /// Documents reachable from the main node of each family
+    families: BTreeMap<String, u64>,
+}
+
+#[derive(Deserialize)]
+struct FamilyCount {
+    family: String,
+    documents: u64,
+}
+
+pub fn stats_main(stats_args: StatsArgs, config_args: ConfigArgs) -> Result<()> {
+    let StatsArgs {
+        format,
+        corpus,
+        window,
+    } = stats_args;
+    window.validate()?;
+
+    let (config, corpus_collection) = match corpus {
+        Corpus::Focused => (
+            focused_graph_config(&config_args)?,
+            get_name::<FocusedCorpus>(),
+        ),
+        Corpus::General => (
+            general_graph_config(&config_args)?,
+            get_name::<GeneralCorpus>(),
+        ),
+    };
+
+    let conn = establish_database_connection(&config)?;
+    let db = conn
+        .db(&config.database)
+        .map_err(|e| anyhow!("Database '{}' can't be opened: {e}", config.database))?;
+
+    let created = match (window.is_unbounded(), corpus) {
+        (true, _) => None,
+        (false, Corpus::General) => bail!("The general corpus has no runs to slice by time"),
+        (false, Corpus::Focused) => Some(created_during(&db, &window)?),
+    };
+
+    let stats = corpus_stats(
+        &db,
+        &config.graph_name(),
+        &corpus_collection,
+        created.as_deref(),
+    )?;
+
+    match format {
+        StatsFormat::Table => print_table(&stats),
+        StatsFormat::Json => println!("{}", serde_json::to_string_pretty(&stats)?),
+    }
+
+    Ok(())
+}
+
+/// Counts the documents of the collections of the graph `graph_name`. The collections are taken
+/// from the graph definition, so the collections of new families are counted without changes.
+/// The families are the vertices the corpus node in `corpus_collection` has edges to. With
+/// `created`, only these vertices and the edges from or to them are counted, see
+/// [`created_during`].
+pub(crate) fn corpus_stats(
+    db: &Database,
+    graph_name: &str,
+    corpus_collection: &str,
+    created: Option<&[String]>,
+) -> Result<CorpusStats> {
+    let (vertex_collections, edge_collections) = graph_collections(db, graph_name)
+        .map_err(|e| anyhow!("Graph '{graph_name}' can't be read: {e}"))?;
+
+    // collections of the graph definition are only created once they are written to
+    let existing: HashSet<String> = db
+        .accessible_collections()?
+        .into_iter()
+        .map(|info| info.name)
+        .collect();
+
+    let mut collections = BTreeMap::new();
+    for (collection, kind) in vertex_collections
+        .into_iter()
+        .map(|c| (c, CollectionKind::Vertex))
+        .chain(
+            edge_collections
+                .into_iter()
+                .map(|c| (c, CollectionKind::Edge)),
+        )
+    {
+        let documents = match existing.contains(&collection) {
+            true => count_documents(db, &collection, kind, created)?,
+            false => 0,
+        };
+        collections.insert(collection, CollectionStats { kind, documents });
+    }
+
+    let edges = collections
+        .values()
+        .filter(|c| matches!(c.kind, CollectionKind::Edge))
+        .map(|c| c.documents)
+        .sum();
+
+    let aql = AqlQuery::builder()
+        .query(
+            "for corpus in @@corpus
+                for family in 1..1 outbound corpus graph @graph
+                    filter family._id != corpus._id
+                    let documents = length(
+                        for v in 1..@depth outbound family graph @graph
+                            options { order: 'bfs', uniqueVertices: 'global' }
+                            filter @created == null || v._id in @created
+                            return 1
+                    )
+                    return { family: family.name || family._key, documents }",
+        )
+        .bind_var("@corpus", corpus_collection)
+        .bind_var("graph", graph_name)
+        .bind_var("depth", FAMILY_DEPTH)
+        .bind_var("created", created)
+        .build();
+
+    // the corpus collection doesn't exist before the first analysis
+    let families: Vec<FamilyCount> = match existing.contains(corpus_collection) {
+        true => db.aql_query(aql)?,
+        false => vec![],
+    };
+
+    Ok(CorpusStats {
+        collections,
+        edges,
+        families: families
+            .into_iter()
+            .map(|f| (f.family, f.documents))
+            .collect(),
+    })
+}
+
+/// Documents of `collection`. With `created`, only these vertices or the edges from or to them
+fn count_documents(
+    db: &Database,
+    collection: &str,
+    kind: CollectionKind,
+    created: Option<&[String]>,
+) -> Result<u64> {
+    let aql = match (created, kind) {
+        (None, _) => AqlQuery::builder()
+            .query("return length(@@collection)")
+            .bind_var("@collection", collection)
+            .build(),
+        (Some(created), CollectionKind::Vertex) => AqlQuery::builder()
+            .query("return count(for d in @@collection filter d._id in @created return 1)")
+            .bind_var("@collection", collection)
+            .bind_var("created", created)
+            .build(),
+        (Some(created), CollectionKind::Edge) => AqlQuery::builder()
+            .query(
+                "return count(
+                    for d in @@collection
+                        filter d._from in @created || d._to in @created
+                        return 1
+                )",
+            )
+            .bind_var("@collection", collection)
+            .bind_var("created", created)
+            .build(),
+    };
+
+    let mut result: Vec<u64> = db.aql_query(aql)?;
+    Ok(result.pop().unwrap_or(0))
+}
+
+fn print_table(stats: &CorpusStats) {
+    let width = stats
+        .collections
+        .keys()
+        .chain(stats.families.keys())
+        .map(String::len)
+        .chain(["collection".len()])
+        .max()
+        .unwrap_or_default();
+
+    println!(
+        "{:<width$}  {:<6}  {:>9}",
+        "collection", "kind", "documents"
+    );
+    for (name, c) in &stats.collections {
+        let kind = match c.kind {
+            CollectionKind::Vertex => "vertex",
+            CollectionKind::Edge => "edge",
+        };
+        println!("{name:<width$}  {kind:<6}  {:>9}", c.documents);
+    }
+    println!("edges: {}", stats.edges);
+
+    if !stats.families.is_empty() {
+        println!();
+        println!("{:<width$}  {:>9}", "family", "documents");
+        for (family, documents) in &stats.families {
+            println!("{family:<width$}  {documents:>9}");
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use serde_json::{Value, json};
+
+/// Candidates with at least this score are considered plausible. If more than one candidate is
+/// plausible the detection is reported as ambiguous.
+pub const PLAUSIBLE_SCORE: f32 = 0.5;
+
+/// Sample type that was suggested by one of the heuristics of a detector
+#[derive(Debug)]
+pub struct Candidate<T> {
+    pub sample_type: T,
+
+    /// Confidence between 0 and 1
+    pub score: f32,
+
+    /// Name of the heuristic that matched
+    pub rule: &'static str,
+}
+
+impl<T> Candidate<T> {
+    /// Attributes that are stored on the node of the detected sample
+    pub fn node_attributes(&self) -> Value {
+        json!({
+            "detection": {
+                "rule": self.rule,
+                "score": self.score,
+            }
+        })
+    }
+}
+
+/// All candidates of a detector, sorted by score (highest first)
+#[derive(Debug)]
+pub struct Detection<T> {
+    candidates: Vec<Candidate<T>>,
+}
+
+impl<T> Default for Detection<T> {
+    fn default() -> Self {
+        Self { candidates: vec![] }
+    }
+}
+
+impl<T> Detection<T> {
+    /// Adds a candidate. Candidates with equal scores keep the order in which they were added.
+    pub fn add(&mut self, sample_type: T, score: f32, rule: &'static str) {
+        let pos = self.candidates.partition_point(|c| c.score >= score);
+        self.candidates.insert(
+            pos,
+            Candidate {
+                sample_type,
+                score,
+                rule,
+            },
+        );
+    }
+
+    pub fn candidates(&self) -> &[Candidate<T>] {
+        &self.candidates
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.candidates.is_empty()
+    }
+
+    pub fn into_best(self) -> Option<Candidate<T>> {
+        self.candidates.into_iter().next()
+    }
+
+    /// Describes the plausible candidates if there is more than one of them
+    pub fn ambiguity_report(&self, sample_filename: &str) -> Option<String> {
+        let plausible: Vec<String> = self
+            .candidates
+            .iter()
+            .filter(|c| c.score >= PLAUSIBLE_SCORE)
+            .map(|c| format!("{} ({:.2})", c.rule, c.score))
+            .collect();
+
+        (plausible.len() > 1).then(|| {
+            format!(
+                "Ambiguous sample type of {sample_filename}: {}",
+                plausible.join(", ")
+            )
+        })
+    }
+}
@@ -0,0 +1,116 @@
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{BufWriter, Write},
+};
+
+use anyhow::{Result, anyhow, bail};
+use arangors::AqlQuery;
+use macon_cag::{
+    export::{to_dot, to_graphml, to_json},
+    prelude::Database,
+    utils::{establish_database_connection, get_name},
+};
+
+use crate::{
+    cli::{ConfigArgs, Corpus, ExportArgs, ExportFormat},
+    graph_creators::{
+        focused_graph::{FocusedCorpus, created_during, focused_graph_config},
+        general_graph::general_graph_config,
+    },
+};
+
+/// Maximum depth of the traversal from the main node of the exported family
+const MAX_DEPTH: u32 = 100;
+
+pub fn export_main(export_args: ExportArgs, config_args: ConfigArgs) -> Result<()> {
+    let ExportArgs {
+        format,
+        out,
+        corpus,
+        family,
+        window,
+    } = export_args;
+    window.validate()?;
+
+    let config = match corpus {
+        Corpus::Focused => focused_graph_config(&config_args)?,
+        Corpus::General => general_graph_config(&config_args)?,
+    };
+
+    let conn = establish_database_connection(&config)?;
+    let db = conn
+        .db(&config.database)
+        .map_err(|e| anyhow!("Database '{}' can't be opened: {e}", config.database))?;
+
+    let only = match (&family, corpus) {
+        (Some(family), Corpus::Focused) => {
+            Some(family_vertices(&db, &config.graph_name(), family)?)
+        }
+        (Some(_), Corpus::General) => bail!("The general corpus has no families to export"),
+        (None, _) => None,
+    };
+
+    let only = match (only, window.is_unbounded(), corpus) {
+        (only, true, _) => only,
+        (_, false, Corpus::General) => bail!("The general corpus has no runs to slice by time"),
+        (None, false, Corpus::Focused) => Some(created_during(&db, &window)?),
+        // the main node of the family is never created by a run, so it is kept
+        (Some(family_vertices), false, Corpus::Focused) => {
+            let created: HashSet<String> = created_during(&db, &window)?.into_iter().collect();
+            Some(
+                family_vertices
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(i, id)| *i == 0 || created.contains(id))
+                    .map(|(_, id)| id)
+                    .collect(),
+            )
+        }
+    };
+    let only = only.as_deref();
+
+    let mut writer = BufWriter::new(File::create(&out)?);
+    let stats = match format {
+        ExportFormat::Graphml => to_graphml(&db, &config.graph_name(), only, &mut writer)?,
+        ExportFormat::Dot => to_dot(&db, &config.graph_name(), only, &mut writer)?,
+        ExportFormat::Json => to_json(&db, &config.graph_name(), only, &mut writer)?,
+    };
+    writer.flush()?;
+
+    println!(
+        "Exported {} vertices and {} edges to {}",
+        stats.vertices,
+        stats.edges,
+        out.display()
+    );
+
+    Ok(())
+}
+
+/// `_id` of the main node of the family named `family` (case-insensitive) and of all vertices
+/// reachable from it
+fn family_vertices(db: &Database, graph_name: &str, family: &str) -> Result<Vec<String>> {
+    let aql = AqlQuery::builder()
+        .query(
+            "for corpus in @@corpus
+                for family in 1..1 outbound corpus graph @graph
+                    filter family._id != corpus._id && lower(family.name) == lower(@family)
+                    return append(
+                        [family._id],
+                        for v in 1..@depth outbound family graph @graph
+                            options { order: 'bfs', uniqueVertices: 'global' }
+                            return v._id
+                    )",
+        )
+        .bind_var("@corpus", get_name::<FocusedCorpus>())
+        .bind_var("graph", graph_name)
+        .bind_var("family", family)
+        .bind_var("depth", MAX_DEPTH)
+        .build();
+
+    let mut result: Vec<Vec<String>> = db.aql_query(aql)?;
+    result
+        .pop()
+        .ok_or(anyhow!("There is no family named '{family}' in the graph"))
+}
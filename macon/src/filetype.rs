@@ -0,0 +1,205 @@
+use anyhow::{Result, anyhow};
+use serde::de::IgnoredAny;
+
+const DEX_MAGIC: [u8; 4] = [0x64, 0x65, 0x78, 0x0a];
+const DEX_HEADER_SIZE: usize = 0x70;
+const DEX_ENDIAN_CONSTANT: u32 = 0x12345678;
+
+const PNG_MAGIC: [u8; 8] = [0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a];
+const JPEG_MAGIC: [u8; 3] = [0xff, 0xd8, 0xff];
+const PDF_MAGIC: &[u8] = b"%PDF-";
+
+/// Name of the format of `data` if it is a format that none of the families has as samples:
+/// images, PDFs and plain JSON or JSON lines, e.g. sandbox reports. `truncated` data is only the
+/// start of a file.
+pub fn non_sample_format(data: &[u8], truncated: bool) -> Option<&'static str> {
+    if data.starts_with(&PNG_MAGIC) {
+        Some("PNG image")
+    } else if data.starts_with(&JPEG_MAGIC) {
+        Some("JPEG image")
+    } else if data.starts_with(PDF_MAGIC) {
+        Some("PDF document")
+    } else if is_json(data, truncated) {
+        Some("JSON")
+    } else {
+        None
+    }
+}
+
+/// Returns true if the data is a JSON object or array or a sequence of them, e.g. JSON lines. A
+/// document at the end of `truncated` data only has to be valid up to the end of the data.
+fn is_json(data: &[u8], truncated: bool) -> bool {
+    let data = data.strip_prefix(&[0xef, 0xbb, 0xbf]).unwrap_or(data);
+
+    if !matches!(
+        data.iter().find(|b| !b.is_ascii_whitespace()),
+        Some(b'{' | b'[')
+    ) {
+        return false;
+    }
+
+    let documents = serde_json::Deserializer::from_slice(data).into_iter::<IgnoredAny>();
+    for document in documents {
+        if let Err(e) = document {
+            return truncated && e.is_eof();
+        }
+    }
+
+    true
+}
+
+/// Returns true if the data starts with a valid DEX header
+pub fn is_dex(data: &[u8]) -> bool {
+    validate_dex(data).is_ok()
+}
+
+/// Validates the DEX header: magic, version string, header size, endianness tag, file size and
+/// the adler32 checksum over the rest of the file
+pub fn validate_dex(data: &[u8]) -> Result<()> {
+    if data.len() < DEX_HEADER_SIZE {
+        return Err(anyhow!("DEX file is smaller than the DEX header"));
+    }
+
+    // "dex\n" followed by a three digit version and a null byte, e.g. "dex\n035\0"
+    if data[0..4] != DEX_MAGIC || !data[4..7].iter().all(u8::is_ascii_digit) || data[7] != 0 {
+        return Err(anyhow!("Invalid DEX magic"));
+    }
+
+    let read_u32 = |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+
+    let checksum = read_u32(8);
+    let file_size = read_u32(32) as usize;
+    let header_size = read_u32(36) as usize;
+    let endian_tag = read_u32(40);
+
+    if endian_tag != DEX_ENDIAN_CONSTANT {
+        return Err(anyhow!("Invalid DEX endian tag {endian_tag:#x}"));
+    }
+
+    if header_size != DEX_HEADER_SIZE {
+        return Err(anyhow!("Invalid DEX header size {header_size:#x}"));
+    }
+
+    if file_size < DEX_HEADER_SIZE || file_size > data.len() {
+        return Err(anyhow!(
+            "DEX file size {file_size} does not match data of size {}",
+            data.len()
+        ));
+    }
+
+    let computed = adler32(&data[12..file_size]);
+    if computed != checksum {
+        return Err(anyhow!(
+            "DEX checksum mismatch (expected {checksum:#x}, got {computed:#x})"
+        ));
+    }
+
+    Ok(())
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD_ADLER: u32 = 65521;
+
+    let (a, b) = data.iter().fold((1u32, 0u32), |(a, b), byte| {
+        let a = (a + *byte as u32) % MOD_ADLER;
+        let b = (b + a) % MOD_ADLER;
+        (a, b)
+    });
+
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// DEX file of version `version` with an empty body after the header
+    fn dex(version: &[u8; 3]) -> Vec<u8> {
+        let size = DEX_HEADER_SIZE + 16;
+        let mut data = vec![0; size];
+        data[0..4].copy_from_slice(&DEX_MAGIC);
+        data[4..7].copy_from_slice(version);
+        data[32..36].copy_from_slice(&(size as u32).to_le_bytes());
+        data[36..40].copy_from_slice(&(DEX_HEADER_SIZE as u32).to_le_bytes());
+        data[40..44].copy_from_slice(&DEX_ENDIAN_CONSTANT.to_le_bytes());
+        fix_checksum(&mut data);
+        data
+    }
+
+    fn fix_checksum(data: &mut [u8]) {
+        let checksum = adler32(&data[12..]);
+        data[8..12].copy_from_slice(&checksum.to_le_bytes());
+    }
+
+    #[test]
+    fn valid_dex_versions() {
+        for version in [b"035", b"038", b"039", b"041"] {
+            validate_dex(&dex(version)).unwrap();
+        }
+
+        // data after the file size of the header is ignored, e.g. appended by a dropper
+        let mut data = dex(b"035");
+        data.extend(b"appended");
+        assert!(is_dex(&data));
+    }
+
+    #[test]
+    fn invalid_dex_magic_and_version() {
+        let mut wrong_magic = dex(b"035");
+        wrong_magic[3] = b' ';
+        let mut missing_null = dex(b"035");
+        missing_null[7] = b'\n';
+
+        for mut data in [dex(b"03a"), dex(b"3 5"), wrong_magic, missing_null] {
+            fix_checksum(&mut data);
+            assert_eq!(
+                validate_dex(&data).unwrap_err().to_string(),
+                "Invalid DEX magic"
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_dex_header_fields() {
+        let mut big_endian = dex(b"035");
+        big_endian[40..44].copy_from_slice(&DEX_ENDIAN_CONSTANT.to_be_bytes());
+        fix_checksum(&mut big_endian);
+        assert!(!is_dex(&big_endian));
+
+        let mut header_size = dex(b"035");
+        header_size[36..40].copy_from_slice(&0x78u32.to_le_bytes());
+        fix_checksum(&mut header_size);
+        assert!(!is_dex(&header_size));
+
+        let mut file_size = dex(b"035");
+        file_size[32..36].copy_from_slice(&0x1000u32.to_le_bytes());
+        fix_checksum(&mut file_size);
+        assert!(!is_dex(&file_size));
+
+        let mut checksum = dex(b"035");
+        checksum[DEX_HEADER_SIZE] ^= 1;
+        assert!(
+            validate_dex(&checksum)
+                .unwrap_err()
+                .to_string()
+                .starts_with("DEX checksum mismatch")
+        );
+    }
+
+    #[test]
+    fn truncated_dex_header() {
+        let data = dex(b"035");
+
+        assert!(!is_dex(b"dex\n035\0"));
+        assert!(!is_dex(&data[..DEX_HEADER_SIZE - 1]));
+        assert_eq!(
+            validate_dex(&data[..DEX_HEADER_SIZE - 1])
+                .unwrap_err()
+                .to_string(),
+            "DEX file is smaller than the DEX header"
+        );
+
+        // the header is complete, but the file size points past the data
+        assert!(!is_dex(&data[..DEX_HEADER_SIZE]));
+    }
+}
@@ -0,0 +1,104 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use anyhow::Result;
+use arangors::Document;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use sha256::digest;
+use tracing::info;
+
+use crate::{
+    classify::{MalwareFamily, classify_sample},
+    cli::AutoArgs,
+    graph_creators::focused_graph::{FocusedCorpus, FocusedGraph},
+    report::SampleEvent,
+    utils::{SampleRef, load_sample},
+};
+
+impl FocusedGraph {
+    /// Classifies the samples and handles them by their family. Samples that can't be classified
+    /// are reported as [`SampleEvent::Unclassified`] instead of being treated as errors.
+    pub fn auto_main(
+        &self,
+        auto_args: &AutoArgs,
+        corpus_node: &Document<FocusedCorpus>,
+    ) -> Result<()> {
+        let classified: Vec<(&PathBuf, Result<MalwareFamily, String>)> = auto_args
+            .main_args
+            .files
+            .par_iter()
+            .map(|entry| {
+                let family = match load_sample(entry) {
+                    Ok(data) => classify_sample(&entry.display().to_string(), &data)
+                        .map(|(family, _, _)| family)
+                        .map_err(|unclassified| unclassified.to_string()),
+                    Err(e) => Err(e.to_string()),
+                };
+                (entry, family)
+            })
+            .collect();
+
+        let mut partitions: BTreeMap<MalwareFamily, Vec<PathBuf>> = BTreeMap::new();
+        let mut unclassified: Vec<(PathBuf, String)> = vec![];
+
+        for (entry, family) in classified {
+            match family {
+                Ok(family) => partitions.entry(family).or_default().push(entry.clone()),
+                Err(reason) => unclassified.push((entry.clone(), reason)),
+            }
+        }
+
+        // DarkWatchmen samples are run in the VM, so they can't be handled without it
+        let vm_args = match partitions.remove(&MalwareFamily::DarkWatchmen) {
+            Some(files) if auto_args.vm_name.is_some() => auto_args.vm_args(files),
+            Some(files) => {
+                let reason = "DarkWatchmen sample, but no VM was given (--vm-name)";
+                unclassified.extend(files.into_iter().map(|f| (f, reason.to_string())));
+                None
+            }
+            None => None,
+        };
+
+        for (family, files) in &partitions {
+            info!(
+                family = family.as_str(),
+                samples = files.len(),
+                "classified samples"
+            );
+        }
+        if let Some(vm_args) = &vm_args {
+            info!(
+                family = MalwareFamily::DarkWatchmen.as_str(),
+                samples = vm_args.main_args.files.len(),
+                "classified samples"
+            );
+        }
+        info!(samples = unclassified.len(), "unclassified samples");
+
+        for (family, files) in &partitions {
+            match family {
+                MalwareFamily::Carnavalheist => self.carnavalheist_main(files, corpus_node)?,
+                MalwareFamily::Coper => self.coper_main(files, corpus_node)?,
+                MalwareFamily::Mintsloader => self.mintsloader_main(files, corpus_node)?,
+                MalwareFamily::DarkWatchmen => unreachable!("removed from the partitions"),
+            }
+        }
+        if let Some(vm_args) = &vm_args {
+            self.dark_watchmen_main(vm_args, corpus_node)?;
+        }
+
+        for (entry, reason) in unclassified {
+            self.errors.send(SampleEvent::Unclassified {
+                file: SampleRef::new(&entry, &self.roots),
+                reason: reason.clone(),
+            });
+
+            let sha256 = match self.output {
+                Some(_) => load_sample(&entry).ok().map(|data| digest(&*data)),
+                None => None,
+            };
+            self.write_skipped(&entry, sha256, reason);
+        }
+
+        Ok(())
+    }
+}
@@ -1,86 +1,46 @@
 pub mod nodes;
 
-use std::{
-    io::Read,
-    path::PathBuf,
-    sync::{Arc, Mutex},
-};
+use std::path::PathBuf;
 
 use anyhow::{Result, anyhow};
 use arangors::Document;
-use base64::{
-    Engine, alphabet,
-    engine::{GeneralPurpose, general_purpose::PAD},
-};
-use indicatif::ParallelProgressIterator;
-use lazy_static::lazy_static;
 use macon_cag::{
     base_creator::{GraphCreatorBase, UpsertResult},
-    utils::ensure_index,
+    transaction::TxnHandle,
 };
-use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 use sha256::digest;
 
 use crate::{
+    classify::MalwareFamily,
+    detection::Detection,
     graph_creators::focused_graph::{
-        FocusedCorpus, FocusedGraph, HasMalwareFamily,
+        FamilyMetadata, FocusedCorpus, FocusedGraph, HasMalwareFamily,
         carnavalheist::nodes::{
             BatchType, Carnavalheist, CarnavalheistBatch, CarnavalheistHasBatch,
             CarnavalheistHasPs, CarnavalheistHasPython, CarnavalheistPs, CarnavalheistPython,
-            PsType,
+            PsType, SAMPLE_COLLECTIONS, ensure_family_collections,
         },
+        samples::SampleOutcome,
+        today,
     },
-    utils::get_string_from_binary,
+    report::{Stage, StageError},
+    utils::{SampleRef, decode_base64_lenient, get_string_from_binary},
 };
 
-lazy_static! {
-    static ref BASE64_DECODER: GeneralPurpose = GeneralPurpose::new(&alphabet::STANDARD, PAD);
-}
-
 impl FocusedGraph {
     pub fn carnavalheist_main(
         &self,
         files: &[PathBuf],
         corpus_node: &Document<FocusedCorpus>,
     ) -> Result<()> {
-        let db = self.get_db();
-        let idx = vec!["sha256sum".to_string()];
-
-        // Create index for sha256sum field
-        ensure_index::<CarnavalheistBatch>(db, idx.clone())?;
-        ensure_index::<CarnavalheistPs>(db, idx.clone())?;
-        ensure_index::<CarnavalheistPython>(db, idx)?;
+        // Create the collections and the index for the sha256sum field
+        ensure_family_collections(self)?;
 
         let main_node = self.carnavalheist_create_main_node(corpus_node)?;
 
-        let errors: Arc<Mutex<Vec<anyhow::Error>>> = Arc::new(Mutex::new(Vec::new()));
-
-        files
-            .par_iter()
-            .progress()
-            .for_each(|entry| match std::fs::File::open(entry) {
-                Ok(mut file) => {
-                    let mut buf = Vec::new();
-                    match file.read_to_end(&mut buf) {
-                        Ok(_) => {
-                            match self.carnavalheist_handle_sample(
-                                &format!("{entry:?}"),
-                                &buf,
-                                &main_node,
-                            ) {
-                                Ok(_) => (),
-                                Err(e) => errors.lock().unwrap().push(e),
-                            }
-                        }
-                        Err(e) => errors.lock().unwrap().push(e.into()),
-                    }
-                }
-                Err(e) => errors.lock().unwrap().push(e.into()),
-            });
-
-        for e in errors.lock().unwrap().iter() {
-            eprintln!("{e}");
-        }
+        self.process_samples(files, MalwareFamily::Carnavalheist, true, |entry, buf| {
+            self.carnavalheist_handle_sample(entry, buf, &main_node)
+        });
 
         Ok(())
     }
@@ -92,12 +52,25 @@ impl FocusedGraph {
         let main_node_data = Carnavalheist {
             name: "Carnavalheist".to_string(),
             display_name: "Carnavalheist".to_string(),
+            metadata: FamilyMetadata::from_json(include_str!("metadata.json"))?,
+            first_analyzed: Some(today()),
         };
 
         let UpsertResult {
             document: main_node,
-            created: _,
-        } = self.upsert_node::<Carnavalheist>(main_node_data, "name", "Carnavalheist")?;
+            created,
+            ..
+        } = self.upsert_node::<Carnavalheist>(main_node_data.clone(), "name", "Carnavalheist")?;
+
+        // keep the metadata of an existing main node up to date
+        if !created {
+            let first_analyzed = main_node
+                .document
+                .first_analyzed
+                .clone()
+                .or(main_node_data.first_analyzed);
+            self.update_family_metadata(&main_node, &main_node_data.metadata, first_analyzed)?;
+        }
 
         self.upsert_edge::<FocusedCorpus, Carnavalheist, HasMalwareFamily>(
             corpus_node,
@@ -109,44 +82,67 @@ impl FocusedGraph {
 
     fn carnavalheist_handle_sample(
         &self,
-        sample_filename: &str,
+        sample: &SampleRef,
         sample_data: &[u8],
         main_node: &Document<Carnavalheist>,
-    ) -> Result<()> {
-        match detect_sample_type(sample_data) {
-            Some(SampleType::BatchBase64) => {
-                let batch_node =
-                    self.carnavalheist_create_batch_node(sample_data, SampleType::BatchBase64)?;
-                self.upsert_edge::<Carnavalheist, CarnavalheistBatch, CarnavalheistHasBatch>(
-                    main_node,
-                    &batch_node,
-                )?;
-            }
-            Some(SampleType::BatchCommand(ps_type)) => {
-                let batch_node = self.carnavalheist_create_batch_node(
-                    sample_data,
-                    SampleType::BatchCommand(ps_type),
-                )?;
-                self.upsert_edge::<Carnavalheist, CarnavalheistBatch, CarnavalheistHasBatch>(
-                    main_node,
-                    &batch_node,
-                )?;
-            }
-            Some(SampleType::Python) => {
-                self.carnavalheist_create_python_node(sample_data)?;
-            }
-            None => {
-                return Err(anyhow!(
-                    "Sample type of the sample {sample_filename} could not be detected"
-                ));
+    ) -> Result<SampleOutcome> {
+        let detection = detect_sample_type(sample_data);
+        let ambiguity = detection.ambiguity_report(&sample.to_string());
+
+        let Some(candidate) = detection.into_best() else {
+            return Err(StageError::wrap(
+                Stage::Detect,
+                anyhow!("Sample type of the sample {sample} could not be detected"),
+            ));
+        };
+        let attributes = candidate.node_attributes();
+        let sample_type = candidate.sample_type.as_str();
+
+        let documents = self.transaction(SAMPLE_COLLECTIONS, |txn| {
+            match candidate.sample_type {
+                SampleType::BatchBase64 => {
+                    let batch_node = self.carnavalheist_create_batch_node(
+                        txn,
+                        sample_data,
+                        SampleType::BatchBase64,
+                    )?;
+                    txn.update_node_attributes(&batch_node, attributes)?;
+                    txn.upsert_edge::<Carnavalheist, CarnavalheistBatch, CarnavalheistHasBatch>(
+                        main_node,
+                        &batch_node,
+                    )?;
+                }
+                SampleType::BatchCommand(ps_type) => {
+                    let batch_node = self.carnavalheist_create_batch_node(
+                        txn,
+                        sample_data,
+                        SampleType::BatchCommand(ps_type),
+                    )?;
+                    txn.update_node_attributes(&batch_node, attributes)?;
+                    txn.upsert_edge::<Carnavalheist, CarnavalheistBatch, CarnavalheistHasBatch>(
+                        main_node,
+                        &batch_node,
+                    )?;
+                }
+                SampleType::Python => {
+                    let python_node = self.carnavalheist_create_python_node(txn, sample_data)?;
+                    txn.update_node_attributes(&python_node, attributes)?;
+                }
             }
-        }
 
-        Ok(())
+            Ok(txn.written_documents())
+        })?;
+
+        Ok(SampleOutcome {
+            sample_type,
+            documents,
+            ambiguity,
+        })
     }
 
     fn carnavalheist_create_batch_node(
         &self,
+        txn: &TxnHandle,
         sample_data: &[u8],
         sample_type: SampleType,
     ) -> Result<Document<CarnavalheistBatch>> {
@@ -166,7 +162,8 @@ impl FocusedGraph {
         let UpsertResult {
             document: batch_node,
             created,
-        } = self.upsert_node::<CarnavalheistBatch>(batch_node_data, "sha256sum", &sha256sum)?;
+            ..
+        } = txn.upsert_node::<CarnavalheistBatch>(batch_node_data, "sha256sum", &sha256sum)?;
 
         // Sample is already in DB => no need for further analysis
         if !created {
@@ -184,8 +181,8 @@ impl FocusedGraph {
             _ => return Err(anyhow!("wrong sample type")),
         };
 
-        let ps_node = self.carnavalheist_create_ps_node(&ps_stage, ps_type)?;
-        self.upsert_edge::<CarnavalheistBatch, CarnavalheistPs, CarnavalheistHasPs>(
+        let ps_node = self.carnavalheist_create_ps_node(txn, &ps_stage, ps_type)?;
+        txn.upsert_edge::<CarnavalheistBatch, CarnavalheistPs, CarnavalheistHasPs>(
             &batch_node,
             &ps_node,
         )?;
@@ -195,6 +192,7 @@ impl FocusedGraph {
 
     fn carnavalheist_create_ps_node(
         &self,
+        txn: &TxnHandle,
         sample_data: &[u8],
         ps_type: PsType,
     ) -> Result<Document<CarnavalheistPs>> {
@@ -208,7 +206,8 @@ impl FocusedGraph {
         let UpsertResult {
             document: ps_node,
             created,
-        } = self.upsert_node::<CarnavalheistPs>(ps_node_data, "sha256sum", &sha256sum)?;
+            ..
+        } = txn.upsert_node::<CarnavalheistPs>(ps_node_data, "sha256sum", &sha256sum)?;
 
         // Sample is already in DB => no need for further analysis
         if !created {
@@ -220,8 +219,8 @@ impl FocusedGraph {
 
         let python_data = extract_python_from_ps(&sample_str, Some(ps_type))?;
 
-        let python_node = self.carnavalheist_create_python_node(&python_data)?;
-        self.upsert_edge::<CarnavalheistPs, CarnavalheistPython, CarnavalheistHasPython>(
+        let python_node = self.carnavalheist_create_python_node(txn, &python_data)?;
+        txn.upsert_edge::<CarnavalheistPs, CarnavalheistPython, CarnavalheistHasPython>(
             &ps_node,
             &python_node,
         )?;
@@ -231,6 +230,7 @@ impl FocusedGraph {
 
     fn carnavalheist_create_python_node(
         &self,
+        txn: &TxnHandle,
         sample_data: &[u8],
     ) -> Result<Document<CarnavalheistPython>> {
         let sha256sum = digest(sample_data);
@@ -242,24 +242,38 @@ impl FocusedGraph {
         let UpsertResult {
             document: python_node,
             created: _,
-        } = self.upsert_node::<CarnavalheistPython>(python_node_data, "sha256sum", &sha256sum)?;
+            ..
+        } = txn.upsert_node::<CarnavalheistPython>(python_node_data, "sha256sum", &sha256sum)?;
 
         Ok(python_node)
     }
 }
 
-enum SampleType {
+pub(crate) enum SampleType {
     BatchBase64,
     BatchCommand(PsType),
     Python,
 }
 
+impl SampleType {
+    /// Name of the sample type in the output of `--output`
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            SampleType::BatchBase64 => "batch_base64",
+            SampleType::BatchCommand(_) => "batch_command",
+            SampleType::Python => "python",
+        }
+    }
+}
+
 fn extract_python_from_ps(sample_str: &str, ps_type: Option<PsType>) -> Result<Vec<u8>> {
     let ps_type = match ps_type {
         Some(ps_type) => Ok(ps_type),
         None => {
             let sample_type = detect_sample_type(sample_str.as_bytes())
-                .ok_or(anyhow!("Error detecting sample type"))?;
+                .into_best()
+                .ok_or(anyhow!("Error detecting sample type"))?
+                .sample_type;
             match sample_type {
                 SampleType::BatchCommand(ps_type) => Ok(ps_type),
                 _ => Err(anyhow!("Error detection PS type")),
@@ -300,7 +314,7 @@ fn extract_from_ps_concat(sample_str: &str) -> Result<Vec<u8>> {
     let mut python_base64 = python_base64.as_bytes().to_vec();
     let times_encoded = sample_str.matches("base64.b64decode(").count();
     for _ in 0..times_encoded {
-        python_base64 = BASE64_DECODER.decode(&python_base64)?;
+        python_base64 = decode_base64_lenient(&python_base64, Default::default())?;
     }
 
     Ok(python_base64)
@@ -334,7 +348,7 @@ fn extract_from_ps_normal(sample_str: &str) -> Result<Vec<u8>> {
     // account for multiple times of encoding
     let times_encoded = sample_str.matches("base64.b64decode(").count();
     for _ in 0..times_encoded {
-        python_base64 = BASE64_DECODER.decode(&python_base64)?;
+        python_base64 = decode_base64_lenient(&python_base64, Default::default())?;
     }
 
     Ok(python_base64)
@@ -355,7 +369,7 @@ fn extract_from_batch_e(sample_str: &str) -> Result<Vec<u8>> {
 
     #[allow(clippy::sliced_string_as_bytes)]
     let ps_base64_encoded = sample_str[start..end].as_bytes();
-    let ps_base64_decoded = BASE64_DECODER.decode(ps_base64_encoded)?;
+    let ps_base64_decoded = decode_base64_lenient(ps_base64_encoded, Default::default())?;
 
     Ok(ps_base64_decoded)
 }
@@ -397,22 +411,39 @@ fn extract_from_batch_command(sample_str: &str) -> Result<Vec<u8>> {
     Ok(sample_str[start..end - 1].as_bytes().to_vec())
 }
 
-fn detect_sample_type(sample_data: &[u8]) -> Option<SampleType> {
+pub(crate) fn detect_sample_type(sample_data: &[u8]) -> Detection<SampleType> {
     let sample_str = get_string_from_binary(sample_data);
 
+    let mut detection = Detection::default();
+
     if sample_str.contains("powershell -WindowStyle Hidden -e") {
-        return Some(SampleType::BatchBase64);
-    } else if sample_str.contains("powershell -WindowStyle Hidden -Command") {
+        detection.add(SampleType::BatchBase64, 0.9, "powershell_hidden_encoded");
+    }
+
+    if sample_str.contains("powershell -WindowStyle Hidden -Command") {
         if sample_str.contains("set \"base64=") {
-            return Some(SampleType::BatchCommand(PsType::Concat));
+            detection.add(
+                SampleType::BatchCommand(PsType::Concat),
+                0.9,
+                "powershell_hidden_command_concat",
+            );
+        } else {
+            detection.add(
+                SampleType::BatchCommand(PsType::Normal),
+                0.8,
+                "powershell_hidden_command",
+            );
         }
-        return Some(SampleType::BatchCommand(PsType::Normal));
-    } else if sample_str.contains("RANDOMIZADO")
-        || sample_str.contains("import pickle")
-        || sample_str.contains("import base64")
-    {
-        return Some(SampleType::Python);
     }
 
-    None
+    // only the strongest python marker is used
+    if sample_str.contains("RANDOMIZADO") {
+        detection.add(SampleType::Python, 0.9, "randomizado_marker");
+    } else if sample_str.contains("import pickle") {
+        detection.add(SampleType::Python, 0.6, "import_pickle");
+    } else if sample_str.contains("import base64") {
+        detection.add(SampleType::Python, 0.5, "import_base64");
+    }
+
+    detection
 }
@@ -1,75 +1,65 @@
 pub mod nodes;
 
-use std::{
-    io::{Cursor, Read},
-    path::PathBuf,
-    sync::{Arc, Mutex},
-};
+use std::{io::Cursor, path::PathBuf};
 
 use anyhow::{Result, anyhow};
 use arangors::Document;
-use indicatif::ParallelProgressIterator;
 use macon_cag::{
-    base_creator::{GraphCreatorBase, UpsertResult},
-    utils::ensure_index,
+    base_creator::{GraphCreatorBase, UpsertMode, UpsertResult},
+    transaction::TxnHandle,
+    utils::CollectionName,
 };
-use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use macon_zip::types::DosDateTime;
 use sha256::digest;
 use zip::ZipArchive;
 
 use crate::{
+    classify::MalwareFamily,
+    detection::Detection,
+    filetype::is_dex,
     graph_creators::focused_graph::{
-        FocusedCorpus, FocusedGraph, HasMalwareFamily,
+        FamilyMetadata, FocusedCorpus, FocusedGraph, HasMalwareFamily,
         coper::nodes::{
             Coper, CoperAPK, CoperDEX, CoperELF, CoperELFArchitecture, CoperHasAPK, CoperHasDEX,
             CoperHasELF, CoperHasInnerAPK,
         },
+        samples::SampleOutcome,
+        today,
     },
-    utils::extract_from_zip,
+    report::{Stage, StageError},
+    utils::{SampleRef, extract_from_zip},
 };
 
+/// Collections that are written to while handling a sample
+const SAMPLE_COLLECTIONS: [&str; 7] = [
+    CoperAPK::NAME,
+    CoperELF::NAME,
+    CoperDEX::NAME,
+    CoperHasAPK::NAME,
+    CoperHasELF::NAME,
+    CoperHasDEX::NAME,
+    CoperHasInnerAPK::NAME,
+];
+
 impl FocusedGraph {
     pub fn coper_main(
         &self,
         files: &[PathBuf],
         corpus_node: &Document<FocusedCorpus>,
     ) -> Result<()> {
-        let db = self.get_db();
         let idx = vec!["sha256sum".to_string()];
 
         // Create index for sha256sum field
-        ensure_index::<CoperAPK>(db, idx.clone())?;
-        ensure_index::<CoperELF>(db, idx.clone())?;
-        ensure_index::<CoperDEX>(db, idx)?;
+        self.ensure_indexes::<CoperAPK>(idx.clone())?;
+        self.ensure_indexes::<CoperELF>(idx.clone())?;
+        self.ensure_indexes::<CoperDEX>(idx)?;
 
         let main_node = self.coper_create_main_node(corpus_node)?;
 
-        let errors: Arc<Mutex<Vec<anyhow::Error>>> = Arc::new(Mutex::new(Vec::new()));
-
         // handle each sample
-        files
-            .par_iter()
-            .progress()
-            .for_each(|entry| match std::fs::File::open(entry) {
-                Ok(mut file) => {
-                    let mut buf = Vec::new();
-                    match file.read_to_end(&mut buf) {
-                        Ok(_) => {
-                            match self.coper_handle_sample(&format!("{entry:?}"), &buf, &main_node)
-                            {
-                                Ok(_) => (),
-                                Err(e) => errors.lock().unwrap().push(e),
-                            }
-                        }
-                        Err(e) => errors.lock().unwrap().push(e.into()),
-                    }
-                }
-                Err(e) => errors.lock().unwrap().push(e.into()),
-            });
-
-        for e in errors.lock().unwrap().iter() {
-            eprintln!("{e}");
-        }
+        self.process_samples(files, MalwareFamily::Coper, true, |entry, buf| {
+            self.coper_handle_sample(entry, buf, &main_node)
+        });
 
         Ok(())
     }
@@ -82,12 +72,25 @@ impl FocusedGraph {
         let coper = Coper {
             name: "Coper".to_string(),
             display_name: "Coper".to_string(),
+            metadata: FamilyMetadata::from_json(include_str!("metadata.json"))?,
+            first_analyzed: Some(today()),
         };
 
         let UpsertResult {
             document: main_node,
-            created: _,
-        } = self.upsert_node::<Coper>(coper, "name", "Coper")?;
+            created,
+            ..
+        } = self.upsert_node::<Coper>(coper.clone(), "name", "Coper")?;
+
+        // keep the metadata of an existing main node up to date
+        if !created {
+            let first_analyzed = main_node
+                .document
+                .first_analyzed
+                .clone()
+                .or(coper.first_analyzed);
+            self.update_family_metadata(&main_node, &coper.metadata, first_analyzed)?;
+        }
 
         self.upsert_edge::<FocusedCorpus, Coper, HasMalwareFamily>(corpus_node, &main_node)?;
 
@@ -96,35 +99,57 @@ impl FocusedGraph {
 
     fn coper_handle_sample(
         &self,
-        sample_filename: &str,
+        sample: &SampleRef,
         sample_data: &[u8],
         main_node: &Document<Coper>,
-    ) -> Result<()> {
-        match detect_sample_type(sample_data) {
-            Some(CoperSampleType::APK) => {
-                let apk_nodes = self.coper_create_apk_node(sample_data)?;
-                for apk_node in apk_nodes {
-                    self.upsert_edge::<Coper, CoperAPK, CoperHasAPK>(main_node, &apk_node)?;
+    ) -> Result<SampleOutcome> {
+        let detection = detect_sample_type(sample_data);
+        let ambiguity = detection.ambiguity_report(&sample.to_string());
+
+        let Some(candidate) = detection.into_best() else {
+            return Err(StageError::wrap(
+                Stage::Detect,
+                anyhow!("Sample type of the sample {sample} could not be detected."),
+            ));
+        };
+        let attributes = candidate.node_attributes();
+        let sample_type = candidate.sample_type.as_str();
+
+        let documents = self.transaction(&SAMPLE_COLLECTIONS, |txn| {
+            match candidate.sample_type {
+                CoperSampleType::APK => {
+                    let apk_nodes = self.coper_create_apk_node(txn, sample_data)?;
+                    let Some(apk_node) = apk_nodes.first() else {
+                        return Ok(txn.written_documents());
+                    };
+                    txn.update_node_attributes(apk_node, attributes)?;
+                    for apk_node in apk_nodes {
+                        txn.upsert_edge::<Coper, CoperAPK, CoperHasAPK>(main_node, &apk_node)?;
+                    }
+                }
+                CoperSampleType::ELF => {
+                    let elf_node = self.coper_create_elf_node(txn, sample_data, None)?;
+                    txn.update_node_attributes(&elf_node, attributes)?;
+                }
+                CoperSampleType::DEX => {
+                    let dex_node = self.coper_create_dex_node(txn, sample_data)?;
+                    txn.update_node_attributes(&dex_node, attributes)?;
                 }
             }
-            Some(CoperSampleType::ELF) => {
-                let _ = self.coper_create_elf_node(sample_data, None)?;
-            }
-            Some(CoperSampleType::DEX) => {
-                let _ = self.coper_create_dex_node(sample_data)?;
-            }
-            None => {
-                return Err(anyhow!(
-                    "Sample type of the sample {sample_filename} could not be detected."
-                ));
-            }
-        }
 
-        Ok(())
+            Ok(txn.written_documents())
+        })?;
+
+        Ok(SampleOutcome {
+            sample_type,
+            documents,
+            ambiguity,
+        })
     }
 
     fn coper_create_elf_node(
         &self,
+        txn: &TxnHandle,
         sample_data: &[u8],
         mut architecture: Option<CoperELFArchitecture>,
     ) -> Result<Document<CoperELF>> {
@@ -143,24 +168,49 @@ impl FocusedGraph {
         let UpsertResult {
             document: elf_node,
             created: _,
-        } = self.upsert_node::<CoperELF>(elf_data, "sha256sum", &sha256sum)?;
+            ..
+        } = txn.upsert_node::<CoperELF>(elf_data, "sha256sum", &sha256sum)?;
 
         Ok(elf_node)
     }
 
-    fn coper_create_apk_node(&self, sample_data: &[u8]) -> Result<Vec<Document<CoperAPK>>> {
+    fn coper_create_apk_node(
+        &self,
+        txn: &TxnHandle,
+        sample_data: &[u8],
+    ) -> Result<Vec<Document<CoperAPK>>> {
         let apk_analysis_result = self.analyse_apk(sample_data);
 
         let sha256sum = digest(sample_data);
         let apk_data = CoperAPK {
             sha256sum: sha256sum.clone(),
             is_cut: apk_analysis_result.is_cut,
+            earliest_entry_timestamp: apk_analysis_result
+                .earliest_entry_timestamp
+                .map(|t| t.to_string()),
+            comment_sha256sum: apk_analysis_result.comment_sha256sum,
+            deleted_at: None,
         };
 
         let UpsertResult {
             document: apk_node,
             created,
-        } = self.upsert_node::<CoperAPK>(apk_data, "sha256sum", &sha256sum)?;
+            deleted,
+            ..
+        } = txn.upsert_node_with_mode::<CoperAPK>(
+            apk_data,
+            "sha256sum",
+            &sha256sum,
+            // keep the analysis results of existing APKs up to date
+            UpsertMode::InsertOrUpdate {
+                overwrite_with_null: false,
+            },
+        )?;
+
+        // retired APKs are not linked or extracted again
+        if deleted {
+            return Ok(vec![]);
+        }
 
         let mut apk_nodes = vec![apk_node];
 
@@ -173,23 +223,32 @@ impl FocusedGraph {
         // create and upsert elf nodes and edges
         if !apk_analysis_result.is_cut {
             // handle elf files in apk
-            for (sample_data, architecture) in apk_analysis_result.elfs {
-                let elf_node = self.coper_create_elf_node(&sample_data, Some(architecture))?;
-                self.upsert_edge::<CoperAPK, CoperELF, CoperHasELF>(&apk_nodes[0], &elf_node)?;
+            for (sample_data, architecture, path) in apk_analysis_result.elfs {
+                let elf_node = self.coper_create_elf_node(txn, &sample_data, Some(architecture))?;
+                let edge = CoperHasELF {
+                    path: Some(path),
+                    ..Default::default()
+                };
+                txn.upsert_edge_with::<CoperAPK, CoperELF, CoperHasELF>(
+                    &apk_nodes[0],
+                    &elf_node,
+                    edge,
+                    false,
+                )?;
             }
 
             // handle dex files in apk
             for sample_data in apk_analysis_result.dexs {
-                let dex_node = self.coper_create_dex_node(&sample_data)?;
-                self.upsert_edge::<CoperAPK, CoperDEX, CoperHasDEX>(&apk_nodes[0], &dex_node)?;
+                let dex_node = self.coper_create_dex_node(txn, &sample_data)?;
+                txn.upsert_edge::<CoperAPK, CoperDEX, CoperHasDEX>(&apk_nodes[0], &dex_node)?;
             }
 
             // handle inner apks of apk
             for sample_data in apk_analysis_result.apks {
-                let inner_apk_nodes = self.coper_create_apk_node(&sample_data)?;
+                let inner_apk_nodes = self.coper_create_apk_node(txn, &sample_data)?;
 
                 for inner_apk_node in inner_apk_nodes {
-                    self.upsert_edge::<CoperAPK, CoperAPK, CoperHasInnerAPK>(
+                    txn.upsert_edge::<CoperAPK, CoperAPK, CoperHasInnerAPK>(
                         &apk_nodes[0],
                         &inner_apk_node,
                     )?;
@@ -201,7 +260,11 @@ impl FocusedGraph {
         Ok(apk_nodes)
     }
 
-    fn coper_create_dex_node(&self, sample_data: &[u8]) -> Result<Document<CoperDEX>> {
+    fn coper_create_dex_node(
+        &self,
+        txn: &TxnHandle,
+        sample_data: &[u8],
+    ) -> Result<Document<CoperDEX>> {
         let sha256sum = digest(sample_data);
         let dex_data = CoperDEX {
             sha256sum: sha256sum.clone(),
@@ -210,7 +273,8 @@ impl FocusedGraph {
         let UpsertResult {
             document: dex_node,
             created: _,
-        } = self.upsert_node::<CoperDEX>(dex_data, "sha256sum", &sha256sum)?;
+            ..
+        } = txn.upsert_node::<CoperDEX>(dex_data, "sha256sum", &sha256sum)?;
 
         Ok(dex_node)
     }
@@ -221,39 +285,60 @@ impl FocusedGraph {
         let Ok(mut archive) = ZipArchive::new(cursor) else {
             return APKAnalysisResult {
                 is_cut: true,
+                earliest_entry_timestamp: None,
+                comment_sha256sum: None,
                 elfs: vec![],
                 dexs: vec![],
                 apks: vec![],
             };
         };
 
-        // extract all filenames that end with .apk
+        let ziparchive = macon_zip::types::ZipArchive::try_from(sample_data).ok();
+
+        let entries = get_apk_entries(ziparchive.as_ref(), &archive);
+
+        // extract all files that end with .apk
         // some samples are wrapped with tanglebot. This tries to get the inner apk(s) and analyse them as well
-        let apk_files: Vec<String> = archive
-            .file_names()
-            .filter(|filename| filename.ends_with(".apk"))
-            .map(|s| s.to_owned())
+        let apk_files: Vec<&ApkEntry> = entries
+            .iter()
+            .filter(|entry| entry.name.ends_with(".apk"))
             .collect();
         let apks = extract_inner_apks_from_apk(&mut archive, apk_files);
 
-        // extract all filenames in the lib/ directory
-        let elf_files: Vec<String> = archive
-            .file_names()
-            .filter(|filename| filename.starts_with("lib/"))
-            .map(|s| s.to_owned())
+        // extract all files in the lib/ directory
+        let elf_files: Vec<&ApkEntry> = entries
+            .iter()
+            .filter(|entry| entry.name.starts_with("lib/"))
             .collect();
         let elfs = extract_elfs_from_apk(&mut archive, elf_files);
 
-        // extract all filenames that end with .dex
-        let dex_files: Vec<String> = archive
-            .file_names()
-            .filter(|filename| filename.ends_with(".dex"))
-            .map(|s| s.to_owned())
+        // extract all files that end with .dex
+        let dex_files: Vec<&ApkEntry> = entries
+            .iter()
+            .filter(|entry| entry.name.ends_with(".dex"))
             .collect();
         let dexs = extract_dexs_from_apk(&mut archive, dex_files);
 
+        // get oldest modification time of all entries
+        let earliest_entry_timestamp = ziparchive.as_ref().and_then(|ziparchive| {
+            ziparchive
+                .central_directory_headers
+                .iter()
+                .filter_map(|cdh| cdh.modified())
+                .min()
+        });
+
+        // attackers hide configuration strings and markers in the comment
+        let comment_sha256sum = ziparchive
+            .as_ref()
+            .map(|ziparchive| ziparchive.comment())
+            .filter(|comment| !comment.is_empty())
+            .map(digest);
+
         APKAnalysisResult {
             is_cut: false,
+            earliest_entry_timestamp,
+            comment_sha256sum,
             elfs,
             dexs,
             apks,
@@ -261,14 +346,52 @@ impl FocusedGraph {
     }
 }
 
+struct ApkEntry {
+    /// Effective name of the entry (honors the Unicode Path extra field)
+    name: String,
+
+    /// Name of the entry as used by the zip crate for extraction
+    archive_name: String,
+}
+
+fn get_apk_entries(
+    ziparchive: Option<&macon_zip::types::ZipArchive>,
+    archive: &ZipArchive<Cursor<&[u8]>>,
+) -> Vec<ApkEntry> {
+    // the central directory headers are in the same order as the entries of the zip crate
+    if let Some(ziparchive) = ziparchive
+        && ziparchive.central_directory_headers.len() == archive.len()
+    {
+        return ziparchive
+            .central_directory_headers
+            .iter()
+            .enumerate()
+            .filter_map(|(i, cdh)| {
+                Some(ApkEntry {
+                    name: cdh.effective_name().into_owned(),
+                    archive_name: archive.name_for_index(i)?.to_owned(),
+                })
+            })
+            .collect();
+    }
+
+    archive
+        .file_names()
+        .map(|name| ApkEntry {
+            name: name.to_owned(),
+            archive_name: name.to_owned(),
+        })
+        .collect()
+}
+
 fn extract_inner_apks_from_apk(
     archive: &mut ZipArchive<Cursor<&[u8]>>,
-    apk_files: Vec<String>,
+    apk_files: Vec<&ApkEntry>,
 ) -> Vec<Vec<u8>> {
     let mut apks = vec![];
 
-    for apk_filename in apk_files {
-        if let Ok(apk_data) = extract_from_zip(archive, &apk_filename, true) {
+    for apk_file in apk_files {
+        if let Ok(apk_data) = extract_from_zip(archive, &apk_file.archive_name, true) {
             // check if file is really a apk file
             if !apk_data.starts_with(&[0x50, 0x4B]) {
                 continue;
@@ -283,12 +406,13 @@ fn extract_inner_apks_from_apk(
 
 fn extract_elfs_from_apk(
     archive: &mut ZipArchive<Cursor<&[u8]>>,
-    elf_files: Vec<String>,
-) -> Vec<(Vec<u8>, CoperELFArchitecture)> {
+    elf_files: Vec<&ApkEntry>,
+) -> Vec<(Vec<u8>, CoperELFArchitecture, String)> {
     let mut elfs = vec![];
 
-    for elf_filename in elf_files {
-        if let Ok(elf_data) = extract_from_zip(archive, &elf_filename, true) {
+    for elf_file in elf_files {
+        let elf_filename = &elf_file.name;
+        if let Ok(elf_data) = extract_from_zip(archive, &elf_file.archive_name, true) {
             // check if file is really a elf file
             if !elf_data.starts_with(&[0x7f, 0x45, 0x4c, 0x46]) {
                 continue;
@@ -308,7 +432,7 @@ fn extract_elfs_from_apk(
                 continue;
             }
 
-            elfs.push((elf_data, architecture));
+            elfs.push((elf_data, architecture, elf_filename.to_string()));
         }
     }
 
@@ -317,14 +441,14 @@ fn extract_elfs_from_apk(
 
 fn extract_dexs_from_apk(
     archive: &mut ZipArchive<Cursor<&[u8]>>,
-    dex_files: Vec<String>,
+    dex_files: Vec<&ApkEntry>,
 ) -> Vec<Vec<u8>> {
     let mut dexs = vec![];
 
-    for dex_filename in dex_files {
-        if let Ok(dex_data) = extract_from_zip(archive, &dex_filename, true) {
+    for dex_file in dex_files {
+        if let Ok(dex_data) = extract_from_zip(archive, &dex_file.archive_name, true) {
             // check if file is really a .dex file
-            if !dex_data.starts_with(&[0x64, 0x65, 0x78, 0x0a]) && dex_data[7] == 0 {
+            if !is_dex(&dex_data) {
                 continue;
             }
 
@@ -336,6 +460,10 @@ fn extract_dexs_from_apk(
 }
 
 fn detect_elf_architecture(sample_data: &[u8]) -> Option<CoperELFArchitecture> {
+    if sample_data.len() < 20 {
+        return None;
+    }
+
     let endianness = sample_data[5];
 
     let architecture;
@@ -360,33 +488,225 @@ fn detect_elf_architecture(sample_data: &[u8]) -> Option<CoperELFArchitecture> {
 }
 
 #[allow(clippy::upper_case_acronyms)]
-enum CoperSampleType {
+pub(crate) enum CoperSampleType {
     APK,
     ELF,
     DEX,
 }
 
-fn detect_sample_type(sample_data: &[u8]) -> Option<CoperSampleType> {
+impl CoperSampleType {
+    /// Name of the sample type in the output of `--output`
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            CoperSampleType::APK => "apk",
+            CoperSampleType::ELF => "elf",
+            CoperSampleType::DEX => "dex",
+        }
+    }
+}
+
+pub(crate) fn detect_sample_type(sample_data: &[u8]) -> Detection<CoperSampleType> {
+    let mut detection = Detection::default();
+
     // check magic bytes at start of file
 
     // APK
-    if sample_data.starts_with(&[0x50, 0x4B]) {
-        return Some(CoperSampleType::APK);
+    if sample_data.starts_with(&[0x50, 0x4B, 0x03, 0x04]) {
+        detection.add(CoperSampleType::APK, 0.8, "zip_local_file_header");
+    } else if sample_data.starts_with(&[0x50, 0x4B]) {
+        detection.add(CoperSampleType::APK, 0.6, "zip_magic");
     }
+
     // DEX
-    else if sample_data.starts_with(&[0x64, 0x65, 0x78, 0x0a]) && sample_data[7] == 0 {
-        return Some(CoperSampleType::DEX);
+    if is_dex(sample_data) {
+        detection.add(CoperSampleType::DEX, 1.0, "dex_header");
+    }
+
     // ELF
-    } else if sample_data.starts_with(&[0x7f, 0x45, 0x4c, 0x46]) {
-        return Some(CoperSampleType::ELF);
+    if sample_data.starts_with(&[0x7f, 0x45, 0x4c, 0x46]) {
+        let score = match detect_elf_architecture(sample_data) {
+            Some(_) => 0.9,
+            None => 0.6,
+        };
+        detection.add(CoperSampleType::ELF, score, "elf_magic");
     }
 
-    None
+    detection
 }
 
 struct APKAnalysisResult {
     is_cut: bool,
-    elfs: Vec<(Vec<u8>, CoperELFArchitecture)>,
+    earliest_entry_timestamp: Option<DosDateTime>,
+    comment_sha256sum: Option<String>,
+    elfs: Vec<(Vec<u8>, CoperELFArchitecture, String)>,
     dexs: Vec<Vec<u8>>,
     apks: Vec<Vec<u8>>,
 }
+
+#[cfg(test)]
+mod tests {
+    use macon_cag::utils::config::Config;
+    use macon_zip::types::crc32;
+
+    use super::*;
+
+    /// Stored entry of [`apk`]
+    struct Entry<'a> {
+        name: &'a [u8],
+        data: &'a [u8],
+
+        /// DOS date and time of the last modification
+        modified: (u16, u16),
+
+        /// Extra field of the local file header and the central directory header
+        extra_field: Vec<u8>,
+    }
+
+    impl<'a> Entry<'a> {
+        fn new(name: &'a [u8], data: &'a [u8]) -> Self {
+            Self {
+                name,
+                data,
+                modified: (0x21, 0),
+                extra_field: vec![],
+            }
+        }
+    }
+
+    /// APK of the uncompressed `entries` with `comment`
+    fn apk(entries: &[Entry], comment: &[u8]) -> Vec<u8> {
+        let mut buf = vec![];
+        let mut central_dir = vec![];
+
+        for entry in entries {
+            let local_header_offset = buf.len() as u32;
+            let (date, time) = entry.modified;
+            let mut header = vec![];
+            header.extend(20u16.to_le_bytes()); // version needed
+            header.extend(0u16.to_le_bytes()); // general purpose
+            header.extend(0u16.to_le_bytes()); // stored
+            header.extend(time.to_le_bytes());
+            header.extend(date.to_le_bytes());
+            header.extend(crc32(entry.data).to_le_bytes());
+            header.extend((entry.data.len() as u32).to_le_bytes());
+            header.extend((entry.data.len() as u32).to_le_bytes());
+            header.extend((entry.name.len() as u16).to_le_bytes());
+            header.extend((entry.extra_field.len() as u16).to_le_bytes());
+
+            buf.extend(b"PK\x03\x04");
+            buf.extend(&header);
+            buf.extend(entry.name);
+            buf.extend(&entry.extra_field);
+            buf.extend(entry.data);
+
+            central_dir.extend(b"PK\x01\x02");
+            central_dir.extend(20u16.to_le_bytes()); // version made by
+            central_dir.extend(&header);
+            central_dir.extend([0; 10]); // comment, disk, attributes
+            central_dir.extend(local_header_offset.to_le_bytes());
+            central_dir.extend(entry.name);
+            central_dir.extend(&entry.extra_field);
+        }
+
+        let central_dir_offset = buf.len() as u32;
+        buf.extend(&central_dir);
+
+        buf.extend(b"PK\x05\x06");
+        buf.extend([0; 4]); // disk numbers
+        buf.extend((entries.len() as u16).to_le_bytes());
+        buf.extend((entries.len() as u16).to_le_bytes());
+        buf.extend((central_dir.len() as u32).to_le_bytes());
+        buf.extend(central_dir_offset.to_le_bytes());
+        buf.extend((comment.len() as u16).to_le_bytes());
+        buf.extend(comment);
+
+        buf
+    }
+
+    #[test]
+    fn earliest_entry_timestamp() -> Result<()> {
+        let gc = FocusedGraph::try_new(&Config::default(), true, false)?;
+
+        let modified =
+            |year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8| DosDateTime {
+                year,
+                month,
+                day,
+                hour,
+                minute,
+                second,
+            };
+        let earliest = modified(2019, 12, 31, 23, 59, 58);
+        let latest = modified(2021, 3, 4, 10, 20, 30);
+
+        let mut layout = Entry::new(b"res/layout/main.xml", b"<LinearLayout/>");
+        layout.modified = latest.encode()?;
+        let mut manifest = Entry::new(b"AndroidManifest.xml", b"<manifest/>");
+        manifest.modified = earliest.encode()?;
+
+        let result = gc.analyse_apk(&apk(&[layout, manifest], b""));
+        assert!(!result.is_cut);
+        assert_eq!(result.earliest_entry_timestamp, Some(earliest));
+        assert_eq!(result.comment_sha256sum, None);
+
+        // invalid dates are ignored
+        let mut layout = Entry::new(b"res/layout/main.xml", b"<LinearLayout/>");
+        layout.modified = latest.encode()?;
+        let mut manifest = Entry::new(b"AndroidManifest.xml", b"<manifest/>");
+        manifest.modified = (0, 0);
+
+        let result = gc.analyse_apk(&apk(&[layout, manifest], b""));
+        assert_eq!(result.earliest_entry_timestamp, Some(latest));
+
+        Ok(())
+    }
+
+    #[test]
+    fn elf_found_by_unicode_path() -> Result<()> {
+        let gc = FocusedGraph::try_new(&Config::default(), true, false)?;
+
+        // the primary name hides the library from tools that ignore the Unicode Path extra field
+        let primary = b"lib/arm64-v8a\\libcoper.so";
+        let mut unicode_path = vec![1];
+        unicode_path.extend(crc32(primary).to_le_bytes());
+        unicode_path.extend(b"lib/arm64-v8a/libcoper.so");
+
+        let elf = b"\x7fELF\x02\x01\x01\0\0\0\0\0\0\0\0\0\x03\0\xb7\0";
+        let mut library = Entry::new(primary, elf);
+        library.extra_field.extend(0x7075u16.to_le_bytes());
+        library
+            .extra_field
+            .extend((unicode_path.len() as u16).to_le_bytes());
+        library.extra_field.extend(unicode_path);
+
+        let result = gc.analyse_apk(&apk(&[library], b""));
+        assert_eq!(result.elfs.len(), 1);
+        let (data, architecture, name) = &result.elfs[0];
+        assert_eq!(data, elf);
+        assert!(matches!(architecture, CoperELFArchitecture::Arm64V8a));
+        assert_eq!(name, "lib/arm64-v8a/libcoper.so");
+
+        Ok(())
+    }
+
+    #[test]
+    fn comment_sha256sum() -> Result<()> {
+        let gc = FocusedGraph::try_new(&Config::default(), true, false)?;
+        let entries = || [Entry::new(b"AndroidManifest.xml", b"<manifest/>")];
+
+        let comment = b"b64:aHR0cHM6Ly9jMi5leGFtcGxl";
+        let result = gc.analyse_apk(&apk(&entries(), comment));
+        assert_eq!(result.comment_sha256sum, Some(digest(&comment[..])));
+
+        // a comment that looks like an EOCD is still the comment
+        let mut fake_eocd = b"PK\x05\x06".to_vec();
+        fake_eocd.extend([0; 18]);
+        let result = gc.analyse_apk(&apk(&entries(), &fake_eocd));
+        assert_eq!(result.comment_sha256sum, Some(digest(&fake_eocd[..])));
+
+        let result = gc.analyse_apk(&apk(&entries(), b""));
+        assert_eq!(result.comment_sha256sum, None);
+
+        Ok(())
+    }
+}
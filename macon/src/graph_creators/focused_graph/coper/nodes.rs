@@ -1,45 +1,81 @@
 use arangors::graph::EdgeDefinition;
-use macon_cag::{impl_edge_attributes, utils::get_name};
+use macon_cag::{
+    base_creator::{EdgeAttributes, SoftDelete},
+    utils::{CollectionName, get_name},
+};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, Default)]
+use crate::graph_creators::focused_graph::FamilyMetadata;
+
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, Default, CollectionName)]
 pub struct Coper {
     pub name: String,
     pub display_name: String,
+
+    #[serde(flatten)]
+    pub metadata: FamilyMetadata,
+
+    /// Date (YYYY-MM-DD) the family was analyzed for the first time
+    pub first_analyzed: Option<String>,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, Default)]
+#[derive(
+    Deserialize, Serialize, Debug, Clone, JsonSchema, Default, EdgeAttributes, CollectionName,
+)]
 pub struct CoperHasAPK {
     pub _key: String,
     pub _from: String,
     pub _to: String,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, Default)]
+#[derive(
+    Deserialize, Serialize, Debug, Clone, JsonSchema, Default, EdgeAttributes, CollectionName,
+)]
 pub struct CoperHasInnerAPK {
     pub _key: String,
     pub _from: String,
     pub _to: String,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, Default)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, Default, CollectionName)]
 pub struct CoperAPK {
     pub sha256sum: String,
 
     // true if the EOCD of the APK/Zip is missing. This indicated the original sample was cut off
     // at some point
     pub is_cut: bool,
+
+    // modification time of the oldest entry of the APK/Zip (ISO 8601 without timezone)
+    pub earliest_entry_timestamp: Option<String>,
+
+    // sha256sum of the comment of the APK/Zip if it is not empty
+    pub comment_sha256sum: Option<String>,
+
+    // time the APK was retired, e.g. as false positive
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<String>,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, Default)]
+impl SoftDelete for CoperAPK {
+    fn deleted_at(&self) -> Option<&str> {
+        self.deleted_at.as_deref()
+    }
+}
+
+#[derive(
+    Deserialize, Serialize, Debug, Clone, JsonSchema, Default, EdgeAttributes, CollectionName,
+)]
 pub struct CoperHasELF {
     pub _key: String,
     pub _from: String,
     pub _to: String,
+
+    // path of the ELF inside the APK
+    pub path: Option<String>,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, CollectionName)]
 pub struct CoperELF {
     pub sha256sum: String,
     pub architecture: Option<CoperELFArchitecture>,
@@ -57,23 +93,20 @@ pub enum CoperELFArchitecture {
     ArmEabiV7a,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, Default)]
+#[derive(
+    Deserialize, Serialize, Debug, Clone, JsonSchema, Default, EdgeAttributes, CollectionName,
+)]
 pub struct CoperHasDEX {
     pub _key: String,
     pub _from: String,
     pub _to: String,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, CollectionName)]
 pub struct CoperDEX {
     pub sha256sum: String,
 }
 
-impl_edge_attributes!(CoperHasAPK);
-impl_edge_attributes!(CoperHasInnerAPK);
-impl_edge_attributes!(CoperHasELF);
-impl_edge_attributes!(CoperHasDEX);
-
 pub fn coper_edge_definitions() -> Vec<EdgeDefinition> {
     vec![
         EdgeDefinition {
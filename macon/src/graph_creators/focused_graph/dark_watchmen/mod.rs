@@ -6,68 +6,60 @@ use std::{
 
 use anyhow::{Result, anyhow};
 use arangors::Document;
-use indicatif::ProgressIterator;
 use macon_cag::{
     base_creator::{GraphCreatorBase, UpsertResult},
-    utils::ensure_index,
+    transaction::TxnHandle,
+    utils::CollectionName,
 };
 use sha256::digest;
 
 use crate::{
+    classify::MalwareFamily,
     cli::VMArgs,
+    detection::Detection,
     graph_creators::focused_graph::{
-        FocusedCorpus, FocusedGraph, HasMalwareFamily,
+        FamilyMetadata, FocusedCorpus, FocusedGraph, HasMalwareFamily,
         dark_watchmen::nodes::{
             DarkWatchmen, DarkWatchmenHasJS, DarkWatchmenHasPE, DarkWatchmenJS, DarkWatchmenPE,
         },
+        samples::SampleOutcome,
+        today,
     },
+    report::{Stage, StageError},
+    utils::SampleRef,
 };
 
 pub mod nodes;
 
+/// Collections that are written to while handling a sample
+const SAMPLE_COLLECTIONS: [&str; 4] = [
+    DarkWatchmenPE::NAME,
+    DarkWatchmenJS::NAME,
+    DarkWatchmenHasPE::NAME,
+    DarkWatchmenHasJS::NAME,
+];
+
 impl FocusedGraph {
     pub fn dark_watchmen_main(
         &self,
         vm_args: &VMArgs,
         corpus_node: &Document<FocusedCorpus>,
     ) -> Result<()> {
-        let db = self.get_db();
         let idx = vec!["sha256sum".to_string()];
 
         // Create index for sha256sum field
-        ensure_index::<DarkWatchmenPE>(db, idx.clone())?;
-        ensure_index::<DarkWatchmenJS>(db, idx.clone())?;
+        self.ensure_indexes::<DarkWatchmenPE>(idx.clone())?;
+        self.ensure_indexes::<DarkWatchmenJS>(idx.clone())?;
 
         let main_node = self.dark_watchmen_create_main_node(corpus_node)?;
 
-        let mut errors = Vec::new();
-
-        vm_args.main_args.files.iter().progress().for_each(|entry| {
-            match std::fs::File::open(entry) {
-                Ok(mut file) => {
-                    let mut buf = Vec::new();
-                    match file.read_to_end(&mut buf) {
-                        Ok(_) => {
-                            match self.dark_watchmen_handle_sample(
-                                &format!("{entry:?}"),
-                                &buf,
-                                &main_node,
-                                vm_args,
-                            ) {
-                                Ok(_) => (),
-                                Err(e) => errors.push(e),
-                            }
-                        }
-                        Err(e) => errors.push(e.into()),
-                    }
-                }
-                Err(e) => errors.push(e.into()),
-            }
-        });
-
-        for e in errors.iter() {
-            eprintln!("{e}");
-        }
+        // the samples are run in the VM one after another
+        self.process_samples(
+            &vm_args.main_args.files,
+            MalwareFamily::DarkWatchmen,
+            false,
+            |entry, buf| self.dark_watchmen_handle_sample(entry, buf, &main_node, vm_args),
+        );
 
         Ok(())
     }
@@ -79,12 +71,25 @@ impl FocusedGraph {
         let main_node_data = DarkWatchmen {
             name: "DarkWatchmen".to_string(),
             display_name: "DarkWatchmen".to_string(),
+            metadata: FamilyMetadata::from_json(include_str!("metadata.json"))?,
+            first_analyzed: Some(today()),
         };
 
         let UpsertResult {
             document: main_node,
-            created: _,
-        } = self.upsert_node(main_node_data, "name", "DarkWatchmen")?;
+            created,
+            ..
+        } = self.upsert_node::<DarkWatchmen>(main_node_data.clone(), "name", "DarkWatchmen")?;
+
+        // keep the metadata of an existing main node up to date
+        if !created {
+            let first_analyzed = main_node
+                .document
+                .first_analyzed
+                .clone()
+                .or(main_node_data.first_analyzed);
+            self.update_family_metadata(&main_node, &main_node_data.metadata, first_analyzed)?;
+        }
 
         self.upsert_edge::<FocusedCorpus, DarkWatchmen, HasMalwareFamily>(corpus_node, &main_node)?;
 
@@ -93,33 +98,51 @@ impl FocusedGraph {
 
     fn dark_watchmen_handle_sample(
         &self,
-        sample_filename: &str,
+        sample: &SampleRef,
         sample_data: &[u8],
         main_node: &Document<DarkWatchmen>,
         vm_args: &VMArgs,
-    ) -> Result<()> {
-        match detect_sample_type(sample_data) {
-            Some(SampleType::PE) => {
-                let pe_node = self.dark_watchmen_create_pe_node(sample_data, vm_args)?;
-                self.upsert_edge::<DarkWatchmen, DarkWatchmenPE, DarkWatchmenHasPE>(
-                    main_node, &pe_node,
-                )?;
-            }
-            Some(SampleType::JS) => {
-                self.dark_watchmen_create_js_node(sample_data)?;
-            }
-            None => {
-                return Err(anyhow!(
-                    "Sample type of the sample {sample_filename} could not be detected"
-                ));
+    ) -> Result<SampleOutcome> {
+        let detection = detect_sample_type(sample_data);
+        let ambiguity = detection.ambiguity_report(&sample.to_string());
+
+        let Some(candidate) = detection.into_best() else {
+            return Err(StageError::wrap(
+                Stage::Detect,
+                anyhow!("Sample type of the sample {sample} could not be detected"),
+            ));
+        };
+        let attributes = candidate.node_attributes();
+        let sample_type = candidate.sample_type.as_str();
+
+        let documents = self.transaction(&SAMPLE_COLLECTIONS, |txn| {
+            match candidate.sample_type {
+                SampleType::PE => {
+                    let pe_node = self.dark_watchmen_create_pe_node(txn, sample_data, vm_args)?;
+                    txn.update_node_attributes(&pe_node, attributes)?;
+                    txn.upsert_edge::<DarkWatchmen, DarkWatchmenPE, DarkWatchmenHasPE>(
+                        main_node, &pe_node,
+                    )?;
+                }
+                SampleType::JS => {
+                    let js_node = self.dark_watchmen_create_js_node(txn, sample_data)?;
+                    txn.update_node_attributes(&js_node, attributes)?;
+                }
             }
-        }
 
-        Ok(())
+            Ok(txn.written_documents())
+        })?;
+
+        Ok(SampleOutcome {
+            sample_type,
+            documents,
+            ambiguity,
+        })
     }
 
     fn dark_watchmen_create_pe_node(
         &self,
+        txn: &TxnHandle,
         sample_data: &[u8],
         vm_args: &VMArgs,
     ) -> Result<Document<DarkWatchmenPE>> {
@@ -136,20 +159,25 @@ impl FocusedGraph {
         let UpsertResult {
             document: pe_node,
             created,
-        } = self.upsert_node::<DarkWatchmenPE>(pe_node_data, "sha256sum", &sha256sum)?;
+            ..
+        } = txn.upsert_node::<DarkWatchmenPE>(pe_node_data, "sha256sum", &sha256sum)?;
 
         // Sample is already in DB => no need for further analysis
         if !created {
             return Ok(pe_node);
         }
 
-        let js_node = self.dark_watchmen_create_js_node(&js_data)?;
-        self.upsert_edge::<DarkWatchmenPE, DarkWatchmenJS, DarkWatchmenHasJS>(&pe_node, &js_node)?;
+        let js_node = self.dark_watchmen_create_js_node(txn, &js_data)?;
+        txn.upsert_edge::<DarkWatchmenPE, DarkWatchmenJS, DarkWatchmenHasJS>(&pe_node, &js_node)?;
 
         Ok(pe_node)
     }
 
-    fn dark_watchmen_create_js_node(&self, sample_data: &[u8]) -> Result<Document<DarkWatchmenJS>> {
+    fn dark_watchmen_create_js_node(
+        &self,
+        txn: &TxnHandle,
+        sample_data: &[u8],
+    ) -> Result<Document<DarkWatchmenJS>> {
         let sha256sum = digest(sample_data);
 
         let js_node_data = DarkWatchmenJS {
@@ -159,29 +187,46 @@ impl FocusedGraph {
         let UpsertResult {
             document: js_node,
             created: _,
-        } = self.upsert_node::<DarkWatchmenJS>(js_node_data, "sha256sum", &sha256sum)?;
+            ..
+        } = txn.upsert_node::<DarkWatchmenJS>(js_node_data, "sha256sum", &sha256sum)?;
 
         Ok(js_node)
     }
 }
 
-enum SampleType {
+pub(crate) enum SampleType {
     PE,
     JS,
 }
 
-fn detect_sample_type(sample_data: &[u8]) -> Option<SampleType> {
+impl SampleType {
+    /// Name of the sample type in the output of `--output`
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            SampleType::PE => "pe",
+            SampleType::JS => "js",
+        }
+    }
+}
+
+pub(crate) fn detect_sample_type(sample_data: &[u8]) -> Detection<SampleType> {
+    let mut detection = Detection::default();
+
     if sample_data.len() < 4 {
-        return None;
+        return detection;
     }
 
     // check of PE magic numbers
-    if sample_data[0..2] == [0x4D, 0x5A] || sample_data[0..4] == [0x50, 0x45, 0x00, 0x00] {
-        Some(SampleType::PE)
+    if sample_data[0..2] == [0x4D, 0x5A] {
+        detection.add(SampleType::PE, 0.9, "mz_magic");
+    } else if sample_data[0..4] == [0x50, 0x45, 0x00, 0x00] {
+        detection.add(SampleType::PE, 0.9, "pe_signature");
     // TODO: implement check for js stage
     } else {
-        Some(SampleType::JS)
+        detection.add(SampleType::JS, 0.1, "fallback");
     }
+
+    detection
 }
 
 /// Extract the JavaScript payload from a PE file (dynamically)
@@ -1,41 +1,53 @@
 use arangors::graph::EdgeDefinition;
-use macon_cag::{impl_edge_attributes, utils::get_name};
+use macon_cag::{
+    base_creator::EdgeAttributes,
+    utils::{CollectionName, get_name},
+};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, Default)]
+use crate::graph_creators::focused_graph::FamilyMetadata;
+
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, Default, CollectionName)]
 pub struct DarkWatchmen {
     pub name: String,
     pub display_name: String,
+
+    #[serde(flatten)]
+    pub metadata: FamilyMetadata,
+
+    /// Date (YYYY-MM-DD) the family was analyzed for the first time
+    pub first_analyzed: Option<String>,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, Default)]
+#[derive(
+    Deserialize, Serialize, Debug, Clone, JsonSchema, Default, EdgeAttributes, CollectionName,
+)]
 pub struct DarkWatchmenHasPE {
     pub _key: String,
     pub _from: String,
     pub _to: String,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, Default)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, Default, CollectionName)]
 pub struct DarkWatchmenPE {
     pub sha256sum: String,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, Default)]
+#[derive(
+    Deserialize, Serialize, Debug, Clone, JsonSchema, Default, EdgeAttributes, CollectionName,
+)]
 pub struct DarkWatchmenHasJS {
     pub _key: String,
     pub _from: String,
     pub _to: String,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, Default)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, Default, CollectionName)]
 pub struct DarkWatchmenJS {
     pub sha256sum: String,
 }
 
-impl_edge_attributes!(DarkWatchmenHasPE);
-impl_edge_attributes!(DarkWatchmenHasJS);
-
 pub fn dark_watchmen_edge_definitions() -> Vec<EdgeDefinition> {
     vec![
         EdgeDefinition {
@@ -0,0 +1,273 @@
+/// Defines the nodes and edges of a malware family:
+///
+/// - the main node `$family` with `name`, `display_name`, [`FamilyMetadata`] and `first_analyzed`
+/// - a node per entry of `nodes` with a `sha256sum` and the given fields
+/// - an edge per entry of `edges` with `_key`, `_from`, `_to` and the given fields, edges with
+///   several sources or targets list them separated by `|`
+/// - `$edge_definitions()` with the edge definitions of the family
+/// - `SAMPLE_COLLECTIONS` with the collections that are written to while handling a sample
+/// - `ensure_family_collections(gc)` that creates the collections of the family and a unique
+///   index on `sha256sum` for the nodes through `FocusedGraph::ensure_indexes`, which warns about
+///   index drift
+///
+/// The names of the collections are the names of the types.
+///
+/// ```ignore
+/// define_family! {
+///     family: Example,
+///     edge_definitions: example_edge_definitions,
+///     nodes: {
+///         ExampleScript { pub script_type: ScriptType },
+///     },
+///     edges: {
+///         ExampleHasScript: Example | ExampleScript -> ExampleScript {},
+///     },
+/// }
+/// ```
+///
+/// [`FamilyMetadata`]: crate::graph_creators::focused_graph::FamilyMetadata
+macro_rules! define_family {
+    (
+        family: $family:ident,
+        edge_definitions: $edge_definitions:ident,
+        nodes: {
+            $(
+                $(#[$node_meta:meta])*
+                $node:ident {
+                    $($(#[$node_field_meta:meta])* $node_vis:vis $node_field:ident: $node_ty:ty),* $(,)?
+                }
+            ),* $(,)?
+        },
+        edges: {
+            $(
+                $(#[$edge_meta:meta])*
+                $edge:ident: $($from:ident)|+ -> $($to:ident)|+ {
+                    $($(#[$edge_field_meta:meta])* $edge_vis:vis $edge_field:ident: $edge_ty:ty),* $(,)?
+                }
+            ),* $(,)?
+        } $(,)?
+    ) => {
+        #[derive(
+            ::serde::Deserialize,
+            ::serde::Serialize,
+            Debug,
+            Clone,
+            ::schemars::JsonSchema,
+            Default,
+            ::macon_cag::utils::CollectionName,
+        )]
+        pub struct $family {
+            pub name: String,
+            pub display_name: String,
+
+            #[serde(flatten)]
+            pub metadata: $crate::graph_creators::focused_graph::FamilyMetadata,
+
+            /// Date (YYYY-MM-DD) the family was analyzed for the first time
+            pub first_analyzed: Option<String>,
+        }
+
+        $(
+            $(#[$node_meta])*
+            #[derive(
+                ::serde::Deserialize,
+                ::serde::Serialize,
+                Debug,
+                Clone,
+                ::schemars::JsonSchema,
+                ::macon_cag::utils::CollectionName,
+            )]
+            pub struct $node {
+                pub sha256sum: String,
+                $($(#[$node_field_meta])* $node_vis $node_field: $node_ty,)*
+            }
+        )*
+
+        $(
+            $(#[$edge_meta])*
+            #[derive(
+                ::serde::Deserialize,
+                ::serde::Serialize,
+                Debug,
+                Clone,
+                ::schemars::JsonSchema,
+                Default,
+                ::macon_cag::base_creator::EdgeAttributes,
+                ::macon_cag::utils::CollectionName,
+            )]
+            pub struct $edge {
+                pub _key: String,
+                pub _from: String,
+                pub _to: String,
+                $($(#[$edge_field_meta])* $edge_vis $edge_field: $edge_ty,)*
+            }
+        )*
+
+        pub fn $edge_definitions() -> Vec<::arangors::graph::EdgeDefinition> {
+            use ::macon_cag::utils::get_name;
+
+            vec![
+                $(
+                    ::arangors::graph::EdgeDefinition {
+                        collection: get_name::<$edge>(),
+                        from: vec![$(get_name::<$from>()),+],
+                        to: vec![$(get_name::<$to>()),+],
+                    },
+                )*
+            ]
+        }
+
+        /// Collections that are written to while handling a sample
+        pub const SAMPLE_COLLECTIONS: &[&str] = &[
+            $(<$node as ::macon_cag::utils::CollectionName>::NAME,)*
+            $(<$edge as ::macon_cag::utils::CollectionName>::NAME,)*
+        ];
+
+        /// Creates the collections of the family and a unique index on `sha256sum` for the nodes
+        pub(in $crate::graph_creators::focused_graph) fn ensure_family_collections(
+            gc: &$crate::graph_creators::focused_graph::FocusedGraph,
+        ) -> ::anyhow::Result<()> {
+            use ::arangors::collection::CollectionType;
+            use ::macon_cag::utils::{CollectionOptions, ensure_collection};
+
+            if gc.dry_run.is_some() {
+                return Ok(());
+            }
+
+            let db = ::macon_cag::base_creator::GraphCreatorBase::get_db(gc)?;
+            let options = CollectionOptions::default();
+
+            ensure_collection::<$family>(db, CollectionType::Document, None, None, options)?;
+            $(
+                ensure_collection::<$node>(db, CollectionType::Document, None, None, options)?;
+                gc.ensure_indexes::<$node>(vec!["sha256sum".to_string()])?;
+            )*
+            $(
+                ensure_collection::<$edge>(db, CollectionType::Edge, None, None, options)?;
+            )*
+
+            Ok(())
+        }
+    };
+}
+
+pub(crate) use define_family;
+
+#[cfg(test)]
+mod tests {
+    use arangors::graph::EdgeDefinition;
+    use macon_cag::{
+        base_creator::EdgeAttributes,
+        utils::{CollectionName, config::Config, edge_key},
+    };
+    use schemars::JsonSchema;
+    use serde::{Deserialize, Serialize};
+
+    use crate::graph_creators::{
+        COLLECTION_PREFIX_LOCK,
+        focused_graph::{FamilyMetadata, FocusedGraph},
+    };
+
+    define_family! {
+        family: Example,
+        edge_definitions: example_edge_definitions,
+        nodes: {
+            ExampleScript { pub script_type: ScriptType },
+            #[derive(Default)]
+            ExamplePayload {},
+        },
+        edges: {
+            ExampleHasScript: Example | ExampleScript -> ExampleScript {},
+            ExampleHasPayload: ExampleScript -> ExamplePayload | ExampleScript {
+                pub stage: u32,
+            },
+        },
+    }
+
+    #[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, PartialEq)]
+    pub enum ScriptType {
+        Loader,
+    }
+
+    #[test]
+    fn edge_definitions() {
+        let _lock = COLLECTION_PREFIX_LOCK.lock().unwrap();
+
+        let names = |ed: &EdgeDefinition| (ed.collection.clone(), ed.from.clone(), ed.to.clone());
+        let edge_definitions: Vec<_> = example_edge_definitions().iter().map(names).collect();
+
+        assert_eq!(
+            edge_definitions,
+            [
+                (
+                    "ExampleHasScript".to_string(),
+                    vec!["Example".to_string(), "ExampleScript".to_string()],
+                    vec!["ExampleScript".to_string()],
+                ),
+                (
+                    "ExampleHasPayload".to_string(),
+                    vec!["ExampleScript".to_string()],
+                    vec!["ExamplePayload".to_string(), "ExampleScript".to_string()],
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn sample_collections() {
+        assert_eq!(Example::NAME, "Example");
+        assert_eq!(
+            SAMPLE_COLLECTIONS,
+            [
+                "ExampleScript",
+                "ExamplePayload",
+                "ExampleHasScript",
+                "ExampleHasPayload"
+            ]
+        );
+    }
+
+    #[test]
+    fn generated_structs() {
+        let main = Example {
+            name: "Example".to_string(),
+            display_name: "Example".to_string(),
+            metadata: FamilyMetadata::default(),
+            first_analyzed: None,
+        };
+        let main = serde_json::to_value(main).unwrap();
+        assert_eq!(main["name"], "Example");
+        assert!(main.get("first_analyzed").is_some());
+
+        let script = ExampleScript {
+            sha256sum: "abc".to_string(),
+            script_type: ScriptType::Loader,
+        };
+        let script = serde_json::to_value(script).unwrap();
+        assert_eq!(script["sha256sum"], "abc");
+        assert_eq!(script["script_type"], "Loader");
+        assert_eq!(ExamplePayload::default().sha256sum, "");
+
+        let mut edge = ExampleHasPayload {
+            stage: 2,
+            ..Default::default()
+        };
+        edge.apply_edge_attributes(
+            "ExampleScript/a".to_string(),
+            "ExamplePayload/b".to_string(),
+        );
+        assert_eq!(edge._from, "ExampleScript/a");
+        assert_eq!(edge._to, "ExamplePayload/b");
+        assert_eq!(
+            edge.get_key(),
+            edge_key("ExampleScript/a", "ExamplePayload/b")
+        );
+        assert_eq!(edge.stage, 2);
+    }
+
+    #[test]
+    fn ensure_family_collections_skips_dry_runs() -> anyhow::Result<()> {
+        let gc = FocusedGraph::try_new(&Config::default(), true, false)?;
+        ensure_family_collections(&gc)
+    }
+}
@@ -0,0 +1,105 @@
+use std::{collections::HashSet, path::PathBuf};
+
+use anyhow::Result;
+use arangors::collection::CollectionType;
+use macon_cag::{
+    base_creator::GraphCreatorBase,
+    filter::Filter,
+    utils::{CollectionName, CollectionOptions, ensure_collection},
+};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha256::digest;
+use tracing::{debug, info};
+
+use crate::{
+    graph_creators::focused_graph::{FocusedGraph, today},
+    report::{Stage, StageError},
+    utils::{SampleRef, load_sample},
+};
+
+/// Maximum number of hashes per lookup of [`FocusedGraph::skip_known`]
+const KNOWN_BATCH_SIZE: usize = 1000;
+
+/// Sample that was analyzed successfully, used by `--skip-known`
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, CollectionName)]
+pub struct IngestedFile {
+    pub sha256sum: String,
+
+    /// Name of the file the sample was read from the first time
+    pub file_name: String,
+
+    /// Date (YYYY-MM-DD) the sample was analyzed
+    pub ingested_at: String,
+}
+
+impl FocusedGraph {
+    /// Creates the collection of the analyzed samples and the index for the sha256sum field
+    pub(super) fn ensure_ingested_collection(&self) -> Result<()> {
+        if self.dry_run.is_none() {
+            ensure_collection::<IngestedFile>(
+                self.get_db()?,
+                CollectionType::Document,
+                Some(vec!["sha256sum".to_string()]),
+                None,
+                CollectionOptions::default(),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes the samples from `files` that were already analyzed. Returns the removed samples
+    /// with their sha256. Samples that can't be read are kept, so they are reported by the family.
+    pub(super) fn skip_known(&self, files: &mut Vec<PathBuf>) -> Result<Vec<(PathBuf, String)>> {
+        let hashes: Vec<Option<String>> = files
+            .par_iter()
+            .map(|file| load_sample(file).ok().map(|data| digest(&*data)))
+            .collect();
+
+        let mut known = HashSet::new();
+        let lookup: Vec<&String> = hashes.iter().flatten().collect();
+        for chunk in lookup.chunks(KNOWN_BATCH_SIZE) {
+            let filter = Filter::new().in_("sha256sum", chunk.iter().map(|h| h.as_str()));
+            for doc in self.get_documents_filtered::<IngestedFile>(filter, None)? {
+                known.insert(doc.document.sha256sum);
+            }
+        }
+
+        let before = files.len();
+        let mut skipped = vec![];
+        for (file, hash) in std::mem::take(files).into_iter().zip(hashes) {
+            match hash {
+                Some(hash) if known.contains(&hash) => skipped.push((file, hash)),
+                _ => files.push(file),
+            }
+        }
+
+        info!(
+            "Skipping {} of {before} samples that were already analyzed",
+            skipped.len()
+        );
+        Ok(skipped)
+    }
+
+    /// Marks the sample read from `file` as analyzed. A failure is reported as an error of the
+    /// sample, as it would be analyzed again with `--skip-known`.
+    pub(super) fn sample_analyzed(&self, file: &SampleRef, data: &[u8]) {
+        let ingested = IngestedFile {
+            sha256sum: digest(data),
+            file_name: file
+                .path()
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            ingested_at: today(),
+        };
+        let sha256sum = ingested.sha256sum.clone();
+
+        match self.upsert_node::<IngestedFile>(ingested, "sha256sum", sha256sum) {
+            Ok(_) => debug!(sample = %file, "analyzed sample"),
+            Err(e) => self.errors.push(file, StageError::wrap(Stage::Db, e)),
+        }
+    }
+}
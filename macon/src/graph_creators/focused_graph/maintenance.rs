@@ -0,0 +1,253 @@
+//! Maintenance jobs that run periodically between the batches of `--watch`, see `--maintenance`
+
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, anyhow};
+use arangors::AqlQuery;
+use macon_cag::{base_creator::GraphCreatorBase, export::graph_collections, utils::get_name};
+use tracing::{debug, info};
+
+use crate::{
+    corpus_stats::corpus_stats,
+    graph_creators::focused_graph::{FocusedCorpus, FocusedGraph},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaintenanceJob {
+    /// Logs the statistics of the corpus, like the `stats` subcommand
+    Stats,
+
+    /// Creates missing indexes and collections and warns about differing ones
+    Reconcile,
+
+    /// Deletes the edges whose start or end vertex doesn't exist anymore
+    Prune,
+}
+
+impl MaintenanceJob {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MaintenanceJob::Stats => "stats",
+            MaintenanceJob::Reconcile => "reconcile",
+            MaintenanceJob::Prune => "prune",
+        }
+    }
+}
+
+/// Job of `--maintenance` and the interval it runs in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaintenanceSchedule {
+    pub job: MaintenanceJob,
+    pub every: Duration,
+}
+
+/// Parses `<job>=<interval>`, e.g. `stats=1h`
+pub fn parse_maintenance(s: &str) -> Result<MaintenanceSchedule, String> {
+    let Some((job, every)) = s.split_once('=') else {
+        return Err("The maintenance job has to be '<job>=<interval>', e.g. stats=1h".to_string());
+    };
+
+    let job = match job {
+        "stats" => MaintenanceJob::Stats,
+        "reconcile" => MaintenanceJob::Reconcile,
+        "prune" => MaintenanceJob::Prune,
+        _ => {
+            return Err(format!(
+                "Unknown maintenance job '{job}', expected stats, reconcile or prune"
+            ));
+        }
+    };
+
+    Ok(MaintenanceSchedule {
+        job,
+        every: parse_interval(every)?,
+    })
+}
+
+/// Parses an interval like `30s`, `15m`, `6h` or `1d`
+fn parse_interval(interval: &str) -> Result<Duration, String> {
+    let (number, factor) = match interval.char_indices().last() {
+        Some((i, 's')) => (&interval[..i], 1),
+        Some((i, 'm')) => (&interval[..i], 60),
+        Some((i, 'h')) => (&interval[..i], 60 * 60),
+        Some((i, 'd')) => (&interval[..i], 24 * 60 * 60),
+        _ => {
+            return Err(format!(
+                "Invalid interval '{interval}', expected e.g. 30s, 15m, 6h or 1d"
+            ));
+        }
+    };
+
+    match number.parse::<u64>() {
+        Ok(0) => Err("The interval has to be longer than 0".to_string()),
+        Ok(number) => number
+            .checked_mul(factor)
+            .map(Duration::from_secs)
+            .ok_or_else(|| format!("Interval '{interval}' is too long")),
+        Err(_) => Err(format!(
+            "Invalid interval '{interval}', expected e.g. 30s, 15m, 6h or 1d"
+        )),
+    }
+}
+
+/// Time each job of `--maintenance` is due next. A job first runs one interval after the start,
+/// a job that took longer than its interval runs again one interval after it finished.
+pub(super) struct Scheduler {
+    jobs: Vec<(MaintenanceSchedule, Instant)>,
+}
+
+impl Scheduler {
+    pub(super) fn new(schedules: &[MaintenanceSchedule], now: Instant) -> Self {
+        Self {
+            jobs: schedules
+                .iter()
+                .map(|schedule| (*schedule, now + schedule.every))
+                .collect(),
+        }
+    }
+
+    /// Time the next job is due, `None` without jobs
+    pub(super) fn next_due(&self) -> Option<Instant> {
+        self.jobs.iter().map(|(_, due)| *due).min()
+    }
+
+    /// Jobs that are due at `now`, in the order they were given. They are due again one interval
+    /// after `now`.
+    pub(super) fn due(&mut self, now: Instant) -> Vec<MaintenanceJob> {
+        let mut jobs = vec![];
+        for (schedule, due) in &mut self.jobs {
+            if *due <= now {
+                jobs.push(schedule.job);
+                *due = now + schedule.every;
+            }
+        }
+        jobs
+    }
+}
+
+impl FocusedGraph {
+    /// Runs a maintenance job on the database
+    pub(super) fn run_maintenance(&self, job: MaintenanceJob) -> Result<()> {
+        debug!(job = job.as_str(), "running maintenance job");
+
+        match job {
+            MaintenanceJob::Stats => {
+                let stats = corpus_stats(
+                    self.get_db()?,
+                    &self.graph,
+                    &get_name::<FocusedCorpus>(),
+                    None,
+                )?;
+                info!(stats = %serde_json::to_string(&stats)?, "corpus statistics");
+            }
+            MaintenanceJob::Reconcile => {
+                self.ensure_main_indexes()?;
+                self.ensure_ingested_collection()?;
+            }
+            MaintenanceJob::Prune => {
+                let removed = self.remove_dangling_edges()?;
+                info!("Removed {removed} edges whose vertices don't exist anymore");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Deletes the edges of the graph whose `_from` or `_to` vertex doesn't exist, e.g. because
+    /// the vertex was deleted outside of macon. Returns the number of deleted edges.
+    fn remove_dangling_edges(&self) -> Result<u64> {
+        let (_, edge_collections) = graph_collections(self.get_db()?, &self.graph)
+            .map_err(|e| anyhow!("Graph '{}' can't be read: {e}", self.graph))?;
+
+        let mut removed = 0;
+        for collection in edge_collections {
+            let aql = AqlQuery::builder()
+                .query(
+                    "let removed = (
+                        for e in @@edges
+                            filter document(e._from) == null || document(e._to) == null
+                            remove e in @@edges
+                            return 1
+                    )
+                    return length(removed)",
+                )
+                .bind_var("@edges", collection.as_str())
+                .build();
+
+            removed += self.run_aql::<u64>(aql)?.into_iter().sum::<u64>();
+        }
+
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule(job: MaintenanceJob, secs: u64) -> MaintenanceSchedule {
+        MaintenanceSchedule {
+            job,
+            every: Duration::from_secs(secs),
+        }
+    }
+
+    #[test]
+    fn maintenance_schedules() {
+        assert_eq!(
+            parse_maintenance("stats=90s"),
+            Ok(schedule(MaintenanceJob::Stats, 90))
+        );
+        assert_eq!(
+            parse_maintenance("reconcile=6h"),
+            Ok(schedule(MaintenanceJob::Reconcile, 6 * 60 * 60))
+        );
+        assert_eq!(
+            parse_maintenance("prune=1d"),
+            Ok(schedule(MaintenanceJob::Prune, 24 * 60 * 60))
+        );
+        assert_eq!(parse_interval("15m"), Ok(Duration::from_secs(15 * 60)));
+
+        for invalid in [
+            "stats",
+            "archive=1h",
+            "stats=",
+            "stats=1",
+            "stats=0m",
+            "stats=-1h",
+        ] {
+            assert!(parse_maintenance(invalid).is_err(), "{invalid}");
+        }
+        assert!(parse_interval("99999999999999999d").is_err());
+    }
+
+    #[test]
+    fn jobs_are_due_once_per_interval() {
+        let start = Instant::now();
+        let mut scheduler = Scheduler::new(
+            &[
+                schedule(MaintenanceJob::Stats, 60),
+                schedule(MaintenanceJob::Prune, 150),
+            ],
+            start,
+        );
+        let at = |secs| start + Duration::from_secs(secs);
+
+        // nothing runs right at the start
+        assert_eq!(scheduler.next_due(), Some(at(60)));
+        assert!(scheduler.due(at(59)).is_empty());
+
+        assert_eq!(scheduler.due(at(60)), [MaintenanceJob::Stats]);
+        assert_eq!(scheduler.next_due(), Some(at(120)));
+
+        // a late check runs every due job once and reschedules from then
+        assert_eq!(
+            scheduler.due(at(200)),
+            [MaintenanceJob::Stats, MaintenanceJob::Prune]
+        );
+        assert_eq!(scheduler.next_due(), Some(at(260)));
+        assert!(scheduler.due(at(259)).is_empty());
+
+        assert_eq!(Scheduler::new(&[], start).next_due(), None);
+    }
+}
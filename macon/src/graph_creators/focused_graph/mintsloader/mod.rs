@@ -3,38 +3,54 @@ pub mod nodes;
 use std::{
     io::{Cursor, Read},
     path::PathBuf,
-    sync::{Arc, Mutex},
 };
 
 use anyhow::{Result, anyhow};
 use arangors::Document;
-use base64::{
-    Engine, alphabet,
-    engine::{GeneralPurpose, general_purpose::PAD},
-};
 use flate2::bufread::GzDecoder;
-use indicatif::ParallelProgressIterator;
 use lazy_static::lazy_static;
 use macon_cag::{
     base_creator::{GraphCreatorBase, UpsertResult},
-    utils::ensure_index,
+    transaction::TxnHandle,
+    utils::CollectionName,
 };
-use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 use regex::Regex;
 use sha256::digest;
 use shunting::{MathContext, ShuntingParser};
+use tracing::warn;
 
 use crate::{
+    classify::MalwareFamily,
+    detection::Detection,
     graph_creators::focused_graph::{
-        FocusedCorpus, FocusedGraph, HasMalwareFamily,
+        FamilyMetadata, FocusedCorpus, FocusedGraph, HasMalwareFamily,
         mintsloader::nodes::{
-            Mintsloader, MintsloaderCS, MintsloaderHasCS, MintsloaderHasPs, MintsloaderHasX509Cert,
-            MintsloaderPs, MintsloaderPsKind, MintsloaderX509Cert,
+            Mintsloader, MintsloaderCS, MintsloaderDga, MintsloaderHasCS, MintsloaderHasPs,
+            MintsloaderHasX509Cert, MintsloaderPs, MintsloaderPsKind, MintsloaderX509Cert,
         },
+        samples::SampleOutcome,
+        today,
     },
-    utils::get_string_from_binary,
+    report::{Stage, StageError},
+    utils::{SampleRef, decode_base64_lenient, get_string_from_binary},
 };
 
+/// Collections that are written to while handling a sample
+const SAMPLE_COLLECTIONS: [&str; 6] = [
+    MintsloaderPs::NAME,
+    MintsloaderCS::NAME,
+    MintsloaderX509Cert::NAME,
+    MintsloaderHasPs::NAME,
+    MintsloaderHasCS::NAME,
+    MintsloaderHasX509Cert::NAME,
+];
+
+/// Extensions of files the scripts write or load, which are no TLDs
+const FILE_EXTENSIONS: [&str; 14] = [
+    "bat", "bin", "dat", "dll", "exe", "html", "js", "json", "log", "php", "ps1", "tmp", "txt",
+    "zip",
+];
+
 lazy_static! {
     static ref RE_FUNCTION: Regex = {
         let s = r#"function\s+(?<function>[A-z0-9]+)\s+\{param\([^\)]+\)"#;
@@ -44,6 +60,35 @@ lazy_static! {
         let s = r#"\("(?<key>[A-z0-9]{12})"\)"#;
         Regex::new(s).unwrap()
     };
+    static ref RE_DGA_SEED: Regex = {
+        let s = r#"(?i)\$\w*(?:seed|key)\w*\s*=\s*["'](?<seed>[^"']+)["']"#;
+        Regex::new(s).unwrap()
+    };
+    // the TLD that is appended to the generated name, e.g. $domain + ".top" or $d += '.top'
+    static ref RE_TLD: Regex = {
+        let s = r#"\$[\w:]+\s*\+=?\s*["']\.(?<tld>[A-Za-z]{2,12})["']"#;
+        Regex::new(s).unwrap()
+    };
+    static ref RE_URL_PATH: Regex = {
+        let s = r#"["'](?<path>/[A-Za-z0-9_\-./]+(?:\?[^"'\s]*)?)["']"#;
+        Regex::new(s).unwrap()
+    };
+    static ref RE_URL: Regex = {
+        let s = r#"https?://[^"'\s)/]*\.(?<tld>[A-Za-z]{2,12})(?<path>/[^"'\s)]*)?"#;
+        Regex::new(s).unwrap()
+    };
+    // the modulo of the date or the seed derived from it, e.g. (Get-Date).DayOfYear % 3 or
+    // $day % 7
+    static ref RE_MODULUS: Regex = {
+        let s = r#"(?i)(?:\$\w*(?:date|day|seed)\w*|\(get-date[^)]*\)(?:\.\w+)*)\s*%\s*(?<modulus>\d+)"#;
+        Regex::new(s).unwrap()
+    };
+    static ref RE_ENCODED_COMMAND: Regex = {
+        // the flag is an argument of the command line or a quoted element of -ArgumentList,
+        // e.g. -ArgumentList "-e <base64>" or -ArgumentList '-enc','<base64>'
+        let s = r#"(?i)["'\s]-(?:e|ec|enc|encodedcommand)(?:['"]?\s*,\s*|\s+)['"]?(?<base64>[A-Za-z0-9+/]{16,}={0,2})"#;
+        Regex::new(s).unwrap()
+    };
 }
 
 impl FocusedGraph {
@@ -52,44 +97,18 @@ impl FocusedGraph {
         files: &[PathBuf],
         corpus_node: &Document<FocusedCorpus>,
     ) -> Result<()> {
-        let db = self.get_db();
         let idx = vec!["sha256sum".to_string()];
 
         // Create index for sha256sum field
-        ensure_index::<MintsloaderPs>(db, idx.clone())?;
-        ensure_index::<MintsloaderCS>(db, idx.clone())?;
-        ensure_index::<MintsloaderX509Cert>(db, idx)?;
+        self.ensure_indexes::<MintsloaderPs>(idx.clone())?;
+        self.ensure_indexes::<MintsloaderCS>(idx.clone())?;
+        self.ensure_indexes::<MintsloaderX509Cert>(idx)?;
 
         let main_node = self.mintsloader_create_main_node(corpus_node)?;
 
-        let errors: Arc<Mutex<Vec<anyhow::Error>>> = Arc::new(Mutex::new(Vec::new()));
-
-        files
-            .par_iter()
-            .progress()
-            .for_each(|entry| match std::fs::File::open(entry) {
-                Ok(mut file) => {
-                    let mut buf = Vec::new();
-                    match file.read_to_end(&mut buf) {
-                        Ok(_) => {
-                            match self.mintsloader_handle_sample(
-                                &format!("{entry:?}"),
-                                &buf,
-                                &main_node,
-                            ) {
-                                Ok(_) => (),
-                                Err(e) => errors.lock().unwrap().push(e),
-                            }
-                        }
-                        Err(e) => errors.lock().unwrap().push(e.into()),
-                    }
-                }
-                Err(e) => errors.lock().unwrap().push(e.into()),
-            });
-
-        for e in errors.lock().unwrap().iter() {
-            eprintln!("{e}");
-        }
+        self.process_samples(files, MalwareFamily::Mintsloader, true, |entry, buf| {
+            self.mintsloader_handle_sample(entry, buf, &main_node)
+        });
 
         Ok(())
     }
@@ -101,12 +120,25 @@ impl FocusedGraph {
         let mintsloader = Mintsloader {
             name: "Mintsloader".to_string(),
             display_name: "Mintsloader".to_string(),
+            metadata: FamilyMetadata::from_json(include_str!("metadata.json"))?,
+            first_analyzed: Some(today()),
         };
 
         let UpsertResult {
             document: main_node,
-            created: _,
-        } = self.upsert_node::<Mintsloader>(mintsloader, "name", "Mintsloader")?;
+            created,
+            ..
+        } = self.upsert_node::<Mintsloader>(mintsloader.clone(), "name", "Mintsloader")?;
+
+        // keep the metadata of an existing main node up to date
+        if !created {
+            let first_analyzed = main_node
+                .document
+                .first_analyzed
+                .clone()
+                .or(mintsloader.first_analyzed);
+            self.update_family_metadata(&main_node, &mintsloader.metadata, first_analyzed)?;
+        }
 
         self.upsert_edge::<FocusedCorpus, Mintsloader, HasMalwareFamily>(corpus_node, &main_node)?;
 
@@ -115,181 +147,242 @@ impl FocusedGraph {
 
     fn mintsloader_handle_sample(
         &self,
-        sample_filename: &str,
+        sample: &SampleRef,
         sample_data: &[u8],
         main_node: &Document<Mintsloader>,
-    ) -> Result<()> {
-        let Some(sample_type) = detect_sample_type(sample_data) else {
-            return Err(anyhow!(
-                "Sample type of the sample {sample_filename} could not be detected"
+    ) -> Result<SampleOutcome> {
+        let detection = detect_sample_type(sample_data);
+        let ambiguity = detection.ambiguity_report(&sample.to_string());
+
+        let Some(candidate) = detection.into_best() else {
+            return Err(StageError::wrap(
+                Stage::Detect,
+                anyhow!("Sample type of the sample {sample} could not be detected"),
             ));
         };
-
-        match sample_type {
-            SampleType::PS(ps_kind) => {
-                let ps_node = self.mintsloader_create_ps_node(sample_data, ps_kind)?;
-                self.upsert_edge::<Mintsloader, MintsloaderPs, MintsloaderHasPs>(
-                    main_node, &ps_node,
-                )?;
-            }
-            SampleType::CS => {
-                self.mintsloader_create_cs_node(sample_data)?;
-            }
-            SampleType::X509 => {
-                self.mintsloader_create_x509_node(sample_data)?;
+        let attributes = candidate.node_attributes();
+        let sample_type = candidate.sample_type.as_str();
+
+        let documents = self.transaction(&SAMPLE_COLLECTIONS, |txn| {
+            match candidate.sample_type {
+                SampleType::PS(ps_kind) => {
+                    let ps_node = self.mintsloader_create_ps_node(txn, sample_data, ps_kind)?;
+                    // the detection of an existing node is kept as it was first analyzed
+                    if ps_node.created {
+                        txn.update_node_attributes(&ps_node.document, attributes)?;
+                    }
+                    txn.upsert_edge::<Mintsloader, MintsloaderPs, MintsloaderHasPs>(
+                        main_node,
+                        &ps_node.document,
+                    )?;
+                }
+                SampleType::CS => {
+                    let cs_node = self.mintsloader_create_cs_node(txn, sample_data)?;
+                    if cs_node.created {
+                        txn.update_node_attributes(&cs_node.document, attributes)?;
+                    }
+                }
+                SampleType::X509 => {
+                    let x509_node = self.mintsloader_create_x509_node(txn, sample_data)?;
+                    if x509_node.created {
+                        txn.update_node_attributes(&x509_node.document, attributes)?;
+                    }
+                }
             }
-        }
 
-        Ok(())
+            Ok(txn.written_documents())
+        })?;
+
+        Ok(SampleOutcome {
+            sample_type,
+            documents,
+            ambiguity,
+        })
     }
 
     fn mintsloader_create_ps_node(
         &self,
+        txn: &TxnHandle,
         sample_data: &[u8],
         ps_kind: PSKind,
-    ) -> Result<Document<MintsloaderPs>> {
+    ) -> Result<UpsertResult<MintsloaderPs>> {
         match ps_kind {
             PSKind::Xor_B64(xor_key, base64) => {
-                self.mintsloader_create_ps_xor_node(sample_data, &xor_key, &base64)
+                self.mintsloader_create_ps_xor_node(txn, sample_data, &xor_key, &base64)
             }
-            PSKind::DGA_iex => self.mintsloader_create_ps_dga_iex_node(sample_data),
-            PSKind::Start_Process => self.mintsloader_create_ps_start_process_node(sample_data),
-            PSKind::Two_Liner => self.mintsloader_create_ps_two_liner_node(sample_data),
+            PSKind::DGA_iex => self.mintsloader_create_ps_dga_iex_node(txn, sample_data),
+            PSKind::Start_Process => {
+                self.mintsloader_create_ps_start_process_node(txn, sample_data)
+            }
+            PSKind::Two_Liner => self.mintsloader_create_ps_two_liner_node(txn, sample_data),
         }
     }
 
     fn mintsloader_create_ps_xor_node(
         &self,
+        txn: &TxnHandle,
         sample_data: &[u8],
         xor_key: &str,
         base64: &str,
-    ) -> Result<Document<MintsloaderPs>> {
+    ) -> Result<UpsertResult<MintsloaderPs>> {
         let sha256sum = digest(sample_data);
 
         let ps_xor_data = MintsloaderPs {
             sha256sum: sha256sum.clone(),
             kind: MintsloaderPsKind::XorBase64,
+            dga: None,
         };
 
-        let UpsertResult {
-            document: ps_xor_node,
-            created,
-        } = self.upsert_node::<MintsloaderPs>(ps_xor_data, "sha256sum", &sha256sum)?;
+        let ps_xor_node = txn.upsert_node::<MintsloaderPs>(ps_xor_data, "sha256sum", &sha256sum)?;
 
         // Sample is already in DB => no need for further analysis
-        if !created {
+        if !ps_xor_node.created {
             return Ok(ps_xor_node);
         }
 
         // extract next stage
         let next_stage = decode_base64_with_xor_key(xor_key, base64)?;
         if next_stage.contains("$executioncontext;") {
-            let ps_dga_iex_node = self.mintsloader_create_ps_dga_iex_node(next_stage.as_bytes())?;
-            self.upsert_edge::<MintsloaderPs, MintsloaderPs, MintsloaderHasPs>(
-                &ps_xor_node,
-                &ps_dga_iex_node,
+            let ps_dga_iex_node =
+                self.mintsloader_create_ps_dga_iex_node(txn, next_stage.as_bytes())?;
+            txn.upsert_edge::<MintsloaderPs, MintsloaderPs, MintsloaderHasPs>(
+                &ps_xor_node.document,
+                &ps_dga_iex_node.document,
             )?;
         } else if next_stage.contains("start-process powershell") {
             let ps_start_process_node =
-                self.mintsloader_create_ps_start_process_node(next_stage.as_bytes())?;
-            self.upsert_edge::<MintsloaderPs, MintsloaderPs, MintsloaderHasPs>(
-                &ps_xor_node,
-                &ps_start_process_node,
+                self.mintsloader_create_ps_start_process_node(txn, next_stage.as_bytes())?;
+            txn.upsert_edge::<MintsloaderPs, MintsloaderPs, MintsloaderHasPs>(
+                &ps_xor_node.document,
+                &ps_start_process_node.document,
             )?;
         }
 
         // check for C# code snippet and X.509 certificate
-        self.mintsloader_extract_cs_and_cert_from_ps(sample_data, &ps_xor_node)?;
+        self.mintsloader_extract_cs_and_cert_from_ps(txn, sample_data, &ps_xor_node.document)?;
 
         Ok(ps_xor_node)
     }
 
     fn mintsloader_create_ps_dga_iex_node(
         &self,
+        txn: &TxnHandle,
         sample_data: &[u8],
-    ) -> Result<Document<MintsloaderPs>> {
+    ) -> Result<UpsertResult<MintsloaderPs>> {
         let sha256sum = digest(sample_data);
 
+        let sample_str = get_string_from_binary(sample_data);
         let ps_dga_iex_data = MintsloaderPs {
             sha256sum: sha256sum.clone(),
             kind: MintsloaderPsKind::DgaIex,
+            dga: Some(extract_dga_parameters(&sample_str)),
         };
 
-        let UpsertResult {
-            document: ps_dga_iex_node,
-            created: _,
-        } = self.upsert_node::<MintsloaderPs>(ps_dga_iex_data, "sha256sum", &sha256sum)?;
+        let ps_dga_iex_node =
+            txn.upsert_node::<MintsloaderPs>(ps_dga_iex_data, "sha256sum", &sha256sum)?;
 
         Ok(ps_dga_iex_node)
     }
 
     fn mintsloader_create_ps_start_process_node(
         &self,
+        txn: &TxnHandle,
         sample_data: &[u8],
-    ) -> Result<Document<MintsloaderPs>> {
+    ) -> Result<UpsertResult<MintsloaderPs>> {
         let sha256sum = digest(sample_data);
 
         let ps_start_process_data = MintsloaderPs {
             sha256sum: sha256sum.clone(),
             kind: MintsloaderPsKind::StartProcess,
+            dga: None,
         };
 
-        let UpsertResult {
-            document: ps_start_process_node,
-            created: _,
-        } = self.upsert_node::<MintsloaderPs>(ps_start_process_data, "sha256sum", &sha256sum)?;
+        let ps_start_process_node =
+            txn.upsert_node::<MintsloaderPs>(ps_start_process_data, "sha256sum", &sha256sum)?;
+
+        // Sample is already in DB => no need for further analysis
+        if !ps_start_process_node.created {
+            return Ok(ps_start_process_node);
+        }
+
+        // extract next stage, the encoded command of the started powershell
+        let sample_str = get_string_from_binary(sample_data);
+        let Some(next_stage) = extract_encoded_command(&sample_str)? else {
+            warn!("Could not find the encoded command in the start-process stage {sha256sum}");
+            return Ok(ps_start_process_node);
+        };
+
+        let next_stage_kind = detect_sample_type(next_stage.as_bytes())
+            .into_best()
+            .map(|candidate| candidate.sample_type);
+        match next_stage_kind {
+            Some(SampleType::PS(ps_kind)) => {
+                let next_stage_node =
+                    self.mintsloader_create_ps_node(txn, next_stage.as_bytes(), ps_kind)?;
+                txn.upsert_edge::<MintsloaderPs, MintsloaderPs, MintsloaderHasPs>(
+                    &ps_start_process_node.document,
+                    &next_stage_node.document,
+                )?;
+            }
+            _ => warn!("Unknown next stage of the start-process stage {sha256sum}"),
+        }
 
         Ok(ps_start_process_node)
     }
 
     fn mintsloader_create_ps_two_liner_node(
         &self,
+        txn: &TxnHandle,
         sample_data: &[u8],
-    ) -> Result<Document<MintsloaderPs>> {
+    ) -> Result<UpsertResult<MintsloaderPs>> {
         let sha256sum = digest(sample_data);
 
         let ps_two_liner_data = MintsloaderPs {
             sha256sum: sha256sum.clone(),
             kind: MintsloaderPsKind::TwoLiner,
+            dga: None,
         };
 
-        let UpsertResult {
-            document: ps_two_liner_node,
-            created,
-        } = self.upsert_node::<MintsloaderPs>(ps_two_liner_data, "sha256sum", &sha256sum)?;
+        let ps_two_liner_node =
+            txn.upsert_node::<MintsloaderPs>(ps_two_liner_data, "sha256sum", &sha256sum)?;
 
         // Sample was not created => already in db => can be aborted here
-        if !created {
+        if !ps_two_liner_node.created {
             return Ok(ps_two_liner_node);
         }
 
         // check for C# code snippet and X.509 certificate
-        self.mintsloader_extract_cs_and_cert_from_ps(sample_data, &ps_two_liner_node)?;
+        self.mintsloader_extract_cs_and_cert_from_ps(
+            txn,
+            sample_data,
+            &ps_two_liner_node.document,
+        )?;
 
         Ok(ps_two_liner_node)
     }
 
-    fn mintsloader_create_cs_node(&self, sample_data: &[u8]) -> Result<Document<MintsloaderCS>> {
+    fn mintsloader_create_cs_node(
+        &self,
+        txn: &TxnHandle,
+        sample_data: &[u8],
+    ) -> Result<UpsertResult<MintsloaderCS>> {
         let sha256sum = digest(sample_data);
 
         let ps_cs_data = MintsloaderCS {
             sha256sum: sha256sum.clone(),
         };
 
-        let UpsertResult {
-            document: ps_cs_node,
-            created: _,
-        } = self.upsert_node::<MintsloaderCS>(ps_cs_data, "sha256sum", &sha256sum)?;
+        let ps_cs_node = txn.upsert_node::<MintsloaderCS>(ps_cs_data, "sha256sum", &sha256sum)?;
 
         Ok(ps_cs_node)
     }
 
     fn mintsloader_create_x509_node(
         &self,
+        txn: &TxnHandle,
         sample_data: &[u8],
-    ) -> Result<Document<MintsloaderX509Cert>> {
-        let base64_decoder = GeneralPurpose::new(&alphabet::STANDARD, PAD);
-        let sample_data = base64_decoder.decode(sample_data)?;
+    ) -> Result<UpsertResult<MintsloaderX509Cert>> {
+        let sample_data = decode_base64_lenient(sample_data, Default::default())?;
 
         let sha256sum = digest(sample_data);
 
@@ -297,16 +390,15 @@ impl FocusedGraph {
             sha256sum: sha256sum.clone(),
         };
 
-        let UpsertResult {
-            document: ps_x509_node,
-            created: _,
-        } = self.upsert_node::<MintsloaderX509Cert>(ps_x509_data, "sha256sum", &sha256sum)?;
+        let ps_x509_node =
+            txn.upsert_node::<MintsloaderX509Cert>(ps_x509_data, "sha256sum", &sha256sum)?;
 
         Ok(ps_x509_node)
     }
 
     fn mintsloader_extract_cs_and_cert_from_ps(
         &self,
+        txn: &TxnHandle,
         sample_data: &[u8],
         ps_node: &Document<MintsloaderPs>,
     ) -> Result<()> {
@@ -315,13 +407,17 @@ impl FocusedGraph {
         for i in 0..2 {
             if let Some(string) = strings.get(i) {
                 if string.starts_with("MIIE") {
-                    let x509_node = self.mintsloader_create_x509_node(string.as_bytes())?;
-                    self.upsert_edge::<MintsloaderPs, MintsloaderX509Cert, MintsloaderHasX509Cert>(
+                    let x509_node = self
+                        .mintsloader_create_x509_node(txn, string.as_bytes())?
+                        .document;
+                    txn.upsert_counted_edge::<MintsloaderPs, MintsloaderX509Cert, MintsloaderHasX509Cert>(
                         ps_node, &x509_node,
                     )?;
                 } else if string.starts_with("using System") {
-                    let cs_node = self.mintsloader_create_cs_node(string.as_bytes())?;
-                    self.upsert_edge::<MintsloaderPs, MintsloaderCS, MintsloaderHasCS>(
+                    let cs_node = self
+                        .mintsloader_create_cs_node(txn, string.as_bytes())?
+                        .document;
+                    txn.upsert_counted_edge::<MintsloaderPs, MintsloaderCS, MintsloaderHasCS>(
                         ps_node, &cs_node,
                     )?;
                 }
@@ -347,7 +443,7 @@ fn extract_key_and_base64_from_ps_xor_base64(sample_str: &str) -> Result<(&str,
         .map(|c| c.extract::<1>())
         .map(|(_, [c])| c);
 
-    let s = r#"\s+"(?<base64>[A-z0-9+/=]+)""#;
+    let s = r#"\s+"(?<base64>[A-z0-9+/=-]+)""#;
     let s = format!("{function_name}{s}");
     let re = Regex::new(&s).unwrap();
     let base64 = re
@@ -362,9 +458,73 @@ fn extract_key_and_base64_from_ps_xor_base64(sample_str: &str) -> Result<(&str,
     Ok(res)
 }
 
+/// Parameters of the DGA of a DGA_iex stage. The constants are searched in the script and in
+/// its deobfuscated strings, the ones that can't be found are left empty.
+fn extract_dga_parameters(sample_str: &str) -> MintsloaderDga {
+    let mut script = sample_str.to_string();
+    for string in get_deobfuscated_strings_from_sample_sorted(sample_str) {
+        script.push('\n');
+        script.push_str(&string);
+    }
+
+    let capture = |re: &Regex| {
+        re.captures(&script)
+            .map(|c| c.extract::<1>())
+            .map(|(_, [c])| c.to_string())
+    };
+
+    // the domain is usually concatenated, otherwise the TLD and path are part of an
+    // interpolated URL like "http://$domain.top/1.php"
+    let url = RE_URL.captures(&script);
+    let url_part = |name: &str| {
+        url.as_ref()
+            .and_then(|c| c.name(name))
+            .map(|m| m.as_str().to_string())
+    };
+
+    let dga_seed = capture(&RE_DGA_SEED);
+    // file names are concatenated like domains
+    let tld = RE_TLD
+        .captures_iter(&script)
+        .map(|c| c["tld"].to_string())
+        .find(|tld| !FILE_EXTENSIONS.contains(&tld.to_lowercase().as_str()))
+        .or_else(|| url_part("tld"))
+        .map(|tld| tld.to_lowercase());
+    let url_path = capture(&RE_URL_PATH).or_else(|| url_part("path"));
+    let modulus = capture(&RE_MODULUS).and_then(|m| m.parse().ok());
+
+    MintsloaderDga {
+        extraction_complete: dga_seed.is_some()
+            && tld.is_some()
+            && url_path.is_some()
+            && modulus.is_some(),
+        dga_seed,
+        tld,
+        url_path,
+        modulus,
+        uses_curl_iex: script.contains("iex(curl"),
+    }
+}
+
+/// Decoded `-EncodedCommand` (or one of its abbreviations) of the powershell started by the
+/// sample. `None` if the sample has none.
+fn extract_encoded_command(sample_str: &str) -> Result<Option<String>> {
+    let base64 = RE_ENCODED_COMMAND
+        .captures(sample_str)
+        .map(|c| c.extract::<1>())
+        .map(|(_, [c])| c);
+
+    let Some(base64) = base64 else {
+        return Ok(None);
+    };
+
+    // encoded commands are UTF-16LE, which is detected by get_string_from_binary
+    let decoded = decode_base64_lenient(base64, Default::default())?;
+    Ok(Some(get_string_from_binary(&decoded)))
+}
+
 fn decode_base64_with_xor_key(xor_key: &str, base64: &str) -> Result<String> {
-    let base64_decoder = GeneralPurpose::new(&alphabet::STANDARD, PAD);
-    let mut res = base64_decoder.decode(base64)?;
+    let mut res = decode_base64_lenient(base64, Default::default())?;
 
     let xor_key = xor_key.as_bytes();
     for i in 0..res.len() {
@@ -381,7 +541,7 @@ fn decode_base64_with_xor_key(xor_key: &str, base64: &str) -> Result<String> {
 }
 
 #[allow(non_camel_case_types)]
-enum PSKind {
+pub(crate) enum PSKind {
     /// Sample is a powershell script.
     /// It has a base64 encoded blob, which is
     ///     1. base64-decoded and
@@ -399,7 +559,10 @@ enum PSKind {
     DGA_iex,
 
     /// Sample is a powershell script.
-    /// It starts a new powershell process with a new alias "rzs"
+    /// It starts a new powershell process with a new alias "rzs", which runs the base64 encoded
+    /// command of the sample
+    ///
+    /// Produces [`SampleType::PS_DGA_iex`]
     Start_Process,
 
     /// Sample is a powershell script with about two lines
@@ -410,7 +573,7 @@ enum PSKind {
 }
 
 #[allow(non_camel_case_types)]
-enum SampleType {
+pub(crate) enum SampleType {
     /// PS
     PS(PSKind),
 
@@ -421,15 +584,34 @@ enum SampleType {
     X509,
 }
 
-fn detect_sample_type(sample_data: &[u8]) -> Option<SampleType> {
+impl SampleType {
+    /// Name of the sample type in the output of `--output`
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            SampleType::PS(PSKind::Xor_B64(..)) => "ps_xor_b64",
+            SampleType::PS(PSKind::DGA_iex) => "ps_dga_iex",
+            SampleType::PS(PSKind::Start_Process) => "ps_start_process",
+            SampleType::PS(PSKind::Two_Liner) => "ps_two_liner",
+            SampleType::CS => "cs",
+            SampleType::X509 => "x509",
+        }
+    }
+}
+
+pub(crate) fn detect_sample_type(sample_data: &[u8]) -> Detection<SampleType> {
     let sample_str = get_string_from_binary(sample_data);
 
+    let mut detection = Detection::default();
+
     if let Ok((xor_key, base64)) = extract_key_and_base64_from_ps_xor_base64(&sample_str) {
-        return Some(SampleType::PS(PSKind::Xor_B64(
-            xor_key.to_owned(),
-            base64.to_owned(),
-        )));
-    } else if sample_str
+        detection.add(
+            SampleType::PS(PSKind::Xor_B64(xor_key.to_owned(), base64.to_owned())),
+            0.95,
+            "xor_key_and_base64",
+        );
+    }
+
+    if sample_str
         .find("$executioncontext;")
         .and(
             sample_str
@@ -438,18 +620,31 @@ fn detect_sample_type(sample_data: &[u8]) -> Option<SampleType> {
         )
         .is_some()
     {
-        return Some(SampleType::PS(PSKind::DGA_iex));
-    } else if sample_str.contains("start-process powershell") {
-        return Some(SampleType::PS(PSKind::Start_Process));
-    } else if sample_str.trim().starts_with("using System") {
-        return Some(SampleType::CS);
-    } else if sample_str.trim().starts_with("MIIE") {
-        return Some(SampleType::X509);
-    } else if sample_str.lines().collect::<Vec<&str>>().len() < 5 {
-        return Some(SampleType::PS(PSKind::Two_Liner));
-    }
-
-    None
+        detection.add(SampleType::PS(PSKind::DGA_iex), 0.9, "executioncontext_iex");
+    }
+
+    if sample_str.contains("start-process powershell") {
+        detection.add(
+            SampleType::PS(PSKind::Start_Process),
+            0.8,
+            "start_process_powershell",
+        );
+    }
+
+    if sample_str.trim().starts_with("using System") {
+        detection.add(SampleType::CS, 0.7, "using_system");
+    }
+
+    if sample_str.trim().starts_with("MIIE") {
+        detection.add(SampleType::X509, 0.7, "x509_prefix");
+    }
+
+    // weak heuristic, only used if nothing else matches
+    if detection.is_empty() && sample_str.lines().count() < 5 {
+        detection.add(SampleType::PS(PSKind::Two_Liner), 0.3, "few_lines");
+    }
+
+    detection
 }
 
 fn get_deobfuscated_strings_from_sample_sorted(sample_str: &str) -> Vec<String> {
@@ -514,3 +709,211 @@ fn get_obfuscated_strings_from_sample(sample_str: &str) -> Vec<String> {
 
     obfuscated_strings
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use macon_cag::{
+        dry_run::JournalEntry,
+        utils::{config::Config, edge_key},
+    };
+
+    use super::*;
+    use crate::{graph_creators::COLLECTION_PREFIX_LOCK, utils::SampleRef};
+
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/mintsloader");
+
+    fn fixture(name: &str) -> String {
+        let data = std::fs::read(Path::new(FIXTURES).join(name)).unwrap();
+        get_string_from_binary(&data)
+    }
+
+    #[test]
+    fn encoded_command_flags() {
+        for name in [
+            "start_process_e.ps1",
+            "start_process_enc.ps1",
+            "start_process_encodedcommand.ps1",
+            "start_process_argumentlist.ps1",
+            "start_process_argumentlist_array.ps1",
+        ] {
+            let sample = fixture(name);
+            let detection = detect_sample_type(sample.as_bytes()).into_best();
+            assert!(
+                matches!(
+                    detection.map(|c| c.sample_type),
+                    Some(SampleType::PS(PSKind::Start_Process))
+                ),
+                "{name}"
+            );
+
+            let next_stage = extract_encoded_command(&sample).unwrap();
+            let next_stage = next_stage.unwrap_or_else(|| panic!("{name}"));
+            assert!(next_stage.contains("$executioncontext;"), "{name}");
+            assert!(matches!(
+                detect_sample_type(next_stage.as_bytes())
+                    .into_best()
+                    .map(|c| c.sample_type),
+                Some(SampleType::PS(PSKind::DGA_iex))
+            ));
+        }
+    }
+
+    #[test]
+    fn dga_parameters_of_global_block_variant() {
+        let sample = fixture("dga_global_block.ps1");
+        assert!(matches!(
+            detect_sample_type(sample.as_bytes())
+                .into_best()
+                .map(|c| c.sample_type),
+            Some(SampleType::PS(PSKind::DGA_iex))
+        ));
+
+        assert_eq!(
+            extract_dga_parameters(&sample),
+            MintsloaderDga {
+                dga_seed: Some("5e83b2d1".to_string()),
+                tld: Some("top".to_string()),
+                url_path: Some("/1.php?s=527".to_string()),
+                modulus: Some(7),
+                uses_curl_iex: false,
+                extraction_complete: true,
+            }
+        );
+    }
+
+    #[test]
+    fn dga_parameters_of_iex_curl_variant() {
+        let sample = fixture("dga_iex_curl.ps1");
+        assert!(matches!(
+            detect_sample_type(sample.as_bytes())
+                .into_best()
+                .map(|c| c.sample_type),
+            Some(SampleType::PS(PSKind::DGA_iex))
+        ));
+
+        assert_eq!(
+            extract_dga_parameters(&sample),
+            MintsloaderDga {
+                dga_seed: Some("a7f3c91e".to_string()),
+                tld: Some("xyz".to_string()),
+                url_path: Some("/api/gate.php?id=7".to_string()),
+                modulus: Some(3),
+                uses_curl_iex: true,
+                extraction_complete: true,
+            }
+        );
+    }
+
+    #[test]
+    fn incomplete_dga_parameters() {
+        let sample = "$a=$executioncontext;\n$ext='.php';\n$x=$y % 26;\niex(curl $u)";
+
+        assert_eq!(
+            extract_dga_parameters(sample),
+            MintsloaderDga {
+                uses_curl_iex: true,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn other_flags_are_no_encoded_command() {
+        let sample =
+            "start-process powershell -ExecutionPolicy Bypass -File AAAAAAAAAAAAAAAAAAAA.ps1";
+        assert!(extract_encoded_command(sample).unwrap().is_none());
+
+        let sample = "start-process powershell -windowstyle hidden-e AAAAAAAAAAAAAAAAAAAA";
+        assert!(extract_encoded_command(sample).unwrap().is_none());
+    }
+
+    fn upsert(collection: &str, alt_key: &str, alt_val: &str, created: bool) -> JournalEntry {
+        JournalEntry::Upsert {
+            collection: collection.to_string(),
+            alt_key: alt_key.to_string(),
+            alt_val: alt_val.into(),
+            from: None,
+            to: None,
+            created,
+            updated: false,
+        }
+    }
+
+    fn upsert_edge(collection: &str, from: &str, to: &str, created: bool) -> JournalEntry {
+        JournalEntry::Upsert {
+            collection: collection.to_string(),
+            alt_key: "_key".to_string(),
+            alt_val: edge_key(from, to).into(),
+            from: Some(from.to_string()),
+            to: Some(to.to_string()),
+            created,
+            updated: false,
+        }
+    }
+
+    #[test]
+    fn dry_run_journal_of_sample() -> Result<()> {
+        let _lock = COLLECTION_PREFIX_LOCK.lock().unwrap();
+
+        let gc = FocusedGraph::try_new(&Config::default(), true, false)?;
+        let corpus = FocusedCorpus {
+            name: "FocusedCorpus".to_string(),
+            display_name: "FocusedCorpus".to_string(),
+        };
+        let corpus_node = gc.upsert_node(corpus, "name", "FocusedCorpus")?.document;
+        let main_node = gc.mintsloader_create_main_node(&corpus_node)?;
+        let main_id = &main_node.header._id;
+        let dry_run = gc.dry_run.as_ref().unwrap();
+
+        let path = Path::new(FIXTURES).join("start_process_encodedcommand.ps1");
+        let sample = SampleRef::new(&path, &[]);
+        let data = std::fs::read(&path)?;
+        let sha256sum = digest(&data);
+        let next_stage = extract_encoded_command(&get_string_from_binary(&data))?.unwrap();
+        let next_stage_sha256sum = digest(next_stage.as_bytes());
+
+        let before = dry_run.journal().len();
+        gc.mintsloader_handle_sample(&sample, &data, &main_node)?;
+        let journal = dry_run.journal().split_off(before);
+
+        // the synthetic ids of the new nodes are the endpoints of the edge between the stages
+        let JournalEntry::Upsert {
+            from: Some(ps_id),
+            to: Some(next_stage_id),
+            ..
+        } = &journal[2]
+        else {
+            panic!("{:?} is no edge", journal[2]);
+        };
+        let (_, ps_key) = ps_id.split_once('/').unwrap();
+
+        assert_eq!(
+            journal,
+            [
+                upsert("MintsloaderPs", "sha256sum", &sha256sum, true),
+                upsert("MintsloaderPs", "sha256sum", &next_stage_sha256sum, true),
+                upsert_edge("MintsloaderHasPs", ps_id, next_stage_id, true),
+                JournalEntry::Update {
+                    collection: "MintsloaderPs".to_string(),
+                    key: ps_key.to_string(),
+                },
+                upsert_edge("MintsloaderHasPs", main_id, ps_id, true),
+            ]
+        );
+
+        // the node of a known sample is neither analyzed nor updated again
+        let before = dry_run.journal().len();
+        gc.mintsloader_handle_sample(&sample, &data, &main_node)?;
+        assert_eq!(
+            dry_run.journal().split_off(before),
+            [
+                upsert("MintsloaderPs", "sha256sum", &sha256sum, false),
+                upsert_edge("MintsloaderHasPs", main_id, ps_id, false),
+            ]
+        );
+
+        Ok(())
+    }
+}
@@ -1,25 +1,63 @@
 use arangors::graph::EdgeDefinition;
-use macon_cag::{impl_edge_attributes, utils::get_name};
+use macon_cag::{
+    base_creator::{CountedEdge, EdgeAttributes},
+    utils::{CollectionName, get_name},
+};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, Default)]
+use crate::graph_creators::focused_graph::FamilyMetadata;
+
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, Default, CollectionName)]
 pub struct Mintsloader {
     pub name: String,
     pub display_name: String,
+
+    #[serde(flatten)]
+    pub metadata: FamilyMetadata,
+
+    /// Date (YYYY-MM-DD) the family was analyzed for the first time
+    pub first_analyzed: Option<String>,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, Default)]
+#[derive(
+    Deserialize, Serialize, Debug, Clone, JsonSchema, Default, EdgeAttributes, CollectionName,
+)]
 pub struct MintsloaderHasPs {
     pub _key: String,
     pub _from: String,
     pub _to: String,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, CollectionName)]
 pub struct MintsloaderPs {
     pub sha256sum: String,
     pub kind: MintsloaderPsKind,
+
+    // parameters of the DGA, only set for DgaIex stages
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dga: Option<MintsloaderDga>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, Default, PartialEq, Eq)]
+pub struct MintsloaderDga {
+    /// Hard-coded string the domains are generated from
+    pub dga_seed: Option<String>,
+
+    /// Top level domain of the generated domains, without the dot
+    pub tld: Option<String>,
+
+    /// Path of the URL on the generated domain, e.g. /1.php?s=527
+    pub url_path: Option<String>,
+
+    /// Modulus the date is reduced with, which sets how long a domain is used
+    pub modulus: Option<u64>,
+
+    /// True for the iex(curl ...) variant, false for $global:block=(curl ...)
+    pub uses_curl_iex: bool,
+
+    /// True if all parameters were found
+    pub extraction_complete: bool,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
@@ -30,33 +68,51 @@ pub enum MintsloaderPsKind {
     TwoLiner,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, Default)]
+#[derive(
+    Deserialize, Serialize, Debug, Clone, JsonSchema, Default, EdgeAttributes, CollectionName,
+)]
 pub struct MintsloaderHasCS {
     pub _key: String,
     pub _from: String,
     pub _to: String,
+
+    // number of times the relationship was observed
+    pub observed: u64,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, Default)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, Default, CollectionName)]
 pub struct MintsloaderCS {
     pub sha256sum: String,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, Default)]
+#[derive(
+    Deserialize, Serialize, Debug, Clone, JsonSchema, Default, EdgeAttributes, CollectionName,
+)]
 pub struct MintsloaderHasX509Cert {
     pub _key: String,
     pub _from: String,
     pub _to: String,
+
+    // number of times the relationship was observed
+    pub observed: u64,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, Default)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, Default, CollectionName)]
 pub struct MintsloaderX509Cert {
     pub sha256sum: String,
 }
 
-impl_edge_attributes!(MintsloaderHasPs);
-impl_edge_attributes!(MintsloaderHasCS);
-impl_edge_attributes!(MintsloaderHasX509Cert);
+impl CountedEdge for MintsloaderHasCS {
+    fn observed(&self) -> u64 {
+        self.observed
+    }
+}
+
+impl CountedEdge for MintsloaderHasX509Cert {
+    fn observed(&self) -> u64 {
+        self.observed
+    }
+}
 
 pub fn mintsloader_edge_definitions() -> Vec<EdgeDefinition> {
     vec![
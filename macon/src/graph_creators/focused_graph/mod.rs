@@ -1,48 +1,94 @@
+mod auto;
 pub mod carnavalheist;
 pub mod coper;
 pub mod dark_watchmen;
+mod family;
+mod ingested;
+pub mod maintenance;
 pub mod mintsloader;
+mod provenance;
+pub mod prune;
+mod samples;
+mod watch;
 
-use std::fmt::Debug;
+pub(crate) use provenance::created_during;
+
+use std::{fmt::Debug, io::Write, path::PathBuf, sync::Arc, time::Instant};
 
 use anyhow::Result;
 use arangors::{Document, graph::EdgeDefinition};
 use macon_cag::{
-    base_creator::GraphCreatorBase,
-    impl_edge_attributes,
+    backend::DbBackend,
+    base_creator::{EdgeAttributes, GraphCreatorBase},
+    dry_run::DryRun,
+    limiter::AdaptiveLimiter,
     prelude::Database,
+    provenance::Provenance,
+    retry::RetryPolicy,
+    stats::RunStats,
     utils::{
-        config::Config, ensure_database, ensure_graph, ensure_index, establish_database_connection,
-        get_name,
+        CollectionName, IndexSpec, config::Config, ensure_database, ensure_graph,
+        establish_database_connection, get_name, health_check, reconcile_indexes,
+        set_wait_for_sync,
     },
 };
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use tracing::{info, warn};
 
 use crate::{
-    cli::{FocusedFamilies, MainArgs},
-    graph_creators::focused_graph::{
-        carnavalheist::nodes::{Carnavalheist, carnavalheist_edge_definitions},
-        coper::nodes::{Coper, coper_edge_definitions},
-        dark_watchmen::nodes::{DarkWatchmen, dark_watchmen_edge_definitions},
-        mintsloader::nodes::{Mintsloader, mintsloader_edge_definitions},
+    cli::{ConfigArgs, FocusedArgs, FocusedFamilies, MainArgs},
+    graph_creators::{
+        corpus_name, db_limiter,
+        focused_graph::{
+            carnavalheist::nodes::{Carnavalheist, carnavalheist_edge_definitions},
+            coper::nodes::{Coper, coper_edge_definitions},
+            dark_watchmen::nodes::{DarkWatchmen, dark_watchmen_edge_definitions},
+            maintenance::Scheduler,
+            mintsloader::nodes::{Mintsloader, mintsloader_edge_definitions},
+            provenance::{IngestRun, provenance_edge_definition, sample_collections},
+            watch::DropWatcher,
+        },
     },
+    output::SampleOutput,
+    prefilter::{Prefilter, SkipReason},
+    report::{ErrorReport, analyzed_per_sec},
 };
 
-#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, Default)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, Default, CollectionName)]
 pub struct FocusedCorpus {
     pub name: String,
     pub display_name: String,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, Default)]
+#[derive(
+    Deserialize, Serialize, Debug, Clone, JsonSchema, Default, EdgeAttributes, CollectionName,
+)]
 pub struct HasMalwareFamily {
     pub _key: String,
     pub _from: String,
     pub _to: String,
 }
 
-impl_edge_attributes!(HasMalwareFamily);
+/// Descriptive metadata of a malware family. Read from the `metadata.json` of each family
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, Default)]
+pub struct FamilyMetadata {
+    pub description: String,
+    pub references: Vec<String>,
+    pub aliases: Vec<String>,
+}
+
+impl FamilyMetadata {
+    pub fn from_json(data: &str) -> Result<Self> {
+        let metadata = serde_json::from_str(data)?;
+        Ok(metadata)
+    }
+}
+
+/// Current date (YYYY-MM-DD)
+pub fn today() -> String {
+    chrono::Utc::now().date_naive().to_string()
+}
 
 fn base_edge_definitions() -> Vec<EdgeDefinition> {
     vec![EdgeDefinition {
@@ -57,21 +103,10 @@ fn base_edge_definitions() -> Vec<EdgeDefinition> {
     }]
 }
 
-struct FocusedGraph {
-    db: Database,
-}
-
-impl FocusedGraph {
-    pub fn try_new(config: &Config) -> Result<Self> {
-        let conn = establish_database_connection(config)?;
-        let db = ensure_database(&conn, &config.database)?;
-
-        Ok(Self { db })
-    }
-}
-
-pub fn focused_graph_main(focused_families: FocusedFamilies) -> Result<()> {
-    let edge_definitions: Vec<EdgeDefinition> = vec![
+/// Edge definitions of the focused graph and the collections of its sample nodes, with the
+/// collection prefix that is set
+fn focused_graph_schema() -> (Vec<EdgeDefinition>, Vec<String>) {
+    let mut edge_definitions: Vec<EdgeDefinition> = vec![
         base_edge_definitions(),
         carnavalheist_edge_definitions(),
         coper_edge_definitions(),
@@ -82,32 +117,310 @@ pub fn focused_graph_main(focused_families: FocusedFamilies) -> Result<()> {
     .flatten()
     .collect();
 
-    let corpus_data = FocusedCorpus {
-        name: "FocusedCorpus".to_string(),
-        display_name: "FocusedCorpus".to_string(),
-    };
+    // the edge definition is part of the graph with --no-provenance as well, so the graph
+    // doesn't change with the option
+    let sample_collections = sample_collections(&edge_definitions);
+    edge_definitions.push(provenance_edge_definition(sample_collections.clone()));
+
+    (edge_definitions, sample_collections)
+}
 
-    let config = Config {
+/// Config of the focused corpus. The database and graph default to `focused_corpus` and
+/// `focused_corpus_graph`, all values can be overridden by the config file, the environment and
+/// the options, see [`ConfigArgs`].
+pub fn focused_graph_config(config_args: &ConfigArgs) -> Result<Config> {
+    let defaults = Config {
         database: "focused_corpus".to_string(),
         graph: "focused_corpus_graph".to_string(),
         ..Default::default()
     };
 
-    let gc = FocusedGraph::try_new(&config)?;
+    config_args.load(defaults)
+}
+
+struct FocusedGraph {
+    /// Not set for dry runs, which don't connect to the database
+    db: Option<Database>,
+    graph: String,
+    limiter: AdaptiveLimiter,
+    retry: RetryPolicy,
+    stats: Arc<RunStats>,
+    errors: ErrorReport,
+
+    /// Set once the node of the run was created, unless disabled with `--no-provenance`
+    provenance: Option<Provenance>,
+    run_node: Option<Document<IngestRun>>,
+
+    /// Set for dry runs, which only record the writes
+    dry_run: Option<DryRun>,
+
+    /// Directories the samples were found in, see [`SampleRef`](crate::utils::SampleRef)
+    roots: Vec<PathBuf>,
+
+    /// Results of the samples, set with `--output`
+    output: Option<SampleOutput>,
+}
+
+impl FocusedGraph {
+    pub fn try_new(config: &Config, dry_run: bool, fail_fast: bool) -> Result<Self> {
+        let db = if dry_run {
+            None
+        } else {
+            health_check(config)?;
+
+            let conn = establish_database_connection(config)?;
+            Some(ensure_database(&conn, &config.database)?)
+        };
+
+        Ok(Self {
+            db,
+            graph: config.graph_name(),
+            limiter: db_limiter(config.max_concurrent_requests),
+            retry: config.retry,
+            stats: Arc::new(RunStats::new()),
+            errors: ErrorReport::new(fail_fast),
+            provenance: None,
+            run_node: None,
+            dry_run: dry_run.then(DryRun::new),
+            roots: vec![],
+            output: None,
+        })
+    }
+
+    /// Hands the samples of `families` to the family. Samples the `prefilter` skips and, with
+    /// `skip_known`, samples that were already analyzed are removed before, both get a skipped
+    /// `--output` record. Returns the files the pre-filter skipped and the number of known
+    /// samples.
+    fn analyze(
+        &self,
+        families: &mut FocusedFamilies,
+        prefilter: Option<&Prefilter>,
+        skip_known: bool,
+        corpus_node: &Document<FocusedCorpus>,
+    ) -> Result<(Vec<(PathBuf, SkipReason)>, usize)> {
+        let prefiltered = match prefilter {
+            Some(prefilter) => prefilter.apply(&mut families.main_args_mut().files),
+            None => vec![],
+        };
+
+        let skipped = match skip_known {
+            true => self.skip_known(&mut families.main_args_mut().files)?,
+            false => vec![],
+        };
+
+        for (file, reason) in &prefiltered {
+            self.write_skipped(file, None, format!("pre-filter: {reason}"));
+        }
+        for (file, sha256) in &skipped {
+            let reason = "already analyzed (--skip-known)".to_string();
+            self.write_skipped(file, Some(sha256.clone()), reason);
+        }
+
+        match families {
+            FocusedFamilies::Carnavalheist(MainArgs { files, .. }) => {
+                self.carnavalheist_main(files, corpus_node)?
+            }
+            FocusedFamilies::Coper(MainArgs { files, .. }) => {
+                self.coper_main(files, corpus_node)?
+            }
+            FocusedFamilies::DarkWatchmen(vm_args) => {
+                self.dark_watchmen_main(vm_args, corpus_node)?
+            }
+            FocusedFamilies::Mintsloader(MainArgs { files, .. }) => {
+                self.mintsloader_main(files, corpus_node)?
+            }
+            FocusedFamilies::Auto(auto_args) => self.auto_main(auto_args, corpus_node)?,
+        }
+
+        Ok((prefiltered, skipped.len()))
+    }
+
+    /// Overwrites the metadata of the main node of a family
+    fn update_family_metadata<T>(
+        &self,
+        main_node: &Document<T>,
+        metadata: &FamilyMetadata,
+        first_analyzed: Option<String>,
+    ) -> Result<()>
+    where
+        T: JsonSchema + CollectionName,
+    {
+        let mut attributes = serde_json::to_value(metadata)?;
+        attributes["first_analyzed"] = first_analyzed.into();
+
+        self.update_node_attributes(main_node, attributes)?;
+        Ok(())
+    }
+
+    /// Creates missing indexes on the collection of `CollType` and warns about extra or
+    /// conflicting ones
+    fn ensure_indexes<CollType>(&self, fields: Vec<String>) -> Result<()>
+    where
+        CollType: JsonSchema + CollectionName,
+    {
+        if self.dry_run.is_some() {
+            return Ok(());
+        }
+
+        let drift = reconcile_indexes::<CollType>(self.get_db()?, vec![IndexSpec::unique(fields)])?;
+        for warning in drift.warnings() {
+            warn!("{warning}");
+        }
+
+        Ok(())
+    }
+
+    /// Creates the indexes for the name field of the corpus and the main nodes of the families
+    fn ensure_main_indexes(&self) -> Result<()> {
+        let idx = vec!["name".to_string()];
+
+        self.ensure_indexes::<FocusedCorpus>(idx.clone())?;
+        self.ensure_indexes::<Carnavalheist>(idx.clone())?;
+        self.ensure_indexes::<Coper>(idx.clone())?;
+        self.ensure_indexes::<DarkWatchmen>(idx.clone())?;
+        self.ensure_indexes::<Mintsloader>(idx)?;
+
+        Ok(())
+    }
+}
+
+/// Analyzes the samples of a family. Returns the number of samples that failed
+pub fn focused_graph_main(focused_args: FocusedArgs, config_args: ConfigArgs) -> Result<usize> {
+    let FocusedArgs {
+        family: mut focused_families,
+        corpus,
+        dry_run,
+        error_report,
+        fail_fast,
+        skip_known,
+        no_prefilter,
+        prefilter_deny_ext,
+        no_provenance,
+        output,
+        watch,
+        maintenance,
+    } = focused_args;
+    let mut samples = focused_families.main_args().files.len();
+
+    // sets the collection prefix, so it has to be loaded before any collection name is resolved
+    let config = focused_graph_config(&config_args)?;
+    let (edge_definitions, sample_collections) = focused_graph_schema();
+
+    let (name, display_name) = corpus.name_and_display_name("FocusedCorpus");
+    let corpus_data = FocusedCorpus { name, display_name };
+
+    let mut gc = FocusedGraph::try_new(&config, dry_run, fail_fast)?;
+    gc.output = output.as_ref().map(SampleOutput::new).transpose()?;
+    gc.roots = focused_families.main_args().roots.clone();
     let corpus_node = gc.init::<FocusedCorpus>(config, corpus_data, edge_definitions)?;
+    gc.ensure_ingested_collection()?;
+
+    if !no_provenance {
+        gc.start_run(sample_collections)?;
+    }
+
+    let prefilter = (!no_prefilter).then(|| Prefilter::new(&prefilter_deny_ext));
+
+    // the watcher is started before the initial pass, so no file dropped during it is missed
+    let mut watcher = match &watch {
+        Some(dir) => {
+            let initial = focused_families.main_args().files.iter().cloned().collect();
+            Some(DropWatcher::new(dir, initial)?)
+        }
+        None => None,
+    };
+
+    let (mut prefiltered, mut skipped) = gc.analyze(
+        &mut focused_families,
+        prefilter.as_ref(),
+        skip_known,
+        &corpus_node,
+    )?;
+
+    if let (Some(watcher), Some(dir)) = (&mut watcher, &watch) {
+        info!(
+            "Watching {} for new samples, stop with Ctrl-C",
+            dir.display()
+        );
+
+        let mut scheduler = Scheduler::new(&maintenance, Instant::now());
+        while let Some(files) = watcher.next_batch(scheduler.next_due()) {
+            // a failed job is tried again at its next interval
+            for job in scheduler.due(Instant::now()) {
+                if let Err(e) = gc.run_maintenance(job) {
+                    warn!("The maintenance job {} failed: {e:#}", job.as_str());
+                }
+            }
+            if files.is_empty() {
+                continue;
+            }
+
+            let main_args = focused_families.main_args_mut();
+            main_args.files = files;
+            if let Err(e) = main_args.expand_archives() {
+                warn!("Reading the new archives failed: {e:#}");
+                continue;
+            }
+            samples += main_args.files.len();
+
+            // the watcher keeps running, the errors of the samples are in the report already
+            match gc.analyze(
+                &mut focused_families,
+                prefilter.as_ref(),
+                skip_known,
+                &corpus_node,
+            ) {
+                Ok((batch_prefiltered, batch_skipped)) => {
+                    prefiltered.extend(batch_prefiltered);
+                    skipped += batch_skipped;
+                }
+                Err(e) => warn!("Analyzing the new samples failed: {e:#}"),
+            }
+        }
+    }
+
+    gc.finish_run()?;
+
+    let failed = gc.errors.finish(
+        error_report.as_deref(),
+        samples,
+        skipped,
+        &prefiltered,
+        &gc.roots,
+        &gc.stats,
+    )?;
+
+    // stdout is left to the results of the samples with --output json
+    let mut summary: Box<dyn Write> = match gc.output.as_ref().is_some_and(|o| o.is_stdout()) {
+        true => Box::new(std::io::stderr()),
+        false => Box::new(std::io::stdout()),
+    };
 
-    match focused_families {
-        FocusedFamilies::Carnavalheist(MainArgs { files }) => {
-            gc.carnavalheist_main(&files, &corpus_node)?
+    match &gc.dry_run {
+        Some(dry_run) => {
+            writeln!(summary, "{}", dry_run.journal_json()?)?;
+
+            writeln!(summary, "collection\tdocuments")?;
+            for (collection, count) in dry_run.document_counts() {
+                writeln!(summary, "{collection}\t{count}")?;
+            }
         }
-        FocusedFamilies::Coper(MainArgs { files }) => gc.coper_main(&files, &corpus_node)?,
-        FocusedFamilies::DarkWatchmen(vm_args) => gc.dark_watchmen_main(&vm_args, &corpus_node)?,
-        FocusedFamilies::Mintsloader(MainArgs { files }) => {
-            gc.mintsloader_main(&files, &corpus_node)?
+        None => {
+            writeln!(summary, "{}", gc.stats.report())?;
+            writeln!(
+                summary,
+                "samples: {samples}, skipped: {skipped}, pre-filtered: {} ({:.2}/s)",
+                prefiltered.len(),
+                analyzed_per_sec(&gc.stats, samples, skipped, prefiltered.len())
+            )?;
         }
     }
 
-    Ok(())
+    if let Some(output) = &gc.output {
+        output.finish()?;
+    }
+
+    Ok(failed)
 }
 
 impl GraphCreatorBase for FocusedGraph {
@@ -118,29 +431,334 @@ impl GraphCreatorBase for FocusedGraph {
         edge_definitions: Vec<EdgeDefinition>,
     ) -> macon_cag::prelude::Result<Document<T>>
     where
-        T: DeserializeOwned + Serialize + Clone + JsonSchema + Debug,
+        T: DeserializeOwned + Serialize + Clone + JsonSchema + CollectionName + Debug,
     {
-        let _ = ensure_graph(&self.db, &config.graph, edge_definitions)?;
+        if self.dry_run.is_none() {
+            let edge_collections: Vec<String> = edge_definitions
+                .iter()
+                .map(|ed| ed.collection.clone())
+                .collect();
+            let _ = ensure_graph(self.get_db()?, &self.graph, edge_definitions, false)?;
 
-        let db = self.get_db();
-        let idx = vec!["name".to_string()];
+            // the edge collections are created by the graph, so the option is set afterwards
+            if config.edge_wait_for_sync {
+                for collection in &edge_collections {
+                    set_wait_for_sync(self.get_db()?, collection, true)?;
+                }
+            }
+        }
 
-        // Create index for name field
-        ensure_index::<FocusedCorpus>(db, idx.clone())?;
-        ensure_index::<Carnavalheist>(db, idx.clone())?;
-        ensure_index::<Coper>(db, idx.clone())?;
-        ensure_index::<DarkWatchmen>(db, idx.clone())?;
-        ensure_index::<Mintsloader>(db, idx)?;
+        self.ensure_main_indexes()?;
 
         // create corpus node
+        let name = corpus_name(&corpus_node_data)?;
         let corpus_node: Document<T> = self
-            .upsert_node::<T>(corpus_node_data, "name", &get_name::<T>())?
+            .upsert_node::<T>(corpus_node_data, "name", name)?
             .document;
 
         Ok(corpus_node)
     }
 
-    fn get_db(&self) -> &Database {
-        &self.db
+    /// Fails for dry runs, they only use the backend of [`Self::get_backend`]
+    fn get_db(&self) -> macon_cag::prelude::Result<&Database> {
+        self.db.as_ref().ok_or_else(|| {
+            macon_cag::error::Error::Generic("Dry runs don't connect to the database".to_string())
+        })
+    }
+
+    fn get_backend(&self) -> &dyn DbBackend {
+        match (&self.dry_run, &self.db) {
+            (Some(dry_run), _) => dry_run,
+            (None, Some(db)) => db,
+            (None, None) => unreachable!("runs without database are dry runs"),
+        }
+    }
+
+    fn get_graph_name(&self) -> Option<&str> {
+        Some(&self.graph)
+    }
+
+    fn get_limiter(&self) -> Option<&AdaptiveLimiter> {
+        Some(&self.limiter)
+    }
+
+    fn get_run_stats(&self) -> Option<&RunStats> {
+        Some(&self.stats)
+    }
+
+    fn get_provenance(&self) -> Option<&Provenance> {
+        self.provenance.as_ref()
+    }
+
+    fn get_retry_policy(&self) -> RetryPolicy {
+        self.retry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeSet, path::Path, time::Instant};
+
+    use arangors::AqlQuery;
+    use macon_cag::utils::set_collection_prefix;
+
+    use super::*;
+    use crate::{
+        graph_creators::COLLECTION_PREFIX_LOCK,
+        output::{SampleRecord, SampleStatus},
+    };
+
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+
+    /// Analyzes a mintsloader sample in a dry run with the collection prefix `prefix`. Returns
+    /// the names of the edge definitions and the collections that were written to.
+    fn dry_run_with_prefix(prefix: &str) -> Result<(BTreeSet<String>, BTreeSet<String>)> {
+        let dir = tempfile::tempdir()?;
+        let config_file = dir.path().join("macon.toml");
+        std::fs::write(&config_file, format!("collection_prefix = \"{prefix}\"\n"))?;
+        let config_args = ConfigArgs {
+            config: Some(config_file),
+            ..Default::default()
+        };
+
+        let config = focused_graph_config(&config_args)?;
+        let (edge_definitions, sample_collections) = focused_graph_schema();
+        let names = edge_definitions
+            .iter()
+            .flat_map(|ed| [&ed.collection].into_iter().chain(&ed.from).chain(&ed.to))
+            .cloned()
+            .collect();
+
+        let mut gc = FocusedGraph::try_new(&config, true, false)?;
+        let corpus_data = FocusedCorpus {
+            name: "FocusedCorpus".to_string(),
+            display_name: "FocusedCorpus".to_string(),
+        };
+        let corpus_node = gc.init::<FocusedCorpus>(config, corpus_data, edge_definitions)?;
+        gc.start_run(sample_collections)?;
+
+        let sample = Path::new(FIXTURES).join("mintsloader/start_process_encodedcommand.ps1");
+        gc.mintsloader_main(&[sample], &corpus_node)?;
+
+        let written = gc.dry_run.as_ref().unwrap().document_counts();
+        Ok((names, written.into_keys().collect()))
+    }
+
+    #[test]
+    fn collection_prefix_applies_to_all_collections() -> Result<()> {
+        let _lock = COLLECTION_PREFIX_LOCK.lock().unwrap();
+
+        let alice = dry_run_with_prefix("alice_");
+        let bob = dry_run_with_prefix("bob_");
+        set_collection_prefix(None);
+        let ((alice_names, alice_written), (bob_names, bob_written)) = (alice?, bob?);
+
+        for (prefix, names) in [
+            ("alice_", alice_names.iter().chain(&alice_written)),
+            ("bob_", bob_names.iter().chain(&bob_written)),
+        ] {
+            for name in names {
+                assert!(
+                    name.starts_with(prefix),
+                    "{name} doesn't start with {prefix}"
+                );
+            }
+        }
+        assert!(alice_written.is_disjoint(&bob_written));
+
+        // the sample nodes are only linked to the run if the edge definitions are prefixed
+        assert!(alice_written.contains("alice_CreatedDuring"));
+        assert!(bob_written.contains("bob_CreatedDuring"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn dry_run_requests_without_backend_fail() -> Result<()> {
+        let gc = FocusedGraph::try_new(&Config::default(), true, false)?;
+
+        assert!(gc.get_documents::<FocusedCorpus>(None, 100, false).is_err());
+        let aql = AqlQuery::builder().query("return 1").build();
+        assert!(gc.aql_query_stream::<serde_json::Value>(aql).is_err());
+        assert!(gc.delete_node_by_id("FocusedCorpus/1", true).is_err());
+
+        // dry runs don't support transactions, so the handle has no database either
+        gc.transaction(&[], |txn| {
+            assert!(txn.get_db().is_err());
+            assert!(
+                txn.get_documents::<FocusedCorpus>(None, 100, false)
+                    .is_err()
+            );
+            Ok(())
+        })?;
+
+        Ok(())
+    }
+
+    fn corpus(name: &str) -> FocusedCorpus {
+        FocusedCorpus {
+            name: name.to_string(),
+            display_name: name.to_uppercase(),
+        }
+    }
+
+    #[test]
+    fn upsert_nodes_of_mixed_batch() -> Result<()> {
+        let _lock = COLLECTION_PREFIX_LOCK.lock().unwrap();
+
+        let gc = FocusedGraph::try_new(&Config::default(), true, false)?;
+        let existing = gc.upsert_node(corpus("existing"), "name", "existing")?;
+        assert!(existing.created);
+
+        let batch = ["new", "existing", "new", "other"];
+        let results = gc.upsert_nodes(batch.map(corpus).to_vec(), "name")?;
+
+        let names: Vec<&str> = results.iter().map(|r| &*r.document.name).collect();
+        assert_eq!(names, batch);
+        let created: Vec<bool> = results.iter().map(|r| r.created).collect();
+        assert_eq!(created, [true, false, false, true]);
+        let ids: Vec<&str> = results.iter().map(|r| &*r.document.header._id).collect();
+        assert_eq!(ids[1], existing.document.header._id);
+        assert_eq!(ids[0], ids[2]);
+        assert_ne!(ids[0], ids[3]);
+
+        let counts = gc.stats.counts(&get_name::<FocusedCorpus>());
+        assert_eq!((counts.created, counts.existing, counts.failed), (3, 2, 0));
+
+        // the documents without the key fail together
+        assert!(
+            gc.upsert_nodes(vec![corpus("a"), corpus("b")], "package_name")
+                .is_err()
+        );
+        assert_eq!(gc.stats.counts(&get_name::<FocusedCorpus>()).failed, 2);
+
+        Ok(())
+    }
+
+    /// Compares [`GraphCreatorBase::upsert_nodes`] with upserting the documents one by one. Needs
+    /// the ArangoDB of `start_arango.sh`:
+    /// `cargo test --release -p macon upsert_nodes_benchmark -- --ignored --nocapture`
+    #[test]
+    #[ignore = "needs a running ArangoDB"]
+    fn upsert_nodes_benchmark() -> Result<()> {
+        const DOCUMENTS: usize = 5000;
+
+        let _lock = COLLECTION_PREFIX_LOCK.lock().unwrap();
+
+        let config = Config {
+            database: "macon_benchmark".to_string(),
+            ..Default::default()
+        };
+        let database = config.database.clone();
+        let conn = establish_database_connection(&config)?;
+        let _ = conn.drop_database(&database);
+
+        let gc = FocusedGraph::try_new(&config, false, false)?;
+        let (edge_definitions, _) = focused_graph_schema();
+        gc.init::<FocusedCorpus>(config, corpus("FocusedCorpus"), edge_definitions)?;
+
+        let started = Instant::now();
+        for i in 0..DOCUMENTS {
+            let name = format!("single-{i}");
+            gc.upsert_node(corpus(&name), "name", &name)?;
+        }
+        let single = started.elapsed();
+
+        let started = Instant::now();
+        let batch = (0..DOCUMENTS)
+            .map(|i| corpus(&format!("bulk-{i}")))
+            .collect();
+        gc.upsert_nodes(batch, "name")?;
+        let bulk = started.elapsed();
+
+        println!("Upserting {DOCUMENTS} documents: {single:?} one by one, {bulk:?} in bulk");
+        conn.drop_database(&database)?;
+        Ok(())
+    }
+
+    #[test]
+    fn collection_name_derive() {
+        #[derive(CollectionName)]
+        struct Plain;
+
+        #[derive(CollectionName)]
+        #[collection(name = "OldName")]
+        struct Renamed;
+
+        assert_eq!(Plain::NAME, "Plain");
+        assert_eq!(Renamed::NAME, "OldName");
+        assert_eq!(FocusedCorpus::NAME, "FocusedCorpus");
+    }
+
+    #[test]
+    fn skipped_files_get_output_records() -> Result<()> {
+        use clap::Parser;
+
+        use crate::cli::{Cli, MainCommands};
+
+        let _lock = COLLECTION_PREFIX_LOCK.lock().unwrap();
+
+        let dir = tempfile::tempdir()?;
+        let config = dir.path().join("macon.toml");
+        let sample = dir.path().join("sample.ps1");
+        let notes = dir.path().join("notes.txt");
+        let empty = dir.path().join("empty.ps1");
+        let output = dir.path().join("output.jsonl");
+        std::fs::write(&config, "")?;
+        std::fs::copy(
+            Path::new(FIXTURES).join("mintsloader/start_process_encodedcommand.ps1"),
+            &sample,
+        )?;
+        std::fs::write(&notes, "triage notes")?;
+        std::fs::write(&empty, "")?;
+
+        let output_arg = format!("json={}", output.display());
+        let args = [
+            "macon".as_ref(),
+            "--config".as_ref(),
+            config.as_os_str(),
+            "focused".as_ref(),
+            "--dry-run".as_ref(),
+            "--output".as_ref(),
+            output_arg.as_ref(),
+            "mintsloader".as_ref(),
+            sample.as_os_str(),
+            notes.as_os_str(),
+            empty.as_os_str(),
+        ];
+        let cli = Cli::try_parse_from(args)?;
+        let MainCommands::Focused(focused_args) = cli.command else {
+            unreachable!("parsed as focused");
+        };
+        focused_graph_main(focused_args, cli.config)?;
+
+        let records: Vec<SampleRecord> = std::fs::read_to_string(&output)?
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?;
+        let status = |path: &Path| {
+            let path = path.display().to_string();
+            let record = records.iter().find(|r| r.path == path).unwrap();
+            (record.status, record.error.clone())
+        };
+
+        assert_eq!(records.len(), 3);
+        assert_eq!(status(&sample), (SampleStatus::Analyzed, None));
+        assert_eq!(
+            status(&notes),
+            (
+                SampleStatus::Skipped,
+                Some("pre-filter: denied extension .txt (--prefilter-deny-ext)".to_string())
+            )
+        );
+        assert_eq!(
+            status(&empty),
+            (
+                SampleStatus::Skipped,
+                Some("pre-filter: empty file".to_string())
+            )
+        );
+
+        Ok(())
     }
 }
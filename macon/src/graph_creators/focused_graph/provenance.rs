@@ -0,0 +1,242 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::Result;
+use arangors::{AqlQuery, graph::EdgeDefinition};
+use macon_cag::{
+    base_creator::{EdgeAttributes, GraphCreatorBase},
+    filter::{AqlFilter, Filter},
+    prelude::Database,
+    provenance::Provenance,
+    utils::{CollectionName, get_name},
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tracing::info;
+
+use crate::{
+    cli::TimeWindowArgs,
+    graph_creators::focused_graph::{
+        FocusedCorpus, FocusedGraph, carnavalheist::nodes::Carnavalheist, coper::nodes::Coper,
+        dark_watchmen::nodes::DarkWatchmen, mintsloader::nodes::Mintsloader,
+    },
+};
+
+/// Invocation of macon that analyzed samples. The sample nodes it created have a
+/// [`CreatedDuring`] edge to it, see `--no-provenance`.
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, CollectionName)]
+pub struct IngestRun {
+    pub run_id: String,
+
+    /// Time (RFC 3339) the run started
+    pub started_at: String,
+
+    /// Time (RFC 3339) the run finished, not set if it was aborted
+    pub finished_at: Option<String>,
+    pub macon_version: String,
+
+    /// Command line of the run, starting with the program
+    pub args: Vec<String>,
+    pub hostname: Option<String>,
+}
+
+#[derive(
+    Deserialize, Serialize, Debug, Clone, JsonSchema, Default, EdgeAttributes, CollectionName,
+)]
+pub struct CreatedDuring {
+    pub _key: String,
+    pub _from: String,
+    pub _to: String,
+}
+
+/// Vertex collections of `edge_definitions` that have sample nodes, i.e. all but the corpus and
+/// the main nodes of the families
+pub(super) fn sample_collections(edge_definitions: &[EdgeDefinition]) -> Vec<String> {
+    let excluded = [
+        get_name::<FocusedCorpus>(),
+        get_name::<Carnavalheist>(),
+        get_name::<Coper>(),
+        get_name::<DarkWatchmen>(),
+        get_name::<Mintsloader>(),
+    ];
+
+    edge_definitions
+        .iter()
+        .flat_map(|ed| ed.from.iter().chain(ed.to.iter()))
+        .filter(|collection| !excluded.contains(collection))
+        .cloned()
+        .collect::<BTreeSet<String>>()
+        .into_iter()
+        .collect()
+}
+
+pub(super) fn provenance_edge_definition(sample_collections: Vec<String>) -> EdgeDefinition {
+    EdgeDefinition {
+        collection: get_name::<CreatedDuring>(),
+        from: sample_collections,
+        to: vec![get_name::<IngestRun>()],
+    }
+}
+
+/// `_id` of the sample nodes created by the runs that started in `window`, found through their
+/// [`CreatedDuring`] edges. Nodes of runs with `--no-provenance` are never part of a window.
+pub(crate) fn created_during(db: &Database, window: &TimeWindowArgs) -> Result<Vec<String>> {
+    // the collections are only created by the first run with provenance
+    let runs = get_name::<IngestRun>();
+    let exists = db
+        .accessible_collections()?
+        .iter()
+        .any(|info| info.name == runs);
+    if !exists {
+        return Ok(vec![]);
+    }
+
+    let (query, bind_vars) = created_during_query(window)?;
+    let aql = AqlQuery::builder()
+        .query(&query)
+        .bind_vars(
+            bind_vars
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.clone()))
+                .collect(),
+        )
+        .build();
+
+    Ok(db.aql_query(aql)?)
+}
+
+/// Query of [`created_during`] and its bind variables
+fn created_during_query(window: &TimeWindowArgs) -> Result<(String, BTreeMap<String, Value>)> {
+    // started_at is written by to_rfc3339 in UTC, so the strings compare by time
+    let mut filter = Filter::new();
+    if let Some(since) = window.since {
+        filter = filter.gte("started_at", since.to_rfc3339());
+    }
+    if let Some(until) = window.until {
+        filter = filter.lt("started_at", until.to_rfc3339());
+    }
+    let AqlFilter {
+        condition,
+        mut bind_vars,
+    } = filter.to_aql("run")?;
+
+    bind_vars.insert("@runs".to_string(), get_name::<IngestRun>().into());
+    bind_vars.insert(
+        "@created_during".to_string(),
+        get_name::<CreatedDuring>().into(),
+    );
+
+    let query = format!(
+        "for run in @@runs
+            filter {condition}
+            for v in 1..1 inbound run @@created_during
+                return distinct v._id"
+    );
+
+    Ok((query, bind_vars))
+}
+
+/// Name of the host from the kernel or the environment
+fn hostname() -> Option<String> {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .map(|hostname| hostname.trim().to_string())
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .filter(|hostname| !hostname.is_empty())
+}
+
+impl FocusedGraph {
+    /// Creates the node of this run. The sample nodes in `sample_collections` that are created
+    /// afterwards are linked to it.
+    pub(super) fn start_run(&mut self, sample_collections: Vec<String>) -> Result<()> {
+        let run = IngestRun {
+            run_id: uuid::Uuid::new_v4().to_string(),
+            started_at: chrono::Utc::now().to_rfc3339(),
+            finished_at: None,
+            macon_version: env!("CARGO_PKG_VERSION").to_string(),
+            args: std::env::args().collect(),
+            hostname: hostname(),
+        };
+        let run_id = run.run_id.clone();
+
+        let run_node = self.create_vertex::<IngestRun>(run)?;
+        info!(
+            "Linking the created sample nodes to run {run_id} ({})",
+            run_node.header._id
+        );
+
+        self.provenance = Some(Provenance::new::<CreatedDuring>(
+            run_node.header._id.clone(),
+            sample_collections,
+        ));
+        self.run_node = Some(run_node);
+
+        Ok(())
+    }
+
+    /// Sets the time the run finished on its node, if there is one
+    pub(super) fn finish_run(&self) -> Result<()> {
+        if let Some(run_node) = &self.run_node {
+            let finished_at = chrono::Utc::now().to_rfc3339();
+            self.update_node_attributes(run_node, json!({ "finished_at": finished_at }))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+    use crate::graph_creators::COLLECTION_PREFIX_LOCK;
+
+    #[test]
+    fn runs_of_a_window() -> Result<()> {
+        let _lock = COLLECTION_PREFIX_LOCK.lock().unwrap();
+
+        let window = TimeWindowArgs {
+            since: Some(Utc.with_ymd_and_hms(2026, 10, 12, 0, 0, 0).unwrap()),
+            until: Some(Utc.with_ymd_and_hms(2026, 10, 19, 0, 0, 0).unwrap()),
+        };
+        let (query, bind_vars) = created_during_query(&window)?;
+
+        assert!(query.contains("filter run.@path0 >= @value0 && run.@path1 < @value1"));
+        assert!(query.contains("inbound run @@created_during"));
+        assert_eq!(bind_vars["@runs"], get_name::<IngestRun>());
+        assert_eq!(bind_vars["@created_during"], get_name::<CreatedDuring>());
+        assert_eq!(bind_vars["path0"], json!(["started_at"]));
+        assert_eq!(bind_vars["value0"], "2026-10-12T00:00:00+00:00");
+        assert_eq!(bind_vars["value1"], "2026-10-19T00:00:00+00:00");
+
+        // the bounds have the format of started_at, so they compare as strings
+        let started_at = Utc
+            .with_ymd_and_hms(2026, 10, 12, 8, 30, 0)
+            .unwrap()
+            .to_rfc3339();
+        assert!(started_at.as_str() >= bind_vars["value0"].as_str().unwrap());
+        assert!(started_at.as_str() < bind_vars["value1"].as_str().unwrap());
+
+        Ok(())
+    }
+
+    #[test]
+    fn open_windows() -> Result<()> {
+        let _lock = COLLECTION_PREFIX_LOCK.lock().unwrap();
+
+        let (query, bind_vars) = created_during_query(&TimeWindowArgs::default())?;
+        assert!(query.contains("filter true"));
+        assert!(!bind_vars.contains_key("value0"));
+
+        let since = TimeWindowArgs {
+            since: Some(Utc.with_ymd_and_hms(2026, 10, 12, 0, 0, 0).unwrap()),
+            until: None,
+        };
+        let (query, _) = created_during_query(&since)?;
+        assert!(query.contains("filter run.@path0 >= @value0\n"));
+
+        Ok(())
+    }
+}
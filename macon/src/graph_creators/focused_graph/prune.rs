@@ -0,0 +1,154 @@
+use anyhow::{Result, anyhow};
+use arangors::AqlQuery;
+use macon_cag::{base_creator::GraphCreatorBase, utils::get_name};
+use serde::Deserialize;
+use tracing::info;
+
+use crate::{
+    cli::{ConfigArgs, PruneArgs},
+    graph_creators::focused_graph::{
+        FocusedCorpus, FocusedGraph, focused_graph_config, ingested::IngestedFile,
+    },
+};
+
+/// Maximum depth of the traversals from the main nodes of the families
+const MAX_DEPTH: u32 = 100;
+
+/// Documents of a family that aren't reachable from any other family
+#[derive(Deserialize, Debug)]
+struct PruneSet {
+    /// `_id` of the main node of the family
+    family: String,
+
+    /// `_id` of the vertices in the order they were reached
+    vertices: Vec<String>,
+
+    /// `sha256sum` of the vertices that have one
+    sha256sums: Vec<String>,
+
+    /// `_id` of the edges that start or end at the main node or one of the vertices
+    edges: Vec<String>,
+}
+
+/// Deletes the main node of a family and all documents that are only reachable through it. The
+/// samples are removed from the [`IngestedFile`] collection as well, so they are analyzed again
+/// with `--skip-known`.
+pub fn prune_main(prune_args: PruneArgs, config_args: ConfigArgs) -> Result<()> {
+    let PruneArgs { family, dry_run } = prune_args;
+
+    let config = focused_graph_config(&config_args)?;
+    let gc = FocusedGraph::try_new(&config, false, false)?;
+
+    let prune_set = find_prune_set(&gc, &family)?;
+
+    if dry_run {
+        println!("vertex\t{}", prune_set.family);
+        for vertex in &prune_set.vertices {
+            println!("vertex\t{vertex}");
+        }
+        for edge in &prune_set.edges {
+            println!("edge\t{edge}");
+        }
+        println!(
+            "Would delete {} vertices and {} edges of family '{family}'",
+            prune_set.vertices.len() + 1,
+            prune_set.edges.len()
+        );
+        return Ok(());
+    }
+
+    // the vertices that were reached last are deleted first, so an interrupted prune doesn't
+    // leave behind vertices that can't be reached from the main node anymore
+    let mut removed = 0;
+    for vertex in prune_set.vertices.iter().rev() {
+        removed += gc.delete_node_by_id(vertex, true)?;
+    }
+    removed += gc.delete_node_by_id(&prune_set.family, true)?;
+
+    let vertices = prune_set.vertices.len() as u64 + 1;
+    let ingested = forget_ingested(&gc, prune_set.sha256sums)?;
+    info!(
+        "Removed {ingested} samples from {}",
+        get_name::<IngestedFile>()
+    );
+
+    println!(
+        "Deleted {vertices} vertices and {} edges of family '{family}'",
+        removed - vertices
+    );
+
+    Ok(())
+}
+
+/// Main node of the family named `family` (case-insensitive) and the vertices and edges that are
+/// only reachable through it. A vertex that can also be reached from another family, e.g. a
+/// certificate used by samples of both, is kept with everything below it.
+fn find_prune_set(gc: &FocusedGraph, family: &str) -> Result<PruneSet> {
+    let aql = AqlQuery::builder()
+        .query(
+            "for corpus in @@corpus
+                for family in 1..1 outbound corpus graph @graph
+                    filter family._id != corpus._id && lower(family.name) == lower(@family)
+                    let others = (
+                        for other in 1..1 outbound corpus graph @graph
+                            filter other._id != corpus._id && other._id != family._id
+                            for v in 0..@depth outbound other graph @graph
+                                options { order: 'bfs', uniqueVertices: 'global' }
+                                return v._id
+                    )
+                    let vertices = (
+                        for v in 1..@depth outbound family graph @graph
+                            options { order: 'bfs', uniqueVertices: 'global' }
+                            filter v._id not in others
+                            return v
+                    )
+                    let edges = unique(
+                        for id in append(vertices[*]._id, [family._id])
+                            for v, e in 1..1 any id graph @graph
+                                return e._id
+                    )
+                    return {
+                        family: family._id,
+                        vertices: vertices[*]._id,
+                        sha256sums: vertices[* filter CURRENT.sha256sum != null return CURRENT.sha256sum],
+                        edges
+                    }",
+        )
+        .bind_var("@corpus", get_name::<FocusedCorpus>())
+        .bind_var("graph", gc.graph.clone())
+        .bind_var("family", family)
+        .bind_var("depth", MAX_DEPTH)
+        .build();
+
+    let mut result: Vec<PruneSet> = gc.run_aql(aql)?;
+    result
+        .pop()
+        .ok_or(anyhow!("There is no family named '{family}' in the graph"))
+}
+
+/// Removes the samples with `sha256sums` from the [`IngestedFile`] collection. Returns the number
+/// of removed samples.
+fn forget_ingested(gc: &FocusedGraph, sha256sums: Vec<String>) -> Result<u64> {
+    let collection_name = get_name::<IngestedFile>();
+
+    // the collection only exists after the first analysis
+    if sha256sums.is_empty() || gc.get_db()?.collection(&collection_name).is_err() {
+        return Ok(0);
+    }
+
+    let aql = AqlQuery::builder()
+        .query(
+            "return length(
+                for d in @@collection_name
+                    filter d.sha256sum in @sha256sums
+                    remove d in @@collection_name
+                    return 1
+            )",
+        )
+        .bind_var("@collection_name", collection_name)
+        .bind_var("sha256sums", sha256sums)
+        .build();
+
+    let mut result: Vec<u64> = gc.run_aql(aql)?;
+    Ok(result.pop().unwrap_or(0))
+}
@@ -0,0 +1,159 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use anyhow::Result;
+use indicatif::ParallelProgressIterator;
+use indicatif::ProgressIterator;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use sha256::digest;
+use tracing::{debug_span, warn};
+
+use crate::{
+    classify::MalwareFamily,
+    graph_creators::focused_graph::FocusedGraph,
+    logging::progress_bar,
+    output::{SampleRecord, SampleStatus},
+    report::{SampleEvent, Stage, StageError},
+    utils::{SampleRef, is_skipped, load_sample},
+};
+
+/// What the handler of a family did with a sample, part of the output of `--output`
+pub(super) struct SampleOutcome {
+    pub(super) sample_type: &'static str,
+
+    /// `_id` of the documents that were created or linked
+    pub(super) documents: Vec<String>,
+
+    /// Plausible sample types if more than one was plausible, see
+    /// [`Detection::ambiguity_report`](crate::detection::Detection::ambiguity_report)
+    pub(super) ambiguity: Option<String>,
+}
+
+impl FocusedGraph {
+    /// Reads the samples in `files` and hands them to `handler`, in parallel if `parallel` is set.
+    /// Samples above the maximum file size are skipped with a warning, errors of reading or
+    /// handling a sample are reported with its path and samples that were handled successfully
+    /// are marked as analyzed for `--skip-known`. With `--fail-fast` the remaining samples are
+    /// skipped after the first error. Ambiguous sample types are reported as
+    /// [`SampleEvent::Ambiguous`]. With `--output` a [`SampleRecord`] of `family` is written for
+    /// every sample.
+    pub(super) fn process_samples(
+        &self,
+        files: &[PathBuf],
+        family: MalwareFamily,
+        parallel: bool,
+        handler: impl Fn(&SampleRef, &[u8]) -> Result<SampleOutcome> + Sync,
+    ) {
+        let process = |entry: &PathBuf| {
+            let start = Instant::now();
+            let sample = SampleRef::new(entry, &self.roots);
+            // names the sample in the debug events of the helpers, e.g. of the zip extraction
+            let _span = debug_span!("sample", sample = %sample).entered();
+            self.errors.send(SampleEvent::Started {
+                file: sample.clone(),
+            });
+
+            let mut record = SampleRecord {
+                path: sample.relative().to_string(),
+                sha256: None,
+                family: Some(family.as_str().to_string()),
+                sample_type: None,
+                documents: vec![],
+                duration_ms: 0,
+                status: SampleStatus::Analyzed,
+                error: None,
+                warnings: vec![],
+            };
+
+            match load_sample(entry) {
+                Ok(buf) => {
+                    if self.output.is_some() {
+                        record.sha256 = Some(digest(&*buf));
+                    }
+
+                    match handler(&sample, &buf) {
+                        Ok(outcome) => {
+                            record.sample_type = Some(outcome.sample_type.to_string());
+                            record.documents = outcome.documents;
+                            if let Some(report) = outcome.ambiguity {
+                                record.warnings.push(report.clone());
+                                self.errors.send(SampleEvent::Ambiguous {
+                                    file: sample.clone(),
+                                    report,
+                                });
+                            }
+                            self.sample_analyzed(&sample, &buf);
+                        }
+                        Err(e) => {
+                            record.status = SampleStatus::Failed;
+                            record.error = Some(e.to_string());
+                            self.errors.push(&sample, e);
+                        }
+                    }
+                }
+                Err(e) if is_skipped(&e) => {
+                    warn!("{e}");
+                    record.status = SampleStatus::Skipped;
+                    record.error = Some(e.to_string());
+                }
+                Err(e) => {
+                    record.status = SampleStatus::Failed;
+                    record.error = Some(e.to_string());
+                    self.errors.push(&sample, StageError::wrap(Stage::Read, e));
+                }
+            }
+
+            let elapsed = start.elapsed();
+            self.errors.send(SampleEvent::Finished {
+                file: sample.clone(),
+                elapsed,
+            });
+
+            if let Some(output) = &self.output {
+                record.duration_ms = elapsed.as_millis() as u64;
+                if let Err(e) = output.write(record) {
+                    warn!(sample = %sample, "Writing the output failed: {e}");
+                }
+            }
+        };
+
+        if parallel {
+            files
+                .par_iter()
+                .progress_with(progress_bar(files.len()))
+                .filter(|_| !self.errors.is_aborted())
+                .for_each(process);
+        } else {
+            files
+                .iter()
+                .progress_with(progress_bar(files.len()))
+                .take_while(|_| !self.errors.is_aborted())
+                .for_each(process);
+        }
+    }
+
+    /// Writes a skipped `--output` record for `file`, which wasn't handed to a family
+    pub(super) fn write_skipped(&self, file: &Path, sha256: Option<String>, reason: String) {
+        let Some(output) = &self.output else {
+            return;
+        };
+
+        let sample = SampleRef::new(file, &self.roots);
+        let record = SampleRecord {
+            path: sample.relative().to_string(),
+            sha256,
+            family: None,
+            sample_type: None,
+            documents: vec![],
+            duration_ms: 0,
+            status: SampleStatus::Skipped,
+            error: Some(reason),
+            warnings: vec![],
+        };
+        if let Err(e) = output.write(record) {
+            warn!(sample = %sample, "Writing the output failed: {e}");
+        }
+    }
+}
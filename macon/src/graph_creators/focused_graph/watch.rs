@@ -0,0 +1,268 @@
+//! Analysis of the samples that are dropped into a directory while macon runs, see `--watch`
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{Receiver, RecvTimeoutError, channel},
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{Result, anyhow};
+use notify::{
+    Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher,
+    event::{AccessKind, AccessMode, ModifyKind, RenameMode},
+};
+use tracing::{debug, info, warn};
+
+/// Time the size of a file has to stay the same before it is analyzed, unless it was closed
+/// after writing
+const SETTLE_TIME: Duration = Duration::from_secs(2);
+
+/// Interval the pending files are checked in
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Exit code of a second Ctrl-C, which doesn't wait for the running samples
+const EXIT_INTERRUPTED: i32 = 130;
+
+/// File that is probably still being written
+struct PendingFile {
+    size: Option<u64>,
+    changed: Instant,
+
+    /// The writer closed the file, so it is complete
+    closed: bool,
+}
+
+/// Watches a directory for new files and hands them out once they are completely written. The
+/// watcher stops on Ctrl-C, a second Ctrl-C exits right away.
+pub(super) struct DropWatcher {
+    // the directory is only watched as long as the watcher lives
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+    pending: HashMap<PathBuf, PendingFile>,
+
+    /// Files that were handed out or analyzed before the watcher started, see [`canonical`]
+    seen: HashSet<PathBuf>,
+    stop: Arc<AtomicBool>,
+}
+
+impl DropWatcher {
+    /// Watches `dir` and its subdirectories. The files in `seen` are not handed out, as they are
+    /// analyzed by the initial pass.
+    pub(super) fn new(dir: &Path, seen: HashSet<PathBuf>) -> Result<Self> {
+        let watcher = Self::watch(dir, seen)?;
+
+        let handler_stop = watcher.stop.clone();
+        ctrlc::set_handler(move || {
+            if handler_stop.swap(true, Ordering::Relaxed) {
+                std::process::exit(EXIT_INTERRUPTED);
+            }
+            info!("Stopping after the running samples, press Ctrl-C again to exit right away");
+        })?;
+
+        Ok(watcher)
+    }
+
+    /// [`Self::new`] without the Ctrl-C handler
+    fn watch(dir: &Path, seen: HashSet<PathBuf>) -> Result<Self> {
+        let (sender, events) = channel();
+        let mut watcher = notify::recommended_watcher(sender)
+            .map_err(|e| anyhow!("Watching {} failed: {e}", dir.display()))?;
+        watcher
+            .watch(dir, RecursiveMode::Recursive)
+            .map_err(|e| anyhow!("Watching {} failed: {e}", dir.display()))?;
+
+        Ok(Self {
+            _watcher: watcher,
+            events,
+            pending: HashMap::new(),
+            seen: seen.iter().map(|path| canonical(path)).collect(),
+            stop: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    /// Waits for files that were completely written since the last batch, sorted by path. An
+    /// empty batch once `until` passed without new files, `None` once the watcher was stopped
+    /// with Ctrl-C.
+    pub(super) fn next_batch(&mut self, until: Option<Instant>) -> Option<Vec<PathBuf>> {
+        loop {
+            if self.stop.load(Ordering::Relaxed) {
+                return None;
+            }
+
+            let timeout = match until {
+                Some(until) => POLL_INTERVAL.min(until.saturating_duration_since(Instant::now())),
+                None => POLL_INTERVAL,
+            };
+            match self.events.recv_timeout(timeout) {
+                Ok(event) => self.handle(event),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    warn!("The watcher of the directory stopped");
+                    return None;
+                }
+            }
+            while let Ok(event) = self.events.try_recv() {
+                self.handle(event);
+            }
+
+            let mut ready = self.settled();
+            if !ready.is_empty() {
+                ready.sort();
+                self.seen.extend(ready.iter().map(|path| canonical(path)));
+                return Some(ready);
+            }
+
+            if until.is_some_and(|until| Instant::now() >= until) {
+                return Some(vec![]);
+            }
+        }
+    }
+
+    fn handle(&mut self, event: notify::Result<Event>) {
+        let event = match event {
+            Ok(event) => event,
+            Err(e) => {
+                warn!("Watching the directory failed: {e}");
+                return;
+            }
+        };
+
+        let (paths, closed) = match event.kind {
+            EventKind::Create(_) | EventKind::Modify(ModifyKind::Data(_) | ModifyKind::Any) => {
+                (&event.paths[..], false)
+            }
+            // the new name of a renamed file is the last path
+            EventKind::Modify(ModifyKind::Name(RenameMode::To | RenameMode::Both)) => (
+                event.paths.last().map(std::slice::from_ref).unwrap_or(&[]),
+                false,
+            ),
+            EventKind::Access(AccessKind::Close(AccessMode::Write)) => (&event.paths[..], true),
+            _ => return,
+        };
+
+        for path in paths {
+            let hidden = path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+            if hidden || !path.is_file() || self.seen.contains(&canonical(path)) {
+                continue;
+            }
+
+            let pending = self.pending.entry(path.clone()).or_insert(PendingFile {
+                size: None,
+                changed: Instant::now(),
+                closed: false,
+            });
+            pending.closed |= closed;
+        }
+    }
+
+    /// Removes the files from the pending ones that were closed or whose size didn't change for
+    /// [`SETTLE_TIME`]
+    fn settled(&mut self) -> Vec<PathBuf> {
+        let mut ready = vec![];
+
+        self.pending.retain(|path, pending| {
+            // the file was removed or renamed before it was complete
+            let Ok(metadata) = path.metadata() else {
+                return false;
+            };
+
+            if pending.size != Some(metadata.len()) {
+                pending.size = Some(metadata.len());
+                pending.changed = Instant::now();
+            }
+
+            if pending.closed || pending.changed.elapsed() >= SETTLE_TIME {
+                debug!(sample = %path.display(), "new sample");
+                ready.push(path.clone());
+                false
+            } else {
+                true
+            }
+        });
+
+        ready
+    }
+}
+
+/// Absolute path of `path` without symbolic links, as the initial files are named as they were
+/// given while the events name them from the watched directory. Paths that can't be resolved,
+/// e.g. members of archives, are kept as they are.
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use notify::event::CreateKind;
+
+    use super::*;
+
+    fn created(path: &Path) -> notify::Result<Event> {
+        Ok(Event::new(EventKind::Create(CreateKind::File)).add_path(path.to_path_buf()))
+    }
+
+    #[test]
+    fn initial_files_are_not_handed_out_again() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        std::fs::create_dir(dir.path().join("sub"))?;
+        let known = dir.path().join("known.bat");
+        let new = dir.path().join("new.bat");
+        std::fs::write(&known, "@echo off")?;
+        std::fs::write(&new, "@echo off")?;
+
+        // the initial file is named differently than in the events
+        let initial = dir.path().join("sub/../known.bat");
+        let mut watcher = DropWatcher::watch(dir.path(), HashSet::from([initial]))?;
+
+        watcher.handle(created(&canonical(&known)));
+        assert!(watcher.pending.is_empty());
+
+        watcher.handle(created(&new));
+        assert_eq!(watcher.pending.keys().collect::<Vec<_>>(), [&new]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn closed_files_are_ready() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let sample = dir.path().join("sample.bat");
+        let hidden = dir.path().join(".partial");
+        std::fs::write(&sample, "@echo off")?;
+        std::fs::write(&hidden, "@echo")?;
+        let mut watcher = DropWatcher::watch(dir.path(), HashSet::new())?;
+
+        watcher.handle(created(&hidden));
+        watcher.handle(created(&sample));
+        assert!(watcher.settled().is_empty());
+
+        let closed = Event::new(EventKind::Access(AccessKind::Close(AccessMode::Write)));
+        watcher.handle(Ok(closed.add_path(sample.clone())));
+        assert_eq!(watcher.settled(), [sample]);
+        assert!(watcher.pending.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn empty_batch_at_the_deadline() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut watcher = DropWatcher::watch(dir.path(), HashSet::new())?;
+
+        let until = Instant::now() + Duration::from_millis(50);
+        assert_eq!(watcher.next_batch(Some(until)), Some(vec![]));
+        assert!(Instant::now() >= until);
+
+        // a deadline that passed already doesn't wait
+        assert_eq!(watcher.next_batch(Some(until)), Some(vec![]));
+
+        Ok(())
+    }
+}
@@ -2,12 +2,13 @@ extern crate ssdeep;
 
 use std::{
     collections::HashMap,
-    io::{Read, Write},
-    path::PathBuf,
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
 };
 
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 use indicatif::ParallelProgressIterator;
 use lavinhash::{HashConfig, model::FuzzyFingerprint};
 use rayon::iter::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
@@ -18,20 +19,32 @@ use smartcore::{
     },
     linalg::basic::matrix::DenseMatrix,
 };
+use tracing::warn;
 
-use crate::graph_creators::general_graph::{
-    GeneralGraph,
-    evaluation::{ClusterEvaluation, eval_clustering},
+use crate::{
+    cli::{ClusterAlgorithm, Distance, GeneralArgs},
+    graph_creators::general_graph::{
+        GeneralGraph,
+        evaluation::{ClusterEvaluation, eval_clustering},
+    },
+    logging::progress_bar,
+    utils::{is_skipped, load_sample},
 };
 
-fn get_labeld_files(files: Vec<PathBuf>) -> HashMap<String, Vec<PathBuf>> {
+/// Groups the files by the name of the directory they are in, which is the malware family of the
+/// sample
+fn get_labeld_files(files: Vec<PathBuf>) -> Result<HashMap<String, Vec<PathBuf>>> {
     let mut map: HashMap<String, Vec<PathBuf>> = HashMap::new();
 
     for file in files {
         let family = file
             .parent()
-            .and_then(|path| path.file_name().and_then(|name|name.to_str()))
-            .map(|s| s.to_string()).expect("Sample has to be in a directory. The directory name indicates the malware family for evaluation");
+            .and_then(|path| path.file_name().and_then(|name| name.to_str()))
+            .map(|s| s.to_string())
+            .ok_or(anyhow!(
+                "Sample '{}' has to be in a directory. The directory name indicates the malware family for evaluation",
+                file.display()
+            ))?;
 
         if let Some(paths) = map.get_mut(&family) {
             paths.push(file);
@@ -40,14 +53,22 @@ fn get_labeld_files(files: Vec<PathBuf>) -> HashMap<String, Vec<PathBuf>> {
         }
     }
 
-    map
+    Ok(map)
 }
 
 impl GeneralGraph {
-    pub fn general_graph_entry(&self, files: Vec<PathBuf>) -> Result<()> {
+    pub fn general_graph_entry(&self, general_args: GeneralArgs) -> Result<()> {
+        let GeneralArgs {
+            main_args,
+            out_dir,
+            distances,
+            algorithm,
+            corpus: _,
+        } = general_args;
+
         let mut nodes = vec![];
 
-        let labeled_files = get_labeld_files(files);
+        let labeled_files = get_labeld_files(main_args.files)?;
 
         for (family, files) in labeled_files {
             let mut tmp_nodes = get_nodes_from_files(files, family)?;
@@ -57,49 +78,104 @@ impl GeneralGraph {
         // ensure nodes is immutable from here on
         let nodes = nodes;
 
-        // let distance_functions = [ssdeep_distance, lavin_distance, tlsh_distance];
-        let mut distance_functions: HashMap<&str, fn(&Node, &Node) -> f64> = HashMap::new();
-        distance_functions.insert("ssdeep", ssdeep_distance);
-        distance_functions.insert("lavin", lavin_distance);
-        distance_functions.insert("tlsh", tlsh_distance);
-        distance_functions.insert("combined", combined_distance);
+        std::fs::create_dir_all(&out_dir)?;
 
-        for (n, d) in distance_functions {
-            let tmp = compute_distance_matrix(&nodes, d);
+        for distance in distances {
+            let (name, distance_function) = distance_function(distance);
+            let tmp = compute_distance_matrix(&nodes, distance_function);
             let distance_matrix = DenseMatrix::from_2d_vec(&tmp)?;
 
-            let filename = format!("dbscan_{n}.csv");
-            let file = Arc::new(Mutex::new(std::fs::File::create(filename)?));
-
-            writeln!(&mut file.lock().unwrap(), "eps,min_pts,prurity,nmi,ri,f5")?;
-
-            (1..100).into_par_iter().progress().for_each(|eps| {
-                for min_pts in 2..100 {
-                    let labels = get_dbscan_labels(&distance_matrix, eps as f64, min_pts);
-                    let cluster = partition_nodes_in_cluster(&labels, &nodes);
-                    let c: Vec<&[&Node]> = cluster.iter().map(|d| d.as_slice()).collect();
-
-                    let ClusterEvaluation {
-                        purity,
-                        nmi,
-                        ri,
-                        f5,
-                    } = eval_clustering(&c);
-
-                    writeln!(
-                        &mut file.lock().unwrap(),
-                        "{eps},{min_pts},{purity},{nmi},{ri},{f5}",
-                    )
-                    .unwrap();
-                }
-            });
+            match algorithm {
+                ClusterAlgorithm::Dbscan => evaluate_dbscan(
+                    &distance_matrix,
+                    &nodes,
+                    &out_dir.join(format!("dbscan_{name}.csv")),
+                )?,
+                ClusterAlgorithm::Kmeans => evaluate_kmeans(
+                    &distance_matrix,
+                    &nodes,
+                    &out_dir.join(format!("kmeans_{name}.csv")),
+                )?,
+            }
         }
 
         Ok(())
     }
 }
 
-#[allow(dead_code)]
+/// Name and distance function of `distance`
+fn distance_function(distance: Distance) -> (&'static str, fn(&Node, &Node) -> f64) {
+    match distance {
+        Distance::Ssdeep => ("ssdeep", ssdeep_distance),
+        Distance::Lavin => ("lavin", lavin_distance),
+        Distance::Tlsh => ("tlsh", tlsh_distance),
+        Distance::Combined => ("combined", combined_distance),
+    }
+}
+
+/// Writes the evaluation of DBSCAN for every `eps` from 1 to 99 and `min_pts` from 2 to 99 to
+/// `path`
+fn evaluate_dbscan(distance_matrix: &DenseMatrix<f64>, nodes: &[Node], path: &Path) -> Result<()> {
+    let file = Arc::new(Mutex::new(File::create(path)?));
+
+    writeln!(&mut file.lock().unwrap(), "eps,min_pts,prurity,nmi,ri,f5")?;
+
+    (1..100)
+        .into_par_iter()
+        .progress_with(progress_bar(99))
+        .for_each(|eps| {
+            for min_pts in 2..100 {
+                let labels = get_dbscan_labels(distance_matrix, eps as f64, min_pts);
+                let cluster = partition_nodes_in_cluster(&labels, nodes);
+                let c: Vec<&[&Node]> = cluster.iter().map(|d| d.as_slice()).collect();
+
+                let ClusterEvaluation {
+                    purity,
+                    nmi,
+                    ri,
+                    f5,
+                } = eval_clustering(&c);
+
+                writeln!(
+                    &mut file.lock().unwrap(),
+                    "{eps},{min_pts},{purity},{nmi},{ri},{f5}",
+                )
+                .unwrap();
+            }
+        });
+
+    Ok(())
+}
+
+/// Writes the evaluation of k-means for 2 to 99 clusters to `path`. Numbers of clusters above the
+/// number of samples are skipped
+fn evaluate_kmeans(distance_matrix: &DenseMatrix<f64>, nodes: &[Node], path: &Path) -> Result<()> {
+    let file = Arc::new(Mutex::new(File::create(path)?));
+
+    writeln!(&mut file.lock().unwrap(), "k,prurity,nmi,ri,f5")?;
+
+    let ks = 2..100.min(nodes.len() + 1);
+    let len = ks.len();
+    ks.into_par_iter()
+        .progress_with(progress_bar(len))
+        .for_each(|k| {
+            let labels = get_kmeans_labels(distance_matrix, k);
+            let cluster = partition_nodes_in_cluster(&labels, nodes);
+            let c: Vec<&[&Node]> = cluster.iter().map(|d| d.as_slice()).collect();
+
+            let ClusterEvaluation {
+                purity,
+                nmi,
+                ri,
+                f5,
+            } = eval_clustering(&c);
+
+            writeln!(&mut file.lock().unwrap(), "{k},{purity},{nmi},{ri},{f5}").unwrap();
+        });
+
+    Ok(())
+}
+
 fn get_dbscan_labels(distance_matrix: &DenseMatrix<f64>, eps: f64, min_pts: usize) -> Vec<usize> {
     DBSCAN::fit(
         distance_matrix,
@@ -111,7 +187,6 @@ fn get_dbscan_labels(distance_matrix: &DenseMatrix<f64>, eps: f64, min_pts: usiz
     .unwrap()
 }
 
-#[allow(dead_code)]
 fn get_kmeans_labels(distance_matrix: &DenseMatrix<f64>, num_clusters: usize) -> Vec<usize> {
     KMeans::fit(
         distance_matrix,
@@ -239,30 +314,34 @@ fn get_nodes_from_files(files: Vec<PathBuf>, family: String) -> Result<Vec<Node>
         // .iter()
         // .take(100)
         .par_iter()
-        .progress()
-        .map(|entry| {
-            let mut file = std::fs::File::open(entry)?;
-
-            let mut buf = Vec::new();
-            file.read_to_end(&mut buf)?;
-
-            let ssdeep_hash = ssdeep::hash(&buf)?;
-
-            let lavin_config = HashConfig {
-                enable_parallel: false,
-                ..Default::default()
-            };
-            let lavinhash = lavinhash::generate_hash(&buf, &lavin_config)?;
-
-            let tmp = tlsh::hash_buf(&buf)?;
-            let tlsh_hash = tmp.to_string();
-
-            Ok(Node {
-                ssdeep_hash,
-                lavinhash,
-                tlsh_hash,
-                family: family.clone(),
-            })
+        .progress_with(progress_bar(files.len()))
+        .filter_map(|entry| match load_sample(entry) {
+            Ok(buf) => Some(node_from_sample(&buf, &family)),
+            Err(e) if is_skipped(&e) => {
+                warn!("{e}");
+                None
+            }
+            Err(e) => Some(Err(e)),
         })
         .collect()
 }
+
+fn node_from_sample(sample_data: &[u8], family: &str) -> Result<Node> {
+    let ssdeep_hash = ssdeep::hash(sample_data)?;
+
+    let lavin_config = HashConfig {
+        enable_parallel: false,
+        ..Default::default()
+    };
+    let lavinhash = lavinhash::generate_hash(sample_data, &lavin_config)?;
+
+    let tmp = tlsh::hash_buf(sample_data)?;
+    let tlsh_hash = tmp.to_string();
+
+    Ok(Node {
+        ssdeep_hash,
+        lavinhash,
+        tlsh_hash,
+        family: family.to_string(),
+    })
+}
@@ -5,32 +5,41 @@ use std::fmt::Debug;
 
 use arangors::{Document, graph::EdgeDefinition};
 use macon_cag::{
-    base_creator::GraphCreatorBase,
-    impl_edge_attributes,
+    backend::DbBackend,
+    base_creator::{EdgeAttributes, GraphCreatorBase},
+    limiter::AdaptiveLimiter,
     prelude::{Database, Result},
+    retry::RetryPolicy,
     utils::{
-        config::Config, ensure_database, ensure_graph, ensure_index, establish_database_connection,
-        get_name,
+        CollectionName, IndexSpec, config::Config, ensure_database, ensure_graph,
+        establish_database_connection, get_name, health_check, reconcile_indexes,
     },
 };
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use tracing::warn;
 
-use crate::cli::MainArgs;
+use crate::{
+    cli::{ConfigArgs, GeneralArgs},
+    graph_creators::{corpus_name, db_limiter},
+};
 
-#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, Default)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, Default, CollectionName)]
 pub struct GeneralCorpus {
     pub name: String,
     pub display_name: String,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, Default)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, Default, CollectionName)]
 pub struct MalwareSample {
     pub sha256sum: String,
     pub ssdeep: String,
+    pub tlsh: String,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, Default)]
+#[derive(
+    Deserialize, Serialize, Debug, Clone, JsonSchema, Default, EdgeAttributes, CollectionName,
+)]
 pub struct SampleDistance {
     pub _key: String,
     pub _from: String,
@@ -38,30 +47,58 @@ pub struct SampleDistance {
     pub ssdeep_distance: u32,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, Default)]
+#[derive(
+    Deserialize, Serialize, Debug, Clone, JsonSchema, Default, EdgeAttributes, CollectionName,
+)]
 pub struct DummyEdge {
     pub _key: String,
     pub _from: String,
     pub _to: String,
 }
 
-impl_edge_attributes!(SampleDistance);
-impl_edge_attributes!(DummyEdge);
+/// Config of the general corpus. The database and graph default to `general_corpus` and
+/// `general_corpus_graph`, all values can be overridden by the config file, the environment and
+/// the options, see [`ConfigArgs`].
+pub fn general_graph_config(config_args: &ConfigArgs) -> anyhow::Result<Config> {
+    let defaults = Config {
+        database: "general_corpus".to_string(),
+        graph: "general_corpus_graph".to_string(),
+        ..Default::default()
+    };
+
+    config_args.load(defaults)
+}
 
 struct GeneralGraph {
     db: Database,
+    graph: String,
+    limiter: AdaptiveLimiter,
+    retry: RetryPolicy,
 }
 
 impl GeneralGraph {
     pub fn try_new(config: &Config) -> Result<Self> {
+        health_check(config)?;
+
         let conn = establish_database_connection(config)?;
         let db = ensure_database(&conn, &config.database)?;
 
-        Ok(Self { db })
+        Ok(Self {
+            db,
+            graph: config.graph_name(),
+            limiter: db_limiter(config.max_concurrent_requests),
+            retry: config.retry,
+        })
     }
 }
 
-pub fn general_graph_main(main_args: MainArgs) -> Result<()> {
+pub fn general_graph_main(
+    general_args: GeneralArgs,
+    config_args: ConfigArgs,
+) -> anyhow::Result<()> {
+    // sets the collection prefix, so it has to be loaded before any collection name is resolved
+    let config = general_graph_config(&config_args)?;
+
     let edge_definitions = vec![
         EdgeDefinition {
             collection: get_name::<SampleDistance>(),
@@ -75,21 +112,13 @@ pub fn general_graph_main(main_args: MainArgs) -> Result<()> {
         },
     ];
 
-    let corpus_data = GeneralCorpus {
-        name: "GeneralCorpus".to_string(),
-        display_name: "GeneralCorpus".to_string(),
-    };
-
-    let config = Config {
-        database: "general_corpus".to_string(),
-        graph: "general_corpus_graph".to_string(),
-        ..Default::default()
-    };
+    let (name, display_name) = general_args.corpus.name_and_display_name("GeneralCorpus");
+    let corpus_data = GeneralCorpus { name, display_name };
 
     let gc = GeneralGraph::try_new(&config)?;
     let _ = gc.init::<GeneralCorpus>(config, corpus_data, edge_definitions)?;
 
-    gc.general_graph_entry(main_args.files)?;
+    gc.general_graph_entry(general_args)?;
 
     Ok(())
 }
@@ -97,30 +126,58 @@ pub fn general_graph_main(main_args: MainArgs) -> Result<()> {
 impl GraphCreatorBase for GeneralGraph {
     fn init<T>(
         &self,
-        config: Config,
+        _config: Config,
         corpus_node_data: T,
         edge_definitions: Vec<EdgeDefinition>,
     ) -> macon_cag::prelude::Result<Document<T>>
     where
-        T: DeserializeOwned + Serialize + Clone + JsonSchema + Debug,
+        T: DeserializeOwned + Serialize + Clone + JsonSchema + CollectionName + Debug,
     {
-        let _ = ensure_graph(&self.db, &config.graph, edge_definitions)?;
+        let _ = ensure_graph(&self.db, &self.graph, edge_definitions, false)?;
 
-        let db = self.get_db();
+        let db = &self.db;
 
         // Create index for name and sha256sum field
-        ensure_index::<GeneralCorpus>(db, vec!["name".to_string()])?;
-        ensure_index::<MalwareSample>(db, vec!["sha256sum".to_string()])?;
+        let drifts = [
+            reconcile_indexes::<GeneralCorpus>(
+                db,
+                vec![IndexSpec::unique(vec!["name".to_string()])],
+            )?,
+            reconcile_indexes::<MalwareSample>(
+                db,
+                vec![IndexSpec::unique(vec!["sha256sum".to_string()])],
+            )?,
+        ];
+        for warning in drifts.iter().flat_map(|drift| drift.warnings()) {
+            warn!("{warning}");
+        }
 
         // create corpus node
+        let name = corpus_name(&corpus_node_data)?;
         let corpus_node: Document<T> = self
-            .upsert_node::<T>(corpus_node_data, "name", &get_name::<T>())?
+            .upsert_node::<T>(corpus_node_data, "name", name)?
             .document;
 
         Ok(corpus_node)
     }
 
-    fn get_db(&self) -> &Database {
+    fn get_db(&self) -> Result<&Database> {
+        Ok(&self.db)
+    }
+
+    fn get_backend(&self) -> &dyn DbBackend {
         &self.db
     }
+
+    fn get_graph_name(&self) -> Option<&str> {
+        Some(&self.graph)
+    }
+
+    fn get_limiter(&self) -> Option<&AdaptiveLimiter> {
+        Some(&self.limiter)
+    }
+
+    fn get_retry_policy(&self) -> RetryPolicy {
+        self.retry
+    }
 }
@@ -1,2 +1,30 @@
 pub mod focused_graph;
 pub mod general_graph;
+
+use std::time::Duration;
+
+use macon_cag::{limiter::AdaptiveLimiter, prelude::Result};
+use serde::Serialize;
+
+/// Database requests slower than this throttle the workers
+const DB_TARGET_LATENCY: Duration = Duration::from_millis(500);
+
+/// Limiter for the database requests of the parsing workers. Without
+/// `max_concurrent_requests` every worker (see `--threads`) may have a request in flight.
+fn db_limiter(max_concurrent_requests: Option<usize>) -> AdaptiveLimiter {
+    let max_limit = max_concurrent_requests.unwrap_or_else(rayon::current_num_threads);
+    AdaptiveLimiter::new(max_limit, DB_TARGET_LATENCY)
+}
+
+/// Name of the corpus node `corpus_node_data`, which the corpus node is looked up by. The
+/// corpus types are only known as generic types by [`GraphCreatorBase::init`].
+///
+/// [`GraphCreatorBase::init`]: macon_cag::base_creator::GraphCreatorBase::init
+fn corpus_name<T: Serialize>(corpus_node_data: &T) -> Result<serde_json::Value> {
+    Ok(serde_json::to_value(corpus_node_data)?["name"].take())
+}
+
+/// Held by the tests that set the collection prefix, which is global to the process, or resolve
+/// collection names
+#[cfg(test)]
+pub(crate) static COLLECTION_PREFIX_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
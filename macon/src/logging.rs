@@ -0,0 +1,55 @@
+use std::{io::Write, sync::LazyLock};
+
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget};
+use tracing_subscriber::{EnvFilter, filter::LevelFilter};
+
+/// Progress bars of the run. Log lines are written while the bars are suspended, so they don't
+/// corrupt them.
+static PROGRESS: LazyLock<MultiProgress> = LazyLock::new(MultiProgress::new);
+
+/// Progress bar for `len` steps that is drawn below the log lines
+pub fn progress_bar(len: usize) -> ProgressBar {
+    PROGRESS.add(ProgressBar::new(len as u64))
+}
+
+/// Stderr with the progress bars suspended while writing
+struct LogWriter;
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        PROGRESS.suspend(|| std::io::stderr().write(buf))
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        std::io::stderr().flush()
+    }
+}
+
+/// Logs to stderr with the level of `quiet` and `verbose`, which `RUST_LOG` overrides, e.g.
+/// `RUST_LOG=macon_cag=debug` shows the database operations. `quiet` also hides the progress
+/// bars.
+pub fn init(quiet: bool, verbose: u8, json: bool) {
+    let level = match (quiet, verbose) {
+        (true, _) => LevelFilter::ERROR,
+        (false, 0) => LevelFilter::INFO,
+        (false, 1) => LevelFilter::DEBUG,
+        (false, _) => LevelFilter::TRACE,
+    };
+
+    if quiet {
+        PROGRESS.set_draw_target(ProgressDrawTarget::hidden());
+    }
+
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::builder()
+                .with_default_directive(level.into())
+                .from_env_lossy(),
+        )
+        .with_writer(|| LogWriter);
+
+    match json {
+        true => subscriber.json().init(),
+        false => subscriber.init(),
+    }
+}
@@ -1,24 +1,109 @@
+mod admin;
+mod archive;
+mod classify;
 mod cli;
+mod corpus_stats;
+mod detection;
+mod export;
+mod filetype;
 mod graph_creators;
+mod logging;
+mod output;
+mod prefilter;
+mod report;
+mod similar;
 mod utils;
+#[cfg(feature = "yara")]
+mod yara;
 
-use anyhow::Result;
+use std::process::ExitCode;
+
+use anyhow::{Result, anyhow};
 use clap::Parser;
+use macon_cag::utils::config::Config;
 
 use crate::{
+    admin::admin_main,
+    classify::classify_main,
     cli::Cli,
-    graph_creators::{focused_graph::focused_graph_main, general_graph::general_graph_main},
+    corpus_stats::stats_main,
+    export::export_main,
+    graph_creators::{
+        focused_graph::{focused_graph_main, prune::prune_main},
+        general_graph::general_graph_main,
+    },
+    similar::similar_main,
 };
 
-fn main() -> Result<()> {
-    let cli = Cli::parse();
+/// Exit code if samples failed
+const EXIT_SAMPLES_FAILED: u8 = 1;
+
+/// Exit code if the run failed, e.g. because of the config or the database connection
+const EXIT_FAILED: u8 = 2;
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(0) => ExitCode::SUCCESS,
+        Ok(_) => ExitCode::from(EXIT_SAMPLES_FAILED),
+        Err(e) => {
+            eprintln!("Error: {e:?}");
+            ExitCode::from(EXIT_FAILED)
+        }
+    }
+}
+
+/// Runs the command and returns the number of samples that failed
+fn run() -> Result<usize> {
+    let mut cli = Cli::parse();
+
+    // logs go to stderr, as some subcommands print their results to stdout
+    logging::init(cli.quiet, cli.verbose, cli.log_json);
+
+    // the global pool is used by all parallel iterators, so it has to be set up before any
+    // sample is processed
+    if let Some(threads) = cli.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads.into())
+            .build_global()
+            .map_err(|e| anyhow!("Setting up {threads} threads failed: {e}"))?;
+    }
+
+    // a broken config file is reported before any sample is touched
+    cli.config.load(Config::default())?;
+
+    #[cfg(feature = "yara")]
+    if let Some(dir) = &cli.yara_rules {
+        yara::set_rules(yara::YaraRules::load(dir)?);
+    }
+
+    // the files that are already in the watched directory are analyzed first
+    if let cli::MainCommands::Focused(focused_args) = &mut cli.command
+        && let Some(dir) = &focused_args.watch
+    {
+        focused_args.family.main_args_mut().files.push(dir.clone());
+    }
+
+    if let Some(main_args) = cli.command.main_args_mut() {
+        utils::set_max_file_size(main_args.max_file_size);
+        main_args.expand_dirs()?;
+        main_args.expand_archives()?;
+        main_args.select_files();
+    }
 
     // dbg!(&cli);
 
     match cli.command {
-        cli::MainCommands::Focused(focused_families) => focused_graph_main(focused_families)?,
-        cli::MainCommands::General(main_args) => general_graph_main(main_args)?,
+        cli::MainCommands::Focused(focused_args) => {
+            return focused_graph_main(focused_args, cli.config);
+        }
+        cli::MainCommands::General(general_args) => general_graph_main(general_args, cli.config)?,
+        cli::MainCommands::Similar(similar_args) => similar_main(similar_args, cli.config)?,
+        cli::MainCommands::Classify(classify_args) => classify_main(classify_args)?,
+        cli::MainCommands::Export(export_args) => export_main(export_args, cli.config)?,
+        cli::MainCommands::Stats(stats_args) => stats_main(stats_args, cli.config)?,
+        cli::MainCommands::Prune(prune_args) => prune_main(prune_args, cli.config)?,
+        cli::MainCommands::Admin(admin_command) => admin_main(admin_command, cli.config)?,
     }
 
-    Ok(())
+    Ok(0)
 }
@@ -0,0 +1,209 @@
+//! Machine-readable results of the analyzed samples, see `--output`
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+
+/// Where the results of `--output` are written to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputTarget {
+    /// All results as JSON lines on stdout once the run is finished
+    Stdout,
+
+    /// Each result as JSON line to the file as soon as its sample is finished
+    File(PathBuf),
+}
+
+/// Parses `json` or `json=<path>`
+pub fn parse_output(s: &str) -> Result<OutputTarget, String> {
+    match s.split_once('=') {
+        None if s == "json" => Ok(OutputTarget::Stdout),
+        Some(("json", "")) => Err("The path of the output is empty".to_string()),
+        Some(("json", path)) => Ok(OutputTarget::File(PathBuf::from(path))),
+        _ => Err("The output has to be 'json' or 'json=<path>'".to_string()),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SampleStatus {
+    Analyzed,
+    Failed,
+
+    /// Not analyzed, e.g. above the maximum file size or not classified
+    Skipped,
+}
+
+/// Result of a sample, one JSON line of `--output`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct SampleRecord {
+    /// Path below the directory the sample was found in, see
+    /// [`SampleRef::relative`](crate::utils::SampleRef::relative)
+    pub path: String,
+
+    /// Not set if the sample can't be read
+    pub sha256: Option<String>,
+
+    /// Not set for samples that couldn't be classified
+    pub family: Option<String>,
+
+    /// Sample type detected by the family, not set if the detection failed
+    pub sample_type: Option<String>,
+
+    /// `_id` of the documents that were created or linked for the sample
+    pub documents: Vec<String>,
+
+    pub duration_ms: u64,
+    pub status: SampleStatus,
+
+    /// Why the sample failed or was skipped
+    pub error: Option<String>,
+    /// Problems of the analysis that didn't fail it, e.g. an ambiguous sample type
+    pub warnings: Vec<String>,
+}
+
+enum Sink {
+    Buffered(Vec<SampleRecord>),
+    Streamed(BufWriter<File>),
+}
+
+/// Collects the [`SampleRecord`]s of a run. They are printed to stdout by [`Self::finish`] or
+/// streamed to a file.
+pub struct SampleOutput {
+    sink: Mutex<Sink>,
+}
+
+impl SampleOutput {
+    pub fn new(target: &OutputTarget) -> Result<Self> {
+        let sink = match target {
+            OutputTarget::Stdout => Sink::Buffered(vec![]),
+            OutputTarget::File(path) => {
+                let file = File::create(path)
+                    .map_err(|e| anyhow!("Creating output '{}' failed: {e}", path.display()))?;
+                Sink::Streamed(BufWriter::new(file))
+            }
+        };
+
+        Ok(Self {
+            sink: Mutex::new(sink),
+        })
+    }
+
+    /// Whether the records are printed to stdout, so other output has to go elsewhere
+    pub fn is_stdout(&self) -> bool {
+        matches!(*self.sink.lock().unwrap(), Sink::Buffered(_))
+    }
+
+    /// Adds `record`. Streamed records are flushed right away, so the file can be followed while
+    /// the run goes on.
+    pub fn write(&self, record: SampleRecord) -> Result<()> {
+        match &mut *self.sink.lock().unwrap() {
+            Sink::Buffered(records) => records.push(record),
+            Sink::Streamed(writer) => {
+                serde_json::to_writer(&mut *writer, &record)?;
+                writeln!(writer)?;
+                writer.flush()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Prints the buffered records to stdout
+    pub fn finish(&self) -> Result<()> {
+        match &mut *self.sink.lock().unwrap() {
+            Sink::Buffered(records) => {
+                let mut stdout = std::io::stdout().lock();
+                for record in records.drain(..) {
+                    serde_json::to_writer(&mut stdout, &record)?;
+                    writeln!(stdout)?;
+                }
+            }
+            Sink::Streamed(writer) => writer.flush()?,
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(path: &str, status: SampleStatus) -> SampleRecord {
+        SampleRecord {
+            path: path.to_string(),
+            sha256: None,
+            family: None,
+            sample_type: None,
+            documents: vec![],
+            duration_ms: 0,
+            status,
+            error: None,
+            warnings: vec![],
+        }
+    }
+
+    #[test]
+    fn parse_targets() {
+        assert_eq!(parse_output("json"), Ok(OutputTarget::Stdout));
+        assert_eq!(
+            parse_output("json=out/results.jsonl"),
+            Ok(OutputTarget::File(PathBuf::from("out/results.jsonl")))
+        );
+        assert!(parse_output("json=").is_err());
+        assert!(parse_output("csv").is_err());
+        assert!(parse_output("csv=out.csv").is_err());
+    }
+
+    #[test]
+    fn records_are_streamed_as_json_lines() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("results.jsonl");
+        let output = SampleOutput::new(&OutputTarget::File(path.clone()))?;
+        assert!(!output.is_stdout());
+
+        output.write(record("a.bat", SampleStatus::Analyzed))?;
+        // written before the run is finished
+        assert_eq!(std::fs::read_to_string(&path)?.lines().count(), 1);
+
+        let mut skipped = record("b.txt", SampleStatus::Skipped);
+        skipped.error = Some("pre-filter: empty file".to_string());
+        output.write(skipped)?;
+        output.finish()?;
+
+        let lines: Vec<serde_json::Value> = std::fs::read_to_string(&path)?
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?;
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["path"], "a.bat");
+        assert_eq!(lines[0]["status"], "analyzed");
+        assert_eq!(lines[1]["status"], "skipped");
+        assert_eq!(lines[1]["error"], "pre-filter: empty file");
+        assert_eq!(lines[1]["sha256"], serde_json::Value::Null);
+
+        Ok(())
+    }
+
+    #[test]
+    fn stdout_records_are_buffered() -> Result<()> {
+        let output = SampleOutput::new(&OutputTarget::Stdout)?;
+        assert!(output.is_stdout());
+
+        output.write(record("a.bat", SampleStatus::Failed))?;
+        match &*output.sink.lock().unwrap() {
+            Sink::Buffered(records) => assert_eq!(records.len(), 1),
+            Sink::Streamed(_) => unreachable!("stdout is buffered"),
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,193 @@
+//! Skips files in the input that obviously aren't samples, like empty placeholders, triage notes
+//! and sandbox reports, before they are analyzed. They would fail with an error or, worse, end up
+//! in the graph as leaf nodes of the permissive sample type detectors.
+
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use tracing::info;
+
+use crate::{filetype::non_sample_format, utils::read_prefix};
+
+/// Number of bytes read from the start of a file to detect its format
+const PREFIX_SIZE: usize = 64 * 1024;
+
+/// Default of `--prefilter-deny-ext`
+pub const DEFAULT_DENIED_EXTENSIONS: [&str; 9] = [
+    "txt", "md", "json", "log", "csv", "png", "jpg", "jpeg", "gif",
+];
+
+/// Why the pre-filter skipped a file
+#[derive(Debug, Clone)]
+pub enum SkipReason {
+    Empty,
+    DeniedExtension(String),
+    Format(&'static str),
+}
+
+impl Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SkipReason::Empty => write!(f, "empty file"),
+            SkipReason::DeniedExtension(extension) => {
+                write!(f, "denied extension .{extension} (--prefilter-deny-ext)")
+            }
+            SkipReason::Format(format) => write!(f, "{format}"),
+        }
+    }
+}
+
+pub struct Prefilter {
+    /// Lowercase extensions without the dot
+    denied_extensions: HashSet<String>,
+}
+
+impl Prefilter {
+    pub fn new(denied_extensions: &[String]) -> Self {
+        Self {
+            denied_extensions: denied_extensions
+                .iter()
+                .map(|e| e.trim_start_matches('.').to_lowercase())
+                .collect(),
+        }
+    }
+
+    /// Why `path` should be skipped, `None` if it may be a sample. Only the start of the file is
+    /// read. Files that can't be read are kept, so their error is reported with the sample.
+    pub fn check(&self, path: &Path) -> Option<SkipReason> {
+        if let Some(extension) = path.extension() {
+            let extension = extension.to_string_lossy().to_lowercase();
+            if self.denied_extensions.contains(&extension) {
+                return Some(SkipReason::DeniedExtension(extension));
+            }
+        }
+
+        let (data, truncated) = read_prefix(path, PREFIX_SIZE).ok()?;
+        if data.is_empty() {
+            return Some(SkipReason::Empty);
+        }
+
+        non_sample_format(&data, truncated).map(SkipReason::Format)
+    }
+
+    /// Removes the files from `files` that should be skipped and returns them with the reason.
+    /// Every skipped file is logged.
+    pub fn apply(&self, files: &mut Vec<PathBuf>) -> Vec<(PathBuf, SkipReason)> {
+        let checked: Vec<(PathBuf, Option<SkipReason>)> = std::mem::take(files)
+            .into_par_iter()
+            .map(|file| {
+                let reason = self.check(&file);
+                (file, reason)
+            })
+            .collect();
+
+        let mut skipped = vec![];
+        for (file, reason) in checked {
+            match reason {
+                Some(reason) => {
+                    info!("Skipping {} (pre-filter): {reason}", file.display());
+                    skipped.push((file, reason));
+                }
+                None => files.push(file),
+            }
+        }
+
+        skipped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(name: &str, data: &[u8]) -> Option<String> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(name);
+        std::fs::write(&path, data).unwrap();
+
+        let denied: Vec<String> = DEFAULT_DENIED_EXTENSIONS.map(str::to_string).to_vec();
+        Prefilter::new(&denied).check(&path).map(|r| r.to_string())
+    }
+
+    #[test]
+    fn skips_obvious_non_samples() {
+        assert_eq!(check("empty.bat", b"").as_deref(), Some("empty file"));
+        assert_eq!(
+            check("notes.TXT", b"triage notes").as_deref(),
+            Some("denied extension .txt (--prefilter-deny-ext)")
+        );
+        assert_eq!(
+            check("image", b"\x89PNG\r\n\x1a\n....").as_deref(),
+            Some("PNG image")
+        );
+        assert_eq!(
+            check("report", b"%PDF-1.7\n").as_deref(),
+            Some("PDF document")
+        );
+        assert_eq!(
+            check("report", b"\xef\xbb\xbf{\"score\": 10}").as_deref(),
+            Some("JSON")
+        );
+    }
+
+    #[test]
+    fn skips_json_lines() {
+        let report =
+            b"{\"event\": \"start\"}\n{\"event\": \"dns\", \"query\": \"a.top\"}\n[1, 2]\n";
+        assert_eq!(check("report.jsonl", report).as_deref(), Some("JSON"));
+    }
+
+    #[test]
+    fn skips_json_larger_than_the_prefix() {
+        let mut report = b"[".to_vec();
+        while report.len() <= PREFIX_SIZE {
+            report.extend_from_slice(b"{\"event\": \"process\", \"pid\": 1234},\n");
+        }
+        report.extend_from_slice(b"{}]");
+
+        assert_eq!(check("report", &report).as_deref(), Some("JSON"));
+    }
+
+    #[test]
+    fn keeps_samples() {
+        assert_eq!(
+            check("sample.bat", b"@echo off\r\npowershell -e AAAA"),
+            None
+        );
+        assert_eq!(
+            check("sample.ps1", b"[Reflection.Assembly]::Load($bytes)"),
+            None
+        );
+        // the first line is JSON, the rest isn't
+        assert_eq!(check("sample", b"{\"a\": 1}\nIEX $payload\n"), None);
+        assert_eq!(check("sample.apk", b"PK\x03\x04"), None);
+    }
+
+    #[test]
+    fn keeps_unreadable_files() {
+        let prefilter = Prefilter::new(&[]);
+        assert!(prefilter.check(Path::new("/nonexistent/sample")).is_none());
+    }
+
+    #[test]
+    fn apply_removes_skipped_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let sample = dir.path().join("sample.bat");
+        let notes = dir.path().join("notes.md");
+        let empty = dir.path().join("empty");
+        std::fs::write(&sample, b"@echo off").unwrap();
+        std::fs::write(&notes, b"notes").unwrap();
+        std::fs::write(&empty, b"").unwrap();
+
+        let mut files = vec![sample.clone(), notes.clone(), empty.clone()];
+        let skipped = Prefilter::new(&["MD".to_string()]).apply(&mut files);
+
+        assert_eq!(files, [sample]);
+        let skipped: Vec<_> = skipped.into_iter().map(|(file, _)| file).collect();
+        assert_eq!(skipped, [notes, empty]);
+    }
+}
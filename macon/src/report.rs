@@ -0,0 +1,471 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{Sender, channel},
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use anyhow::{Result, anyhow};
+use macon_cag::stats::{CollectionCounts, RunStats};
+use serde::Serialize;
+use tracing::{debug, trace, warn};
+
+use crate::{prefilter::SkipReason, utils::SampleRef};
+
+/// Stage of the analysis of a sample
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stage {
+    /// Reading the sample from disk
+    Read,
+
+    /// Detecting the sample type
+    Detect,
+
+    /// Extracting the next stages and attributes
+    Extract,
+
+    /// Writing to the database
+    Db,
+}
+
+/// Error that happened in `stage`. The error is displayed as is, the stage is only used for the
+/// [`ErrorReport`]
+#[derive(Debug)]
+pub struct StageError {
+    pub stage: Stage,
+    pub error: anyhow::Error,
+}
+
+impl StageError {
+    pub fn wrap(stage: Stage, error: impl Into<anyhow::Error>) -> anyhow::Error {
+        anyhow::Error::new(Self {
+            stage,
+            error: error.into(),
+        })
+    }
+}
+
+impl Display for StageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+impl std::error::Error for StageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.error.source()
+    }
+}
+
+/// Stage of `error`: the stage of its [`StageError`], otherwise `db` for errors of the database
+/// and `extract` for all others
+pub fn stage_of(error: &anyhow::Error) -> Stage {
+    if let Some(e) = error.downcast_ref::<StageError>() {
+        return e.stage;
+    }
+
+    match error.downcast_ref::<macon_cag::error::Error>() {
+        // errors of the analysis that are returned from a transaction
+        Some(macon_cag::error::Error::Other(inner)) => stage_of(inner),
+        Some(_) => Stage::Db,
+        None => Stage::Extract,
+    }
+}
+
+/// Line of the error report for a failed sample, the file is relative to the directory it was
+/// found in
+#[derive(Serialize)]
+struct ReportLine<'a> {
+    file: &'a str,
+    stage: Stage,
+    error: String,
+}
+
+/// Line of the error report for a sample with an ambiguous sample type
+#[derive(Serialize)]
+struct AmbiguousLine<'a> {
+    file: &'a str,
+    ambiguous: &'a str,
+}
+
+/// Line of the error report for a sample that couldn't be classified, see `auto`
+#[derive(Serialize)]
+struct UnclassifiedLine<'a> {
+    file: &'a str,
+    unclassified: &'a str,
+}
+
+/// Line of the error report for a file the pre-filter skipped
+#[derive(Serialize)]
+struct SkippedLine<'a> {
+    file: &'a str,
+    skipped: String,
+}
+
+/// Last line of the error report with the stats of the run
+#[derive(Serialize)]
+struct SummaryLine {
+    summary: Summary,
+}
+
+#[derive(Serialize)]
+struct Summary {
+    samples: usize,
+    skipped: usize,
+    prefiltered: usize,
+    failed: usize,
+    ambiguous: usize,
+    unclassified: usize,
+    elapsed_secs: f64,
+    samples_per_sec: f64,
+    collections: BTreeMap<String, CollectionCounts>,
+}
+
+/// Samples per second that were analyzed out of `samples`, without the ones that were `skipped`
+/// as known and the `prefiltered` ones
+pub fn analyzed_per_sec(
+    stats: &RunStats,
+    samples: usize,
+    skipped: usize,
+    prefiltered: usize,
+) -> f64 {
+    stats.throughput(samples.saturating_sub(skipped + prefiltered))
+}
+
+/// Progress of the analysis of a sample, see [`ErrorReport::send`]
+#[derive(Debug)]
+pub enum SampleEvent {
+    Started {
+        file: SampleRef,
+    },
+    Finished {
+        file: SampleRef,
+        elapsed: Duration,
+    },
+    Failed {
+        file: SampleRef,
+        error: anyhow::Error,
+    },
+
+    /// More than one sample type is plausible, the sample was analyzed as the most likely one
+    Ambiguous {
+        file: SampleRef,
+        report: String,
+    },
+
+    /// The family of the sample couldn't be determined, so it wasn't analyzed
+    Unclassified {
+        file: SampleRef,
+        reason: String,
+    },
+}
+
+/// Events of the reporter thread that end up in the error report, in the order they happened
+#[derive(Default)]
+struct Reported {
+    /// Failed samples with their errors
+    failures: Vec<(SampleRef, anyhow::Error)>,
+
+    /// Samples with an ambiguous sample type with the plausible candidates
+    ambiguous: Vec<(SampleRef, String)>,
+
+    /// Samples that couldn't be classified with the reason
+    unclassified: Vec<(SampleRef, String)>,
+}
+
+/// Errors of the samples of a run. The [`SampleEvent`]s are sent to a reporter thread that logs
+/// each failure as it happens, so systemic problems show up long before the run is finished, and
+/// collects the failures and ambiguous samples for [`ErrorReport::finish`].
+pub struct ErrorReport {
+    events: Mutex<Option<Sender<SampleEvent>>>,
+    reporter: Mutex<Option<JoinHandle<Reported>>>,
+
+    /// Abort the run after the first error
+    fail_fast: bool,
+    aborted: AtomicBool,
+}
+
+impl ErrorReport {
+    pub fn new(fail_fast: bool) -> Self {
+        let (sender, receiver) = channel();
+
+        let reporter = std::thread::spawn(move || {
+            let mut reported = Reported::default();
+
+            for event in receiver {
+                match event {
+                    SampleEvent::Started { file } => trace!(sample = %file, "started sample"),
+                    SampleEvent::Finished { file, elapsed } => debug!(
+                        sample = %file,
+                        elapsed_ms = elapsed.as_millis() as u64,
+                        "processed sample"
+                    ),
+                    SampleEvent::Failed { file, error } => {
+                        warn!(sample = %file, stage = ?stage_of(&error), "{error}");
+                        reported.failures.push((file, error));
+                    }
+                    SampleEvent::Ambiguous { file, report } => {
+                        warn!(sample = %file, "{report}");
+                        reported.ambiguous.push((file, report));
+                    }
+                    SampleEvent::Unclassified { file, reason } => {
+                        warn!(sample = %file, "{reason}");
+                        reported.unclassified.push((file, reason));
+                    }
+                }
+            }
+
+            reported
+        });
+
+        Self {
+            events: Mutex::new(Some(sender)),
+            reporter: Mutex::new(Some(reporter)),
+            fail_fast,
+            aborted: AtomicBool::new(false),
+        }
+    }
+
+    /// Hands `event` to the reporter thread. Events after [`ErrorReport::finish`] are dropped.
+    pub fn send(&self, event: SampleEvent) {
+        if matches!(event, SampleEvent::Failed { .. }) && self.fail_fast {
+            self.aborted.store(true, Ordering::Relaxed);
+        }
+
+        if let Some(events) = self.events.lock().unwrap().as_ref() {
+            // the reporter only stops once the sender is dropped
+            let _ = events.send(event);
+        }
+    }
+
+    pub fn push(&self, file: &SampleRef, error: anyhow::Error) {
+        self.send(SampleEvent::Failed {
+            file: file.clone(),
+            error,
+        });
+    }
+
+    /// Set after the first error with `fail_fast`. The remaining samples are skipped then, the
+    /// ones that are already being analyzed are finished.
+    pub fn is_aborted(&self) -> bool {
+        self.aborted.load(Ordering::Relaxed)
+    }
+
+    /// Waits for the reporter thread and writes the errors as JSON lines to `path`, followed by
+    /// the number of failed samples out of `samples`. The errors were already logged by the
+    /// reporter thread. The errors are followed by a `{"file": ..., "ambiguous": ...}` line for
+    /// every sample with an ambiguous sample type, a `{"file": ..., "unclassified": ...}` line for
+    /// every sample that couldn't be classified, a `{"file": ..., "skipped": ...}` line for
+    /// every file that was `prefiltered` and a `{"summary": ...}` line with the `stats` of the run
+    /// and the number of `skipped` samples. The files are named relative to the directory in
+    /// `roots` they were found in. Returns the number of failed samples.
+    pub fn finish(
+        &self,
+        path: Option<&Path>,
+        samples: usize,
+        skipped: usize,
+        prefiltered: &[(PathBuf, SkipReason)],
+        roots: &[PathBuf],
+        stats: &RunStats,
+    ) -> Result<usize> {
+        // the reporter finishes once the channel is closed
+        self.events.lock().unwrap().take();
+        let Reported {
+            failures: errors,
+            ambiguous,
+            unclassified,
+        } = match self.reporter.lock().unwrap().take() {
+            Some(reporter) => reporter
+                .join()
+                .map_err(|_| anyhow!("The reporter of the errors panicked"))?,
+            None => Reported::default(),
+        };
+
+        match path {
+            Some(path) => {
+                let file = File::create(path).map_err(|e| {
+                    anyhow!("Creating error report '{}' failed: {e}", path.display())
+                })?;
+                let mut writer = BufWriter::new(file);
+
+                for (file, error) in errors.iter() {
+                    let line = ReportLine {
+                        file: file.relative(),
+                        stage: stage_of(error),
+                        error: format!("{error:#}"),
+                    };
+                    serde_json::to_writer(&mut writer, &line)?;
+                    writeln!(writer)?;
+                }
+
+                for (file, report) in ambiguous.iter() {
+                    let line = AmbiguousLine {
+                        file: file.relative(),
+                        ambiguous: report,
+                    };
+                    serde_json::to_writer(&mut writer, &line)?;
+                    writeln!(writer)?;
+                }
+
+                for (file, reason) in unclassified.iter() {
+                    let line = UnclassifiedLine {
+                        file: file.relative(),
+                        unclassified: reason,
+                    };
+                    serde_json::to_writer(&mut writer, &line)?;
+                    writeln!(writer)?;
+                }
+
+                for (file, reason) in prefiltered {
+                    let file = SampleRef::new(file, roots);
+                    let line = SkippedLine {
+                        file: file.relative(),
+                        skipped: reason.to_string(),
+                    };
+                    serde_json::to_writer(&mut writer, &line)?;
+                    writeln!(writer)?;
+                }
+
+                let summary = SummaryLine {
+                    summary: Summary {
+                        samples,
+                        skipped,
+                        prefiltered: prefiltered.len(),
+                        failed: errors.len(),
+                        ambiguous: ambiguous.len(),
+                        unclassified: unclassified.len(),
+                        elapsed_secs: stats.elapsed().as_secs_f64(),
+                        samples_per_sec: analyzed_per_sec(
+                            stats,
+                            samples,
+                            skipped,
+                            prefiltered.len(),
+                        ),
+                        collections: stats.snapshot(),
+                    },
+                };
+                serde_json::to_writer(&mut writer, &summary)?;
+                writeln!(writer)?;
+                writer.flush()?;
+
+                eprintln!(
+                    "{} of {samples} samples failed, see {}",
+                    errors.len(),
+                    path.display()
+                );
+            }
+            None => {
+                if !errors.is_empty() {
+                    eprintln!("{} of {samples} samples failed", errors.len());
+                }
+            }
+        }
+
+        if !ambiguous.is_empty() {
+            eprintln!(
+                "{} of {samples} samples had an ambiguous sample type",
+                ambiguous.len()
+            );
+        }
+
+        if !unclassified.is_empty() {
+            eprintln!(
+                "{} of {samples} samples couldn't be classified",
+                unclassified.len()
+            );
+        }
+
+        if !prefiltered.is_empty() {
+            eprintln!(
+                "{} of {samples} files were skipped by the pre-filter (--no-prefilter)",
+                prefiltered.len()
+            );
+        }
+
+        if self.is_aborted() {
+            eprintln!("Aborted after the first error (--fail-fast)");
+        }
+
+        Ok(errors.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+
+    #[test]
+    fn report_lists_failures_ambiguous_unclassified_and_prefiltered_files() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("report.jsonl");
+        let roots = [PathBuf::from("/samples")];
+        let sample = |name: &str| SampleRef::new(&Path::new("/samples").join(name), &roots);
+
+        let report = ErrorReport::new(false);
+        report.send(SampleEvent::Ambiguous {
+            file: sample("a.bat"),
+            report: "Ambiguous sample type of a.bat: base64 (0.90), command (0.60)".to_string(),
+        });
+        report.push(
+            &sample("b.bat"),
+            StageError::wrap(Stage::Detect, anyhow!("not detected")),
+        );
+        report.send(SampleEvent::Unclassified {
+            file: sample("c.bin"),
+            reason: "no family matched".to_string(),
+        });
+        let prefiltered = [(PathBuf::from("/samples/notes.txt"), SkipReason::Empty)];
+
+        let failed = report.finish(Some(&path), 4, 1, &prefiltered, &roots, &RunStats::new())?;
+        assert_eq!(failed, 1);
+
+        let lines: Vec<Value> = std::fs::read_to_string(&path)?
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?;
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0]["file"], "b.bat");
+        assert_eq!(lines[0]["stage"], "detect");
+        assert_eq!(lines[1]["file"], "a.bat");
+        assert!(lines[1]["ambiguous"].as_str().unwrap().contains("base64"));
+        assert_eq!(lines[2]["file"], "c.bin");
+        assert_eq!(lines[2]["unclassified"], "no family matched");
+        assert_eq!(lines[3]["file"], "notes.txt");
+        assert_eq!(lines[3]["skipped"], "empty file");
+
+        let summary = &lines[4]["summary"];
+        assert_eq!(summary["failed"], 1);
+        assert_eq!(summary["ambiguous"], 1);
+        assert_eq!(summary["unclassified"], 1);
+        assert_eq!(summary["skipped"], 1);
+        assert_eq!(summary["prefiltered"], 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn fail_fast_aborts_after_the_first_failure() {
+        let report = ErrorReport::new(true);
+        let file = SampleRef::new(Path::new("sample"), &[]);
+
+        report.send(SampleEvent::Ambiguous {
+            file: file.clone(),
+            report: String::new(),
+        });
+        assert!(!report.is_aborted());
+
+        report.push(&file, anyhow!("failed"));
+        assert!(report.is_aborted());
+    }
+}
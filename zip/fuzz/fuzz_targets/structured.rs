@@ -0,0 +1,17 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use macon_zip::types::{SerializeOptions, ZipArchive};
+
+// Mutates the parsed structures instead of raw bytes so the fuzzer gets past the signature checks
+fuzz_target!(|archive: ZipArchive| {
+    let options = SerializeOptions {
+        repair_descriptor_flag: true,
+    };
+    let Ok(data) = archive.to_bytes(options) else {
+        return;
+    };
+
+    let _ = ZipArchive::try_from(&data[..]);
+    let _ = macon_zip::try_remove_encryption_bits(&data);
+});
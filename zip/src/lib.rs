@@ -1,19 +1,70 @@
-mod types;
+#[allow(clippy::len_without_is_empty)]
+pub mod types;
+
+use std::borrow::Cow;
 
 use anyhow::Result;
 
-use crate::types::ZipArchive;
+use crate::types::{SerializeOptions, ZipArchive};
+
+/// Bit of `general_purpose` that marks an entry as encrypted
+pub const ENCRYPTION_BIT: u16 = 1;
+
+/// Bit of `general_purpose` that marks an entry as followed by a data descriptor
+pub const DATA_DESCRIPTOR_BIT: u16 = 1 << 3;
+
+/// Bit of `general_purpose` that marks the file name and comment of an entry as UTF-8
+pub const UTF8_BIT: u16 = 1 << 11;
+
+/// Returns true if at least one entry of the archive has the encryption bit set
+pub fn needs_encryption_bit_fix(data: &[u8]) -> Result<bool> {
+    let ziparchive = ZipArchive::try_from(data)?;
+
+    let lfh_flags = ziparchive
+        .zip_files
+        .iter()
+        .map(|zipfile| zipfile.local_file_header.general_purpose);
+    let cdh_flags = ziparchive
+        .central_directory_headers
+        .iter()
+        .map(|cdh| cdh.general_purpose);
+
+    Ok(lfh_flags
+        .chain(cdh_flags)
+        .any(|gp| gp & ENCRYPTION_BIT != 0))
+}
 
-pub fn try_remove_encryption_bits(data: &[u8]) -> Result<Vec<u8>> {
+/// Removes the encryption bit from every entry of the archive.
+///
+/// Returns the archive and the number of entries that were modified. If no entry had the
+/// encryption bit set, the original data is returned without copying it.
+pub fn try_remove_encryption_bits(data: &[u8]) -> Result<(Cow<'_, [u8]>, usize)> {
     let mut ziparchive = ZipArchive::try_from(data)?;
 
-    for zipfile in ziparchive.zip_files.iter_mut() {
-        zipfile.local_file_header.general_purpose &= !1;
-    }
+    let modified_entries = ziparchive.modify_flags(ENCRYPTION_BIT, 0);
 
-    for cdh in ziparchive.central_directory_headers.iter_mut() {
-        cdh.general_purpose &= !1;
+    if modified_entries == 0 {
+        return Ok((Cow::Borrowed(data), 0));
     }
 
-    Ok(ziparchive.to_bytes())
+    let options = SerializeOptions {
+        repair_descriptor_flag: true,
+    };
+
+    Ok((Cow::Owned(ziparchive.to_bytes(options)?), modified_entries))
+}
+
+/// Applies [`ZipArchive::modify_flags`] to the archive and returns the modified archive.
+///
+/// The data descriptor bit is repaired afterwards, so it stays set for entries that are followed
+/// by a data descriptor.
+pub fn modify_flags_bytes(data: &[u8], clear_mask: u16, set_mask: u16) -> Result<Vec<u8>> {
+    let mut ziparchive = ZipArchive::try_from(data)?;
+    ziparchive.modify_flags(clear_mask, set_mask);
+
+    let options = SerializeOptions {
+        repair_descriptor_flag: true,
+    };
+
+    ziparchive.to_bytes(options)
 }
@@ -1,31 +1,111 @@
+use std::{
+    borrow::Cow,
+    fmt::Display,
+    io::{self, Write},
+};
+
 use anyhow::{Error, Result, anyhow};
 
+use crate::DATA_DESCRIPTOR_BIT;
+
+/// Options for serializing a [`ZipArchive`] or [`ZipFile`]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SerializeOptions {
+    /// Fix the data descriptor bit of entries where it doesn't match the presence of a data
+    /// descriptor instead of returning an error
+    pub repair_descriptor_flag: bool,
+}
+
 #[derive(Debug, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct ZipArchive<'a> {
+    /// Entries in the order of the central directory: `zip_files[i]` is the entry that
+    /// `central_directory_headers[i]` points to. Archives that are built by hand can have fewer
+    /// entries than headers.
     pub zip_files: Vec<ZipFile<'a>>,
     pub central_directory_headers: Vec<CDH<'a>>,
     pub eocd: EOCD<'a>,
+
+    /// Problems that were tolerated while parsing the archive
+    pub warnings: Vec<String>,
 }
 
 impl ZipArchive<'_> {
-    #[allow(clippy::wrong_self_convention)]
-    pub fn to_bytes(self) -> Vec<u8> {
-        let zipfiles = self
-            .zip_files
-            .into_iter()
-            .flat_map(|zf| zf.to_bytes())
-            .collect();
+    /// Comment of the archive
+    pub fn comment(&self) -> &[u8] {
+        &self.eocd.comment
+    }
+
+    /// Replaces the comment of the archive. [`Self::to_bytes`] fails if the comment is longer
+    /// than `u16::MAX` bytes.
+    pub fn set_comment(&mut self, data: Vec<u8>) {
+        self.eocd.comment_length = data.len().min(u16::MAX as usize) as u16;
+        self.eocd.comment = Cow::Owned(data);
+    }
 
-        let cdhs = self
+    /// Clears the bits of `clear_mask` and then sets the bits of `set_mask` in the
+    /// `general_purpose` flags of every local file header and central directory header.
+    ///
+    /// Returns the number of entries where at least one of the flags changed. The local file
+    /// header and the central directory header of an entry share the index, see
+    /// [`Self::zip_files`], so an entry is counted once even if both headers changed.
+    pub fn modify_flags(&mut self, clear_mask: u16, set_mask: u16) -> usize {
+        let modify = |gp: &mut u16| {
+            let old = *gp;
+            *gp = (old & !clear_mask) | set_mask;
+            old != *gp
+        };
+
+        let entries = self
             .central_directory_headers
-            .into_iter()
-            .flat_map(|zf| zf.to_bytes())
-            .collect();
+            .len()
+            .max(self.zip_files.len());
+        let mut modified = vec![false; entries];
 
-        vec![zipfiles, cdhs, self.eocd.to_bytes()]
-            .into_iter()
-            .flatten()
-            .collect()
+        for (i, cdh) in self.central_directory_headers.iter_mut().enumerate() {
+            modified[i] |= modify(&mut cdh.general_purpose);
+        }
+        for (i, zipfile) in self.zip_files.iter_mut().enumerate() {
+            modified[i] |= modify(&mut zipfile.local_file_header.general_purpose);
+        }
+
+        modified.into_iter().filter(|m| *m).count()
+    }
+
+    pub fn len(&self) -> usize {
+        let zipfiles: usize = self.zip_files.iter().map(|zf| zf.len()).sum();
+        let cdhs: usize = self
+            .central_directory_headers
+            .iter()
+            .map(|cdh| cdh.len())
+            .sum();
+
+        zipfiles + cdhs + self.eocd.len()
+    }
+
+    pub fn to_bytes(&self, options: SerializeOptions) -> Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(self.len());
+        self.write_to(&mut buf, options)?;
+        Ok(buf)
+    }
+
+    pub fn write_to<W: Write>(&self, w: &mut W, options: SerializeOptions) -> io::Result<()> {
+        for zipfile in &self.zip_files {
+            zipfile.write_to(w, options)?;
+        }
+
+        for (i, cdh) in self.central_directory_headers.iter().enumerate() {
+            let mut general_purpose = cdh.general_purpose;
+            // keep the flag of the central directory in sync with the repaired local file header
+            if options.repair_descriptor_flag
+                && let Some(zipfile) = self.zip_files.get(i)
+            {
+                general_purpose = zipfile.descriptor_flag_repaired(general_purpose);
+            }
+            cdh.write_with_flags(w, general_purpose)?;
+        }
+
+        self.eocd.write_to(w)
     }
 }
 
@@ -34,21 +114,57 @@ impl<'a> TryFrom<&'a [u8]> for ZipArchive<'a> {
     fn try_from(value: &'a [u8]) -> Result<Self, Self::Error> {
         let mut ziparchive = ZipArchive::default();
 
-        let eocd = EOCD::try_from(value)?;
+        let (eocd, eocd_pos, warning) = EOCD::find(value)?;
         ziparchive.eocd = eocd;
+        ziparchive.warnings.extend(warning);
 
+        // parse up to the EOCD instead of trusting central_dir_size
         let start = ziparchive.eocd.central_dir_offset as usize;
-        let stop = start + ziparchive.eocd.central_dir_size as usize;
+        let central_dir = value
+            .get(start..eocd_pos)
+            .ok_or(anyhow!("central directory out of bounds"))?;
+
+        ziparchive.central_directory_headers = CDH::get_vec_from_bytes(central_dir)?;
 
-        ziparchive.central_directory_headers = CDH::get_vec_from_bytes(&value[start..stop])?;
+        let central_dir_size: usize = ziparchive
+            .central_directory_headers
+            .iter()
+            .map(|cdh| cdh.len())
+            .sum();
+        if central_dir_size != ziparchive.eocd.central_dir_size as usize {
+            ziparchive.warnings.push(format!(
+                "EOCD states a central directory size of {} bytes, but it has {central_dir_size} bytes",
+                ziparchive.eocd.central_dir_size
+            ));
+        }
+
+        let entries = ziparchive.central_directory_headers.len();
+        if entries != ziparchive.eocd.cental_dir_entries_total as usize {
+            ziparchive.warnings.push(format!(
+                "EOCD states {} entries, but the central directory has {entries} entries",
+                ziparchive.eocd.cental_dir_entries_total
+            ));
+        }
 
         let mut zip_files = vec![];
 
         for cdh in &ziparchive.central_directory_headers {
-            let zipfile = ZipFile::try_from_with_compressed_size(
-                &value[cdh.local_header_offset as usize..],
-                cdh,
-            )?;
+            let local_file = value
+                .get(cdh.local_header_offset as usize..)
+                .ok_or(anyhow!("invalid local_header_offset"))?;
+            let zipfile = ZipFile::try_from_with_compressed_size(local_file, cdh)?;
+            if let Some(dd) = &zipfile.data_discriptor
+                && (dd.crc_32 != cdh.crc_32 || dd.compressed_size != cdh.compressed_size)
+            {
+                ziparchive.warnings.push(format!(
+                    "Data descriptor of entry '{}' doesn't match the central directory (crc {:#010x} != {:#010x}, compressed size {} != {})",
+                    cdh.effective_name(),
+                    dd.crc_32,
+                    cdh.crc_32,
+                    dd.compressed_size,
+                    cdh.compressed_size
+                ));
+            }
             zip_files.push(zipfile);
         }
 
@@ -59,6 +175,7 @@ impl<'a> TryFrom<&'a [u8]> for ZipArchive<'a> {
 }
 
 #[derive(Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct ZipFile<'a> {
     pub local_file_header: LocalFileHeader<'a>,
     pub file_data: &'a [u8],
@@ -84,15 +201,23 @@ impl<'a> ZipFile<'a> {
         let start = local_file_header.len();
         let stop = start + cdh.compressed_size as usize;
 
-        let file_data = &value[start..stop];
+        let file_data = value
+            .get(start..stop)
+            .ok_or(anyhow!("invalid compressed_size"))?;
 
-        let data_discriptor = match local_file_header.general_purpose & (1 << 3) != 0 {
+        // the bit can be set without a data descriptor following the file data, which is
+        // recognized by the next header starting right after it. A data descriptor that doesn't
+        // match the central directory is kept, so the offsets stay valid when the entry is
+        // written again, see ZipArchive::try_from for the warning.
+        let following = &value[stop..];
+        let data_discriptor = match local_file_header.general_purpose & DATA_DESCRIPTOR_BIT != 0 {
             false => None,
-            true => {
-                let start = local_file_header.len() + file_data.len();
-                let data_discriptor = DataDiscriptor::try_from(&value[start..])?;
-                Some(data_discriptor)
+            true if following.starts_with(&LFH_SIGNATURE)
+                || following.starts_with(&CDH_SIGNATURE) =>
+            {
+                None
             }
+            true => DataDiscriptor::try_from(following).ok(),
         };
 
         Ok(Self {
@@ -102,14 +227,51 @@ impl<'a> ZipFile<'a> {
         })
     }
 
-    pub fn to_bytes(&self) -> Vec<u8> {
-        let mut tmp = vec![self.local_file_header.to_bytes(), self.file_data.to_vec()];
+    /// Serializes the entry. The data descriptor bit of `general_purpose` has to match the
+    /// presence of [`Self::data_discriptor`], otherwise an error is returned or the bit is
+    /// repaired depending on `options`.
+    pub fn to_bytes(&self, options: SerializeOptions) -> Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(self.len());
+        self.write_to(&mut buf, options)?;
+        Ok(buf)
+    }
+
+    /// See [`Self::to_bytes`]
+    pub fn write_to<W: Write>(&self, w: &mut W, options: SerializeOptions) -> io::Result<()> {
+        let general_purpose = self.local_file_header.general_purpose;
+        let has_flag = general_purpose & DATA_DESCRIPTOR_BIT != 0;
+        let has_descriptor = self.data_discriptor.is_some();
+
+        if has_flag != has_descriptor && !options.repair_descriptor_flag {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Entry '{}' has the data descriptor bit {} but {} data descriptor",
+                    self.local_file_header.effective_name(),
+                    if has_flag { "set" } else { "cleared" },
+                    if has_descriptor { "a" } else { "no" },
+                ),
+            ));
+        }
+
+        self.local_file_header
+            .write_with_flags(w, self.descriptor_flag_repaired(general_purpose))?;
+        w.write_all(self.file_data)?;
 
         if let Some(dd) = &self.data_discriptor {
-            tmp.push(dd.to_bytes());
+            dd.write_to(w)?;
         }
 
-        tmp.into_iter().flatten().collect()
+        Ok(())
+    }
+
+    /// Returns `general_purpose` with the data descriptor bit matching [`Self::data_discriptor`].
+    /// The layout of the entry is never changed, so no descriptor is synthesized or dropped.
+    fn descriptor_flag_repaired(&self, general_purpose: u16) -> u16 {
+        match self.data_discriptor {
+            Some(_) => general_purpose | DATA_DESCRIPTOR_BIT,
+            None => general_purpose & !DATA_DESCRIPTOR_BIT,
+        }
     }
 }
 
@@ -124,11 +286,12 @@ impl std::fmt::Debug for ZipFile<'_> {
 }
 
 #[derive(Debug, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct LocalFileHeader<'a> {
     pub signature: u32,
     pub version_needed_to_extract: u16,
     pub general_purpose: u16,
-    pub compression_method: u16,
+    pub compression_method: CompressionMethod,
     pub last_mod_file_time: u16,
     pub last_mod_file_date: u16,
     pub crc_32: u32,
@@ -136,44 +299,75 @@ pub struct LocalFileHeader<'a> {
     pub uncompressed_size: u32,
     pub file_name_length: u16,
     pub extra_field_length: u16,
-    pub file_name: &'a str,
+    /// Raw file name. Not necessarily UTF-8, see [`Self::effective_name`]
+    pub file_name: &'a [u8],
     pub extra_field: &'a [u8],
 }
 
-impl LocalFileHeader<'_> {
+impl<'a> LocalFileHeader<'a> {
     pub fn len(&self) -> usize {
         30 + self.file_name_length as usize + self.extra_field_length as usize
     }
 
-    fn to_bytes(&self) -> Vec<u8> {
-        vec![
-            self.signature.to_le_bytes().to_vec(),
-            self.version_needed_to_extract.to_le_bytes().to_vec(),
-            self.general_purpose.to_le_bytes().to_vec(),
-            self.compression_method.to_le_bytes().to_vec(),
-            self.last_mod_file_time.to_le_bytes().to_vec(),
-            self.last_mod_file_date.to_le_bytes().to_vec(),
-            self.crc_32.to_le_bytes().to_vec(),
-            self.compressed_size.to_le_bytes().to_vec(),
-            self.uncompressed_size.to_le_bytes().to_vec(),
-            self.file_name_length.to_le_bytes().to_vec(),
-            self.extra_field_length.to_le_bytes().to_vec(),
-            self.file_name.as_bytes().to_vec(),
-            self.extra_field.to_vec(),
-        ]
-        .into_iter()
-        .flatten()
-        .collect()
+    pub fn extra_fields(&self) -> ExtraFields<'a> {
+        ExtraFields::new(self.extra_field)
+    }
+
+    /// File name from a valid Unicode Path extra field, otherwise the (lossy decoded) file name
+    pub fn effective_name(&self) -> Cow<'a, str> {
+        effective_name(self.file_name, self.extra_fields())
+    }
+
+    /// Last modification time of the entry. `None` if the stored DOS date/time is invalid
+    pub fn modified(&self) -> Option<DosDateTime> {
+        DosDateTime::decode(self.last_mod_file_date, self.last_mod_file_time)
+    }
+
+    pub fn set_modified(&mut self, modified: DosDateTime) -> Result<()> {
+        (self.last_mod_file_date, self.last_mod_file_time) = modified.encode()?;
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.len());
+        self.write_to(&mut buf)
+            .expect("writing to a Vec never fails");
+        buf
+    }
+
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.write_with_flags(w, self.general_purpose)
+    }
+
+    /// Writes the header with `general_purpose` in place of [`Self::general_purpose`]
+    fn write_with_flags<W: Write>(&self, w: &mut W, general_purpose: u16) -> io::Result<()> {
+        w.write_all(&self.signature.to_le_bytes())?;
+        w.write_all(&self.version_needed_to_extract.to_le_bytes())?;
+        w.write_all(&general_purpose.to_le_bytes())?;
+        w.write_all(&u16::from(self.compression_method).to_le_bytes())?;
+        w.write_all(&self.last_mod_file_time.to_le_bytes())?;
+        w.write_all(&self.last_mod_file_date.to_le_bytes())?;
+        w.write_all(&self.crc_32.to_le_bytes())?;
+        w.write_all(&self.compressed_size.to_le_bytes())?;
+        w.write_all(&self.uncompressed_size.to_le_bytes())?;
+        w.write_all(&self.file_name_length.to_le_bytes())?;
+        w.write_all(&self.extra_field_length.to_le_bytes())?;
+        w.write_all(self.file_name)?;
+        w.write_all(self.extra_field)
     }
 }
 
 impl<'a> TryFrom<&'a [u8]> for LocalFileHeader<'a> {
     type Error = Error;
     fn try_from(value: &'a [u8]) -> Result<Self, Self::Error> {
+        if value.len() < 30 {
+            return Err(anyhow!("local file header truncated"));
+        }
+
         let signature = u32::from_le_bytes(value[0..4].try_into()?);
         let version_needed_to_extract = u16::from_le_bytes(value[4..6].try_into()?);
         let general_purpose = u16::from_le_bytes(value[6..8].try_into()?);
-        let compression_method = u16::from_le_bytes(value[8..10].try_into()?);
+        let compression_method = u16::from_le_bytes(value[8..10].try_into()?).into();
         let last_mod_file_time = u16::from_le_bytes(value[10..12].try_into()?);
         let last_mod_file_date = u16::from_le_bytes(value[12..14].try_into()?);
         let crc_32 = u32::from_le_bytes(value[14..18].try_into()?);
@@ -187,7 +381,7 @@ impl<'a> TryFrom<&'a [u8]> for LocalFileHeader<'a> {
         if stop > value.len() {
             return Err(anyhow!("invalid file_name_length"));
         }
-        let file_name = std::str::from_utf8(&value[start..stop])?;
+        let file_name = &value[start..stop];
 
         start += file_name_length as usize;
         stop += extra_field_length as usize;
@@ -221,7 +415,220 @@ impl<'a> TryFrom<&'a [u8]> for LocalFileHeader<'a> {
     }
 }
 
+/// Compression method of an entry as stored in [`LocalFileHeader`] and [`CDH`].
+/// Unknown values are kept in [`CompressionMethod::Other`] so they are written back unchanged.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum CompressionMethod {
+    #[default]
+    Stored,
+    Deflate,
+    Deflate64,
+    Lzma,
+    Aes,
+    Other(u16),
+}
+
+impl CompressionMethod {
+    /// Returns true if entries with this compression method can be extracted
+    pub fn is_supported(&self) -> bool {
+        matches!(
+            self,
+            CompressionMethod::Stored | CompressionMethod::Deflate | CompressionMethod::Deflate64
+        )
+    }
+}
+
+impl From<u16> for CompressionMethod {
+    fn from(value: u16) -> Self {
+        match value {
+            0 => CompressionMethod::Stored,
+            8 => CompressionMethod::Deflate,
+            9 => CompressionMethod::Deflate64,
+            14 => CompressionMethod::Lzma,
+            99 => CompressionMethod::Aes,
+            v => CompressionMethod::Other(v),
+        }
+    }
+}
+
+impl From<CompressionMethod> for u16 {
+    fn from(value: CompressionMethod) -> Self {
+        match value {
+            CompressionMethod::Stored => 0,
+            CompressionMethod::Deflate => 8,
+            CompressionMethod::Deflate64 => 9,
+            CompressionMethod::Lzma => 14,
+            CompressionMethod::Aes => 99,
+            CompressionMethod::Other(v) => v,
+        }
+    }
+}
+
+impl Display for CompressionMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompressionMethod::Stored => write!(f, "Stored"),
+            CompressionMethod::Deflate => write!(f, "Deflate"),
+            CompressionMethod::Deflate64 => write!(f, "Deflate64"),
+            CompressionMethod::Lzma => write!(f, "LZMA"),
+            CompressionMethod::Aes => write!(f, "AES"),
+            CompressionMethod::Other(v) => write!(f, "Unknown({v})"),
+        }
+    }
+}
+
+/// Header id of the Info-ZIP Unicode Path extra field
+const UNICODE_PATH_EXTRA_FIELD_ID: u16 = 0x7075;
+
+/// Record of the extra field of a [`LocalFileHeader`] or [`CDH`]
+#[derive(Debug, Clone, Copy)]
+pub struct ExtraField<'a> {
+    pub id: u16,
+    pub data: &'a [u8],
+}
+
+/// Iterator over the records of an extra field. Stops at the first truncated record.
+#[derive(Debug, Clone)]
+pub struct ExtraFields<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> ExtraFields<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+}
+
+impl<'a> Iterator for ExtraFields<'a> {
+    type Item = ExtraField<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.len() < 4 {
+            return None;
+        }
+
+        let id = u16::from_le_bytes([self.data[0], self.data[1]]);
+        let size = u16::from_le_bytes([self.data[2], self.data[3]]) as usize;
+
+        let Some(data) = self.data.get(4..4 + size) else {
+            self.data = &[];
+            return None;
+        };
+
+        self.data = &self.data[4 + size..];
+        Some(ExtraField { id, data })
+    }
+}
+
+fn effective_name<'a>(file_name: &'a [u8], mut extra_fields: ExtraFields<'a>) -> Cow<'a, str> {
+    // version (1 byte), crc32 of the standard file name (4 bytes), utf-8 file name
+    let unicode_path = extra_fields.find_map(|field| {
+        if field.id != UNICODE_PATH_EXTRA_FIELD_ID || field.data.len() < 5 || field.data[0] != 1 {
+            return None;
+        }
+
+        let name_crc = u32::from_le_bytes(field.data[1..5].try_into().ok()?);
+        if name_crc != crc32(file_name) {
+            return None;
+        }
+
+        std::str::from_utf8(&field.data[5..]).ok()
+    });
+
+    match unicode_path {
+        Some(name) => Cow::Borrowed(name),
+        None => String::from_utf8_lossy(file_name),
+    }
+}
+
+/// CRC-32 (ISO-HDLC) as used by the zip format
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb88320 & (!(crc & 1)).wrapping_add(1));
+        }
+    }
+
+    !crc
+}
+
+/// Date and time as stored in the MS-DOS format used by zip entries. The DOS format starts at
+/// 1980 and only has a resolution of two seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DosDateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl DosDateTime {
+    /// Decodes a DOS date and time. Returns `None` if a field is out of range (e.g. month 0).
+    pub fn decode(date: u16, time: u16) -> Option<Self> {
+        let datetime = Self {
+            year: 1980 + (date >> 9),
+            month: ((date >> 5) & 0xf) as u8,
+            day: (date & 0x1f) as u8,
+            hour: (time >> 11) as u8,
+            minute: ((time >> 5) & 0x3f) as u8,
+            second: ((time & 0x1f) * 2) as u8,
+        };
+
+        datetime.is_valid().then_some(datetime)
+    }
+
+    /// Encodes to a DOS `(date, time)` pair. Odd seconds are rounded down.
+    pub fn encode(&self) -> Result<(u16, u16)> {
+        if !self.is_valid() || self.year > 1980 + 0x7f {
+            return Err(anyhow!("{self} can not be represented as DOS date/time"));
+        }
+
+        let date = ((self.year - 1980) << 9) | ((self.month as u16) << 5) | self.day as u16;
+        let time =
+            ((self.hour as u16) << 11) | ((self.minute as u16) << 5) | (self.second as u16 / 2);
+
+        Ok((date, time))
+    }
+
+    fn is_valid(&self) -> bool {
+        let days_in_month = match self.month {
+            1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+            4 | 6 | 9 | 11 => 30,
+            2 if self.year.is_multiple_of(4)
+                && (!self.year.is_multiple_of(100) || self.year.is_multiple_of(400)) =>
+            {
+                29
+            }
+            2 => 28,
+            _ => return false,
+        };
+
+        self.year >= 1980
+            && (1..=days_in_month).contains(&self.day)
+            && self.hour < 24
+            && self.minute < 60
+            && self.second < 60
+    }
+}
+
+impl Display for DosDateTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
 #[derive(Debug, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct DataDiscriptor {
     pub signature: Option<u32>,
     pub crc_32: u32,
@@ -237,23 +644,29 @@ impl DataDiscriptor {
     }
 
     pub fn to_bytes(&self) -> Vec<u8> {
-        let mut tmp = vec![
-            self.crc_32.to_le_bytes().to_vec(),
-            self.compressed_size.to_le_bytes().to_vec(),
-            self.uncompressed_size.to_le_bytes().to_vec(),
-        ];
+        let mut buf = Vec::with_capacity(self.len());
+        self.write_to(&mut buf)
+            .expect("writing to a Vec never fails");
+        buf
+    }
 
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
         if let Some(sig) = self.signature {
-            tmp.insert(0, sig.to_le_bytes().to_vec());
+            w.write_all(&sig.to_le_bytes())?;
         }
-
-        tmp.into_iter().flatten().collect()
+        w.write_all(&self.crc_32.to_le_bytes())?;
+        w.write_all(&self.compressed_size.to_le_bytes())?;
+        w.write_all(&self.uncompressed_size.to_le_bytes())
     }
 }
 
 impl TryFrom<&[u8]> for DataDiscriptor {
     type Error = Error;
     fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        if value.len() < 12 {
+            return Err(anyhow!("data discriptor truncated"));
+        }
+
         let signature = match u32::from_le_bytes(value[0..4].try_into()?) {
             v if v == 0x8074b50 => Some(v),
             _ => None,
@@ -263,6 +676,9 @@ impl TryFrom<&[u8]> for DataDiscriptor {
             true => 4,
             false => 0,
         };
+        if value.len() < start + 12 {
+            return Err(anyhow!("data discriptor truncated"));
+        }
 
         let crc_32 = u32::from_le_bytes(value[start..start + 4].try_into()?);
         let compressed_size = u32::from_le_bytes(value[start + 4..start + 8].try_into()?);
@@ -279,13 +695,14 @@ impl TryFrom<&[u8]> for DataDiscriptor {
 
 #[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct CDH<'a> {
     /// central file header signature (0x02014b50 (LE))
     pub signature: u32,
     pub version_made_by: u16,
     pub version_needed_to_extract: u16,
     pub general_purpose: u16,
-    pub compression_method: u16,
+    pub compression_method: CompressionMethod,
     pub last_mod_file_time: u16,
     pub last_mod_file_date: u16,
     pub crc_32: u32,
@@ -298,7 +715,8 @@ pub struct CDH<'a> {
     pub internal_file_attributes: u16,
     pub external_file_attributes: u32,
     pub local_header_offset: u32,
-    pub file_name: &'a str,
+    /// Raw file name. Not necessarily UTF-8, see [`Self::effective_name`]
+    pub file_name: &'a [u8],
     pub extra_field: &'a [u8],
     pub file_comment: &'a [u8],
 }
@@ -312,11 +730,32 @@ impl<'a> CDH<'a> {
             + 46
     }
 
+    pub fn extra_fields(&self) -> ExtraFields<'a> {
+        ExtraFields::new(self.extra_field)
+    }
+
+    /// File name from a valid Unicode Path extra field, otherwise the (lossy decoded) file name
+    pub fn effective_name(&self) -> Cow<'a, str> {
+        effective_name(self.file_name, self.extra_fields())
+    }
+
+    /// Last modification time of the entry. `None` if the stored DOS date/time is invalid
+    pub fn modified(&self) -> Option<DosDateTime> {
+        DosDateTime::decode(self.last_mod_file_date, self.last_mod_file_time)
+    }
+
+    pub fn set_modified(&mut self, modified: DosDateTime) -> Result<()> {
+        (self.last_mod_file_date, self.last_mod_file_time) = modified.encode()?;
+        Ok(())
+    }
+
+    /// Parses consecutive central directory headers. Parsing stops at the first position that
+    /// doesn't start with the CDH signature (e.g. the EOCD).
     pub fn get_vec_from_bytes(value: &'a [u8]) -> Result<Vec<Self>, Error> {
         let mut cdhs = vec![];
         let mut pos = 0;
 
-        while pos < value.len() {
+        while value.get(pos..pos + 4) == Some(&CDH_SIGNATURE[..]) {
             let cdh = CDH::try_from(&value[pos..])?;
             pos += cdh.len();
             cdhs.push(cdh);
@@ -324,43 +763,54 @@ impl<'a> CDH<'a> {
         Ok(cdhs)
     }
 
-    fn to_bytes(&self) -> Vec<u8> {
-        vec![
-            self.signature.to_le_bytes().to_vec(),
-            self.version_made_by.to_le_bytes().to_vec(),
-            self.version_needed_to_extract.to_le_bytes().to_vec(),
-            self.general_purpose.to_le_bytes().to_vec(),
-            self.compression_method.to_le_bytes().to_vec(),
-            self.last_mod_file_time.to_le_bytes().to_vec(),
-            self.last_mod_file_date.to_le_bytes().to_vec(),
-            self.crc_32.to_le_bytes().to_vec(),
-            self.compressed_size.to_le_bytes().to_vec(),
-            self.uncompressed_size.to_le_bytes().to_vec(),
-            self.file_name_length.to_le_bytes().to_vec(),
-            self.extra_field_length.to_le_bytes().to_vec(),
-            self.file_comment_length.to_le_bytes().to_vec(),
-            self.disk_number_start.to_le_bytes().to_vec(),
-            self.internal_file_attributes.to_le_bytes().to_vec(),
-            self.external_file_attributes.to_le_bytes().to_vec(),
-            self.local_header_offset.to_le_bytes().to_vec(),
-            self.file_name.as_bytes().to_vec(),
-            self.extra_field.to_vec(),
-            self.file_comment.to_vec(),
-        ]
-        .into_iter()
-        .flatten()
-        .collect()
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.len());
+        self.write_to(&mut buf)
+            .expect("writing to a Vec never fails");
+        buf
+    }
+
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.write_with_flags(w, self.general_purpose)
+    }
+
+    /// Writes the header with `general_purpose` in place of [`Self::general_purpose`]
+    fn write_with_flags<W: Write>(&self, w: &mut W, general_purpose: u16) -> io::Result<()> {
+        w.write_all(&self.signature.to_le_bytes())?;
+        w.write_all(&self.version_made_by.to_le_bytes())?;
+        w.write_all(&self.version_needed_to_extract.to_le_bytes())?;
+        w.write_all(&general_purpose.to_le_bytes())?;
+        w.write_all(&u16::from(self.compression_method).to_le_bytes())?;
+        w.write_all(&self.last_mod_file_time.to_le_bytes())?;
+        w.write_all(&self.last_mod_file_date.to_le_bytes())?;
+        w.write_all(&self.crc_32.to_le_bytes())?;
+        w.write_all(&self.compressed_size.to_le_bytes())?;
+        w.write_all(&self.uncompressed_size.to_le_bytes())?;
+        w.write_all(&self.file_name_length.to_le_bytes())?;
+        w.write_all(&self.extra_field_length.to_le_bytes())?;
+        w.write_all(&self.file_comment_length.to_le_bytes())?;
+        w.write_all(&self.disk_number_start.to_le_bytes())?;
+        w.write_all(&self.internal_file_attributes.to_le_bytes())?;
+        w.write_all(&self.external_file_attributes.to_le_bytes())?;
+        w.write_all(&self.local_header_offset.to_le_bytes())?;
+        w.write_all(self.file_name)?;
+        w.write_all(self.extra_field)?;
+        w.write_all(self.file_comment)
     }
 }
 
 impl<'a> TryFrom<&'a [u8]> for CDH<'a> {
     type Error = Error;
     fn try_from(value: &'a [u8]) -> Result<Self, Self::Error> {
+        if value.len() < 46 {
+            return Err(anyhow!("central directory header truncated"));
+        }
+
         let signature = u32::from_le_bytes(value[0..4].try_into()?);
         let version_made_by = u16::from_le_bytes(value[4..6].try_into()?);
         let version_needed_to_extract = u16::from_le_bytes(value[6..8].try_into()?);
         let general_purpose = u16::from_le_bytes(value[8..10].try_into()?);
-        let compression_method = u16::from_le_bytes(value[10..12].try_into()?);
+        let compression_method = u16::from_le_bytes(value[10..12].try_into()?).into();
         let last_mod_file_time = u16::from_le_bytes(value[12..14].try_into()?);
         let last_mod_file_date = u16::from_le_bytes(value[14..16].try_into()?);
         let crc_32 = u32::from_le_bytes(value[16..20].try_into()?);
@@ -379,7 +829,7 @@ impl<'a> TryFrom<&'a [u8]> for CDH<'a> {
         if stop > value.len() {
             return Err(anyhow!("invalid file_name_length"));
         }
-        let file_name = std::str::from_utf8(&value[start..stop])?;
+        let file_name = &value[start..stop];
 
         start += file_name_length as usize;
         stop += extra_field_length as usize;
@@ -391,7 +841,7 @@ impl<'a> TryFrom<&'a [u8]> for CDH<'a> {
         start += extra_field_length as usize;
         stop += file_comment_length as usize;
         if stop > value.len() {
-            return Err(anyhow!("invalid extra_field_length"));
+            return Err(anyhow!("invalid file_comment_length"));
         }
         let file_comment = &value[start..stop];
 
@@ -420,8 +870,13 @@ impl<'a> TryFrom<&'a [u8]> for CDH<'a> {
     }
 }
 
+const EOCD_SIGNATURE: [u8; 4] = [0x50, 0x4b, 0x5, 0x6];
+const LFH_SIGNATURE: [u8; 4] = [0x50, 0x4b, 0x3, 0x4];
+const CDH_SIGNATURE: [u8; 4] = [0x50, 0x4b, 0x1, 0x2];
+
 #[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct EOCD<'a> {
     /// end of central directory signature (0x06064b50 (LE))
     pub signature: u32,
@@ -448,49 +903,124 @@ pub struct EOCD<'a> {
     pub comment_length: u16,
 
     /// zipfile comment (variable size)
-    pub comment: &'a [u8],
+    pub comment: Cow<'a, [u8]>,
 }
 
 impl EOCD<'_> {
+    /// Length of the serialized EOCD, which has the length of [`Self::comment`] and not of
+    /// `comment_length`
     #[allow(dead_code)]
     #[inline(always)]
     pub fn len(&self) -> usize {
-        self.comment_length as usize + 22
+        self.comment.len() + 22
     }
 
-    fn to_bytes(&self) -> Vec<u8> {
-        vec![
-            self.signature.to_le_bytes().to_vec(),
-            self.disk_number.to_le_bytes().to_vec(),
-            self.central_dir_start_disk.to_le_bytes().to_vec(),
-            self.cental_dir_entries_disk.to_le_bytes().to_vec(),
-            self.cental_dir_entries_total.to_le_bytes().to_vec(),
-            self.central_dir_size.to_le_bytes().to_vec(),
-            self.central_dir_offset.to_le_bytes().to_vec(),
-            self.comment_length.to_le_bytes().to_vec(),
-            self.comment.to_vec(),
-        ]
-        .into_iter()
-        .flatten()
-        .collect()
+    /// Serializes the EOCD. `comment_length` is always derived from the comment.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(22 + self.comment.len());
+        self.write_to(&mut buf)?;
+        Ok(buf)
     }
-}
 
-impl<'a> TryFrom<&'a [u8]> for EOCD<'a> {
-    type Error = Error;
+    /// See [`Self::to_bytes`]
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let comment_length = u16::try_from(self.comment.len()).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Comment of {} bytes exceeds the maximum length of {}",
+                    self.comment.len(),
+                    u16::MAX
+                ),
+            )
+        })?;
+
+        w.write_all(&self.signature.to_le_bytes())?;
+        w.write_all(&self.disk_number.to_le_bytes())?;
+        w.write_all(&self.central_dir_start_disk.to_le_bytes())?;
+        w.write_all(&self.cental_dir_entries_disk.to_le_bytes())?;
+        w.write_all(&self.cental_dir_entries_total.to_le_bytes())?;
+        w.write_all(&self.central_dir_size.to_le_bytes())?;
+        w.write_all(&self.central_dir_offset.to_le_bytes())?;
+        w.write_all(&comment_length.to_le_bytes())?;
+        w.write_all(&self.comment)
+    }
+}
 
-    fn try_from(value: &'a [u8]) -> Result<Self, Self::Error> {
-        let pos = value
+impl<'a> EOCD<'a> {
+    /// Searches the EOCD from the end of the buffer. Samples can contain fake EOCD signatures
+    /// (e.g. in the zip comment or in appended data), so every candidate is validated: the comment
+    /// has to end exactly at the end of the buffer and the central directory has to start with a
+    /// CDH signature. If no candidate ends at the end of the buffer, the last one with a valid
+    /// central directory is used, the data appended after it is ignored. If no candidate is
+    /// valid, the last signature in the buffer is used. A warning is returned in both cases.
+    ///
+    /// Returns the EOCD, its offset in the buffer and the warning.
+    pub fn find(value: &'a [u8]) -> Result<(Self, usize, Option<String>), Error> {
+        let min_pos = value.len().saturating_sub(u16::MAX as usize + 22);
+
+        let candidates: Vec<usize> = value
             .windows(4)
+            .enumerate()
+            .skip(min_pos)
             .rev()
-            .position(|w| w == [0x50, 0x4b, 0x5, 0x6])
-            .ok_or(anyhow!("EOCD not found"))?;
+            .filter(|(_, w)| *w == EOCD_SIGNATURE)
+            .map(|(pos, _)| pos)
+            .collect();
 
-        if pos >= u16::MAX as usize + 22 {
+        let Some(&last) = candidates.first() else {
             return Err(anyhow!("EOCD not found"));
+        };
+
+        for &pos in &candidates {
+            if let Ok(eocd) = Self::parse_at(value, pos)
+                && pos + eocd.len() == value.len()
+                && eocd.has_central_dir_at(value, pos)
+            {
+                return Ok((eocd, pos, None));
+            }
         }
 
-        let pos = value.len() - (pos + 4);
+        for &pos in &candidates {
+            if let Ok(eocd) = Self::parse_at(value, pos)
+                && eocd.has_central_dir_at(value, pos)
+            {
+                let appended = value.len() - pos - eocd.len();
+                let warning = format!("{appended} bytes appended after the EOCD are ignored");
+                return Ok((eocd, pos, Some(warning)));
+            }
+        }
+
+        let eocd = Self::parse_at(value, last)?;
+        let warning = format!(
+            "None of the {} EOCD candidates is valid, using the one at offset {last}",
+            candidates.len()
+        );
+
+        Ok((eocd, last, Some(warning)))
+    }
+
+    /// Whether the central directory of the EOCD at `pos` starts with a CDH signature
+    fn has_central_dir_at(&self, value: &[u8], pos: usize) -> bool {
+        // central_dir_size is not checked, because some samples lie about it. The central
+        // directory is parsed up to the EOCD instead.
+        let start = self.central_dir_offset as usize;
+        if start > pos {
+            return false;
+        }
+
+        // empty archive
+        if start == pos {
+            return self.cental_dir_entries_total == 0;
+        }
+
+        value.get(start..start + 4) == Some(&CDH_SIGNATURE[..])
+    }
+
+    fn parse_at(value: &'a [u8], pos: usize) -> Result<Self, Error> {
+        if pos + 22 > value.len() {
+            return Err(anyhow!("EOCD truncated"));
+        }
 
         let signature = u32::from_le_bytes(value[pos..pos + 4].try_into()?);
         let disk_number = u16::from_le_bytes(value[pos + 4..pos + 6].try_into()?);
@@ -503,12 +1033,10 @@ impl<'a> TryFrom<&'a [u8]> for EOCD<'a> {
 
         let start = pos + 22;
         let stop = pos + 22 + comment_length as usize;
-        if stop as usize > value.len() {
+        if stop > value.len() {
             return Err(anyhow!("invalid comment_length"));
         }
-        let comment = &value[start..stop];
-
-        assert_eq!(comment_length as usize, comment.len());
+        let comment = Cow::Borrowed(&value[start..stop]);
 
         Ok(Self {
             signature,
@@ -523,3 +1051,816 @@ impl<'a> TryFrom<&'a [u8]> for EOCD<'a> {
         })
     }
 }
+
+impl<'a> TryFrom<&'a [u8]> for EOCD<'a> {
+    type Error = Error;
+
+    fn try_from(value: &'a [u8]) -> Result<Self, Self::Error> {
+        Self::find(value).map(|(eocd, _, _)| eocd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ENCRYPTION_BIT, UTF8_BIT, modify_flags_bytes, needs_encryption_bit_fix,
+        try_remove_encryption_bits,
+    };
+
+    /// Stored entry of [`archive`]
+    struct Entry<'a> {
+        name: &'a [u8],
+        data: &'a [u8],
+        general_purpose: u16,
+
+        /// Extra field of the local file header and the central directory header
+        extra_field: Vec<u8>,
+
+        /// crc and compressed size of the data descriptor (with signature) after the data
+        descriptor: Option<(u32, u32)>,
+    }
+
+    impl<'a> Entry<'a> {
+        fn new(name: &'a [u8], data: &'a [u8]) -> Self {
+            Self {
+                name,
+                data,
+                general_purpose: 0,
+                extra_field: vec![],
+                descriptor: None,
+            }
+        }
+
+        /// Adds the extra field `id` with `data`
+        fn with_extra_field(mut self, id: u16, data: &[u8]) -> Self {
+            self.extra_field.extend(id.to_le_bytes());
+            self.extra_field.extend((data.len() as u16).to_le_bytes());
+            self.extra_field.extend(data);
+            self
+        }
+
+        fn with_descriptor(mut self, crc_32: u32, compressed_size: u32) -> Self {
+            self.general_purpose |= DATA_DESCRIPTOR_BIT;
+            self.descriptor = Some((crc_32, compressed_size));
+            self
+        }
+    }
+
+    /// Archive of the uncompressed `entries` with `comment`
+    fn archive(entries: &[Entry], comment: &[u8]) -> Vec<u8> {
+        let mut buf = vec![];
+        let mut central_dir = vec![];
+
+        for entry in entries {
+            let local_header_offset = buf.len() as u32;
+            let crc_32 = crc32(entry.data);
+            let size = entry.data.len() as u32;
+            let name_length = entry.name.len() as u16;
+            let extra_field_length = entry.extra_field.len() as u16;
+
+            buf.extend(LFH_SIGNATURE);
+            buf.extend(20u16.to_le_bytes());
+            buf.extend(entry.general_purpose.to_le_bytes());
+            buf.extend([0; 6]); // compression method, time and date
+            buf.extend(crc_32.to_le_bytes());
+            buf.extend(size.to_le_bytes());
+            buf.extend(size.to_le_bytes());
+            buf.extend(name_length.to_le_bytes());
+            buf.extend(extra_field_length.to_le_bytes());
+            buf.extend(entry.name);
+            buf.extend(&entry.extra_field);
+            buf.extend(entry.data);
+            if let Some((dd_crc_32, dd_compressed_size)) = entry.descriptor {
+                buf.extend(0x8074b50u32.to_le_bytes());
+                buf.extend(dd_crc_32.to_le_bytes());
+                buf.extend(dd_compressed_size.to_le_bytes());
+                buf.extend(size.to_le_bytes());
+            }
+
+            central_dir.extend(CDH_SIGNATURE);
+            central_dir.extend(20u16.to_le_bytes());
+            central_dir.extend(20u16.to_le_bytes());
+            central_dir.extend(entry.general_purpose.to_le_bytes());
+            central_dir.extend([0; 6]); // compression method, time and date
+            central_dir.extend(crc_32.to_le_bytes());
+            central_dir.extend(size.to_le_bytes());
+            central_dir.extend(size.to_le_bytes());
+            central_dir.extend(name_length.to_le_bytes());
+            central_dir.extend(extra_field_length.to_le_bytes());
+            central_dir.extend([0; 10]); // comment, disk, attributes
+            central_dir.extend(local_header_offset.to_le_bytes());
+            central_dir.extend(entry.name);
+            central_dir.extend(&entry.extra_field);
+        }
+
+        let central_dir_offset = buf.len() as u32;
+        buf.extend(&central_dir);
+
+        let entries = entries.len() as u16;
+        buf.extend(EOCD_SIGNATURE);
+        buf.extend([0; 4]); // disk numbers
+        buf.extend(entries.to_le_bytes());
+        buf.extend(entries.to_le_bytes());
+        buf.extend((central_dir.len() as u32).to_le_bytes());
+        buf.extend(central_dir_offset.to_le_bytes());
+        buf.extend((comment.len() as u16).to_le_bytes());
+        buf.extend(comment);
+
+        buf
+    }
+
+    const REPAIR: SerializeOptions = SerializeOptions {
+        repair_descriptor_flag: true,
+    };
+
+    #[test]
+    fn round_trip_with_data_descriptors() {
+        let data = archive(
+            &[
+                Entry::new(b"a.txt", b"abc").with_descriptor(crc32(b"abc"), 3),
+                Entry::new(b"b.txt", b"defg"),
+            ],
+            b"",
+        );
+
+        let ziparchive = ZipArchive::try_from(&data[..]).unwrap();
+        assert!(ziparchive.warnings.is_empty(), "{:?}", ziparchive.warnings);
+        assert!(ziparchive.zip_files[0].data_discriptor.is_some());
+        assert!(ziparchive.zip_files[1].data_discriptor.is_none());
+        assert_eq!(ziparchive.to_bytes(Default::default()).unwrap(), data);
+    }
+
+    #[test]
+    fn mismatching_data_descriptor_is_kept_and_reported() {
+        let data = archive(
+            &[
+                Entry::new(b"a.txt", b"abc").with_descriptor(0xdeadbeef, 4),
+                Entry::new(b"b.txt", b"defg"),
+            ],
+            b"",
+        );
+
+        let ziparchive = ZipArchive::try_from(&data[..]).unwrap();
+        assert_eq!(ziparchive.warnings.len(), 1);
+        assert!(ziparchive.warnings[0].contains("'a.txt'"));
+
+        // dropping the descriptor would shift the following entries and the central directory
+        let dd = ziparchive.zip_files[0].data_discriptor.as_ref().unwrap();
+        assert_eq!((dd.crc_32, dd.compressed_size), (0xdeadbeef, 4));
+        assert_eq!(ziparchive.to_bytes(Default::default()).unwrap(), data);
+    }
+
+    #[test]
+    fn descriptor_bit_without_data_descriptor() {
+        let mut entry = Entry::new(b"a.txt", b"abc");
+        entry.general_purpose |= DATA_DESCRIPTOR_BIT;
+        let data = archive(&[entry, Entry::new(b"b.txt", b"defg")], b"");
+
+        let ziparchive = ZipArchive::try_from(&data[..]).unwrap();
+        assert!(ziparchive.zip_files[0].data_discriptor.is_none());
+
+        let repaired = ziparchive.to_bytes(REPAIR).unwrap();
+        assert_eq!(repaired.len(), data.len());
+        let repaired = ZipArchive::try_from(&repaired[..]).unwrap();
+        assert_eq!(
+            repaired.zip_files[0].local_file_header.general_purpose & DATA_DESCRIPTOR_BIT,
+            0
+        );
+        assert_eq!(
+            repaired.central_directory_headers[0].general_purpose & DATA_DESCRIPTOR_BIT,
+            0
+        );
+    }
+
+    #[test]
+    fn serialize_flag_and_descriptor_combinations() {
+        let descriptor = || DataDiscriptor {
+            signature: Some(0x8074b50),
+            crc_32: crc32(b"abc"),
+            compressed_size: 3,
+            uncompressed_size: 3,
+        };
+
+        // (bit set, data descriptor, consistent)
+        let cases = [
+            (true, true, true),
+            (false, false, true),
+            (true, false, false),
+            (false, true, false),
+        ];
+
+        for (flag, has_descriptor, consistent) in cases {
+            let zipfile = ZipFile {
+                local_file_header: LocalFileHeader {
+                    signature: 0x04034b50,
+                    general_purpose: if flag { DATA_DESCRIPTOR_BIT } else { 0 },
+                    ..Default::default()
+                },
+                file_data: b"abc",
+                data_discriptor: has_descriptor.then(descriptor),
+            };
+
+            assert_eq!(
+                zipfile.to_bytes(Default::default()).is_ok(),
+                consistent,
+                "bit set: {flag}, data descriptor: {has_descriptor}"
+            );
+
+            let repaired = zipfile.to_bytes(REPAIR).unwrap();
+            let general_purpose = u16::from_le_bytes([repaired[6], repaired[7]]);
+            assert_eq!(
+                general_purpose & DATA_DESCRIPTOR_BIT != 0,
+                has_descriptor,
+                "bit set: {flag}, data descriptor: {has_descriptor}"
+            );
+            assert_eq!(repaired.len(), zipfile.len());
+        }
+    }
+
+    #[test]
+    fn set_comment_round_trip() {
+        let data = archive(&[Entry::new(b"a.txt", b"abc")], b"old comment");
+        let mut ziparchive = ZipArchive::try_from(&data[..]).unwrap();
+        assert_eq!(ziparchive.comment(), b"old comment");
+
+        ziparchive.set_comment(b"config=c2.example;key=42".to_vec());
+        assert_eq!(ziparchive.comment(), b"config=c2.example;key=42");
+
+        let bytes = ziparchive.to_bytes(Default::default()).unwrap();
+        assert_eq!(bytes.len(), ziparchive.len());
+        assert_eq!(
+            bytes,
+            archive(&[Entry::new(b"a.txt", b"abc")], b"config=c2.example;key=42")
+        );
+
+        let reparsed = ZipArchive::try_from(&bytes[..]).unwrap();
+        assert!(reparsed.warnings.is_empty(), "{:?}", reparsed.warnings);
+        assert_eq!(reparsed.comment(), b"config=c2.example;key=42");
+        assert_eq!(reparsed.zip_files[0].file_data, b"abc");
+
+        ziparchive.set_comment(vec![]);
+        assert_eq!(
+            ziparchive.to_bytes(Default::default()).unwrap(),
+            archive(&[Entry::new(b"a.txt", b"abc")], b"")
+        );
+    }
+
+    #[test]
+    fn oversized_comment_fails_to_serialize() {
+        let data = archive(&[Entry::new(b"a.txt", b"abc")], b"");
+        let mut ziparchive = ZipArchive::try_from(&data[..]).unwrap();
+
+        ziparchive.set_comment(vec![b'x'; u16::MAX as usize]);
+        assert_eq!(ziparchive.eocd.len(), u16::MAX as usize + 22);
+        assert!(ziparchive.to_bytes(Default::default()).is_ok());
+
+        // the length is not clamped to the length that can be stored
+        ziparchive.set_comment(vec![b'x'; u16::MAX as usize + 1]);
+        assert_eq!(ziparchive.eocd.len(), u16::MAX as usize + 23);
+        assert!(ziparchive.to_bytes(Default::default()).is_err());
+    }
+
+    #[test]
+    fn clear_encryption_and_descriptor_bits() {
+        let mut encrypted = Entry::new(b"a.txt", b"abc").with_descriptor(crc32(b"abc"), 3);
+        encrypted.general_purpose |= ENCRYPTION_BIT;
+        let data = archive(&[encrypted, Entry::new(b"b.txt", b"defg")], b"");
+
+        let mut ziparchive = ZipArchive::try_from(&data[..]).unwrap();
+        assert_eq!(
+            ziparchive.modify_flags(ENCRYPTION_BIT | DATA_DESCRIPTOR_BIT, 0),
+            1
+        );
+        assert_eq!(ziparchive.central_directory_headers[0].general_purpose, 0);
+
+        // the descriptor bit is restored for the entry that is followed by a data descriptor
+        let modified = modify_flags_bytes(&data, ENCRYPTION_BIT | DATA_DESCRIPTOR_BIT, 0).unwrap();
+        assert_eq!(modified.len(), data.len());
+        let modified = ZipArchive::try_from(&modified[..]).unwrap();
+        assert!(modified.warnings.is_empty(), "{:?}", modified.warnings);
+        for (zipfile, cdh) in modified
+            .zip_files
+            .iter()
+            .zip(&modified.central_directory_headers)
+        {
+            assert_eq!(
+                zipfile.local_file_header.general_purpose & ENCRYPTION_BIT,
+                0
+            );
+            assert_eq!(cdh.general_purpose & ENCRYPTION_BIT, 0);
+        }
+        assert_eq!(
+            modified.zip_files[0].local_file_header.general_purpose,
+            DATA_DESCRIPTOR_BIT
+        );
+        assert_eq!(
+            modified.central_directory_headers[0].general_purpose,
+            DATA_DESCRIPTOR_BIT
+        );
+        assert_eq!(modified.zip_files[1].file_data, b"defg");
+    }
+
+    #[test]
+    fn set_utf8_bit() {
+        let data = archive(
+            &[Entry::new(b"a.txt", b"abc"), Entry::new(b"b.txt", b"defg")],
+            b"",
+        );
+
+        let mut ziparchive = ZipArchive::try_from(&data[..]).unwrap();
+        assert_eq!(ziparchive.modify_flags(0, UTF8_BIT), 2);
+        assert_eq!(ziparchive.modify_flags(0, UTF8_BIT), 0);
+
+        let modified = modify_flags_bytes(&data, 0, UTF8_BIT).unwrap();
+        let modified = ZipArchive::try_from(&modified[..]).unwrap();
+        for (zipfile, cdh) in modified
+            .zip_files
+            .iter()
+            .zip(&modified.central_directory_headers)
+        {
+            assert_eq!(zipfile.local_file_header.general_purpose, UTF8_BIT);
+            assert_eq!(cdh.general_purpose, UTF8_BIT);
+        }
+    }
+
+    #[test]
+    fn modified_entries_are_counted_once() {
+        let mut first = Entry::new(b"a.txt", b"abc");
+        first.general_purpose |= ENCRYPTION_BIT;
+        let mut second = Entry::new(b"b.txt", b"defg");
+        second.general_purpose |= ENCRYPTION_BIT;
+        let mut data = archive(&[first, second], b"");
+
+        // only the central directory header of the first entry has the bit
+        data[6..8].copy_from_slice(&0u16.to_le_bytes());
+
+        let mut ziparchive = ZipArchive::try_from(&data[..]).unwrap();
+        assert_eq!(ziparchive.zip_files[0].local_file_header.general_purpose, 0);
+        assert_eq!(ziparchive.modify_flags(ENCRYPTION_BIT, 0), 2);
+        assert_eq!(ziparchive.modify_flags(ENCRYPTION_BIT, 0), 0);
+
+        let (fixed, modified_entries) = try_remove_encryption_bits(&data).unwrap();
+        assert_eq!(modified_entries, 2);
+        assert!(!needs_encryption_bit_fix(&fixed).unwrap());
+    }
+
+    /// Serialization with nested vectors as it was before `write_to`, the reference of the
+    /// `write_to` tests
+    mod nested_vectors {
+        use super::*;
+
+        fn local_file_header(lfh: &LocalFileHeader) -> Vec<u8> {
+            vec![
+                lfh.signature.to_le_bytes().to_vec(),
+                lfh.version_needed_to_extract.to_le_bytes().to_vec(),
+                lfh.general_purpose.to_le_bytes().to_vec(),
+                u16::from(lfh.compression_method).to_le_bytes().to_vec(),
+                lfh.last_mod_file_time.to_le_bytes().to_vec(),
+                lfh.last_mod_file_date.to_le_bytes().to_vec(),
+                lfh.crc_32.to_le_bytes().to_vec(),
+                lfh.compressed_size.to_le_bytes().to_vec(),
+                lfh.uncompressed_size.to_le_bytes().to_vec(),
+                lfh.file_name_length.to_le_bytes().to_vec(),
+                lfh.extra_field_length.to_le_bytes().to_vec(),
+                lfh.file_name.to_vec(),
+                lfh.extra_field.to_vec(),
+            ]
+            .into_iter()
+            .flatten()
+            .collect()
+        }
+
+        fn data_descriptor(dd: &DataDiscriptor) -> Vec<u8> {
+            let mut tmp = vec![
+                dd.crc_32.to_le_bytes().to_vec(),
+                dd.compressed_size.to_le_bytes().to_vec(),
+                dd.uncompressed_size.to_le_bytes().to_vec(),
+            ];
+            if let Some(sig) = dd.signature {
+                tmp.insert(0, sig.to_le_bytes().to_vec());
+            }
+
+            tmp.into_iter().flatten().collect()
+        }
+
+        pub(super) fn zip_file(zipfile: &ZipFile, repair: bool) -> Vec<u8> {
+            let mut lfh = local_file_header(&zipfile.local_file_header);
+            let general_purpose = zipfile.local_file_header.general_purpose;
+            if repair {
+                lfh[6..8].copy_from_slice(
+                    &zipfile
+                        .descriptor_flag_repaired(general_purpose)
+                        .to_le_bytes(),
+                );
+            }
+
+            let mut tmp = vec![lfh, zipfile.file_data.to_vec()];
+            if let Some(dd) = &zipfile.data_discriptor {
+                tmp.push(data_descriptor(dd));
+            }
+
+            tmp.into_iter().flatten().collect()
+        }
+
+        fn cdh(cdh: &CDH, general_purpose: u16) -> Vec<u8> {
+            vec![
+                cdh.signature.to_le_bytes().to_vec(),
+                cdh.version_made_by.to_le_bytes().to_vec(),
+                cdh.version_needed_to_extract.to_le_bytes().to_vec(),
+                general_purpose.to_le_bytes().to_vec(),
+                u16::from(cdh.compression_method).to_le_bytes().to_vec(),
+                cdh.last_mod_file_time.to_le_bytes().to_vec(),
+                cdh.last_mod_file_date.to_le_bytes().to_vec(),
+                cdh.crc_32.to_le_bytes().to_vec(),
+                cdh.compressed_size.to_le_bytes().to_vec(),
+                cdh.uncompressed_size.to_le_bytes().to_vec(),
+                cdh.file_name_length.to_le_bytes().to_vec(),
+                cdh.extra_field_length.to_le_bytes().to_vec(),
+                cdh.file_comment_length.to_le_bytes().to_vec(),
+                cdh.disk_number_start.to_le_bytes().to_vec(),
+                cdh.internal_file_attributes.to_le_bytes().to_vec(),
+                cdh.external_file_attributes.to_le_bytes().to_vec(),
+                cdh.local_header_offset.to_le_bytes().to_vec(),
+                cdh.file_name.to_vec(),
+                cdh.extra_field.to_vec(),
+                cdh.file_comment.to_vec(),
+            ]
+            .into_iter()
+            .flatten()
+            .collect()
+        }
+
+        fn eocd(eocd: &EOCD) -> Vec<u8> {
+            vec![
+                eocd.signature.to_le_bytes().to_vec(),
+                eocd.disk_number.to_le_bytes().to_vec(),
+                eocd.central_dir_start_disk.to_le_bytes().to_vec(),
+                eocd.cental_dir_entries_disk.to_le_bytes().to_vec(),
+                eocd.cental_dir_entries_total.to_le_bytes().to_vec(),
+                eocd.central_dir_size.to_le_bytes().to_vec(),
+                eocd.central_dir_offset.to_le_bytes().to_vec(),
+                (eocd.comment.len() as u16).to_le_bytes().to_vec(),
+                eocd.comment.to_vec(),
+            ]
+            .into_iter()
+            .flatten()
+            .collect()
+        }
+
+        pub(super) fn archive(ziparchive: &ZipArchive, repair: bool) -> Vec<u8> {
+            let mut zipfiles = vec![];
+            for zipfile in &ziparchive.zip_files {
+                zipfiles.extend(zip_file(zipfile, repair));
+            }
+
+            let cdhs = ziparchive
+                .zip_files
+                .iter()
+                .zip(&ziparchive.central_directory_headers)
+                .flat_map(|(zipfile, header)| {
+                    let general_purpose = match repair {
+                        true => zipfile.descriptor_flag_repaired(header.general_purpose),
+                        false => header.general_purpose,
+                    };
+                    cdh(header, general_purpose)
+                })
+                .collect();
+
+            vec![zipfiles, cdhs, eocd(&ziparchive.eocd)]
+                .into_iter()
+                .flatten()
+                .collect()
+        }
+    }
+
+    #[test]
+    fn write_to_matches_nested_vectors() {
+        let mut flagged = Entry::new(b"c.txt", b"hij");
+        flagged.general_purpose |= DATA_DESCRIPTOR_BIT;
+
+        let archives = [
+            archive(
+                &[
+                    Entry::new(b"a.txt", b"abc").with_descriptor(crc32(b"abc"), 3),
+                    Entry::new(b"b.txt", b"defg"),
+                ],
+                b"",
+            ),
+            archive(
+                &[Entry::new(b"a.txt", b"abc").with_extra_field(0xcafe, b"\x01\x02\x03")],
+                b"archive comment",
+            ),
+            archive(&[Entry::new(b"empty", b""), flagged], &fake_eocd()),
+        ];
+
+        for data in &archives {
+            let mut ziparchive = ZipArchive::try_from(&data[..]).unwrap();
+            ziparchive.central_directory_headers[0].file_comment = b"entry comment";
+            ziparchive.central_directory_headers[0].file_comment_length = 13;
+
+            let expected = nested_vectors::archive(&ziparchive, true);
+            assert_eq!(ziparchive.to_bytes(REPAIR).unwrap(), expected);
+            assert_eq!(expected.len(), ziparchive.len());
+
+            let mut written = vec![];
+            ziparchive.write_to(&mut written, REPAIR).unwrap();
+            assert_eq!(written, expected);
+
+            if ziparchive.to_bytes(Default::default()).is_ok() {
+                assert_eq!(
+                    ziparchive.to_bytes(Default::default()).unwrap(),
+                    nested_vectors::archive(&ziparchive, false)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn write_to_matches_nested_vectors_without_descriptor_signature() {
+        let zipfile = ZipFile {
+            local_file_header: LocalFileHeader {
+                signature: 0x04034b50,
+                general_purpose: DATA_DESCRIPTOR_BIT,
+                file_name_length: 5,
+                file_name: b"a.txt",
+                ..Default::default()
+            },
+            file_data: b"abc",
+            data_discriptor: Some(DataDiscriptor {
+                signature: None,
+                crc_32: crc32(b"abc"),
+                compressed_size: 3,
+                uncompressed_size: 3,
+            }),
+        };
+
+        let expected = nested_vectors::zip_file(&zipfile, false);
+        assert_eq!(expected.len(), zipfile.len());
+        assert_eq!(zipfile.to_bytes(Default::default()).unwrap(), expected);
+    }
+
+    /// Overwrites the field at `offset` of the EOCD of `data`, which has no comment
+    fn patch_eocd(data: &mut [u8], offset: usize, value: &[u8]) {
+        let eocd_pos = data.len() - 22;
+        data[eocd_pos + offset..eocd_pos + offset + value.len()].copy_from_slice(value);
+    }
+
+    #[test]
+    fn wrong_central_dir_size_is_reported() {
+        let entries = [Entry::new(b"a.txt", b"abc"), Entry::new(b"b.txt", b"defg")];
+        let original = archive(&entries, b"");
+        let central_dir_size = (46 + 5) * 2;
+
+        // smaller than one header, and larger than the archive
+        for size in [20u32, 10_000] {
+            let mut data = original.clone();
+            patch_eocd(&mut data, 12, &size.to_le_bytes());
+
+            let ziparchive = ZipArchive::try_from(&data[..]).unwrap();
+            assert_eq!(ziparchive.central_directory_headers.len(), 2);
+            assert_eq!(ziparchive.zip_files.len(), 2);
+            assert_eq!(ziparchive.zip_files[1].file_data, b"defg");
+            assert_eq!(
+                ziparchive.central_directory_headers[1].effective_name(),
+                "b.txt"
+            );
+            assert_eq!(
+                ziparchive.warnings,
+                [format!(
+                    "EOCD states a central directory size of {size} bytes, but it has \
+                     {central_dir_size} bytes"
+                )]
+            );
+        }
+    }
+
+    #[test]
+    fn wrong_entry_count_is_reported() {
+        let entries = [Entry::new(b"a.txt", b"abc"), Entry::new(b"b.txt", b"defg")];
+        let mut data = archive(&entries, b"");
+        patch_eocd(&mut data, 10, &3u16.to_le_bytes());
+
+        let ziparchive = ZipArchive::try_from(&data[..]).unwrap();
+        assert_eq!(ziparchive.central_directory_headers.len(), 2);
+        assert_eq!(ziparchive.zip_files.len(), 2);
+        assert_eq!(
+            ziparchive.warnings,
+            ["EOCD states 3 entries, but the central directory has 2 entries"]
+        );
+    }
+
+    #[test]
+    fn truncated_file_comment_is_an_error() {
+        let data = archive(&[Entry::new(b"a.txt", b"abc")], b"");
+        let central_dir_offset = 30 + 5 + 3;
+        let mut cdh = data[central_dir_offset..data.len() - 22].to_vec();
+        cdh[32..34].copy_from_slice(&1u16.to_le_bytes());
+
+        let error = CDH::try_from(&cdh[..]).err().unwrap();
+        assert_eq!(error.to_string(), "invalid file_comment_length");
+    }
+
+    /// Unicode Path extra field of `name` for an entry whose primary name has the crc `name_crc`
+    fn unicode_path(name_crc: u32, name: &str) -> Vec<u8> {
+        let mut data = vec![1];
+        data.extend(name_crc.to_le_bytes());
+        data.extend(name.as_bytes());
+        data
+    }
+
+    #[test]
+    fn unicode_path_with_matching_crc_is_the_name() {
+        // "übersicht.txt" in code page 437
+        let primary = b"\x81bersicht.txt";
+        let data = archive(
+            &[Entry::new(primary, b"abc").with_extra_field(
+                UNICODE_PATH_EXTRA_FIELD_ID,
+                &unicode_path(crc32(primary), "übersicht.txt"),
+            )],
+            b"",
+        );
+
+        let ziparchive = ZipArchive::try_from(&data[..]).unwrap();
+        assert_eq!(ziparchive.zip_files[0].local_file_header.file_name, primary);
+        assert_eq!(
+            ziparchive.central_directory_headers[0].effective_name(),
+            "übersicht.txt"
+        );
+        assert_eq!(
+            ziparchive.zip_files[0].local_file_header.effective_name(),
+            "übersicht.txt"
+        );
+    }
+
+    #[test]
+    fn unicode_path_with_other_crc_is_ignored() {
+        // the primary name was changed by a tool that doesn't know the extra field
+        let data = archive(
+            &[Entry::new(b"renamed.txt", b"abc").with_extra_field(
+                UNICODE_PATH_EXTRA_FIELD_ID,
+                &unicode_path(crc32(b"original.txt"), "original.txt"),
+            )],
+            b"",
+        );
+
+        let ziparchive = ZipArchive::try_from(&data[..]).unwrap();
+        assert_eq!(
+            ziparchive.central_directory_headers[0].effective_name(),
+            "renamed.txt"
+        );
+    }
+
+    #[test]
+    fn truncated_unicode_path_is_ignored() {
+        let name = b"a.txt";
+
+        // version and a partial crc
+        let short =
+            Entry::new(name, b"abc").with_extra_field(UNICODE_PATH_EXTRA_FIELD_ID, &[1, 0, 0]);
+
+        // the record claims more data than the extra field has
+        let mut overlong = Entry::new(name, b"abc");
+        overlong
+            .extra_field
+            .extend(UNICODE_PATH_EXTRA_FIELD_ID.to_le_bytes());
+        overlong.extra_field.extend(64u16.to_le_bytes());
+        overlong
+            .extra_field
+            .extend(unicode_path(crc32(name), "b.txt"));
+
+        // only version 1 is defined
+        let mut version_2 = unicode_path(crc32(name), "b.txt");
+        version_2[0] = 2;
+        let version_2 =
+            Entry::new(name, b"abc").with_extra_field(UNICODE_PATH_EXTRA_FIELD_ID, &version_2);
+
+        for entry in [short, overlong, version_2] {
+            let data = archive(&[entry], b"");
+            let ziparchive = ZipArchive::try_from(&data[..]).unwrap();
+            assert_eq!(
+                ziparchive.central_directory_headers[0].effective_name(),
+                "a.txt"
+            );
+        }
+    }
+
+    fn datetime(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> DosDateTime {
+        DosDateTime {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        }
+    }
+
+    #[test]
+    fn dos_epoch() {
+        let epoch = datetime(1980, 1, 1, 0, 0, 0);
+        assert_eq!(DosDateTime::decode(0x21, 0), Some(epoch));
+        assert_eq!(epoch.encode().unwrap(), (0x21, 0));
+
+        // the date fields are all 0 before the epoch is added
+        assert_eq!(DosDateTime::decode(0, 0), None);
+        assert!(datetime(1979, 12, 31, 23, 59, 58).encode().is_err());
+    }
+
+    #[test]
+    fn dos_seconds_have_two_second_resolution() {
+        let (date, time) = datetime(2024, 5, 17, 13, 37, 59).encode().unwrap();
+        assert_eq!(time & 0x1f, 29);
+        assert_eq!(
+            DosDateTime::decode(date, time),
+            Some(datetime(2024, 5, 17, 13, 37, 58))
+        );
+
+        // 30 two-second units are a full minute
+        assert_eq!(DosDateTime::decode(date, (time & !0x1f) | 30), None);
+    }
+
+    #[test]
+    fn invalid_dos_dates() {
+        let date = |year: u16, month: u16, day: u16| ((year - 1980) << 9) | (month << 5) | day;
+
+        assert_eq!(DosDateTime::decode(date(2023, 0, 1), 0), None);
+        assert_eq!(DosDateTime::decode(date(2023, 13, 1), 0), None);
+        assert_eq!(DosDateTime::decode(date(2023, 1, 0), 0), None);
+        assert_eq!(DosDateTime::decode(date(2023, 4, 31), 0), None);
+        assert_eq!(DosDateTime::decode(date(2023, 2, 29), 0), None);
+        assert_eq!(DosDateTime::decode(date(2100, 2, 29), 0), None);
+        assert_eq!(
+            DosDateTime::decode(date(2024, 2, 29), 0),
+            Some(datetime(2024, 2, 29, 0, 0, 0))
+        );
+        assert_eq!(
+            DosDateTime::decode(date(2000, 2, 29), 0),
+            Some(datetime(2000, 2, 29, 0, 0, 0))
+        );
+
+        // hour 24 and minute 60
+        assert_eq!(DosDateTime::decode(date(2023, 1, 1), 24 << 11), None);
+        assert_eq!(DosDateTime::decode(date(2023, 1, 1), 60 << 5), None);
+        assert!(datetime(2023, 2, 29, 0, 0, 0).encode().is_err());
+    }
+
+    #[test]
+    fn dos_round_trip() {
+        for datetime in [
+            datetime(1980, 1, 1, 0, 0, 0),
+            datetime(2009, 2, 13, 23, 31, 30),
+            datetime(2024, 2, 29, 12, 0, 2),
+            datetime(2107, 12, 31, 23, 59, 58),
+        ] {
+            let (date, time) = datetime.encode().unwrap();
+            assert_eq!(DosDateTime::decode(date, time), Some(datetime));
+        }
+    }
+
+    #[test]
+    fn dos_years_end_in_2107() {
+        assert_eq!(
+            datetime(2107, 12, 31, 0, 0, 0).encode().unwrap().0 >> 9,
+            0x7f
+        );
+        assert!(datetime(2108, 1, 1, 0, 0, 0).encode().is_err());
+        assert!(datetime(u16::MAX, 1, 1, 0, 0, 0).encode().is_err());
+    }
+
+    /// EOCD signature with a central directory at offset 0, where the first local file header is
+    fn fake_eocd() -> Vec<u8> {
+        let mut buf = EOCD_SIGNATURE.to_vec();
+        buf.extend([0; 4]);
+        buf.extend(1u16.to_le_bytes());
+        buf.extend(1u16.to_le_bytes());
+        buf.extend(46u32.to_le_bytes());
+        buf.extend(0u32.to_le_bytes());
+        buf.extend(0u16.to_le_bytes());
+        buf
+    }
+
+    #[test]
+    fn fake_eocd_in_comment() {
+        let data = archive(&[Entry::new(b"a.txt", b"abc")], &fake_eocd());
+
+        let ziparchive = ZipArchive::try_from(&data[..]).unwrap();
+        assert!(ziparchive.warnings.is_empty(), "{:?}", ziparchive.warnings);
+        assert_eq!(ziparchive.comment(), fake_eocd());
+        assert_eq!(ziparchive.central_directory_headers.len(), 1);
+        assert_eq!(ziparchive.to_bytes(Default::default()).unwrap(), data);
+    }
+
+    #[test]
+    fn fake_eocd_appended() {
+        let original = archive(&[Entry::new(b"a.txt", b"abc")], b"");
+        let mut data = original.clone();
+        data.extend(fake_eocd());
+
+        let ziparchive = ZipArchive::try_from(&data[..]).unwrap();
+        assert_eq!(ziparchive.warnings.len(), 1);
+        assert_eq!(ziparchive.central_directory_headers.len(), 1);
+        assert_eq!(ziparchive.zip_files[0].file_data, b"abc");
+        assert_eq!(ziparchive.to_bytes(Default::default()).unwrap(), original);
+    }
+}
@@ -0,0 +1,33 @@
+//! Inputs the fuzzer found panics with, see `fuzz/`. Parsing them has to fail or succeed, but
+//! never panic.
+
+use std::{fs, path::Path};
+
+use macon_zip::{
+    needs_encryption_bit_fix, try_remove_encryption_bits,
+    types::{SerializeOptions, ZipArchive},
+};
+
+#[test]
+fn fuzz_regressions_dont_panic() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fuzz_regressions");
+    let mut inputs = 0;
+
+    for entry in fs::read_dir(&dir).unwrap() {
+        let path = entry.unwrap().path();
+        let data = fs::read(&path).unwrap();
+
+        if let Ok(ziparchive) = ZipArchive::try_from(&data[..]) {
+            let options = SerializeOptions {
+                repair_descriptor_flag: true,
+            };
+            let _ = ziparchive.to_bytes(options);
+        }
+        let _ = needs_encryption_bit_fix(&data);
+        let _ = try_remove_encryption_bits(&data);
+
+        inputs += 1;
+    }
+
+    assert!(inputs > 0, "no inputs in {}", dir.display());
+}